@@ -0,0 +1,8 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        // Use the bundled protoc so building doesn't need one installed
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_build::compile_protos("proto/blob_store.proto").unwrap();
+    }
+}
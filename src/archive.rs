@@ -0,0 +1,875 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BlobId, BlobMetadata, TenantId, Result,
+    cancel::CancellationToken,
+    checksum::Checksum,
+    coordinator::{ConflictPolicy, Coordinator, PutOptions},
+    error::{Error, ResultExt},
+    filename,
+    tenant::TenantConfig,
+};
+
+/// Path of the manifest entry, always the first entry of an archive
+const MANIFEST_PATH: &str = "manifest.json";
+
+/// Directory holding blob entries inside an archive
+const BLOB_DIR: &str = "blobs";
+
+/// Selects which blobs a partial export includes. Every criterion that is
+/// set must match; the default filter matches all blobs.
+#[derive(Debug, Clone, Default)]
+pub struct BlobFilter {
+    /// Tags the blob must carry, each with this value among its values
+    pub tags: BTreeMap<String, String>,
+    /// Only blobs created at or after this time
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only blobs created before this time
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only these blobs
+    pub blob_ids: Option<HashSet<BlobId>>,
+}
+
+impl BlobFilter {
+    /// Returns true if the blob passes every criterion of the filter
+    pub fn matches(&self, metadata: &BlobMetadata) -> bool {
+        self.tags.iter().all(|(key, value)| metadata.tags.contains(key, value))
+            && self.created_after.is_none_or(|after| metadata.created_at >= after)
+            && self.created_before.is_none_or(|before| metadata.created_at < before)
+            && self.blob_ids.as_ref().is_none_or(|ids| ids.contains(&metadata.blob_id))
+    }
+}
+
+/// Options controlling how a tenant is exported
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Name archive entries after this tag's value (e.g. `filename`) instead
+    /// of the blob id. Blobs without the tag fall back to their id.
+    pub name_tag: Option<String>,
+    /// Export only the blobs matching this filter
+    pub filter: BlobFilter,
+}
+
+/// File formats for a tenant's metadata inventory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataFormat {
+    /// One row per blob, tags flattened into a single `key=value;...` column
+    #[default]
+    Csv,
+    /// A JSON array of blob metadata records
+    Json,
+}
+
+/// Formats for a checksum manifest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// `<checksum>  <blob id>` lines, as `sha256sum -c` style tools expect
+    #[default]
+    Text,
+    /// One JSON object per line with the tenant, blob id and checksum
+    Json,
+}
+
+/// One line of a JSON checksum manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestLine {
+    pub tenant_id: TenantId,
+    pub blob_id: BlobId,
+    pub checksum: Checksum,
+}
+
+/// Flattens tags into one `key=value` list separated by semicolons, with a
+/// pair for each value of a multi-valued tag
+fn flatten_tags(metadata: &BlobMetadata) -> String {
+    metadata
+        .tags
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// One exported blob, as recorded in the archive manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub metadata: BlobMetadata,
+}
+
+/// One line of a metadata replication stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum StreamRecord {
+    Tenant { tenant_id: TenantId, config: TenantConfig },
+    Blob { metadata: BlobMetadata },
+    Name { tenant_id: TenantId, name: String, blob_id: BlobId },
+}
+
+/// How one tenant's blob metadata differs between two stores, as reported
+/// by `Coordinator::diff_metadata`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataDiff {
+    /// Blobs only this store has
+    pub only_in_self: Vec<BlobId>,
+    /// Blobs only the other store has
+    pub only_in_other: Vec<BlobId>,
+    /// Blobs both stores have with a different size or checksum
+    pub differing: Vec<BlobId>,
+}
+
+impl MetadataDiff {
+    /// Returns true if both stores hold the same blobs
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.differing.is_empty()
+    }
+}
+
+/// How a directory of extracted blobs compares to the store, as reported by
+/// `Coordinator::verify_against_manifest`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportVerifyReport {
+    /// Blobs whose file matches their stored checksum
+    pub matched: Vec<BlobId>,
+    /// Blobs whose file has other content, with the file that was checked
+    pub mismatched: Vec<(BlobId, PathBuf)>,
+    /// Blobs with no file in the directory
+    pub missing: Vec<BlobId>,
+    /// True if verification stopped early because it was cancelled
+    pub cancelled: bool,
+}
+
+impl ExportVerifyReport {
+    /// Returns true if every blob's file was found and matched
+    pub fn is_clean(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Picks a unique archive path for each blob. When two blobs would share a
+/// name, later ones get their id appended.
+fn entry_paths(blobs: &[BlobMetadata], name_tag: Option<&str>) -> Vec<String> {
+    let mut used = HashSet::new();
+    blobs
+        .iter()
+        .map(|metadata| {
+            let id = metadata.blob_id.to_string();
+            let name = name_tag
+                .and_then(|tag| metadata.tags.get(tag))
+                .map(filename::encode_filename)
+                .unwrap_or_else(|| id.clone());
+            let name = if used.contains(&name) {
+                format!("{}_{}", name, id)
+            } else {
+                name
+            };
+            used.insert(name.clone());
+            format!("{}/{}", BLOB_DIR, name)
+        })
+        .collect()
+}
+
+/// Appends one file entry to the archive, streaming its contents
+fn append_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    size: u64,
+    mtime: i64,
+    data: impl Read,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.max(0) as u64);
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+impl Coordinator {
+    /// Writes a tenant's blobs matching `options.filter` to a tar archive along
+    /// with a manifest of their metadata. Returns the number of blobs exported.
+    /// The token is checked between blobs; a cancelled export is closed after
+    /// the last whole blob, so it still imports, with only the blobs written.
+    pub fn export_tenant(
+        &self,
+        tenant_id: &TenantId,
+        writer: impl Write,
+        options: &ExportOptions,
+        cancel: &CancellationToken,
+    ) -> Result<usize> {
+        let context = || format!("while exporting tenant {}", tenant_id.as_str());
+        let mut blobs = self.list_blobs(tenant_id).with_context(context)?;
+        blobs.retain(|metadata| options.filter.matches(metadata));
+        let paths = entry_paths(&blobs, options.name_tag.as_deref());
+        let manifest: Vec<ManifestEntry> = paths
+            .into_iter()
+            .zip(blobs)
+            .map(|(path, metadata)| ManifestEntry { path, metadata })
+            .collect();
+
+        let mut builder = tar::Builder::new(writer);
+        let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+        let now = self.now().timestamp();
+        append_entry(&mut builder, MANIFEST_PATH, manifest_json.len() as u64, now, &manifest_json[..])
+            .with_context(context)?;
+
+        let mut count = 0;
+        for entry in &manifest {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let reader = self.get_blob(tenant_id, &entry.metadata.blob_id).with_context(context)?;
+            let mtime = entry.metadata.created_at.timestamp();
+            append_entry(&mut builder, &entry.path, entry.metadata.size, mtime, reader)
+                .with_context(context)?;
+            count += 1;
+        }
+        builder.into_inner()?.flush()?;
+
+        Ok(count)
+    }
+
+    /// Checks files extracted from an export against the checksums the store
+    /// records for the tenant's blobs, like `sha256sum -c`. If the export's
+    /// `manifest.json` was extracted into `dir` too, each blob's file is
+    /// found at its path in the manifest; otherwise it is looked up directly
+    /// in `dir` by the blob's id, then by its `filename` tag. The token is
+    /// checked between files.
+    pub fn verify_against_manifest(
+        &self,
+        tenant_id: &TenantId,
+        dir: &Path,
+        cancel: &CancellationToken,
+    ) -> Result<ExportVerifyReport> {
+        let context = || format!("while verifying the export of tenant {} in {}", tenant_id.as_str(), dir.display());
+        let manifest_path = dir.join(MANIFEST_PATH);
+        let paths: HashMap<BlobId, String> = if manifest_path.is_file() {
+            let file = File::open(&manifest_path).map_err(Into::into).with_context(context)?;
+            let manifest: Vec<ManifestEntry> =
+                serde_json::from_reader(BufReader::new(file)).map_err(Into::into).with_context(context)?;
+            manifest.into_iter().map(|entry| (entry.metadata.blob_id, entry.path)).collect()
+        } else {
+            HashMap::new()
+        };
+
+        let mut report = ExportVerifyReport::default();
+        for metadata in self.list_blobs(tenant_id).with_context(context)? {
+            if cancel.is_cancelled() {
+                report.cancelled = true;
+                break;
+            }
+            let candidates = match paths.get(&metadata.blob_id) {
+                Some(path) => vec![dir.join(path)],
+                None => {
+                    let by_tag = metadata.tags.get("filename").map(|name| dir.join(filename::encode_filename(name)));
+                    [Some(dir.join(metadata.blob_id.to_string())), by_tag].into_iter().flatten().collect()
+                }
+            };
+            let Some(path) = candidates.into_iter().find(|path| path.is_file()) else {
+                report.missing.push(metadata.blob_id);
+                continue;
+            };
+            let file = File::open(&path).map_err(Into::into).with_context(context)?;
+            if metadata.checksum.verify_against(BufReader::new(file)).with_context(context)? {
+                report.matched.push(metadata.blob_id);
+            } else {
+                report.mismatched.push((metadata.blob_id, path));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Writes an inventory of a tenant's blob metadata, one record per blob.
+    /// Records are streamed as they are read. Returns the number written.
+    /// With `pretty`, JSON records are indented over several lines; fields
+    /// always appear in declaration order and tags sorted by key.
+    pub fn export_metadata(
+        &self,
+        tenant_id: &TenantId,
+        format: MetadataFormat,
+        pretty: bool,
+        mut writer: impl Write,
+    ) -> Result<usize> {
+        let context = || format!("while exporting metadata for tenant {}", tenant_id.as_str());
+        let blobs = self.iter_blobs(tenant_id).with_context(context)?;
+        let mut count = 0;
+
+        match format {
+            MetadataFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(writer);
+                csv.write_record(["blob_id", "size", "checksum", "created_at", "tags"])
+                    .map_err(std::io::Error::from)?;
+                for metadata in blobs {
+                    let metadata = metadata.with_context(context)?;
+                    csv.write_record([
+                        metadata.blob_id.to_string(),
+                        metadata.size.to_string(),
+                        metadata.checksum.to_string(),
+                        metadata.created_at.to_rfc3339(),
+                        flatten_tags(&metadata),
+                    ])
+                    .map_err(std::io::Error::from)?;
+                    count += 1;
+                }
+                csv.flush()?;
+            }
+            MetadataFormat::Json => {
+                writer.write_all(b"[")?;
+                for metadata in blobs {
+                    let metadata = metadata.with_context(context)?;
+                    if count > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    if pretty {
+                        let record = serde_json::to_string_pretty(&metadata)?;
+                        writer.write_all(b"\n  ")?;
+                        writer.write_all(record.replace('\n', "\n  ").as_bytes())?;
+                    } else {
+                        writer.write_all(b"\n  ")?;
+                        serde_json::to_writer(&mut writer, &metadata)?;
+                    }
+                    count += 1;
+                }
+                writer.write_all(b"\n]\n")?;
+                writer.flush()?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Writes the id and checksum of every live blob of `tenant_id`, or of
+    /// every tenant if `None`, one per line as they are read. Returns the
+    /// number of blobs written.
+    pub fn export_checksum_manifest(
+        &self,
+        tenant_id: Option<&TenantId>,
+        format: ManifestFormat,
+        mut writer: impl Write,
+    ) -> Result<usize> {
+        let entries: Box<dyn Iterator<Item = Result<(TenantId, BlobId, Checksum)>>> = match tenant_id {
+            Some(tenant_id) => {
+                let entries = self.checksum_manifest(tenant_id)?;
+                let tenant_id = tenant_id.clone();
+                Box::new(entries.into_iter().map(move |(blob_id, checksum)| Ok((tenant_id.clone(), blob_id, checksum))))
+            }
+            None => Box::new(self.iter_checksum_manifest()?),
+        };
+        let mut count = 0;
+        for entry in entries {
+            let (tenant_id, blob_id, checksum) = entry?;
+            match format {
+                ManifestFormat::Text => writeln!(writer, "{}  {}", checksum.to_hex(), blob_id)?,
+                ManifestFormat::Json => {
+                    serde_json::to_writer(&mut writer, &ManifestLine { tenant_id, blob_id, checksum })?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Writes every tenant registration, blob metadata record and name
+    /// binding, but no blob data, as JSON lines for `import_metadata_stream`.
+    /// Each tenant's line precedes its blobs, which precede its names.
+    /// Returns the number of blobs written.
+    pub fn export_metadata_stream(&self, mut writer: impl Write) -> Result<usize> {
+        let context = || "while exporting the metadata stream".to_string();
+        let mut count = 0;
+        for tenant_id in self.list_tenants().with_context(context)? {
+            let config = self.get_tenant_config(&tenant_id).with_context(context)?;
+            let blobs = self.iter_blobs(&tenant_id).with_context(context)?;
+            serde_json::to_writer(&mut writer, &StreamRecord::Tenant { tenant_id: tenant_id.clone(), config })?;
+            writer.write_all(b"\n")?;
+            for metadata in blobs {
+                let metadata = metadata.with_context(context)?;
+                serde_json::to_writer(&mut writer, &StreamRecord::Blob { metadata })?;
+                writer.write_all(b"\n")?;
+                count += 1;
+            }
+            for (name, blob_id) in self.list_names(&tenant_id).with_context(context)? {
+                let tenant_id = tenant_id.clone();
+                serde_json::to_writer(&mut writer, &StreamRecord::Name { tenant_id, name, blob_id })?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Loads a stream written by `export_metadata_stream`, registering
+    /// missing tenants and recording each blob as metadata-only: it shows up
+    /// in listings and searches, but reading it fails with `DataNotLocal`.
+    /// Blobs that already exist here or have a live tombstone are skipped,
+    /// as are names already bound. Returns the number of blobs imported.
+    pub fn import_metadata_stream(&self, reader: impl Read) -> Result<usize> {
+        let context = || "while importing the metadata stream".to_string();
+        let mut count = 0;
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line)? {
+                // Tenants that are already registered keep their settings
+                StreamRecord::Tenant { tenant_id, config } => {
+                    self.register_tenant_with_config(tenant_id, config).with_context(context)?;
+                }
+                // Blobs deleted here since the export stay deleted
+                StreamRecord::Blob { metadata } => match self.put_metadata_only(metadata) {
+                    Ok(true) => count += 1,
+                    Ok(false) => {}
+                    Err(Error::BlobTombstoned { blob_id, .. }) => {
+                        tracing::debug!(%blob_id, "skipping tombstoned blob in metadata stream");
+                    }
+                    Err(e) => return Err(e).with_context(context),
+                },
+                // Names of skipped blobs, or taken here, are left alone
+                StreamRecord::Name { tenant_id, name, blob_id } => match self.link_name(&tenant_id, &name, &blob_id) {
+                    Ok(()) => {}
+                    Err(e) if e.is_not_found()
+                        || matches!(e.root_cause(), Error::BlobAlreadyExists(_) | Error::InvalidTenant(_)) => {}
+                    Err(e) => return Err(e).with_context(context),
+                },
+            }
+        }
+        Ok(count)
+    }
+
+    /// Compares a tenant's live blob metadata in this store against
+    /// `other`, e.g. to check a replica or migration. Both stores are only
+    /// read. Blobs are matched by id and compared by size and checksum.
+    pub fn diff_metadata(&self, other: &Coordinator, tenant_id: &TenantId) -> Result<MetadataDiff> {
+        let context = || format!("while diffing metadata of tenant {}", tenant_id.as_str());
+        let ours = self.list_blobs(tenant_id).with_context(context)?;
+        let theirs = other.list_blobs(tenant_id).with_context(context)?;
+        let their_index: HashMap<&BlobId, &BlobMetadata> =
+            theirs.iter().map(|metadata| (&metadata.blob_id, metadata)).collect();
+        let our_ids: HashSet<&BlobId> = ours.iter().map(|metadata| &metadata.blob_id).collect();
+
+        let mut diff = MetadataDiff::default();
+        for metadata in &ours {
+            match their_index.get(&metadata.blob_id) {
+                None => diff.only_in_self.push(metadata.blob_id.clone()),
+                Some(theirs) if theirs.size != metadata.size || theirs.checksum != metadata.checksum => {
+                    diff.differing.push(metadata.blob_id.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.only_in_other = theirs
+            .iter()
+            .filter(|metadata| !our_ids.contains(&metadata.blob_id))
+            .map(|metadata| metadata.blob_id.clone())
+            .collect();
+        Ok(diff)
+    }
+
+    /// Restores blobs from an archive written by `export_tenant` into a tenant,
+    /// keeping their ids, tags and creation times. Each blob's checksum is
+    /// verified against the manifest as it is stored.
+    pub fn import_tenant(&self, tenant_id: &TenantId, reader: impl Read) -> Result<Vec<BlobId>> {
+        let context = || format!("while importing into tenant {}", tenant_id.as_str());
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = archive.entries()?;
+
+        let mut manifest: HashMap<String, BlobMetadata> = match entries.next() {
+            Some(entry) => {
+                let entry = entry?;
+                if entry.path()?.to_string_lossy() != MANIFEST_PATH {
+                    return Err(Error::System("Archive does not start with a manifest".into()));
+                }
+                let entries: Vec<ManifestEntry> = serde_json::from_reader(entry)?;
+                entries.into_iter().map(|e| (e.path, e.metadata)).collect()
+            }
+            None => return Err(Error::System("Archive is empty".into())),
+        };
+
+        let mut imported = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let metadata = manifest
+                .remove(&path)
+                .ok_or_else(|| Error::System(format!("Archive entry {} is not in the manifest", path)))?;
+
+            let options = PutOptions {
+                expected_checksum: Some(metadata.checksum),
+                trailer_checksum: None,
+                tags: metadata.tags,
+                blob_id: Some(metadata.blob_id),
+                created_at: Some(metadata.created_at),
+                content_type: metadata.content_type,
+                retain_until: metadata.retain_until,
+                expires_at: metadata.expires_at,
+                on_conflict: ConflictPolicy::Error,
+                size_hint: Some(metadata.size),
+                extensions: metadata.extensions,
+                upload_locked: false,
+            };
+            imported.push(self.store_blob(tenant_id, entry, options).with_context(context)?);
+        }
+
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::Tags;
+    use std::io::Cursor;
+
+    fn filename_tag(name: &str) -> Tags {
+        [("filename".to_string(), name.to_string())].into()
+    }
+
+    #[test]
+    fn test_export_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let mut tags = filename_tag("report, \"final\".txt");
+        tags.add("owner", "ops");
+        let tagged = coordinator.put_blob_with_tags(&tenant_id, Cursor::new(b"report"), tags).unwrap();
+        let plain = coordinator.put_blob(&tenant_id, Cursor::new(b"plain")).unwrap();
+
+        let mut csv_bytes = Vec::new();
+        let count = coordinator.export_metadata(&tenant_id, MetadataFormat::Csv, false, &mut csv_bytes).unwrap();
+        assert_eq!(count, 2);
+
+        let mut reader = csv::Reader::from_reader(&csv_bytes[..]);
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["blob_id", "size", "checksum", "created_at", "tags"]
+        );
+        let rows: HashMap<String, csv::StringRecord> = reader
+            .records()
+            .map(|r| r.unwrap())
+            .map(|r| (r[0].to_string(), r))
+            .collect();
+        let row = &rows[&tagged.to_string()];
+        assert_eq!(&row[1], "6");
+        assert_eq!(&row[2], checksum_of(b"report").to_string());
+        assert_eq!(&row[4], "filename=report, \"final\".txt;owner=ops");
+        assert_eq!(&rows[&plain.to_string()][4], "");
+
+        let mut json_bytes = Vec::new();
+        coordinator.export_metadata(&tenant_id, MetadataFormat::Json, false, &mut json_bytes).unwrap();
+        let records: Vec<BlobMetadata> = serde_json::from_slice(&json_bytes).unwrap();
+        assert_eq!(records.len(), 2);
+
+        // Pretty output spreads each record over several lines but holds the same data
+        let mut pretty_bytes = Vec::new();
+        coordinator.export_metadata(&tenant_id, MetadataFormat::Json, true, &mut pretty_bytes).unwrap();
+        let compact = String::from_utf8(json_bytes).unwrap();
+        let pretty = String::from_utf8(pretty_bytes).unwrap();
+        assert_eq!(compact.lines().count(), 4);
+        assert!(pretty.lines().count() > 10);
+        assert!(pretty.contains("\n    \"blob_id\": "));
+        let compact: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        let pretty: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(compact, pretty);
+    }
+
+    #[test]
+    fn test_metadata_stream_replication() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = Coordinator::new(temp_dir.path().join("source")).unwrap().with_packfiles(1 << 20).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let config = TenantConfig { display_name: Some("Posts".into()), ..Default::default() };
+        source.register_tenant_with_config(tenant_id.clone(), config.clone()).unwrap();
+        source.register_tenant(TenantId::new("empty")).unwrap();
+        let tagged = source.put_blob_with_tags(&tenant_id, Cursor::new(b"report"), filename_tag("report.txt")).unwrap();
+        let plain = source.put_blob(&tenant_id, Cursor::new(b"plain")).unwrap();
+        source.link_name(&tenant_id, "latest", &plain).unwrap();
+
+        let mut stream = Vec::new();
+        assert_eq!(source.export_metadata_stream(&mut stream).unwrap(), 2);
+
+        // A blob the replica already holds keeps its own record and data
+        let replica = Coordinator::new(temp_dir.path().join("replica")).unwrap();
+        replica.register_tenant_with_config(tenant_id.clone(), config).unwrap();
+        let local = replica.put_blob(&tenant_id, Cursor::new(b"local")).unwrap();
+        assert_eq!(replica.list_blobs(&tenant_id).unwrap()[0].seq, 0);
+        let options = PutOptions { blob_id: Some(tagged.clone()), ..Default::default() };
+        replica.store_blob(&tenant_id, Cursor::new(b"report"), options).unwrap();
+        assert_eq!(replica.import_metadata_stream(&stream[..]).unwrap(), 1);
+        let mut data = Vec::new();
+        replica.get_blob(&tenant_id, &tagged).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"report");
+        replica.delete_blob(&tenant_id, &local).unwrap();
+        replica.delete_blob(&tenant_id, &tagged).unwrap();
+        replica.delete_blob(&tenant_id, &plain).unwrap();
+        assert_eq!(replica.import_metadata_stream(&stream[..]).unwrap(), 2);
+
+        // Imported records are sequenced locally and aren't packed here
+        let imported = replica.list_blobs(&tenant_id).unwrap();
+        assert!(imported.iter().all(|metadata| metadata.pack.is_none() && metadata.seq > 1));
+        assert_eq!(replica.list_names(&tenant_id).unwrap(), vec![("latest".to_string(), plain.clone())]);
+
+        // Tenants and listings come across
+        let mut tenants = replica.list_tenants().unwrap();
+        tenants.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(tenants, vec![TenantId::new("empty"), tenant_id.clone()]);
+        assert_eq!(replica.get_tenant_config(&tenant_id).unwrap().display_name.as_deref(), Some("Posts"));
+        let blobs = replica.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 2);
+        let metadata = blobs.iter().find(|metadata| metadata.blob_id == tagged).unwrap();
+        assert_eq!(metadata.checksum, checksum_of(b"report"));
+        assert_eq!(metadata.tags, filename_tag("report.txt"));
+        assert!(metadata.metadata_only);
+
+        // The data stays on the source
+        let err = replica.get_blob(&tenant_id, &plain).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::DataNotLocal(_)));
+        assert!(replica.verify_all(&crate::cancel::CancellationToken::new()).unwrap().corrupted.is_empty());
+        assert!(replica.check_consistency().unwrap().missing_chunks.is_empty());
+
+        // A blob deleted on a replica that keeps tombstones isn't brought
+        // back by importing the stream again
+        let replica = replica.with_tombstones(chrono::Duration::hours(1));
+        replica.delete_blob(&tenant_id, &plain).unwrap();
+        replica.import_metadata_stream(&stream[..]).unwrap();
+        assert!(!replica.blob_exists(&tenant_id, &plain).unwrap());
+        assert!(replica.blob_exists(&tenant_id, &tagged).unwrap());
+    }
+
+    #[test]
+    fn test_diff_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = Coordinator::new(temp_dir.path().join("source")).unwrap();
+        let replica = Coordinator::new(temp_dir.path().join("replica")).unwrap();
+        let tenant_id = TenantId::new("posts");
+        source.register_tenant(tenant_id.clone()).unwrap();
+        replica.register_tenant(tenant_id.clone()).unwrap();
+
+        let put_both = |data: &[u8], replica_data: &[u8]| {
+            let blob_id = source.put_blob(&tenant_id, Cursor::new(data.to_vec())).unwrap();
+            let options = PutOptions { blob_id: Some(blob_id.clone()), ..Default::default() };
+            replica.store_blob(&tenant_id, Cursor::new(replica_data.to_vec()), options).unwrap();
+            blob_id
+        };
+        put_both(b"same", b"same");
+        let changed = put_both(b"original", b"modified");
+        let resized = put_both(b"short", b"much longer");
+        let source_only = source.put_blob(&tenant_id, Cursor::new(b"source")).unwrap();
+        let replica_only = replica.put_blob(&tenant_id, Cursor::new(b"replica")).unwrap();
+
+        let diff = source.diff_metadata(&replica, &tenant_id).unwrap();
+        assert_eq!(diff.only_in_self, vec![source_only.clone()]);
+        assert_eq!(diff.only_in_other, vec![replica_only.clone()]);
+        assert_eq!(diff.differing, vec![changed.clone(), resized.clone()]);
+
+        // The view from the other side is the mirror image
+        let reverse = replica.diff_metadata(&source, &tenant_id).unwrap();
+        assert_eq!(reverse.only_in_self, vec![replica_only]);
+        assert_eq!(reverse.only_in_other, vec![source_only]);
+        assert_eq!(reverse.differing, vec![changed, resized]);
+        assert!(source.diff_metadata(&source, &tenant_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checksum_manifest() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let posts = TenantId::new("posts");
+        let images = TenantId::new("images");
+        coordinator.register_tenant(posts.clone()).unwrap();
+        coordinator.register_tenant(images.clone()).unwrap();
+        let first = coordinator.put_blob(&posts, Cursor::new(b"first")).unwrap();
+        let second = coordinator.put_blob(&posts, Cursor::new(b"second")).unwrap();
+        let image = coordinator.put_blob(&images, Cursor::new(b"image")).unwrap();
+
+        let manifest = coordinator.checksum_manifest(&posts).unwrap();
+        assert_eq!(manifest, vec![(first.clone(), checksum_of(b"first")), (second.clone(), checksum_of(b"second"))]);
+
+        // Text lines split back into checksum and id
+        let mut text = Vec::new();
+        assert_eq!(coordinator.export_checksum_manifest(Some(&posts), ManifestFormat::Text, &mut text).unwrap(), 2);
+        let parsed: Vec<(BlobId, Checksum)> = String::from_utf8(text)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let (checksum, blob_id) = line.split_once("  ").unwrap();
+                (blob_id.parse().unwrap(), checksum.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(parsed, manifest);
+
+        // The whole store streams as JSON lines
+        let mut json = Vec::new();
+        assert_eq!(coordinator.export_checksum_manifest(None, ManifestFormat::Json, &mut json).unwrap(), 3);
+        let lines: Vec<ManifestLine> =
+            json.split(|&b| b == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+        assert!(lines.contains(&ManifestLine { tenant_id: images, blob_id: image, checksum: checksum_of(b"image") }));
+        assert!(lines.contains(&ManifestLine { tenant_id: posts, blob_id: first, checksum: checksum_of(b"first") }));
+    }
+
+    fn checksum_of(data: &[u8]) -> Checksum {
+        crate::checksum::checksum_of(data).unwrap()
+    }
+
+    #[test]
+    fn test_export_entry_naming_and_import() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let report = coordinator
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"first report"), filename_tag("report.txt"))
+            .unwrap();
+        let untagged = coordinator.put_blob(&tenant_id, Cursor::new(b"untagged")).unwrap();
+        let duplicate = coordinator
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"second report"), filename_tag("report.txt"))
+            .unwrap();
+        let unsafe_name = coordinator
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"unsafe"), filename_tag("a:b"))
+            .unwrap();
+
+        let options = ExportOptions { name_tag: Some("filename".into()), ..Default::default() };
+        let mut archive_bytes = Vec::new();
+        let count = coordinator.export_tenant(&tenant_id, &mut archive_bytes, &options, &CancellationToken::new()).unwrap();
+        assert_eq!(count, 4);
+
+        let mut archive = tar::Archive::new(Cursor::new(&archive_bytes));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "manifest.json".to_string(),
+                "blobs/report.txt".to_string(),
+                format!("blobs/{}", untagged),
+                format!("blobs/report.txt_{}", duplicate),
+                "blobs/a%3Ab".to_string(),
+            ]
+        );
+
+        // Restoring into a fresh store keeps ids, tags and contents
+        let restore_dir = tempfile::tempdir().unwrap();
+        let restored = Coordinator::new(restore_dir.path()).unwrap();
+        restored.register_tenant(tenant_id.clone()).unwrap();
+        let imported = restored.import_tenant(&tenant_id, Cursor::new(&archive_bytes)).unwrap();
+        assert_eq!(imported, vec![report.clone(), untagged, duplicate, unsafe_name]);
+
+        let mut data = Vec::new();
+        restored.get_blob(&tenant_id, &report).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"first report");
+        let blobs = restored.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs[0].tags, filename_tag("report.txt"));
+
+        // A cancelled export writes no blobs but is still a valid archive
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let mut partial = Vec::new();
+        assert_eq!(coordinator.export_tenant(&tenant_id, &mut partial, &options, &cancel).unwrap(), 0);
+        let empty = Coordinator::in_memory().unwrap();
+        empty.register_tenant(tenant_id.clone()).unwrap();
+        assert!(empty.import_tenant(&tenant_id, Cursor::new(&partial)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_filtered_export() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let team = |name: &str| BTreeMap::from([("team".to_string(), name.to_string())]);
+        let ops_a = coordinator.put_blob_with_tags(&tenant_id, Cursor::new(b"ops a"), team("ops").into()).unwrap();
+        let dev = coordinator.put_blob_with_tags(&tenant_id, Cursor::new(b"dev"), team("dev").into()).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"untagged")).unwrap();
+        let ops_b = coordinator.put_blob_with_tags(&tenant_id, Cursor::new(b"ops b"), team("ops").into()).unwrap();
+
+        let options = ExportOptions {
+            filter: BlobFilter { tags: team("ops"), ..Default::default() },
+            ..Default::default()
+        };
+        let mut archive_bytes = Vec::new();
+        assert_eq!(coordinator.export_tenant(&tenant_id, &mut archive_bytes, &options, &CancellationToken::new()).unwrap(), 2);
+
+        let mut archive = tar::Archive::new(Cursor::new(&archive_bytes));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["manifest.json".to_string(), format!("blobs/{}", ops_a), format!("blobs/{}", ops_b)]
+        );
+
+        let restore_dir = tempfile::tempdir().unwrap();
+        let restored = Coordinator::new(restore_dir.path()).unwrap();
+        restored.register_tenant(tenant_id.clone()).unwrap();
+        let imported = restored.import_tenant(&tenant_id, Cursor::new(&archive_bytes)).unwrap();
+        assert_eq!(imported, vec![ops_a.clone(), ops_b]);
+        assert!(!restored.blob_exists(&tenant_id, &dev).unwrap());
+
+        // Id and time filters combine with tag filters
+        let ids_only = BlobFilter { blob_ids: Some([ops_a.clone(), dev].into()), ..Default::default() };
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.iter().filter(|m| ids_only.matches(m)).count(), 2);
+        let none_yet = BlobFilter { created_before: Some(blobs[0].created_at), ..Default::default() };
+        assert!(!blobs.iter().any(|m| none_yet.matches(m)));
+    }
+
+    #[test]
+    fn test_verify_against_manifest() {
+        let coordinator = Coordinator::in_memory().unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let report = coordinator
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"quarterly"), filename_tag("report.txt"))
+            .unwrap();
+        let notes = coordinator.put_blob(&tenant_id, Cursor::new(b"notes")).unwrap();
+        let photo = coordinator
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"cat"), filename_tag("cat.png"))
+            .unwrap();
+
+        let options = ExportOptions { name_tag: Some("filename".into()), ..Default::default() };
+        let mut archive_bytes = Vec::new();
+        coordinator.export_tenant(&tenant_id, &mut archive_bytes, &options, &CancellationToken::new()).unwrap();
+        let extract_dir = tempfile::tempdir().unwrap();
+        tar::Archive::new(Cursor::new(&archive_bytes)).unpack(extract_dir.path()).unwrap();
+        let verified = coordinator.verify_against_manifest(&tenant_id, extract_dir.path(), &CancellationToken::new()).unwrap();
+        assert!(verified.is_clean());
+        assert_eq!(verified.matched, [report.clone(), notes.clone(), photo.clone()]);
+
+        // Only the corrupted file is flagged
+        let corrupted = extract_dir.path().join("blobs/report.txt");
+        std::fs::write(&corrupted, b"quarterlY").unwrap();
+        let verified = coordinator.verify_against_manifest(&tenant_id, extract_dir.path(), &CancellationToken::new()).unwrap();
+        assert_eq!(verified.mismatched, [(report.clone(), corrupted)]);
+        assert_eq!(verified.matched, [notes.clone(), photo.clone()]);
+        assert!(verified.missing.is_empty());
+
+        // Without the manifest, files are found by id or filename tag
+        let blobs_dir = extract_dir.path().join("blobs");
+        std::fs::remove_file(blobs_dir.join("cat.png")).unwrap();
+        let verified = coordinator.verify_against_manifest(&tenant_id, &blobs_dir, &CancellationToken::new()).unwrap();
+        assert_eq!(verified.mismatched, [(report, blobs_dir.join("report.txt"))]);
+        assert_eq!(verified.matched, [notes]);
+        assert_eq!(verified.missing, [photo]);
+
+        // A cancelled token stops before any file is checked
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let verified = coordinator.verify_against_manifest(&tenant_id, &blobs_dir, &cancel).unwrap();
+        assert!(verified.cancelled);
+        assert!(verified.matched.is_empty() && verified.mismatched.is_empty() && verified.missing.is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A counting Bloom filter: like a plain Bloom filter, a negative answer is
+/// definitive, but items can also be removed again
+pub struct BloomFilter {
+    counters: Vec<u8>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Creates a filter sized for `expected_items` at the given false positive rate
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let items = expected_items.max(1) as f64;
+        let slots = (-items * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = ((slots / items) * ln2).round().clamp(1.0, 16.0);
+
+        Self {
+            counters: vec![0; slots as usize],
+            hashes: hashes as u32,
+        }
+    }
+
+    /// Returns the counter slots for an item using double hashing
+    fn slots<T: Hash + ?Sized>(&self, item: &T) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h1 = hasher.finish();
+        0x9e37_79b9_7f4a_7c15_u64.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+
+        let len = self.counters.len() as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for slot in self.slots(item).collect::<Vec<_>>() {
+            self.counters[slot] = self.counters[slot].saturating_add(1);
+        }
+    }
+
+    /// Removes a previously inserted item. Saturated counters are never
+    /// decremented, so removal can't introduce false negatives.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) {
+        for slot in self.slots(item).collect::<Vec<_>>() {
+            if self.counters[slot] != u8::MAX {
+                self.counters[slot] = self.counters[slot].saturating_sub(1);
+            }
+        }
+    }
+
+    /// Returns false if the item is definitely absent, true if it may be present
+    pub fn may_contain<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.slots(item).all(|slot| self.counters[slot] > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_operations() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(&i);
+        }
+        assert!((0..1000).all(|i| filter.may_contain(&i)));
+
+        // False positives stay rare
+        let false_positives = (1000..11000).filter(|i| filter.may_contain(i)).count();
+        assert!(false_positives < 500);
+
+        // Removing items keeps the rest present
+        for i in 0..500 {
+            filter.remove(&i);
+        }
+        assert!((500..1000).all(|i| filter.may_contain(&i)));
+    }
+}
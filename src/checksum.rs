@@ -1,8 +1,96 @@
-use sha2::{Sha256, Digest};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Sha512, Digest};
+use subtle::ConstantTimeEq;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
 use std::io::{self, Read};
+use std::path::Path;
+
+/// Files at least this large are hashed through a memory map
+pub const MMAP_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Textual encodings a digest can be rendered in. The store always keeps
+/// lowercase hex; other encodings are for callers that expect them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigestEncoding {
+    /// Lowercase hex, the canonical stored form
+    #[default]
+    Hex,
+    /// Uppercase hex
+    HexUpper,
+    /// Standard padded base64 (RFC 4648)
+    Base64,
+}
+
+impl DigestEncoding {
+    /// Renders raw digest bytes in this encoding
+    pub fn encode(self, digest: &[u8]) -> String {
+        match self {
+            DigestEncoding::Hex => digest.iter().map(|b| format!("{:02x}", b)).collect(),
+            DigestEncoding::HexUpper => digest.iter().map(|b| format!("{:02X}", b)).collect(),
+            DigestEncoding::Base64 => {
+                let mut encoded = String::with_capacity(digest.len().div_ceil(3) * 4);
+                for chunk in digest.chunks(3) {
+                    let buffer = chunk.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32)
+                        << (8 * (3 - chunk.len()));
+                    for i in 0..4 {
+                        if i <= chunk.len() {
+                            encoded.push(BASE64_ALPHABET[(buffer >> (18 - 6 * i)) as usize & 63] as char);
+                        } else {
+                            encoded.push('=');
+                        }
+                    }
+                }
+                encoded
+            }
+        }
+    }
+}
+
+/// Decodes a SHA-256 digest written in any supported encoding
+fn decode_digest(digest: &str) -> Option<Vec<u8>> {
+    let digest = digest.trim();
+    if digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return (0..64)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digest[i..i + 2], 16).ok())
+            .collect();
+    }
+
+    // Base64, with or without padding
+    let mut bytes = Vec::with_capacity(32);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in digest.trim_end_matches('=').bytes() {
+        let value = BASE64_ALPHABET.iter().position(|&a| a == c)?;
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    (bytes.len() == 32).then_some(bytes)
+}
+
+/// Compares two byte strings in time that depends only on their lengths,
+/// not on where they first differ, so comparing a secret such as an auth
+/// token against a guess leaks nothing about the secret. Use it for checksum
+/// comparisons too.
+pub fn constant_time_eq(a: impl AsRef<[u8]>, b: impl AsRef<[u8]>) -> bool {
+    a.as_ref().ct_eq(b.as_ref()).into()
+}
 
 /// Computes the SHA-256 hash of the given data
-pub fn compute_sha256<R: Read>(mut reader: R) -> io::Result<String> {
+pub fn compute_sha256<R: Read>(reader: R) -> io::Result<String> {
+    compute_sha256_encoded(reader, DigestEncoding::Hex)
+}
+
+/// Computes the SHA-256 hash of the given data, rendered in `encoding`
+pub fn compute_sha256_encoded<R: Read>(mut reader: R, encoding: DigestEncoding) -> io::Result<String> {
     let mut hasher = Sha256::new();
     let mut buffer = [0; 8192]; // 8KB buffer
 
@@ -14,13 +102,548 @@ pub fn compute_sha256<R: Read>(mut reader: R) -> io::Result<String> {
         hasher.update(&buffer[..count]);
     }
 
-    Ok(format!("{:x}", hasher.finalize()))
+    Ok(encoding.encode(&hasher.finalize()))
+}
+
+/// Hashes and counts the bytes read through it, for hashing data while it
+/// is being consumed by something else
+pub struct HashingReader<R> {
+    inner: R,
+    algorithm: ChecksumAlgorithm,
+    hasher: Hasher,
+    size: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    /// Hashes with SHA-256, the algorithm new blobs are recorded with
+    pub fn new(inner: R) -> Self {
+        Self::with_algorithm(inner, ChecksumAlgorithm::Sha256)
+    }
+
+    pub fn with_algorithm(inner: R, algorithm: ChecksumAlgorithm) -> Self {
+        Self { inner, algorithm, hasher: Hasher::new(algorithm), size: 0 }
+    }
+
+    /// Returns the number of bytes read and their checksum
+    pub fn finish(self) -> (u64, Checksum) {
+        (self.size, Checksum::new(self.algorithm.name(), self.hasher.finish_raw()))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
+
+/// Digest algorithms a put can record alongside the primary checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    /// MD5, as used for S3 ETags
+    Md5,
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    /// Looks up an algorithm by its lowercase name, as used in `<algo>:<hex>` checksums
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "md5" => Some(ChecksumAlgorithm::Md5),
+            "sha256" => Some(ChecksumAlgorithm::Sha256),
+            "sha512" => Some(ChecksumAlgorithm::Sha512),
+            "blake3" => Some(ChecksumAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// The algorithm's lowercase name, as used in `<algo>:<hex>` checksums
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// An in-progress digest of one `ChecksumAlgorithm`
+#[derive(Clone)]
+enum Hasher {
+    Md5(md5::Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Hasher::Md5(md5::Md5::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            ChecksumAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        DigestEncoding::Hex.encode(&self.finish_raw())
+    }
+
+    fn finish_raw(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Feeds the bytes read through it to one hasher per requested algorithm,
+/// so several digests are computed in a single pass over the data
+pub struct MultiHashingReader<R> {
+    inner: R,
+    hashers: Vec<(ChecksumAlgorithm, Hasher)>,
+}
+
+impl<R: Read> MultiHashingReader<R> {
+    pub fn new(inner: R, algorithms: &BTreeSet<ChecksumAlgorithm>) -> Self {
+        let hashers = algorithms.iter().map(|&algorithm| (algorithm, Hasher::new(algorithm))).collect();
+        Self { inner, hashers }
+    }
+
+    /// Returns the hex digest of everything read, per algorithm
+    pub fn finish(self) -> BTreeMap<ChecksumAlgorithm, String> {
+        self.hashers.into_iter().map(|(algorithm, hasher)| (algorithm, hasher.finish())).collect()
+    }
+
+    /// Like `finish`, returning each digest as a `Checksum`
+    pub fn finish_checksums(self) -> BTreeMap<ChecksumAlgorithm, Checksum> {
+        self.hashers
+            .into_iter()
+            .map(|(algorithm, hasher)| (algorithm, Checksum::new(algorithm.name(), hasher.finish_raw())))
+            .collect()
+    }
+}
+
+impl<R: Read> Read for MultiHashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for (_, hasher) in &mut self.hashers {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Hashes the bytes read through it and, at end of stream, checks them
+/// against the expected size and checksum. The final read fails with an
+/// `InvalidData` error wrapping `Error::SizeMismatch` or
+/// `Error::ChecksumMismatch` if they differ.
+pub struct VerifyingReader<R> {
+    inner: HashingReader<R>,
+    expected_size: u64,
+    expected_checksum: Checksum,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    /// Hashes with the expected checksum's algorithm. One this build can't
+    /// compute is hashed as SHA-256, so it fails as a mismatch.
+    pub fn new(inner: R, expected_size: u64, expected_checksum: Checksum) -> Self {
+        let algorithm = ChecksumAlgorithm::from_name(expected_checksum.algorithm()).unwrap_or(ChecksumAlgorithm::Sha256);
+        Self { inner: HashingReader::with_algorithm(inner, algorithm), expected_size, expected_checksum }
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 || buf.is_empty() {
+            return Ok(n);
+        }
+
+        let size = self.inner.size;
+        let actual = Checksum::new(self.inner.algorithm.name(), self.inner.hasher.clone().finish_raw());
+        let err = if size != self.expected_size {
+            crate::error::Error::SizeMismatch { expected: self.expected_size, actual: size }
+        } else if !actual.matches(&self.expected_checksum) {
+            crate::error::Error::ChecksumMismatch { expected: self.expected_checksum.clone(), actual }
+        } else {
+            return Ok(0);
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Checksums of each fixed-size chunk of a blob's content, so corruption can
+/// be pinned to one chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkChecksums {
+    /// Bytes per chunk; the last chunk may be shorter
+    pub chunk_size: u64,
+    /// Hex SHA-256 digest of each chunk, in order
+    pub checksums: Vec<String>,
+}
+
+/// Hashes each `chunk_size` chunk of the bytes read through it, alongside
+/// whatever else consumes them. A no-op without a chunk size.
+pub struct ChunkHashingReader<R> {
+    inner: R,
+    chunk_size: Option<u64>,
+    hasher: Sha256,
+    filled: u64,
+    checksums: Vec<String>,
+}
+
+impl<R: Read> ChunkHashingReader<R> {
+    pub fn new(inner: R, chunk_size: Option<u64>) -> Self {
+        Self {
+            inner,
+            chunk_size: chunk_size.map(|size| size.max(1)),
+            hasher: Sha256::new(),
+            filled: 0,
+            checksums: Vec::new(),
+        }
+    }
+
+    /// Returns the chunk checksums of everything read, if chunking is on
+    pub fn finish(mut self) -> Option<ChunkChecksums> {
+        let chunk_size = self.chunk_size?;
+        if self.filled > 0 {
+            self.checksums.push(DigestEncoding::Hex.encode(&self.hasher.finalize_reset()));
+        }
+        Some(ChunkChecksums { chunk_size, checksums: self.checksums })
+    }
+}
+
+impl<R: Read> Read for ChunkHashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let Some(chunk_size) = self.chunk_size else {
+            return Ok(n);
+        };
+        let mut rest = &buf[..n];
+        while !rest.is_empty() {
+            let take = rest.len().min((chunk_size - self.filled) as usize);
+            self.hasher.update(&rest[..take]);
+            self.filled += take as u64;
+            rest = &rest[take..];
+            if self.filled == chunk_size {
+                self.checksums.push(DigestEncoding::Hex.encode(&self.hasher.finalize_reset()));
+                self.filled = 0;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Checks each chunk of the bytes read through it against `ChunkChecksums`
+/// as it streams, so a blob is verified without a separate pass over it.
+/// Each chunk is read in full and checked before any of its bytes are
+/// returned; a corrupt, missing or extra chunk fails the read with an
+/// `InvalidData` error wrapping `Error::ChunkChecksumMismatch`.
+pub struct ChunkVerifyingReader<R> {
+    inner: R,
+    blob_id: String,
+    expected: ChunkChecksums,
+    /// The current chunk, verified, and how much of it has been returned
+    chunk: Vec<u8>,
+    served: usize,
+    index: usize,
+}
+
+impl<R: Read> ChunkVerifyingReader<R> {
+    pub fn new(inner: R, blob_id: String, expected: ChunkChecksums) -> Self {
+        Self { inner, blob_id, expected, chunk: Vec::new(), served: 0, index: 0 }
+    }
+
+    /// Reads and checks the next chunk, leaving it empty at the end of the data
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.chunk.clear();
+        self.served = 0;
+        let chunk_size = self.expected.chunk_size.max(1);
+        self.inner.by_ref().take(chunk_size).read_to_end(&mut self.chunk)?;
+        if self.chunk.is_empty() {
+            // Chunks recorded but never read mean the data was truncated
+            if self.index < self.expected.checksums.len() {
+                return Err(self.mismatch());
+            }
+            return Ok(());
+        }
+        let actual = compute_sha256(&self.chunk[..])?;
+        if !self.expected.checksums.get(self.index).is_some_and(|expected| constant_time_eq(expected, &actual)) {
+            self.chunk.clear();
+            return Err(self.mismatch());
+        }
+        self.index += 1;
+        Ok(())
+    }
+
+    fn mismatch(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            crate::error::Error::ChunkChecksumMismatch { blob_id: self.blob_id.clone(), chunk: self.index },
+        )
+    }
+}
+
+impl<R: Read> Read for ChunkVerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.served == self.chunk.len() {
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.chunk.len() - self.served);
+        buf[..n].copy_from_slice(&self.chunk[self.served..self.served + n]);
+        self.served += n;
+        Ok(n)
+    }
+}
+
+/// Computes the SHA-256 hash of a file by memory-mapping it and hashing the
+/// mapping in one pass.
+///
+/// If another process truncates the file while it is mapped, touching the
+/// lost pages raises SIGBUS and kills the process; that can't be caught, so
+/// callers should only map files nothing else writes to (blob files are
+/// immutable once stored). A size change noticed around the hash is reported
+/// as an error rather than returning a digest of inconsistent data.
+pub fn compute_sha256_mmap(path: &Path) -> io::Result<String> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        // Empty files can't be mapped
+        return compute_sha256(io::empty());
+    }
+
+    // SAFETY: the mapping is only read, and only for files the store treats
+    // as immutable; see the truncation caveat above
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    let digest = Sha256::digest(&mmap[..]);
+    if file.metadata()?.len() != len {
+        return Err(io::Error::other("file changed size while being hashed"));
+    }
+    Ok(DigestEncoding::Hex.encode(&digest))
+}
+
+/// Computes the SHA-256 hash of a file, using a memory map for files of at
+/// least `MMAP_THRESHOLD` bytes and buffered reads otherwise or if mapping fails
+pub fn compute_sha256_file(path: &Path) -> io::Result<String> {
+    if std::fs::metadata(path)?.len() >= MMAP_THRESHOLD {
+        if let Ok(digest) = compute_sha256_mmap(path) {
+            return Ok(digest);
+        }
+    }
+    compute_sha256(File::open(path)?)
 }
 
-/// Verifies that the given data matches the expected checksum
+/// Computes the SHA-256 checksum of the given data
+pub fn checksum_of<R: Read>(reader: R) -> io::Result<Checksum> {
+    let mut reader = HashingReader::new(reader);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finish().1)
+}
+
+/// Computes the SHA-256 checksum of a file, as `compute_sha256_file` does
+pub fn checksum_file(path: &Path) -> io::Result<Checksum> {
+    let hex = compute_sha256_file(path)?;
+    Checksum::parse(&hex).map_err(io::Error::other)
+}
+
+/// Computes the checksum of the given data with `algorithm`
+pub fn checksum_of_as<R: Read>(reader: R, algorithm: ChecksumAlgorithm) -> io::Result<Checksum> {
+    let mut reader = HashingReader::with_algorithm(reader, algorithm);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finish().1)
+}
+
+/// Computes the checksum of a file with `algorithm`, memory-mapping large
+/// files for SHA-256 as `checksum_file` does
+pub fn checksum_file_as(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<Checksum> {
+    match algorithm {
+        ChecksumAlgorithm::Sha256 => checksum_file(path),
+        algorithm => checksum_of_as(File::open(path)?, algorithm),
+    }
+}
+
+/// Re-renders a digest given in any supported encoding
+pub fn reencode(digest: &str, encoding: DigestEncoding) -> Option<String> {
+    decode_digest(digest).map(|bytes| encoding.encode(&bytes))
+}
+
+/// Algorithm of the checksums this build records for new blobs
+pub const ALGORITHM: &str = "sha256";
+
+/// A digest and the algorithm that produced it. Parses bare digests, as
+/// stored before checksums carried their algorithm, as SHA-256, and always
+/// prints and serializes as `<algo>:<hex>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum {
+    algorithm: String,
+    digest: Vec<u8>,
+}
+
+impl Checksum {
+    /// Wraps a raw digest computed with `algorithm`
+    pub fn new(algorithm: &str, digest: impl Into<Vec<u8>>) -> Self {
+        Self { algorithm: algorithm.to_ascii_lowercase(), digest: digest.into() }
+    }
+
+    /// Parses `<algo>:<digest>`, or a bare digest as SHA-256. The digest is
+    /// hex, or for SHA-256 any `DigestEncoding`.
+    pub fn parse(checksum: &str) -> crate::Result<Self> {
+        let (algorithm, digest) = match checksum.trim().split_once(':') {
+            Some((algorithm, digest)) => (algorithm.to_ascii_lowercase(), digest),
+            None => (ALGORITHM.to_string(), checksum.trim()),
+        };
+        let invalid = |reason: &str| crate::error::Error::InvalidChecksum {
+            input: checksum.to_string(),
+            reason: reason.to_string(),
+        };
+        if algorithm.is_empty() {
+            return Err(invalid("missing algorithm"));
+        }
+        let hex = digest.len() % 2 == 0 && digest.bytes().all(|b| b.is_ascii_hexdigit());
+        let digest = if hex {
+            (0..digest.len()).step_by(2).map(|i| u8::from_str_radix(&digest[i..i + 2], 16).unwrap()).collect()
+        } else if algorithm == ALGORITHM {
+            decode_digest(digest).ok_or_else(|| invalid("not a hex or base64 SHA-256 digest"))?
+        } else {
+            return Err(invalid("not a hex digest"));
+        };
+        Ok(Self { algorithm, digest })
+    }
+
+    /// Name of the algorithm, lowercase
+    pub fn algorithm(&self) -> &str {
+        &self.algorithm
+    }
+
+    /// The raw digest bytes
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Returns true if no digest was recorded
+    pub fn is_empty(&self) -> bool {
+        self.digest.is_empty()
+    }
+
+    /// The digest as lowercase hex, without the algorithm
+    pub fn to_hex(&self) -> String {
+        DigestEncoding::Hex.encode(&self.digest)
+    }
+
+    /// Bare hex for SHA-256 and `<algo>:<hex>` otherwise, the form sidecar
+    /// files and index keys keep so existing ones stay valid
+    pub fn to_compact_string(&self) -> String {
+        if self.algorithm == ALGORITHM {
+            self.to_hex()
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Compares with `other` in constant time
+    pub fn matches(&self, other: &Checksum) -> bool {
+        self.algorithm == other.algorithm && constant_time_eq(&self.digest, &other.digest)
+    }
+
+    /// This checksum's algorithm, failing with `UnsupportedChecksumAlgorithm`
+    /// if this build can't compute it
+    pub fn supported_algorithm(&self) -> crate::Result<ChecksumAlgorithm> {
+        ChecksumAlgorithm::from_name(&self.algorithm)
+            .ok_or_else(|| crate::error::Error::UnsupportedChecksumAlgorithm(self.algorithm.clone()))
+    }
+
+    /// Hashes `reader` with this checksum's algorithm and compares the result.
+    /// Fails with `UnsupportedChecksumAlgorithm` for algorithms this build
+    /// can't compute.
+    pub fn verify_against(&self, mut reader: impl Read) -> crate::Result<bool> {
+        let mut hasher = Hasher::new(self.supported_algorithm()?);
+        let mut buffer = [0; 8192];
+        loop {
+            let count = reader.read(&mut buffer)?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+        }
+        Ok(constant_time_eq(&self.digest, hasher.finish_raw()))
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.to_hex())
+    }
+}
+
+impl std::str::FromStr for Checksum {
+    type Err = crate::error::Error;
+
+    fn from_str(checksum: &str) -> crate::Result<Self> {
+        Self::parse(checksum)
+    }
+}
+
+impl Serialize for Checksum {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Checksum {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let checksum = String::deserialize(deserializer)?;
+        Self::parse(&checksum).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Returns the algorithm of a stored checksum: the `<algo>` of an
+/// `<algo>:<digest>` checksum, or `ALGORITHM` for a bare digest
+pub fn algorithm_of(checksum: &str) -> String {
+    match checksum.split_once(':') {
+        Some((algo, _)) => algo.to_ascii_lowercase(),
+        None => ALGORITHM.to_string(),
+    }
+}
+
+/// Parses a caller-supplied checksum, either bare or in `<algo>:<digest>` form
+/// with the digest in any `DigestEncoding`, failing unless it is of the
+/// algorithm the store records
+pub fn parse_checksum(checksum: &str) -> crate::Result<Checksum> {
+    if let Some((algo, _)) = checksum.split_once(':').filter(|(algo, _)| !algo.eq_ignore_ascii_case(ALGORITHM)) {
+        return Err(crate::error::Error::UnsupportedChecksumAlgorithm(algo.to_string()));
+    }
+    Checksum::parse(checksum)
+}
+
+/// Verifies that the given data matches the expected checksum, which may be
+/// in any `DigestEncoding`
 pub fn verify_checksum<R: Read>(reader: R, expected: &str) -> crate::Result<bool> {
     let actual = compute_sha256(reader)?;
-    Ok(actual == expected)
+    Ok(reencode(expected, DigestEncoding::Hex).is_some_and(|expected| constant_time_eq(&actual, &expected)))
 }
 
 #[cfg(test)]
@@ -28,6 +651,58 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    #[test]
+    fn test_verifying_reader() {
+        let data = b"Hello, World!";
+        let expected = checksum_of(&data[..]).unwrap();
+        let mut read = Vec::new();
+        VerifyingReader::new(&data[..], 13, expected.clone()).read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        let err = VerifyingReader::new(&b"Hello, Wor1d!"[..], 13, expected.clone())
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let inner = err.into_inner().unwrap().downcast::<crate::error::Error>().unwrap();
+        assert!(matches!(*inner, crate::error::Error::ChecksumMismatch { .. }));
+
+        let err = VerifyingReader::new(&data[..5], 13, expected).read_to_end(&mut Vec::new()).unwrap_err();
+        let inner = err.into_inner().unwrap().downcast::<crate::error::Error>().unwrap();
+        assert!(matches!(*inner, crate::error::Error::SizeMismatch { expected: 13, actual: 5 }));
+    }
+
+    #[test]
+    fn test_chunk_checksums() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        let mut hashing = ChunkHashingReader::new(&data[..], Some(1000));
+        io::copy(&mut hashing, &mut io::sink()).unwrap();
+        let chunks = hashing.finish().unwrap();
+        assert_eq!(chunks.checksums.len(), 3);
+        assert_eq!(chunks.checksums[2], compute_sha256(&data[2000..]).unwrap());
+
+        let mut verified = Vec::new();
+        ChunkVerifyingReader::new(&data[..], "blob".into(), chunks.clone()).read_to_end(&mut verified).unwrap();
+        assert_eq!(verified, data);
+
+        // A flipped byte is reported in the chunk holding it, and none of
+        // that chunk's bytes are returned
+        let mut corrupt = data.clone();
+        corrupt[1500] ^= 1;
+        let mut returned = Vec::new();
+        let err = ChunkVerifyingReader::new(&corrupt[..], "blob".into(), chunks.clone())
+            .read_to_end(&mut returned)
+            .unwrap_err();
+        assert_eq!(returned, data[..1000]);
+        let inner = err.into_inner().unwrap().downcast::<crate::error::Error>().unwrap();
+        assert!(matches!(*inner, crate::error::Error::ChunkChecksumMismatch { chunk: 1, .. }));
+
+        // So is a missing tail
+        let err = ChunkVerifyingReader::new(&data[..2000], "blob".into(), chunks)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let inner = err.into_inner().unwrap().downcast::<crate::error::Error>().unwrap();
+        assert!(matches!(*inner, crate::error::Error::ChunkChecksumMismatch { chunk: 2, .. }));
+    }
+
     #[test]
     fn test_compute_sha256() {
         let data = b"Hello, World!";
@@ -46,4 +721,96 @@ mod tests {
         assert!(verify_checksum(Cursor::new(data), expected).unwrap());
         assert!(!verify_checksum(Cursor::new(b"Different data"), expected).unwrap());
     }
+
+    #[test]
+    fn test_constant_time_eq() {
+        let checksum = compute_sha256(Cursor::new(b"Hello, World!")).unwrap();
+        assert!(constant_time_eq(&checksum, checksum.clone()));
+        assert!(constant_time_eq(b"", b""));
+
+        // Differences anywhere, including in length, are caught
+        let mut last_differs = checksum.clone().into_bytes();
+        *last_differs.last_mut().unwrap() ^= 1;
+        assert!(!constant_time_eq(&checksum, &last_differs));
+        assert!(!constant_time_eq(&checksum, &checksum[..63]));
+        assert!(!constant_time_eq("token-a", "token-b"));
+        assert!(!constant_time_eq("", "x"));
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let hex = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+        assert_eq!(parse_checksum(hex).unwrap().to_hex(), hex);
+        assert_eq!(parse_checksum(&format!("sha256:{}", hex)).unwrap().to_hex(), hex);
+        assert_eq!(parse_checksum(&format!("SHA256:{}", hex.to_uppercase())).unwrap().to_hex(), hex);
+        assert!(parse_checksum(&format!("md5:{}", hex)).is_err());
+    }
+
+    #[test]
+    fn test_checksum_type() {
+        let hex = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+
+        // Bare digests written before checksums carried their algorithm are SHA-256
+        let legacy = Checksum::parse(hex).unwrap();
+        assert_eq!(legacy.algorithm(), "sha256");
+        assert_eq!(legacy.digest().len(), 32);
+        assert_eq!(legacy.to_string(), format!("sha256:{}", hex));
+        assert_eq!(legacy.to_compact_string(), hex);
+        assert_eq!(Checksum::parse(&format!("SHA256:{}", hex.to_uppercase())).unwrap(), legacy);
+        assert_eq!(Checksum::parse("sha256:3/1gIbsr1bCvZ2KQgJ7DpTGR3YHH9wpLKGiKNiGCmG8=").unwrap(), legacy);
+        let md5 = Checksum::parse("md5:65a8e27d8879283831b664bd8b7f0ad4").unwrap();
+        assert_eq!((md5.algorithm(), md5.to_hex().as_str()), ("md5", "65a8e27d8879283831b664bd8b7f0ad4"));
+        assert!(!md5.matches(&Checksum::new("sha256", md5.digest())));
+        assert!(matches!(Checksum::parse("sha256:xyz"), Err(crate::error::Error::InvalidChecksum { .. })));
+        assert!(Checksum::parse("md5:abc").is_err());
+
+        // Serializes prefixed and reads back either form
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert_eq!(json, format!("\"sha256:{}\"", hex));
+        assert_eq!(serde_json::from_str::<Checksum>(&json).unwrap(), legacy);
+        assert_eq!(serde_json::from_str::<Checksum>(&format!("\"{}\"", hex)).unwrap(), legacy);
+
+        assert!(legacy.verify_against(&b"Hello, World!"[..]).unwrap());
+        assert!(!legacy.verify_against(&b"Hello, World"[..]).unwrap());
+        assert!(md5.verify_against(&b"Hello, World!"[..]).unwrap());
+        let err = Checksum::new("crc32c", [0; 4]).verify_against(&b""[..]).unwrap_err();
+        assert!(matches!(err, crate::error::Error::UnsupportedChecksumAlgorithm(_)));
+    }
+
+    #[test]
+    fn test_mmap_matches_buffered() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("large.bin");
+        let data: Vec<u8> = (0..6 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let buffered = compute_sha256(Cursor::new(&data)).unwrap();
+        assert_eq!(compute_sha256_mmap(&path).unwrap(), buffered);
+        assert_eq!(compute_sha256_file(&path).unwrap(), buffered);
+
+        let empty = temp_dir.path().join("empty.bin");
+        std::fs::write(&empty, b"").unwrap();
+        assert_eq!(compute_sha256_mmap(&empty).unwrap(), compute_sha256(io::empty()).unwrap());
+    }
+
+    #[test]
+    fn test_digest_encodings() {
+        let data = b"Hello, World!";
+        let hex = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+        let base64 = "3/1gIbsr1bCvZ2KQgJ7DpTGR3YHH9wpLKGiKNiGCmG8=";
+
+        for encoding in [DigestEncoding::Hex, DigestEncoding::HexUpper, DigestEncoding::Base64] {
+            let digest = compute_sha256_encoded(Cursor::new(data), encoding).unwrap();
+            // Any encoding round-trips back to the canonical form and verifies
+            assert_eq!(reencode(&digest, DigestEncoding::Hex).unwrap(), hex);
+            assert_eq!(parse_checksum(&digest).unwrap().to_hex(), hex);
+            assert!(verify_checksum(Cursor::new(data), &digest).unwrap());
+            assert!(!verify_checksum(Cursor::new(b"Different data"), &digest).unwrap());
+        }
+
+        assert_eq!(compute_sha256_encoded(Cursor::new(data), DigestEncoding::Base64).unwrap(), base64);
+        assert_eq!(reencode(hex, DigestEncoding::HexUpper).unwrap(), hex.to_uppercase());
+        assert_eq!(reencode(base64.trim_end_matches('='), DigestEncoding::Hex).unwrap(), hex);
+        assert!(reencode("not a digest", DigestEncoding::Hex).is_none());
+    }
 } 
\ No newline at end of file
@@ -1,107 +1,1113 @@
 use std::path::{Path, PathBuf};
 use std::fs::{self, File};
-use std::io::{self, Read, Write, Seek, SeekFrom, copy};
-use crate::{BlobId, Result, error::Error};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use crate::{BlobId, Result, checksum::{Checksum, ChecksumAlgorithm}, error::{Error, read_only_at}};
 use crate::checksum;
+use crate::compression::Compression;
+
+/// Name prefix of the temp files `tempfile` creates for in-progress writes
+const TEMP_PREFIX: &str = ".tmp";
+
+/// Granularity at which zero runs are skipped instead of written, matching
+/// the usual filesystem block size
+const SPARSE_BLOCK: usize = 4096;
+
+/// Copies `data` into `file`, seeking over all-zero blocks instead of
+/// writing them so filesystems that support it leave holes. Reads of a hole
+/// return zeros, so the content is unchanged. Returns the bytes copied.
+fn copy_sparse(data: &mut impl Read, file: &mut File) -> std::io::Result<u64> {
+    let mut buf = vec![0; 16 * SPARSE_BLOCK];
+    let mut size = 0;
+    loop {
+        // Fill the buffer so zero blocks stay aligned across short reads
+        let mut filled = 0;
+        while filled < buf.len() {
+            match data.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+
+        for block in buf[..filled].chunks(SPARSE_BLOCK) {
+            if block.iter().all(|&b| b == 0) {
+                file.seek(SeekFrom::Current(block.len() as i64))?;
+            } else {
+                file.write_all(block)?;
+            }
+        }
+        size += filled as u64;
+    }
+
+    // A trailing hole only exists once the length is set
+    file.set_len(size)?;
+    Ok(size)
+}
+
+/// Decompresses a blob file, returning the size and checksum of its
+/// content under `algorithm`. Data that fails to decode yields the digest of
+/// what decoded before the failure, which won't match the recorded checksum.
+fn hash_decompressed(
+    path: &Path,
+    compression: Compression,
+    algorithm: ChecksumAlgorithm,
+) -> std::io::Result<(u64, Checksum)> {
+    let mut decoded = checksum::HashingReader::with_algorithm(compression.decoder(File::open(path)?)?, algorithm);
+    let _ = std::io::copy(&mut decoded, &mut std::io::sink());
+    Ok(decoded.finish())
+}
+
+/// Moves a finished temp file into place as a blob. On failure the checksum
+/// file written just before is removed so no checksum outlives its blob; the
+/// temp file itself is deleted when the returned error drops it.
+fn persist_blob(temp_file: tempfile::NamedTempFile, blob_path: &Path, checksum_path: &Path) -> Result<()> {
+    if let Err(e) = temp_file.persist(blob_path) {
+        let _ = fs::remove_file(checksum_path);
+        return Err(Error::Persist(format!(
+            "could not move blob into place at {}: {}",
+            blob_path.display(),
+            e.error
+        )));
+    }
+    Ok(())
+}
+
+/// Sets a path's Unix permission bits. A no-op on other platforms.
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+/// Unix permission bits for the files and directories a store creates,
+/// e.g. `FileMode { file: 0o640, dir: 0o750 }`. Ignored on other platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMode {
+    /// Mode of blob and checksum files
+    pub file: u32,
+    /// Mode of the storage and chunk directories
+    pub dir: u32,
+}
+
+/// Storage tier holding a blob's chunk files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tier {
+    /// The primary root, where new blobs are written
+    #[default]
+    Hot,
+    /// The secondary root that old blobs are migrated to
+    Cold,
+}
+
+/// A cached blob's contents and the info they were verified against
+type CachedBlob = (Arc<[u8]>, BlobInfo);
+
+/// Contents of small, recently read blobs, verified when they were cached
+struct BlobCache {
+    entries: LruCache<(Tier, BlobId), CachedBlob>,
+    /// Total size of the cached contents
+    bytes: u64,
+    max_bytes: u64,
+    /// Larger blobs are always read from disk
+    max_blob_size: u64,
+}
+
+impl BlobCache {
+    fn insert(&mut self, key: (Tier, BlobId), data: Arc<[u8]>, info: BlobInfo) {
+        self.bytes += data.len() as u64;
+        if let Some((old, _)) = self.entries.put(key, (data, info)) {
+            self.bytes -= old.len() as u64;
+        }
+        while self.bytes > self.max_bytes {
+            match self.entries.pop_lru() {
+                Some((_, (evicted, _))) => self.bytes -= evicted.len() as u64,
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &(Tier, BlobId)) {
+        if let Some((data, _)) = self.entries.pop(key) {
+            self.bytes -= data.len() as u64;
+        }
+    }
+}
 
 pub struct ChunkStore {
     root_dir: PathBuf,
+    /// Name of the directory holding blob files under each tier's root
+    chunks_dir: String,
+    /// Root of the cold tier, if one is configured
+    cold_root: Option<PathBuf>,
+    /// Number of full checksum reads performed, for instrumentation
+    checksum_reads: AtomicU64,
+    /// Whether each blob is read back and re-hashed after it is written
+    write_verify: bool,
+    /// Number of read-back verifications performed, for instrumentation
+    write_verifies: AtomicU64,
+    /// Permissions applied to created files and directories, if configured
+    file_mode: Option<FileMode>,
+    /// Whether each blob's checksum is also written to a `.chk` sidecar file
+    sidecars: bool,
+    /// Whether writes are refused, for stores opened read-only
+    read_only: bool,
+    /// Algorithm new blobs' checksums are computed with
+    algorithm: ChecksumAlgorithm,
+    /// Root of a secondary copy of the blob files, read when a blob fails
+    /// verification, if one is configured
+    backup_root: Option<PathBuf>,
+    /// Whether a blob served from the backup root is copied back over the
+    /// corrupt primary
+    heal_from_backup: bool,
+    /// Number of reads served from the backup root, for instrumentation
+    backup_reads: AtomicU64,
+    /// In-memory copies of small hot blobs, if configured
+    blob_cache: Option<Mutex<BlobCache>>,
+    /// Number of reads served from the blob cache, for instrumentation
+    cache_hits: AtomicU64,
+    /// Runs on the persisted blob file before it is read back, so tests can
+    /// simulate a faulty write
+    #[cfg(test)]
+    after_persist: Option<fn(&Path)>,
+}
+
+/// Reader over a blob's contents returned by the read paths
+pub struct BlobReader {
+    inner: Box<dyn Read + Send>,
+}
+
+impl BlobReader {
+    pub fn new(inner: impl Read + Send + 'static) -> Self {
+        Self { inner: Box::new(inner) }
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+/// Reads the bytes of a blob from `position` up to `end`, failing with
+/// `SizeMismatch` if the data ends first rather than returning a short read
+pub(crate) struct RangeReader<R> {
+    inner: R,
+    position: u64,
+    end: u64,
 }
 
-#[derive(Debug)]
+impl<R: Read> RangeReader<R> {
+    /// Wraps `inner`, which must already be positioned at `position`
+    pub(crate) fn new(inner: R, position: u64, end: u64) -> Self {
+        Self { inner, position, end }
+    }
+}
+
+impl<R: Read> Read for RangeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.end - self.position;
+        if remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let limit = buf.len().min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..limit])?;
+        if n == 0 {
+            let err = Error::SizeMismatch { expected: self.end, actual: self.position };
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, err));
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct BlobInfo {
     pub size: u64,
-    pub checksum: String,
+    pub checksum: Checksum,
 }
 
 impl ChunkStore {
     pub fn new(root_dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::with_dir_name(root_dir, "chunks")
+    }
+
+    /// Creates a store keeping blob files in `dir_name` under the root
+    pub fn with_dir_name(root_dir: impl Into<PathBuf>, dir_name: &str) -> Result<Self> {
         let root_dir = root_dir.into();
-        fs::create_dir_all(&root_dir)?;
-        fs::create_dir_all(root_dir.join("chunks"))?;
-        Ok(Self { root_dir })
+        fs::create_dir_all(root_dir.join(dir_name)).map_err(read_only_at(&root_dir))?;
+        Ok(Self::at(root_dir, dir_name))
+    }
+
+    /// Opens the blob files under an existing root, such as a filesystem
+    /// snapshot, without creating anything. Every write fails with `ReadOnly`.
+    pub fn open_read_only(root_dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_read_only_with_dir_name(root_dir, "chunks")
+    }
+
+    /// Like `open_read_only`, for blob files kept in `dir_name` under the root
+    pub fn open_read_only_with_dir_name(root_dir: impl Into<PathBuf>, dir_name: &str) -> Result<Self> {
+        let root_dir = root_dir.into();
+        if !root_dir.join(dir_name).is_dir() {
+            return Err(Error::System(format!("No blob files found at {}", root_dir.join(dir_name).display())));
+        }
+        Ok(Self { read_only: true, ..Self::at(root_dir, dir_name) })
+    }
+
+    fn at(root_dir: PathBuf, dir_name: &str) -> Self {
+        Self {
+            root_dir,
+            chunks_dir: dir_name.to_string(),
+            cold_root: None,
+            checksum_reads: AtomicU64::new(0),
+            write_verify: false,
+            write_verifies: AtomicU64::new(0),
+            file_mode: None,
+            sidecars: true,
+            read_only: false,
+            algorithm: ChecksumAlgorithm::Sha256,
+            backup_root: None,
+            heal_from_backup: false,
+            backup_reads: AtomicU64::new(0),
+            blob_cache: None,
+            cache_hits: AtomicU64::new(0),
+            #[cfg(test)]
+            after_persist: None,
+        }
+    }
+
+    /// Fails if the store was opened read-only
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly(self.root_dir.clone()));
+        }
+        Ok(())
+    }
+
+    /// Adds a cold tier rooted at `cold_root`, typically on slower, cheaper storage
+    pub fn with_cold_root(mut self, cold_root: impl Into<PathBuf>) -> Result<Self> {
+        let cold_root = cold_root.into();
+        fs::create_dir_all(cold_root.join(&self.chunks_dir))?;
+        self.cold_root = Some(cold_root);
+        self.apply_dir_mode(Tier::Cold)?;
+        Ok(self)
+    }
+
+    /// Falls back to the copy of a blob under `backup_root`, laid out like
+    /// this store's hot root, when the primary copy of a hot blob fails its
+    /// checksum. The backup is verified against the same checksum before it
+    /// is served; if it fails too, the read fails with the primary's
+    /// `ChecksumMismatch`. Cold blobs have no backup, and `verify_primary`
+    /// never falls back. Nothing is ever written to the backup root.
+    pub fn with_backup_root(mut self, backup_root: impl Into<PathBuf>) -> Self {
+        self.backup_root = Some(backup_root.into());
+        self
+    }
+
+    /// Replaces a corrupt primary copy with the backup that was served in
+    /// its place, so later reads don't need the backup. Off by default.
+    pub fn with_backup_healing(mut self, enabled: bool) -> Self {
+        self.heal_from_backup = enabled;
+        self
+    }
+
+    /// Returns the path of a blob whose primary copy in `tier` failed its
+    /// checksum: its backup if one passes `verify`, or after healing, the
+    /// repaired primary. `None` if there is no backup or it is bad too.
+    fn verified_backup(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        verify: impl Fn(&Path) -> Result<()>,
+    ) -> Option<PathBuf> {
+        // The backup root mirrors the hot root only
+        if tier != Tier::Hot {
+            return None;
+        }
+        let backup_path = self.backup_root.as_ref()?.join(&self.chunks_dir).join(format!("{}.blob", blob_id));
+        if let Err(e) = verify(&backup_path) {
+            tracing::warn!(%blob_id, error = %e, "backup copy of corrupt blob is unusable");
+            return None;
+        }
+        tracing::warn!(%blob_id, "blob failed verification, serving its backup copy");
+        self.backup_reads.fetch_add(1, Ordering::Relaxed);
+        if !self.heal_from_backup || self.read_only {
+            return Some(backup_path);
+        }
+        let blob_path = self.blob_path_in(tier, blob_id);
+        let healed = self.temp_file_in(tier).and_then(|mut temp_file| {
+            copy_sparse(&mut File::open(&backup_path)?, temp_file.as_file_mut())?;
+            temp_file.flush()?;
+            temp_file.persist(&blob_path).map_err(|e| Error::Persist(e.error.to_string()))?;
+            Ok(())
+        });
+        self.invalidate(tier, blob_id);
+        match healed {
+            Ok(()) => Some(blob_path),
+            Err(e) => {
+                tracing::warn!(%blob_id, error = %e, "failed to repair blob from its backup copy");
+                Some(backup_path)
+            }
+        }
+    }
+
+    /// Serves a blob from its backup after its primary copy failed with
+    /// `mismatch`, which is returned if the backup can't stand in
+    fn read_backup(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        expected_size: Option<u64>,
+        expected_checksum: &Checksum,
+        mismatch: Error,
+    ) -> Result<(BlobReader, BlobInfo)> {
+        let verify = |path: &Path| {
+            let size = fs::metadata(path)?.len();
+            if let Some(expected) = expected_size.filter(|&expected| expected != size) {
+                return Err(Error::SizeMismatch { expected, actual: size });
+            }
+            self.checksum_reads.fetch_add(1, Ordering::Relaxed);
+            let actual = checksum::checksum_file_as(path, expected_checksum.supported_algorithm()?)?;
+            if !actual.matches(expected_checksum) {
+                return Err(Error::ChecksumMismatch { expected: expected_checksum.clone(), actual });
+            }
+            Ok(())
+        };
+        let Some(path) = self.verified_backup(blob_id, tier, verify) else {
+            return Err(mismatch);
+        };
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok((BlobReader::new(file), BlobInfo { size, checksum: expected_checksum.clone() }))
+    }
+
+    /// Returns how many reads were served from the backup root
+    pub fn backup_reads(&self) -> u64 {
+        self.backup_reads.load(Ordering::Relaxed)
+    }
+
+    /// Checks a blob's primary copy in `tier` against its size and
+    /// checksum, as stored or compressed with `compression`, without
+    /// falling back to the backup root or the blob cache, so scrubbing sees
+    /// the primary's damage
+    pub fn verify_primary(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        size: u64,
+        compressed: Option<(u64, Compression)>,
+        expected_checksum: Option<&Checksum>,
+    ) -> Result<()> {
+        let blob_path = self.blob_path_in(tier, blob_id);
+        let stored_size = compressed.map_or(size, |(compressed_size, _)| compressed_size);
+        let actual = self.blob_size_in(blob_id, tier)?;
+        if actual != stored_size {
+            return Err(Error::SizeMismatch { expected: stored_size, actual });
+        }
+        let expected_checksum = self.expected_checksum(&self.checksum_path_in(tier, blob_id), expected_checksum)?;
+        if let Some((_, compression)) = compressed {
+            return self.check_decompressed(&blob_path, size, compression, &expected_checksum);
+        }
+        self.checksum_reads.fetch_add(1, Ordering::Relaxed);
+        let actual = checksum::checksum_file_as(&blob_path, expected_checksum.supported_algorithm()?)?;
+        if !actual.matches(&expected_checksum) {
+            return Err(Error::ChecksumMismatch { expected: expected_checksum, actual });
+        }
+        Ok(())
+    }
+
+    /// Reads every blob back and re-hashes it right after writing, failing
+    /// the put with `WriteVerifyFailed` if the stored bytes differ. Catches
+    /// filesystem and hardware faults at write time, at the cost of an
+    /// extra read per put.
+    pub fn with_write_verify(mut self, enabled: bool) -> Self {
+        self.write_verify = enabled;
+        self
+    }
+
+    /// Controls whether checksum sidecar files are written. Without them,
+    /// reads must be given the expected checksum, as the coordinator does
+    /// from each blob's metadata; this saves a file, a write and a read per
+    /// blob. Sidecars are on by default so a standalone store is self-checking.
+    pub fn with_sidecars(mut self, enabled: bool) -> Self {
+        self.sidecars = enabled;
+        self
+    }
+
+    /// Returns true if checksum sidecar files are written
+    pub fn has_sidecars(&self) -> bool {
+        self.sidecars
+    }
+
+    /// Writes a blob's checksum sidecar, if sidecars are enabled
+    fn write_checksum(&self, checksum_path: &Path, checksum: &Checksum) -> Result<()> {
+        if self.sidecars {
+            fs::write(checksum_path, checksum.to_compact_string())?;
+            self.apply_file_mode(checksum_path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the checksum recorded in a blob's sidecar, or `None` if it has
+    /// none
+    pub(crate) fn read_sidecar(&self, tier: Tier, blob_id: &BlobId) -> Result<Option<Checksum>> {
+        match fs::read_to_string(self.checksum_path_in(tier, blob_id)) {
+            Ok(contents) => Checksum::parse(&contents).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Atomically replaces a blob's sidecar with `checksum`. Writes one if
+    /// sidecars are enabled or the blob already has one, so an existing
+    /// sidecar never goes stale.
+    pub(crate) fn replace_sidecar(&self, tier: Tier, blob_id: &BlobId, checksum: &Checksum) -> Result<()> {
+        self.check_writable()?;
+        let checksum_path = self.checksum_path_in(tier, blob_id);
+        if !self.sidecars && !checksum_path.exists() {
+            return Ok(());
+        }
+        let dir = checksum_path.parent().expect("sidecars live in a tier directory");
+        let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+        temp_file.write_all(checksum.to_compact_string().as_bytes())?;
+        temp_file.persist(&checksum_path)?;
+        self.invalidate(tier, blob_id);
+        self.apply_file_mode(&checksum_path)
+    }
+
+    /// Returns the checksum a read should verify against: the one given by
+    /// the caller, or else the blob's sidecar
+    fn expected_checksum(&self, checksum_path: &Path, expected_checksum: Option<&Checksum>) -> Result<Checksum> {
+        match expected_checksum {
+            Some(checksum) => Ok(checksum.clone()),
+            None => Checksum::parse(&fs::read_to_string(checksum_path)?),
+        }
+    }
+
+    /// Applies `mode` to blob and checksum files written from now on and to
+    /// the storage directories of every tier
+    pub fn with_file_mode(mut self, mode: FileMode) -> Result<Self> {
+        self.file_mode = Some(mode);
+        self.apply_dir_mode(Tier::Hot)?;
+        if self.cold_root.is_some() {
+            self.apply_dir_mode(Tier::Cold)?;
+        }
+        Ok(self)
+    }
+
+    /// Sets the configured directory mode on a tier's root and chunks directory
+    fn apply_dir_mode(&self, tier: Tier) -> Result<()> {
+        if let Some(mode) = self.file_mode {
+            let root = self.tier_root(tier);
+            set_mode(root, mode.dir)?;
+            set_mode(&root.join(&self.chunks_dir), mode.dir)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the configured file mode on a newly created file
+    fn apply_file_mode(&self, path: &Path) -> Result<()> {
+        if let Some(mode) = self.file_mode {
+            set_mode(path, mode.file)?;
+        }
+        Ok(())
+    }
+
+    /// Returns how many blobs have been read back to verify a write
+    pub fn write_verifies(&self) -> u64 {
+        self.write_verifies.load(Ordering::Relaxed)
+    }
+
+    /// Computes new blobs' checksums with `algorithm` instead of SHA-256.
+    /// Existing blobs are verified with whatever algorithm they recorded.
+    pub fn with_checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Returns the root directory of a tier. Without a cold root configured,
+    /// both tiers resolve to the hot root.
+    fn tier_root(&self, tier: Tier) -> &Path {
+        match (tier, &self.cold_root) {
+            (Tier::Cold, Some(cold_root)) => cold_root,
+            _ => &self.root_dir,
+        }
+    }
+
+    /// Returns how many times a blob has been read in full to verify its checksum
+    pub fn checksum_reads(&self) -> u64 {
+        self.checksum_reads.load(Ordering::Relaxed)
+    }
+
+    /// Keeps the contents of uncompressed blobs of at most `max_blob_size`
+    /// bytes in memory once read, up to `max_bytes` in total, evicting the
+    /// least recently read first. Cache hits skip the filesystem and the
+    /// checksum read, as the contents were verified when they were cached.
+    pub fn with_blob_cache(mut self, max_bytes: u64, max_blob_size: u64) -> Self {
+        self.blob_cache = Some(Mutex::new(BlobCache {
+            entries: LruCache::unbounded(),
+            bytes: 0,
+            max_bytes,
+            max_blob_size: max_blob_size.min(max_bytes),
+        }));
+        self
+    }
+
+    /// Returns how many reads were served from the blob cache
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+
+    /// Drops a blob's cached contents after its files in `tier` change
+    fn invalidate(&self, tier: Tier, blob_id: &BlobId) {
+        if let Some(cache) = &self.blob_cache {
+            cache.lock().unwrap().remove(&(tier, blob_id.clone()));
+        }
+    }
+
+    /// Returns a blob's cached contents if they satisfy the read
+    pub(crate) fn cached(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        expected_size: Option<u64>,
+        expected_checksum: Option<&Checksum>,
+    ) -> Option<(BlobReader, BlobInfo)> {
+        let mut cache = self.blob_cache.as_ref()?.lock().unwrap();
+        let (data, info) = cache.entries.get(&(tier, blob_id.clone()))?;
+        // Anything unexpected is left to the disk read to report
+        if expected_size.is_some_and(|size| size != info.size)
+            || expected_checksum.is_some_and(|checksum| !info.checksum.matches(checksum))
+        {
+            return None;
+        }
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        Some((BlobReader::new(std::io::Cursor::new(data.clone())), info.clone()))
     }
 
     /// Returns the path to a blob file
     fn blob_path(&self, blob_id: &BlobId) -> PathBuf {
-        self.root_dir
-            .join("chunks")
-            .join(format!("{}.blob", blob_id.to_string()))
+        self.blob_path_in(Tier::Hot, blob_id)
     }
 
     /// Returns the path to a blob's checksum file
     fn checksum_path(&self, blob_id: &BlobId) -> PathBuf {
-        self.root_dir
-            .join("chunks")
-            .join(format!("{}.blob.chk", blob_id.to_string()))
+        self.checksum_path_in(Tier::Hot, blob_id)
+    }
+
+    /// Returns the path to a blob file in the given tier
+    pub(crate) fn blob_path_in(&self, tier: Tier, blob_id: &BlobId) -> PathBuf {
+        self.tier_root(tier)
+            .join(&self.chunks_dir)
+            .join(format!("{}.blob", blob_id))
+    }
+
+    /// Returns the path to a blob's checksum file in the given tier
+    pub(crate) fn checksum_path_in(&self, tier: Tier, blob_id: &BlobId) -> PathBuf {
+        self.tier_root(tier)
+            .join(&self.chunks_dir)
+            .join(format!("{}.blob.chk", blob_id))
     }
 
     /// Stores a blob and its checksum, returns the blob info
-    pub fn put_blob(&self, blob_id: &BlobId, mut data: impl Read) -> Result<BlobInfo> {
+    pub fn put_blob(&self, blob_id: &BlobId, data: impl Read) -> Result<BlobInfo> {
+        self.put_blob_preallocated(blob_id, data, None)
+    }
+
+    /// Like `put_blob`, first reserving `size_hint` bytes of disk for the
+    /// file so it is laid out contiguously and a full disk fails before any
+    /// data is streamed. The hint only sizes the allocation; the file ends
+    /// up as long as the data.
+    pub fn put_blob_preallocated(&self, blob_id: &BlobId, data: impl Read, size_hint: Option<u64>) -> Result<BlobInfo> {
+        self.check_writable()?;
         let blob_path = self.blob_path(blob_id);
         let checksum_path = self.checksum_path(blob_id);
 
-        // Create a temporary file for the blob
-        let mut temp_file = tempfile::NamedTempFile::new()?;
-        
-        // Copy data to temp file while computing size
-        let size = copy(&mut data, &mut temp_file)?;
-        temp_file.flush()?;
-        temp_file.seek(SeekFrom::Start(0))?;
+        // Create a temporary file for the blob next to its final path
+        let mut temp_file = self.temp_file_in(Tier::Hot)?;
+        if let Some(size_hint) = size_hint.filter(|&size_hint| size_hint > 0) {
+            fs2::FileExt::allocate(temp_file.as_file(), size_hint)?;
+        }
 
-        // Compute checksum
-        let checksum = checksum::compute_sha256(&mut temp_file)?;
+        // Copy data to temp file, hashing it on the way through and leaving
+        // zero runs as holes
+        let mut data = checksum::HashingReader::with_algorithm(data, self.algorithm);
+        copy_sparse(&mut data, temp_file.as_file_mut())?;
+        temp_file.flush()?;
+        let (size, checksum) = data.finish();
 
         // Write checksum file
-        fs::write(&checksum_path, &checksum)?;
+        self.write_checksum(&checksum_path, &checksum)?;
 
         // Persist the blob file
-        temp_file.persist(&blob_path)?;
+        persist_blob(temp_file, &blob_path, &checksum_path)?;
+        self.invalidate(Tier::Hot, blob_id);
+
+        if self.write_verify {
+            #[cfg(test)]
+            if let Some(after_persist) = self.after_persist {
+                after_persist(&blob_path);
+            }
+
+            self.write_verifies.fetch_add(1, Ordering::Relaxed);
+            let actual = checksum::checksum_file_as(&blob_path, self.algorithm)?;
+            if !actual.matches(&checksum) {
+                fs::remove_file(&blob_path)?;
+                if self.sidecars {
+                    fs::remove_file(&checksum_path)?;
+                }
+                return Err(Error::WriteVerifyFailed { expected: checksum, actual });
+            }
+        }
 
         Ok(BlobInfo { size, checksum })
     }
 
-    /// Retrieves a blob and verifies its checksum
-    pub fn get_blob(&self, blob_id: &BlobId) -> Result<(impl Read, BlobInfo)> {
+    /// Stores a blob compressed with `compression`. The checksum file records
+    /// the checksum of the uncompressed content, and the returned info its
+    /// uncompressed size; the compressed size is returned alongside.
+    pub fn put_blob_compressed(
+        &self,
+        blob_id: &BlobId,
+        data: impl Read,
+        compression: Compression,
+    ) -> Result<(BlobInfo, u64)> {
+        self.check_writable()?;
         let blob_path = self.blob_path(blob_id);
         let checksum_path = self.checksum_path(blob_id);
 
+        // Hash the uncompressed bytes as they are fed to the encoder
+        let mut temp_file = self.temp_file_in(Tier::Hot)?;
+        let mut data = checksum::HashingReader::with_algorithm(data, self.algorithm);
+        compression.compress(&mut data, &mut temp_file)?;
+        temp_file.flush()?;
+        let compressed_size = temp_file.as_file().metadata()?.len();
+        let (size, checksum) = data.finish();
+
+        self.write_checksum(&checksum_path, &checksum)?;
+        persist_blob(temp_file, &blob_path, &checksum_path)?;
+        self.invalidate(Tier::Hot, blob_id);
+
+        if self.write_verify {
+            self.write_verifies.fetch_add(1, Ordering::Relaxed);
+            let (_, actual) = hash_decompressed(&blob_path, compression, self.algorithm)?;
+            if !actual.matches(&checksum) {
+                fs::remove_file(&blob_path)?;
+                if self.sidecars {
+                    fs::remove_file(&checksum_path)?;
+                }
+                return Err(Error::WriteVerifyFailed { expected: checksum, actual });
+            }
+        }
+
+        Ok((BlobInfo { size, checksum }, compressed_size))
+    }
+
+    /// Retrieves a blob stored with `compression` from the given tier. The
+    /// stored length is checked against `compressed_size` first, then the
+    /// decompressed content against `size` and `expected_checksum`, or the
+    /// checksum sidecar if none is given.
+    pub fn get_blob_compressed(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        size: u64,
+        compressed_size: u64,
+        compression: Compression,
+        expected_checksum: Option<&Checksum>,
+    ) -> Result<BlobReader> {
+        let blob_path = self.verify_compressed(blob_id, tier, size, compressed_size, compression, expected_checksum)?;
+        Ok(BlobReader { inner: compression.decoder(File::open(&blob_path)?)? })
+    }
+
+    /// Like `get_blob_compressed`, after the same checks returning the
+    /// stored bytes still compressed, for clients that decode them
+    /// themselves
+    pub fn get_blob_encoded(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        size: u64,
+        compressed_size: u64,
+        compression: Compression,
+        expected_checksum: Option<&Checksum>,
+    ) -> Result<BlobReader> {
+        let blob_path = self.verify_compressed(blob_id, tier, size, compressed_size, compression, expected_checksum)?;
+        Ok(BlobReader::new(File::open(&blob_path)?))
+    }
+
+    /// Checks a compressed blob's stored length and decompressed content,
+    /// returning its file's path
+    fn verify_compressed(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        size: u64,
+        compressed_size: u64,
+        compression: Compression,
+        expected_checksum: Option<&Checksum>,
+    ) -> Result<PathBuf> {
+        let blob_path = self.blob_path_in(tier, blob_id);
+        let actual = self.blob_size_in(blob_id, tier)?;
+        if actual != compressed_size {
+            return Err(Error::SizeMismatch { expected: compressed_size, actual });
+        }
+        let expected_checksum = self.expected_checksum(&self.checksum_path_in(tier, blob_id), expected_checksum)?;
+
+        match self.check_decompressed(&blob_path, size, compression, &expected_checksum) {
+            Err(mismatch @ Error::ChecksumMismatch { .. }) => {
+                let verify = |path: &Path| {
+                    let actual = fs::metadata(path)?.len();
+                    if actual != compressed_size {
+                        return Err(Error::SizeMismatch { expected: compressed_size, actual });
+                    }
+                    self.check_decompressed(path, size, compression, &expected_checksum)
+                };
+                self.verified_backup(blob_id, tier, verify).ok_or(mismatch)
+            }
+            result => result.map(|()| blob_path),
+        }
+    }
+
+    /// Checks that a compressed blob file decodes to `size` bytes matching
+    /// `expected_checksum`
+    fn check_decompressed(
+        &self,
+        path: &Path,
+        size: u64,
+        compression: Compression,
+        expected_checksum: &Checksum,
+    ) -> Result<()> {
+        self.checksum_reads.fetch_add(1, Ordering::Relaxed);
+        let (decoded_size, actual) = hash_decompressed(path, compression, expected_checksum.supported_algorithm()?)?;
+        if !actual.matches(expected_checksum) {
+            return Err(Error::ChecksumMismatch { expected: expected_checksum.clone(), actual });
+        }
+        if decoded_size != size {
+            return Err(Error::SizeMismatch { expected: size, actual: decoded_size });
+        }
+        Ok(())
+    }
+
+    /// Retrieves a blob and verifies its checksum
+    pub fn get_blob(&self, blob_id: &BlobId) -> Result<(BlobReader, BlobInfo)> {
+        self.open_verified(blob_id, Tier::Hot, None, None)
+    }
+
+    /// Retrieves a blob from the given tier whose size is known, failing with
+    /// `SizeMismatch` before the checksum read if the file length differs.
+    /// The content is verified against `expected_checksum`, or the checksum
+    /// sidecar if none is given.
+    pub fn get_blob_sized(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        expected_size: u64,
+        expected_checksum: Option<&Checksum>,
+    ) -> Result<(BlobReader, BlobInfo)> {
+        self.open_verified(blob_id, tier, Some(expected_size), expected_checksum)
+    }
+
+    fn open_verified(
+        &self,
+        blob_id: &BlobId,
+        tier: Tier,
+        expected_size: Option<u64>,
+        expected_checksum: Option<&Checksum>,
+    ) -> Result<(BlobReader, BlobInfo)> {
+        if let Some(cached) = self.cached(blob_id, tier, expected_size, expected_checksum) {
+            return Ok(cached);
+        }
+        let blob_path = self.blob_path_in(tier, blob_id);
+        let checksum_path = self.checksum_path_in(tier, blob_id);
+
         if !blob_path.exists() {
             return Err(Error::BlobNotFound(blob_id.to_string()));
         }
 
-        let expected_checksum = fs::read_to_string(&checksum_path)?;
-        let file = File::open(&blob_path)?;
+        let mut file = File::open(&blob_path)?;
         let size = file.metadata()?.len();
-        
-        // Create a copy for checksum verification
-        let mut verify_file = File::open(&blob_path)?;
-        
-        // Verify checksum
-        if !checksum::verify_checksum(&mut verify_file, &expected_checksum)? {
-            return Err(Error::ChecksumMismatch {
-                expected: expected_checksum,
-                actual: checksum::compute_sha256(&mut verify_file)?,
-            });
+
+        // A length mismatch is caught from the file's metadata alone
+        if let Some(expected) = expected_size {
+            if size != expected {
+                return Err(Error::SizeMismatch { expected, actual: size });
+            }
+        }
+        let expected_checksum = self.expected_checksum(&checksum_path, expected_checksum)?;
+
+        // Small blobs are read once, verified in memory and cached
+        let cache_max = self.blob_cache.as_ref().map(|cache| cache.lock().unwrap().max_blob_size);
+        if cache_max.is_some_and(|max| size <= max) {
+            let mut data = Vec::with_capacity(size as usize);
+            file.read_to_end(&mut data)?;
+            self.checksum_reads.fetch_add(1, Ordering::Relaxed);
+            let actual = checksum::checksum_of_as(&data[..], expected_checksum.supported_algorithm()?)?;
+            if !actual.matches(&expected_checksum) {
+                let mismatch = Error::ChecksumMismatch { expected: expected_checksum.clone(), actual };
+                return self.read_backup(blob_id, tier, expected_size, &expected_checksum, mismatch);
+            }
+            let data: Arc<[u8]> = data.into();
+            let info = BlobInfo { size: data.len() as u64, checksum: expected_checksum };
+            if let Some(cache) = &self.blob_cache {
+                cache.lock().unwrap().insert((tier, blob_id.clone()), data.clone(), info.clone());
+            }
+            return Ok((BlobReader::new(std::io::Cursor::new(data)), info));
+        }
+
+        // Verify checksum using a separate handle
+        self.checksum_reads.fetch_add(1, Ordering::Relaxed);
+        let actual = checksum::checksum_file_as(&blob_path, expected_checksum.supported_algorithm()?)?;
+        if !actual.matches(&expected_checksum) {
+            let mismatch = Error::ChecksumMismatch { expected: expected_checksum.clone(), actual };
+            return self.read_backup(blob_id, tier, expected_size, &expected_checksum, mismatch);
         }
 
-        Ok((file, BlobInfo { size, checksum: expected_checksum }))
+        Ok((BlobReader::new(file), BlobInfo { size, checksum: expected_checksum }))
     }
 
-    /// Deletes a blob and its checksum file
-    pub fn delete_blob(&self, blob_id: &BlobId) -> Result<()> {
-        let blob_path = self.blob_path(blob_id);
-        let checksum_path = self.checksum_path(blob_id);
+    /// Lists the ids of all blobs with a data file in the given tier
+    pub fn list_blob_ids(&self, tier: Tier) -> Result<Vec<BlobId>> {
+        let mut blob_ids = Vec::new();
+        for entry in fs::read_dir(self.tier_root(tier).join(&self.chunks_dir))? {
+            let name = entry?.file_name();
+            let id = name.to_str().and_then(|name| name.strip_suffix(".blob")?.parse().ok());
+            blob_ids.extend(id);
+        }
+        Ok(blob_ids)
+    }
+
+    /// Deletes temp files left in the chunks directories by writes that
+    /// never finished, skipping any modified within `grace` as they may
+    /// belong to a write still in progress. Returns the removed paths.
+    pub fn remove_stale_temp_files(&self, grace: Duration) -> Result<Vec<PathBuf>> {
+        self.check_writable()?;
+        let mut tiers = vec![Tier::Hot];
+        if self.has_cold_tier() {
+            tiers.push(Tier::Cold);
+        }
+        let mut removed = Vec::new();
+        for tier in tiers {
+            for entry in fs::read_dir(self.tier_root(tier).join(&self.chunks_dir))? {
+                let entry = entry?;
+                if !entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+                    continue;
+                }
+                let modified = entry.metadata()?.modified()?;
+                if modified.elapsed().map_or(true, |age| age < grace) {
+                    continue;
+                }
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => removed.push(entry.path()),
+                    // Finished or cleaned up by someone else meanwhile
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Returns true if a separate cold tier is configured
+    pub fn has_cold_tier(&self) -> bool {
+        self.cold_root.is_some()
+    }
+
+    /// Returns a blob's stored length from the file's metadata, without
+    /// reading its contents or verifying its checksum
+    pub fn blob_size(&self, blob_id: &BlobId) -> Result<u64> {
+        self.blob_size_in(blob_id, Tier::Hot)
+    }
+
+    /// Stored length of a blob in the given tier, see `blob_size`
+    pub(crate) fn blob_size_in(&self, blob_id: &BlobId, tier: Tier) -> Result<u64> {
+        match fs::metadata(self.blob_path_in(tier, blob_id)) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::BlobNotFound(blob_id.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns how long ago a blob's file in `tier` was last written
+    pub(crate) fn blob_age_in(&self, blob_id: &BlobId, tier: Tier) -> Result<Duration> {
+        let modified = match fs::metadata(self.blob_path_in(tier, blob_id)) {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::BlobNotFound(blob_id.to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // A timestamp in the future reads as brand new
+        Ok(modified.elapsed().unwrap_or_default())
+    }
+
+    /// Opens a blob's bytes from the hot tier WITHOUT checking its size or
+    /// checksum. Only for salvaging data from a blob known to be corrupt;
+    /// normal reads must go through `get_blob`.
+    pub fn get_blob_raw(&self, blob_id: &BlobId) -> Result<impl Read + Send> {
+        self.open_raw(blob_id, Tier::Hot)
+    }
+
+    /// Unverified open from any tier, see `get_blob_raw`
+    pub(crate) fn open_raw(&self, blob_id: &BlobId, tier: Tier) -> Result<File> {
+        match File::open(self.blob_path_in(tier, blob_id)) {
+            Ok(file) => Ok(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(Error::BlobNotFound(blob_id.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Creates a temp file in a tier's chunks directory, so persisting it is
+    /// a rename within one filesystem, with the configured file mode applied
+    fn temp_file_in(&self, tier: Tier) -> Result<tempfile::NamedTempFile> {
+        let temp_file = tempfile::NamedTempFile::new_in(self.tier_root(tier).join(&self.chunks_dir))?;
+        self.apply_file_mode(temp_file.path())?;
+        Ok(temp_file)
+    }
+
+    /// Copies a blob and its checksum file into another tier. The source
+    /// copy is kept until `remove_from_tier` is called.
+    pub fn copy_to_tier(&self, blob_id: &BlobId, from: Tier, to: Tier) -> Result<()> {
+        self.check_writable()?;
+        // Without a cold root both tiers are the same directory, and the
+        // caller removing the source copy would delete the only one
+        if self.tier_root(from) == self.tier_root(to) {
+            return Err(Error::System(format!("tiers {:?} and {:?} share a root", from, to)));
+        }
+        let source = self.blob_path_in(from, blob_id);
+        if !source.exists() {
+            return Err(Error::BlobNotFound(blob_id.to_string()));
+        }
+
+        // Write through a temp file in the target directory so a crash never
+        // leaves a partial blob there
+        let mut temp_file = self.temp_file_in(to)?;
+        copy_sparse(&mut File::open(&source)?, temp_file.as_file_mut())?;
+        temp_file.flush()?;
+        let checksum_path = self.checksum_path_in(to, blob_id);
+        if self.sidecars {
+            fs::copy(self.checksum_path_in(from, blob_id), &checksum_path)?;
+            self.apply_file_mode(&checksum_path)?;
+        }
+        persist_blob(temp_file, &self.blob_path_in(to, blob_id), &checksum_path)?;
+        self.invalidate(to, blob_id);
+        Ok(())
+    }
+
+    /// Gives blob `to` the same content as blob `from` within one tier. With
+    /// `link`, the new blob file is a hard link to the source's, so the two
+    /// share their data on disk until both are deleted; if linking fails,
+    /// e.g. on a filesystem without hard links, the bytes are copied
+    /// instead. Returns whether the file was linked.
+    pub fn copy_blob(&self, from: &BlobId, to: &BlobId, tier: Tier, link: bool) -> Result<bool> {
+        self.check_writable()?;
+        let source = self.blob_path_in(tier, from);
+        if !source.exists() {
+            return Err(Error::BlobNotFound(from.to_string()));
+        }
+        let blob_path = self.blob_path_in(tier, to);
+        let checksum_path = self.checksum_path_in(tier, to);
+        self.invalidate(tier, to);
+        if self.sidecars {
+            fs::copy(self.checksum_path_in(tier, from), &checksum_path)?;
+            self.apply_file_mode(&checksum_path)?;
+        }
+        if link {
+            match fs::hard_link(&source, &blob_path) {
+                Ok(()) => return Ok(true),
+                Err(e) => tracing::debug!(blob_id = %from, error = %e, "could not hard-link blob, copying it"),
+            }
+        }
+
+        let mut temp_file = self.temp_file_in(tier)?;
+        copy_sparse(&mut File::open(&source)?, temp_file.as_file_mut())?;
+        temp_file.flush()?;
+        persist_blob(temp_file, &blob_path, &checksum_path)?;
+        Ok(false)
+    }
+
+    /// Overwrites the bytes of a blob's file in `tier` starting at `offset`
+    /// with `data`. The file is rewritten through a temp file, so a crash
+    /// leaves the old content and blobs hard-linked to it keep theirs.
+    pub fn patch_blob(&self, blob_id: &BlobId, tier: Tier, offset: u64, data: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        let blob_path = self.blob_path_in(tier, blob_id);
+        if !blob_path.exists() {
+            return Err(Error::BlobNotFound(blob_id.to_string()));
+        }
+        let mut temp_file = self.temp_file_in(tier)?;
+        copy_sparse(&mut File::open(&blob_path)?, temp_file.as_file_mut())?;
+        temp_file.seek(SeekFrom::Start(offset))?;
+        temp_file.write_all(data)?;
+        temp_file.as_file().sync_all()?;
+        self.invalidate(tier, blob_id);
+        temp_file.persist(&blob_path).map_err(|e| {
+            Error::Persist(format!("could not move blob into place at {}: {}", blob_path.display(), e.error))
+        })?;
+        Ok(())
+    }
 
-        if blob_path.exists() {
-            fs::remove_file(&blob_path)?;
+    /// Moves the hot files of blob `staged` into place as blob `to`,
+    /// replacing any files `to` has there. Returns false if `staged` has no
+    /// blob file, e.g. because it was already moved.
+    pub fn move_blob(&self, staged: &BlobId, to: &BlobId) -> Result<bool> {
+        self.check_writable()?;
+        let source = self.blob_path(staged);
+        if !source.exists() {
+            return Ok(false);
         }
+        self.invalidate(Tier::Hot, staged);
+        self.invalidate(Tier::Hot, to);
+        // The sidecar may already have moved if an earlier move was interrupted
+        let checksum_path = self.checksum_path(staged);
         if checksum_path.exists() {
-            fs::remove_file(&checksum_path)?;
+            fs::rename(checksum_path, self.checksum_path(to))?;
         }
+        fs::rename(source, self.blob_path(to))?;
+        Ok(true)
+    }
 
+    /// Removes a blob's files from one tier only. Without a cold root there
+    /// is no cold copy to remove, so that is a no-op rather than a delete
+    /// of the hot copy.
+    pub fn remove_from_tier(&self, blob_id: &BlobId, tier: Tier) -> Result<()> {
+        self.check_writable()?;
+        if tier == Tier::Cold && self.cold_root.is_none() {
+            return Ok(());
+        }
+        self.invalidate(tier, blob_id);
+        for path in [self.blob_path_in(tier, blob_id), self.checksum_path_in(tier, blob_id)] {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
         Ok(())
     }
+
+    /// Deletes a blob and its checksum file from every tier
+    pub fn delete_blob(&self, blob_id: &BlobId) -> Result<()> {
+        self.remove_from_tier(blob_id, Tier::Hot)?;
+        self.remove_from_tier(blob_id, Tier::Cold)
+    }
 }
 
 #[cfg(test)]
@@ -109,6 +1115,31 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    #[test]
+    fn test_open_read_only() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let blob_id = BlobId::new();
+        ChunkStore::new(temp_dir.path()).unwrap().put_blob(&blob_id, Cursor::new(b"snapshot")).unwrap();
+
+        let store = ChunkStore::open_read_only(temp_dir.path()).unwrap();
+        let (mut reader, info) = store.get_blob(&blob_id).unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"snapshot");
+        assert_eq!(info.size, 8);
+
+        let err = store.put_blob(&BlobId::new(), Cursor::new(b"new")).unwrap_err();
+        assert!(matches!(err, Error::ReadOnly(_)));
+        assert!(matches!(store.delete_blob(&blob_id).unwrap_err(), Error::ReadOnly(_)));
+        assert!(store.get_blob(&blob_id).is_ok());
+        assert_eq!(fs::read_dir(temp_dir.path().join("chunks")).unwrap().count(), 2);
+
+        // Never creates the directories it would read from
+        let missing = temp_dir.path().join("missing");
+        assert!(ChunkStore::open_read_only(&missing).is_err());
+        assert!(!missing.exists());
+    }
+
     #[test]
     fn test_chunk_store_operations() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -134,4 +1165,356 @@ mod tests {
         store.delete_blob(&blob_id).unwrap();
         assert!(store.get_blob(&blob_id).is_err());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_size_mismatch_skips_checksum_read() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+        store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        store.get_blob_sized(&blob_id, Tier::Hot, 13, None).unwrap();
+        assert_eq!(store.checksum_reads(), 1);
+
+        // Truncate the file: the mismatch is reported without reading it
+        File::options().write(true).open(store.blob_path(&blob_id)).unwrap().set_len(5).unwrap();
+        match store.get_blob_sized(&blob_id, Tier::Hot, 13, None) {
+            Err(Error::SizeMismatch { expected: 13, actual: 5 }) => {}
+            _ => panic!("expected a size mismatch"),
+        }
+        assert_eq!(store.checksum_reads(), 1);
+    }
+
+    #[test]
+    fn test_checksum_mismatch_reports_actual_checksum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+        let info = store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        fs::write(store.blob_path(&blob_id), b"Tampered!!!!!").unwrap();
+        match store.get_blob(&blob_id) {
+            Err(Error::ChecksumMismatch { expected, actual }) => {
+                assert_eq!(expected, info.checksum);
+                assert_eq!(actual, checksum::checksum_of(Cursor::new(b"Tampered!!!!!")).unwrap());
+            }
+            _ => panic!("expected a checksum mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_raw_read_skips_verification() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+        store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        fs::write(store.blob_path(&blob_id), b"Hello, Wor").unwrap();
+        assert!(store.get_blob(&blob_id).is_err());
+
+        let mut data = Vec::new();
+        store.get_blob_raw(&blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, Wor");
+        assert_eq!(store.checksum_reads(), 1);
+
+        assert!(matches!(store.get_blob_raw(&BlobId::new()), Err(Error::BlobNotFound(_))));
+    }
+
+    #[test]
+    fn test_blob_cache() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap().with_blob_cache(64, 16);
+        let read = |blob_id: &BlobId| {
+            let mut data = Vec::new();
+            store.get_blob(blob_id)?.0.read_to_end(&mut data)?;
+            Ok::<_, Error>(data)
+        };
+        let small = BlobId::new();
+        store.put_blob(&small, Cursor::new(b"thumbnail")).unwrap();
+        let large = BlobId::new();
+        store.put_blob(&large, Cursor::new(vec![7; 32])).unwrap();
+
+        // The first read verifies and caches the blob; the second doesn't
+        // touch its file at all
+        assert_eq!(read(&small).unwrap(), b"thumbnail");
+        assert_eq!((store.checksum_reads(), store.cache_hits()), (1, 0));
+        fs::write(store.blob_path(&small), b"tampered!").unwrap();
+        assert_eq!(read(&small).unwrap(), b"thumbnail");
+        assert_eq!((store.checksum_reads(), store.cache_hits()), (1, 1));
+
+        // Blobs over the size threshold are read from disk every time
+        read(&large).unwrap();
+        read(&large).unwrap();
+        assert_eq!((store.checksum_reads(), store.cache_hits()), (3, 1));
+
+        // Overwriting or deleting a blob drops its cached copy
+        store.put_blob(&small, Cursor::new(b"thumbnail2")).unwrap();
+        assert_eq!(read(&small).unwrap(), b"thumbnail2");
+        assert_eq!(store.checksum_reads(), 4);
+        store.delete_blob(&small).unwrap();
+        assert!(matches!(read(&small), Err(Error::BlobNotFound(_))));
+    }
+
+    #[test]
+    fn test_backup_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backup = ChunkStore::new(temp_dir.path().join("backup")).unwrap();
+        let store = ChunkStore::new(temp_dir.path().join("primary"))
+            .unwrap()
+            .with_backup_root(temp_dir.path().join("backup"));
+        let read = |store: &ChunkStore, blob_id: &BlobId| {
+            let mut data = Vec::new();
+            store.get_blob(blob_id)?.0.read_to_end(&mut data)?;
+            Ok::<_, Error>(data)
+        };
+        let blob_id = BlobId::new();
+        store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+        backup.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        // A corrupt primary is served from the backup and left as is
+        fs::write(store.blob_path(&blob_id), b"Hello, Wxrld!").unwrap();
+        assert_eq!(read(&store, &blob_id).unwrap(), b"Hello, World!");
+        assert_eq!(fs::read(store.blob_path(&blob_id)).unwrap(), b"Hello, Wxrld!");
+        assert_eq!(store.backup_reads(), 1);
+
+        // Verification checks the primary only
+        let expected = checksum::checksum_of(&b"Hello, World!"[..]).unwrap();
+        assert!(matches!(
+            store.verify_primary(&blob_id, Tier::Hot, 13, None, Some(&expected)),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert_eq!(store.backup_reads(), 1);
+
+        // With healing the primary is repaired from the backup
+        let store = store.with_backup_healing(true);
+        assert_eq!(read(&store, &blob_id).unwrap(), b"Hello, World!");
+        assert_eq!(fs::read(store.blob_path(&blob_id)).unwrap(), b"Hello, World!");
+
+        // A bad backup doesn't stand in; the primary's mismatch is reported
+        fs::write(store.blob_path(&blob_id), b"Hello, Wxrld!").unwrap();
+        fs::write(backup.blob_path(&blob_id), b"Hello, Wyrld!").unwrap();
+        assert!(matches!(read(&store, &blob_id), Err(Error::ChecksumMismatch { actual, .. })
+            if actual == checksum::checksum_of(&b"Hello, Wxrld!"[..]).unwrap()));
+
+        // Compressed blobs fall back the same way
+        let compressed_id = BlobId::new();
+        let data = "all work and no play ".repeat(100);
+        let (info, compressed_size) =
+            store.put_blob_compressed(&compressed_id, Cursor::new(data.clone()), Compression::Gzip).unwrap();
+        backup.put_blob_compressed(&compressed_id, Cursor::new(data.clone()), Compression::Gzip).unwrap();
+        let mut stored = fs::read(store.blob_path(&compressed_id)).unwrap();
+        let middle = stored.len() / 2;
+        stored[middle] ^= 0xff;
+        fs::write(store.blob_path(&compressed_id), stored).unwrap();
+        assert!(store
+            .verify_primary(&compressed_id, Tier::Hot, info.size, Some((compressed_size, Compression::Gzip)), None)
+            .is_err());
+        let mut read = String::new();
+        store
+            .get_blob_compressed(&compressed_id, Tier::Hot, info.size, compressed_size, Compression::Gzip, None)
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, data);
+
+        // Cold blobs aren't mirrored in the backup root
+        let store = ChunkStore::new(temp_dir.path().join("primary"))
+            .unwrap()
+            .with_cold_root(temp_dir.path().join("cold"))
+            .unwrap()
+            .with_backup_root(temp_dir.path().join("backup"));
+        let cold_id = BlobId::new();
+        store.put_blob(&cold_id, Cursor::new(b"Hello, World!")).unwrap();
+        backup.put_blob(&cold_id, Cursor::new(b"Hello, World!")).unwrap();
+        store.copy_to_tier(&cold_id, Tier::Hot, Tier::Cold).unwrap();
+        fs::write(store.blob_path_in(Tier::Cold, &cold_id), b"Hello, Wxrld!").unwrap();
+        let mut cold = Vec::new();
+        let result = store
+            .get_blob_sized(&cold_id, Tier::Cold, 13, None)
+            .and_then(|(mut reader, _)| Ok(reader.read_to_end(&mut cold)?));
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn test_blob_size() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+        store.put_blob(&blob_id, Cursor::new(vec![7; 1234])).unwrap();
+
+        assert_eq!(store.blob_size(&blob_id).unwrap(), 1234);
+        assert_eq!(store.checksum_reads(), 0);
+        assert!(matches!(store.blob_size(&BlobId::new()), Err(Error::BlobNotFound(_))));
+    }
+
+    #[test]
+    fn test_write_verify() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap().with_write_verify(true);
+        let blob_id = BlobId::new();
+        store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+        assert_eq!(store.write_verifies(), 1);
+
+        let mut store = store;
+        store.after_persist = Some(|path| fs::write(path, b"Hello, Wxrld!").unwrap());
+        let corrupted = BlobId::new();
+        match store.put_blob(&corrupted, Cursor::new(b"Hello, World!")) {
+            Err(Error::WriteVerifyFailed { expected, actual }) => assert_ne!(expected, actual),
+            _ => panic!("expected write verification to fail"),
+        }
+        assert_eq!(store.write_verifies(), 2);
+        assert!(!store.blob_path(&corrupted).exists());
+        assert!(!store.checksum_path(&corrupted).exists());
+
+        // Verification is off by default
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        store.put_blob(&BlobId::new(), Cursor::new(b"fast")).unwrap();
+        assert_eq!(store.write_verifies(), 0);
+    }
+
+    #[test]
+    fn test_put_reads_source_once() {
+        struct CountingReader<'a, R> {
+            inner: R,
+            bytes: &'a std::cell::Cell<usize>,
+        }
+        impl<R: Read> Read for CountingReader<'_, R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.inner.read(buf)?;
+                self.bytes.set(self.bytes.get() + n);
+                Ok(n)
+            }
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let mut data = vec![0; 3 * SPARSE_BLOCK];
+        data.extend((0..200_000u32).map(|i| (i % 251) as u8));
+
+        let bytes = std::cell::Cell::new(0);
+        let blob_id = BlobId::new();
+        let info = store
+            .put_blob(&blob_id, CountingReader { inner: Cursor::new(&data), bytes: &bytes })
+            .unwrap();
+        assert_eq!(bytes.get(), data.len());
+        assert_eq!(info.size, data.len() as u64);
+        assert_eq!(info.checksum, checksum::checksum_of(&data[..]).unwrap());
+        assert_eq!(info.checksum, checksum::checksum_file(&store.blob_path(&blob_id)).unwrap());
+    }
+
+    #[test]
+    fn test_failed_persist_cleans_up() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+
+        // A non-empty directory in the blob's place makes the final rename fail
+        let blocker = store.blob_path(&blob_id);
+        fs::create_dir(&blocker).unwrap();
+        fs::write(blocker.join("keep"), b"x").unwrap();
+
+        for result in [
+            store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).map(|_| ()),
+            store.put_blob_compressed(&blob_id, Cursor::new(b"Hello, World!"), Compression::Zstd(3)).map(|_| ()),
+        ] {
+            match result {
+                Err(Error::Persist(msg)) => assert!(msg.contains(&blob_id.to_string()), "{}", msg),
+                other => panic!("expected a persist error, got {:?}", other),
+            }
+            assert!(!store.checksum_path(&blob_id).exists());
+            let entries: Vec<_> = fs::read_dir(temp_dir.path().join("chunks"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            assert_eq!(entries, vec![blocker.clone()]);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode_of = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cold_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path())
+            .unwrap()
+            .with_file_mode(FileMode { file: 0o640, dir: 0o750 })
+            .unwrap()
+            .with_cold_root(cold_dir.path())
+            .unwrap();
+        let blob_id = BlobId::new();
+        store.put_blob(&blob_id, Cursor::new(b"Hello, World!")).unwrap();
+        let compressed = BlobId::new();
+        store.put_blob_compressed(&compressed, Cursor::new(b"Hello, World!"), Compression::Zstd(3)).unwrap();
+        store.copy_to_tier(&blob_id, Tier::Hot, Tier::Cold).unwrap();
+
+        for tier in [Tier::Hot, Tier::Cold] {
+            assert_eq!(mode_of(&store.blob_path_in(tier, &blob_id)), 0o640);
+            assert_eq!(mode_of(&store.checksum_path_in(tier, &blob_id)), 0o640);
+            assert_eq!(mode_of(store.tier_root(tier)), 0o750);
+            assert_eq!(mode_of(&store.tier_root(tier).join("chunks")), 0o750);
+        }
+        assert_eq!(mode_of(&store.blob_path(&compressed)), 0o640);
+        assert_eq!(mode_of(&store.checksum_path(&compressed)), 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_zero_runs_are_stored_sparsely() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+
+        // 16 MiB of zeros with a little data at the start, middle and end
+        let mut data = vec![0u8; 16 * 1024 * 1024];
+        data[..5].copy_from_slice(b"image");
+        data[8 * 1024 * 1024 + 3] = 0xff;
+        let len = data.len();
+        data[len - 1] = 1;
+
+        let info = store.put_blob(&blob_id, Cursor::new(&data)).unwrap();
+        assert_eq!(info.size, data.len() as u64);
+        assert_eq!(info.checksum, checksum::checksum_of(&data[..]).unwrap());
+
+        let on_disk = fs::metadata(store.blob_path(&blob_id)).unwrap().blocks() * 512;
+        assert!(on_disk < 1024 * 1024, "{} bytes allocated", on_disk);
+
+        let (mut reader, _) = store.get_blob(&blob_id).unwrap();
+        let mut stored = Vec::new();
+        reader.read_to_end(&mut stored).unwrap();
+        assert!(stored == data);
+    }
+
+    #[test]
+    fn test_compressed_blobs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = ChunkStore::new(temp_dir.path()).unwrap();
+        let blob_id = BlobId::new();
+        let data = b"abc".repeat(10_000);
+
+        let (info, compressed_size) = store.put_blob_compressed(&blob_id, Cursor::new(&data), Compression::Zstd(3)).unwrap();
+        assert_eq!(info.size, data.len() as u64);
+        assert_eq!(info.checksum, checksum::checksum_of(&data[..]).unwrap());
+        assert!(compressed_size < 1000);
+        assert_eq!(store.blob_size(&blob_id).unwrap(), compressed_size);
+
+        let mut stored = Vec::new();
+        store
+            .get_blob_compressed(&blob_id, Tier::Hot, info.size, compressed_size, Compression::Zstd(3), None)
+            .unwrap()
+            .read_to_end(&mut stored)
+            .unwrap();
+        assert!(stored == data);
+
+        // Damaged compressed data is caught before any bytes are returned
+        let mut raw = fs::read(store.blob_path(&blob_id)).unwrap();
+        let middle = raw.len() / 2;
+        raw[middle] ^= 0xff;
+        fs::write(store.blob_path(&blob_id), &raw).unwrap();
+        assert!(store.get_blob_compressed(&blob_id, Tier::Hot, info.size, compressed_size, Compression::Zstd(3), None).is_err());
+    }
+}
@@ -1,11 +1,16 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::path::{Path, PathBuf};
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, IsTerminal};
 
 use crate::{
-    BlobId, TenantId,
-    coordinator::Coordinator,
+    BlobId, IdFormat, TenantId,
+    archive::{ManifestFormat, MetadataFormat},
+    cancel::CancellationToken,
+    chunk_store::BlobInfo,
+    compression::Compression,
+    coordinator::{Coordinator, OptimizeOptions},
+    error::Error,
     Result,
 };
 
@@ -16,10 +21,98 @@ pub struct Cli {
     #[arg(short, long, default_value = "storage")]
     storage_dir: PathBuf,
 
+    /// Format used to print and parse blob IDs
+    #[arg(long, value_enum, default_value_t = IdFormatArg::Uuid)]
+    id_format: IdFormatArg,
+
+    /// Compress newly stored blobs with this algorithm
+    #[arg(long, value_enum)]
+    compression: Option<CompressionAlgorithm>,
+
+    /// zstd level for newly stored blobs (1-22); implies zstd if no
+    /// algorithm is given
+    #[arg(long)]
+    compression_level: Option<i32>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Compression algorithms selectable with `--compression`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CompressionAlgorithm {
+    None,
+    Zstd,
+    Lz4,
+    Gzip,
+}
+
+/// Blob id formats selectable with `--id-format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum IdFormatArg {
+    /// Hyphenated UUID
+    Uuid,
+    /// 32 lowercase hex digits
+    Hex,
+    /// 26 RFC 4648 base32 characters, unpadded
+    Base32,
+}
+
+impl From<IdFormatArg> for IdFormat {
+    fn from(arg: IdFormatArg) -> Self {
+        match arg {
+            IdFormatArg::Uuid => IdFormat::Uuid,
+            IdFormatArg::Hex => IdFormat::Hex,
+            IdFormatArg::Base32 => IdFormat::Base32,
+        }
+    }
+}
+
+/// Inventory formats selectable with `export-metadata --format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum MetadataFormatArg {
+    /// One row per blob, tags flattened into a single `key=value;...` column
+    Csv,
+    /// A JSON array of blob metadata records
+    Json,
+}
+
+impl From<MetadataFormatArg> for MetadataFormat {
+    fn from(arg: MetadataFormatArg) -> Self {
+        match arg {
+            MetadataFormatArg::Csv => MetadataFormat::Csv,
+            MetadataFormatArg::Json => MetadataFormat::Json,
+        }
+    }
+}
+
+/// Manifest formats selectable with `manifest --format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ManifestFormatArg {
+    /// `<checksum>  <blob id>` lines, as `sha256sum -c` style tools expect
+    Text,
+    /// One JSON object per line with the tenant, blob id and checksum
+    Json,
+}
+
+impl From<ManifestFormatArg> for ManifestFormat {
+    fn from(arg: ManifestFormatArg) -> Self {
+        match arg {
+            ManifestFormatArg::Text => ManifestFormat::Text,
+            ManifestFormatArg::Json => ManifestFormat::Json,
+        }
+    }
+}
+
+/// zstd level used when `--compression zstd` is given without a level
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// `put --file` path that reads the blob from stdin
+const STDIN_PATH: &str = "-";
+
+/// Tag recording the name of the file a blob was stored from
+const FILENAME_TAG: &str = "filename";
+
 #[derive(Subcommand)]
 enum Commands {
     /// Register a new tenant
@@ -29,8 +122,19 @@ enum Commands {
         tenant: String,
     },
 
+    /// Register several tenants at once
+    RegisterTenants {
+        /// Tenant IDs
+        #[arg(short, long = "tenant", required = true)]
+        tenants: Vec<String>,
+    },
+
     /// List all registered tenants
-    ListTenants,
+    ListTenants {
+        /// Only list tenants whose names start with this prefix
+        #[arg(long)]
+        prefix: Option<String>,
+    },
 
     /// Store a blob
     Put {
@@ -38,9 +142,25 @@ enum Commands {
         #[arg(short, long)]
         tenant: String,
 
-        /// Path to the file to store
+        /// Path to the file to store, or - to read stdin
         #[arg(short, long)]
         file: PathBuf,
+
+        /// Name recorded in the blob's filename tag instead of the file's name
+        #[arg(long)]
+        filename: Option<String>,
+    },
+
+    /// Store a blob fetched from a URL
+    #[cfg(feature = "http-client")]
+    PutUrl {
+        /// Tenant ID
+        #[arg(short, long)]
+        tenant: String,
+
+        /// URL to fetch
+        #[arg(short, long)]
+        url: String,
     },
 
     /// Retrieve a blob
@@ -49,13 +169,17 @@ enum Commands {
         #[arg(short, long)]
         tenant: String,
 
-        /// Blob ID
+        /// Blob ID, or an unambiguous prefix of it
         #[arg(short, long)]
         blob: String,
 
         /// Output file path (optional, defaults to stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Print the verified checksum and size to stderr after the download
+        #[arg(long)]
+        print_checksum: bool,
     },
 
     /// List blobs for a tenant
@@ -63,6 +187,58 @@ enum Commands {
         /// Tenant ID
         #[arg(short, long)]
         tenant: String,
+
+        /// Show the first 8 hex digits of each blob ID
+        #[arg(long)]
+        short: bool,
+    },
+
+    /// Export an inventory of a tenant's blob metadata
+    ExportMetadata {
+        /// Tenant ID
+        #[arg(short, long)]
+        tenant: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = MetadataFormatArg::Csv)]
+        format: MetadataFormatArg,
+
+        /// Indent JSON records over several lines instead of one per line
+        #[arg(long)]
+        pretty: bool,
+
+        /// Output file path (optional, defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Print the id and checksum of every blob, for checking against an
+    /// external record
+    Manifest {
+        /// Tenant ID (optional, defaults to every tenant)
+        #[arg(short, long)]
+        tenant: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ManifestFormatArg::Text)]
+        format: ManifestFormatArg,
+
+        /// Output file path (optional, defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Check files extracted from an export against the tenant's stored
+    /// checksums
+    VerifyExport {
+        /// Tenant ID
+        #[arg(short, long)]
+        tenant: String,
+
+        /// Directory the export was extracted into, or a directory of blob
+        /// files named by id or filename
+        #[arg(short, long)]
+        dir: PathBuf,
     },
 
     /// Delete a blob
@@ -71,82 +247,707 @@ enum Commands {
         #[arg(short, long)]
         tenant: String,
 
-        /// Blob ID
+        /// Blob ID, or an unambiguous prefix of it
         #[arg(short, long)]
         blob: String,
     },
+
+    /// Verify the size and checksum of every blob in the store
+    Verify,
+
+    /// Reclaim space in the metadata database (may be I/O heavy)
+    Compact,
+
+    /// Run periodic housekeeping: delete expired blobs and orphaned data,
+    /// then compact the metadata database and pack files
+    Optimize {
+        /// Skip deleting expired blobs, orphan chunks and dangling memberships
+        #[arg(long)]
+        skip_gc: bool,
+
+        /// Skip compacting the metadata database
+        #[arg(long)]
+        skip_compact: bool,
+
+        /// Skip compacting pack files
+        #[arg(long)]
+        skip_packs: bool,
+    },
+
+    /// Show the number and total size of each tenant's blobs
+    Stats,
+
+    /// Open an interactive shell that runs commands against the store
+    /// without reopening it for each one
+    Shell,
 }
 
 pub fn run() -> Result<()> {
-    let cli = Cli::parse();
-    let coordinator = Coordinator::new(&cli.storage_dir)?;
+    // Ctrl-C asks long-running commands to stop at the next safe point
+    let cancel = CancellationToken::new();
+    let handler_token = cancel.clone();
+    ctrlc::set_handler(move || handler_token.cancel())
+        .map_err(|e| Error::System(format!("Failed to install Ctrl-C handler: {}", e)))?;
+
+    execute(Cli::parse(), &cancel)
+}
+
+/// Prints a failed command's error to `stderr` as a one-line message and
+/// returns the process exit code for it
+pub fn report_error(err: &Error, mut stderr: impl io::Write) -> u8 {
+    let _ = writeln!(stderr, "Error: {}", err);
+    err.exit_code()
+}
+
+/// Runs a parsed command against the store
+pub fn execute(cli: Cli, cancel: &CancellationToken) -> Result<()> {
+    let mut coordinator = Coordinator::new_or_read_only(&cli.storage_dir)?;
+    let compression = match cli.compression {
+        Some(CompressionAlgorithm::None) => None,
+        Some(CompressionAlgorithm::Zstd) => {
+            Some(Compression::Zstd(cli.compression_level.unwrap_or(DEFAULT_ZSTD_LEVEL)))
+        }
+        Some(CompressionAlgorithm::Lz4) => Some(Compression::Lz4),
+        Some(CompressionAlgorithm::Gzip) => Some(Compression::Gzip),
+        None => cli.compression_level.map(Compression::Zstd),
+    };
+    if let Some(compression) = compression {
+        coordinator = coordinator.with_compression(compression);
+    }
+
+    let mut stdout = io::stdout().lock();
+    if let Commands::Shell = cli.command {
+        let interactive = io::stdin().is_terminal();
+        return run_shell(&coordinator, cli.id_format.into(), cancel, io::stdin().lock(), &mut stdout, interactive);
+    }
+    run_command(&coordinator, &cli.command, cli.id_format.into(), cancel, &mut stdout)
+}
+
+/// One line of shell input, parsed as a subcommand
+#[derive(Parser)]
+#[command(no_binary_name = true, disable_help_flag = true, disable_version_flag = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+/// Reads commands from `input` one per line and runs them against an
+/// already open store until `exit` or end of input. A failing command
+/// prints its error and the shell carries on.
+fn run_shell(
+    coordinator: &Coordinator,
+    id_format: IdFormat,
+    cancel: &CancellationToken,
+    input: impl io::BufRead,
+    out: &mut dyn io::Write,
+    interactive: bool,
+) -> Result<()> {
+    let prompt = |out: &mut dyn io::Write| -> Result<()> {
+        if interactive {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        Ok(())
+    };
+
+    prompt(out)?;
+    for line in input.lines() {
+        let line = line?;
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                writeln!(out, "Error: {}", e)?;
+                prompt(out)?;
+                continue;
+            }
+        };
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        match words.as_slice() {
+            [] => {}
+            ["exit" | "quit"] => break,
+            ["help"] => {
+                let help = ShellLine::command().render_long_help();
+                writeln!(out, "{}", help)?;
+                writeln!(out, "Type exit or quit to leave the shell.")?;
+            }
+            _ => match ShellLine::try_parse_from(&words) {
+                Ok(parsed) => {
+                    if let Err(e) = run_command(coordinator, &parsed.command, id_format, cancel, out) {
+                        writeln!(out, "Error: {}", e)?;
+                    }
+                }
+                Err(e) => writeln!(out, "{}", e.render())?,
+            },
+        }
+        prompt(out)?;
+    }
+    Ok(())
+}
 
-    match &cli.command {
+/// Splits a shell line into words the way a POSIX shell would: quotes keep
+/// spaces in a word, single quotes are literal, and a backslash escapes the
+/// next character outside single quotes
+fn split_words(line: &str) -> std::result::Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote"),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash"),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Runs one command, writing its output to `out`
+fn run_command(
+    coordinator: &Coordinator,
+    command: &Commands,
+    id_format: IdFormat,
+    cancel: &CancellationToken,
+    out: &mut dyn io::Write,
+) -> Result<()> {
+    match command {
         Commands::RegisterTenant { tenant } => {
             coordinator.register_tenant(TenantId::new(tenant))?;
-            println!("Tenant '{}' registered successfully", tenant);
+            writeln!(out, "Tenant '{}' registered successfully", tenant)?;
         }
 
-        Commands::ListTenants => {
-            let tenants = coordinator.list_tenants()?;
-            println!("Registered tenants:");
+        Commands::RegisterTenants { tenants } => {
+            let tenant_ids: Vec<_> = tenants.iter().map(TenantId::new).collect();
+            for (tenant_id, outcome) in coordinator.register_tenants(&tenant_ids)? {
+                match outcome {
+                    Ok(()) => writeln!(out, "Tenant '{}' registered successfully", tenant_id.as_str())?,
+                    Err(e) => writeln!(out, "Tenant '{}' not registered: {}", tenant_id.as_str(), e)?,
+                }
+            }
+        }
+
+        Commands::ListTenants { prefix } => {
+            let tenants = match prefix {
+                Some(prefix) => coordinator.list_tenants_by_prefix(prefix)?,
+                None => coordinator.list_tenants()?,
+            };
+            writeln!(out, "Registered tenants:")?;
             for tenant in tenants {
-                println!("- {}", tenant.as_str());
+                writeln!(out, "- {}", tenant.as_str())?;
             }
         }
 
-        Commands::Put { tenant, file } => {
+        Commands::Put { tenant, file, filename } => {
             let tenant_id = TenantId::new(tenant);
-            let file = File::open(file)?;
-            let blob_id = coordinator.put_blob(&tenant_id, file)?;
-            println!("Blob stored successfully. ID: {}", blob_id.to_string());
+            let tags = source_filename(file, filename.as_deref())
+                .map(|name| [(FILENAME_TAG.to_string(), name)].into())
+                .unwrap_or_default();
+            let blob_id = if file == Path::new(STDIN_PATH) {
+                coordinator.put_blob_with_tags(&tenant_id, io::stdin().lock(), tags)?
+            } else {
+                coordinator.put_blob_with_tags(&tenant_id, File::open(file)?, tags)?
+            };
+            writeln!(out, "Blob stored successfully. ID: {}", blob_id.format(id_format))?;
+        }
+
+        #[cfg(feature = "http-client")]
+        Commands::PutUrl { tenant, url } => {
+            let tenant_id = TenantId::new(tenant);
+            let body = fetch_url(url)?;
+            let tags = [("source_url".to_string(), url.clone())].into();
+            let blob_id = coordinator.put_blob_with_tags(&tenant_id, body, tags)?;
+            writeln!(out, "Blob stored successfully. ID: {}", blob_id.format(id_format))?;
+        }
+
+        Commands::Get { tenant, blob, output, print_checksum } => {
+            let tenant_id = TenantId::new(tenant);
+            let blob_id = parse_blob_arg(coordinator, &tenant_id, blob, id_format)?;
+            let (reader, info) = coordinator.get_blob_with_info(&tenant_id, &blob_id)?;
+            let info = print_checksum.then_some(&info);
+
+            match output {
+                Some(path) => write_blob(reader, info, File::create(path)?, io::stderr())?,
+                None => write_blob(reader, info, &mut *out, io::stderr())?,
+            }
         }
 
-        Commands::Get { tenant, blob, output } => {
+        Commands::ListBlobs { tenant, short } => {
             let tenant_id = TenantId::new(tenant);
-            let blob_id = BlobId::from_str(blob)?;
-            let mut reader = coordinator.get_blob(&tenant_id, &blob_id)?;
+            let blobs = coordinator.list_blobs(&tenant_id)?;
+            writeln!(out, "Blobs for tenant '{}':", tenant)?;
+            for metadata in blobs {
+                if *short {
+                    writeln!(out, "- ID: {}", metadata.blob_id.short())?;
+                } else {
+                    writeln!(out, "- ID: {}", metadata.blob_id)?;
+                }
+                writeln!(out, "  Size: {} bytes", metadata.size)?;
+                if let Some(compressed_size) = metadata.compressed_size {
+                    writeln!(
+                        out,
+                        "  Compressed: {} bytes with {} (ratio {:.2})",
+                        compressed_size,
+                        metadata.stored_compression().name(),
+                        metadata.compression_ratio()
+                    )?;
+                }
+                writeln!(out, "  Checksum: {}", metadata.checksum)?;
+                writeln!(out, "  Created: {}", metadata.created_at)?;
+                if let Some(last_accessed) = metadata.last_accessed {
+                    writeln!(out, "  Last accessed: {}", last_accessed)?;
+                }
+                if let Some(content_type) = &metadata.content_type {
+                    writeln!(out, "  Content-Type: {}", content_type)?;
+                }
+                for (key, value) in metadata.tags.iter() {
+                    writeln!(out, "  Tag: {}={}", key, value)?;
+                }
+            }
+        }
 
+        Commands::ExportMetadata { tenant, format, pretty, output } => {
+            let tenant_id = TenantId::new(tenant);
             match output {
                 Some(path) => {
-                    let mut file = File::create(path)?;
-                    io::copy(&mut reader, &mut file)?;
+                    let file = io::BufWriter::new(File::create(path)?);
+                    let count = coordinator.export_metadata(&tenant_id, (*format).into(), *pretty, file)?;
+                    writeln!(out, "Exported metadata for {} blobs", count)?;
                 }
                 None => {
-                    let mut stdout = io::stdout();
-                    io::copy(&mut reader, &mut stdout)?;
+                    coordinator.export_metadata(&tenant_id, (*format).into(), *pretty, &mut *out)?;
                 }
             }
         }
 
-        Commands::ListBlobs { tenant } => {
-            let tenant_id = TenantId::new(tenant);
-            let blobs = coordinator.list_blobs(&tenant_id)?;
-            println!("Blobs for tenant '{}':", tenant);
-            for metadata in blobs {
-                println!("- ID: {}", metadata.blob_id.to_string());
-                println!("  Size: {} bytes", metadata.size);
-                println!("  Checksum: {}", metadata.checksum);
-                println!("  Created: {}", metadata.created_at);
+        Commands::Manifest { tenant, format, output } => {
+            let tenant_id = tenant.as_deref().map(TenantId::new);
+            match output {
+                Some(path) => {
+                    let file = io::BufWriter::new(File::create(path)?);
+                    let count = coordinator.export_checksum_manifest(tenant_id.as_ref(), (*format).into(), file)?;
+                    writeln!(out, "Wrote checksums for {} blobs", count)?;
+                }
+                None => {
+                    coordinator.export_checksum_manifest(tenant_id.as_ref(), (*format).into(), &mut *out)?;
+                }
+            }
+        }
+
+        Commands::VerifyExport { tenant, dir } => {
+            let report = coordinator.verify_against_manifest(&TenantId::new(tenant), dir, cancel)?;
+            for (blob_id, path) in &report.mismatched {
+                writeln!(out, "- {}: {} does not match", blob_id.format(id_format), path.display())?;
+            }
+            for blob_id in &report.missing {
+                writeln!(out, "- {}: no file found", blob_id.format(id_format))?;
+            }
+            writeln!(
+                out,
+                "Verified {} files: {} matched, {} mismatched, {} missing",
+                report.matched.len() + report.mismatched.len(),
+                report.matched.len(),
+                report.mismatched.len(),
+                report.missing.len()
+            )?;
+            if report.cancelled {
+                writeln!(out, "Verification interrupted")?;
             }
         }
 
         Commands::Delete { tenant, blob } => {
             let tenant_id = TenantId::new(tenant);
-            let blob_id = BlobId::from_str(blob)?;
+            let blob_id = parse_blob_arg(coordinator, &tenant_id, blob, id_format)?;
             coordinator.delete_blob(&tenant_id, &blob_id)?;
-            println!("Blob deleted successfully");
+            writeln!(out, "Blob deleted successfully")?;
+        }
+
+        Commands::Verify => {
+            let report = coordinator.verify_all(cancel)?;
+            for (blob_id, error) in &report.corrupted {
+                writeln!(out, "- {}: {}", blob_id.format(id_format), error)?;
+            }
+            if report.cancelled {
+                writeln!(out, "Verification interrupted after {} blobs", report.checked)?;
+            }
+            writeln!(out, "Verified {} blobs, {} corrupted", report.checked, report.corrupted.len())?;
+        }
+
+        Commands::Stats => {
+            for tenant_id in coordinator.list_tenants()? {
+                let blobs = coordinator.list_blob_ids(&tenant_id)?;
+                let bytes = coordinator.tenant_usage(&tenant_id)?;
+                let ratio = coordinator.compression_ratio(&tenant_id)?;
+                writeln!(
+                    out,
+                    "{}: {} blobs, {} bytes, compression ratio {:.2}",
+                    tenant_id.as_str(),
+                    blobs.len(),
+                    bytes,
+                    ratio
+                )?;
+            }
+        }
+
+        Commands::Shell => {
+            writeln!(out, "Already in a shell")?;
+        }
+
+        Commands::Compact => {
+            let report = coordinator.compact_metadata()?;
+            writeln!(
+                out,
+                "Metadata compacted: {} bytes before, {} bytes after",
+                report.before_bytes, report.after_bytes
+            )?;
+        }
+
+        Commands::Optimize { skip_gc, skip_compact, skip_packs } => {
+            let options = OptimizeOptions {
+                gc: !skip_gc,
+                compact_metadata: !skip_compact,
+                compact_packs: !skip_packs,
+                ..OptimizeOptions::default()
+            };
+            let report = coordinator.optimize(options, cancel)?;
+            if let Some(gc) = report.gc {
+                writeln!(
+                    out,
+                    "Garbage collected: {} expired blobs, {} orphan chunks, {} dangling memberships",
+                    gc.expired_blobs, gc.orphan_chunks, gc.dangling_members
+                )?;
+            }
+            if let Some(metadata) = report.metadata {
+                writeln!(
+                    out,
+                    "Metadata compacted: {} bytes before, {} bytes after",
+                    metadata.before_bytes, metadata.after_bytes
+                )?;
+            }
+            if let Some(reclaimed) = report.packs {
+                writeln!(out, "Packs compacted: {} bytes reclaimed", reclaimed)?;
+            }
+            if report.cancelled {
+                writeln!(out, "Optimize interrupted")?;
+            }
         }
     }
 
     Ok(())
 }
 
-impl BlobId {
-    fn from_str(s: &str) -> Result<Self> {
-        let uuid = uuid::Uuid::parse_str(s).map_err(|_| {
-            crate::error::Error::System("Invalid blob ID format".into())
-        })?;
-        Ok(Self(uuid))
+/// Returns the name to record for a blob stored from `file`: the override if
+/// given, otherwise the file's base name. Data read from stdin has no name.
+fn source_filename(file: &Path, filename: Option<&str>) -> Option<String> {
+    match filename {
+        Some(filename) => Some(filename.to_string()),
+        None if file == Path::new(STDIN_PATH) => None,
+        None => file.file_name().map(|name| name.to_string_lossy().into_owned()),
+    }
+}
+
+/// Parses a full blob ID, falling back to resolving a short hex prefix
+/// against the tenant's blobs when the ID format is hex-based. Input that
+/// can't be either fails with `InvalidBlobId` explaining the expected form.
+fn parse_blob_arg(coordinator: &Coordinator, tenant_id: &TenantId, blob: &str, id_format: IdFormat) -> Result<BlobId> {
+    let is_hex_prefix = !blob.is_empty() && blob.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    match BlobId::parse(blob, id_format) {
+        Ok(blob_id) => Ok(blob_id),
+        Err(_) if id_format != IdFormat::Base32 && is_hex_prefix => coordinator.resolve_blob_id(tenant_id, blob),
+        Err(e) => Err(e),
+    }
+}
+
+/// Streams a blob to `out`, then reports its checksum and size on `report`
+/// if `info` is given, keeping `out` free of anything but blob bytes
+fn write_blob(
+    mut reader: impl io::Read,
+    info: Option<&BlobInfo>,
+    mut out: impl io::Write,
+    mut report: impl io::Write,
+) -> Result<()> {
+    io::copy(&mut reader, &mut out)?;
+    out.flush()?;
+    if let Some(info) = info {
+        writeln!(report, "Checksum: {}", info.checksum)?;
+        writeln!(report, "Size: {} bytes", info.size)?;
+    }
+    Ok(())
+}
+
+/// Opens a streaming reader over the body of a successful HTTP GET
+#[cfg(feature = "http-client")]
+fn fetch_url(url: &str) -> Result<impl io::Read + Send> {
+    let response = ureq::get(url).call().map_err(|e| match e {
+        ureq::Error::Status(status, _) => Error::HttpStatus { url: url.to_string(), status },
+        other => Error::Http(format!("failed to fetch {}: {}", url, other)),
+    })?;
+    if response.status() != 200 {
+        return Err(Error::HttpStatus { url: url.to_string(), status: response.status() });
+    }
+    Ok(response.into_reader())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_prints_checksum_to_stderr() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, io::Cursor::new(b"Hello, World!")).unwrap();
+
+        let (reader, info) = coordinator.get_blob_with_info(&tenant_id, &blob_id).unwrap();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        write_blob(reader, Some(&info), &mut stdout, &mut stderr).unwrap();
+        assert_eq!(stdout, b"Hello, World!");
+        let stderr = String::from_utf8(stderr).unwrap();
+        let checksum = crate::checksum::compute_sha256(&b"Hello, World!"[..]).unwrap();
+        assert!(stderr.contains(&format!("Checksum: sha256:{}", checksum)));
+        assert!(stderr.contains("Size: 13 bytes"));
+
+        // Without the flag nothing goes to stderr
+        let (reader, _) = coordinator.get_blob_with_info(&tenant_id, &blob_id).unwrap();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        write_blob(reader, None, &mut stdout, &mut stderr).unwrap();
+        assert_eq!(stdout, b"Hello, World!");
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    fn test_shell_session() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("post.txt");
+        std::fs::write(&file_path, b"Hello, World!").unwrap();
+        let coordinator = Coordinator::new(temp_dir.path().join("storage")).unwrap();
+        let shell = |script: &str| {
+            let mut out = Vec::new();
+            run_shell(&coordinator, IdFormat::Uuid, &CancellationToken::new(), script.as_bytes(), &mut out, false)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let script = format!(
+            "register-tenant -t posts\nput -t posts -f {}\nlist-blobs -t posts\nstats\nfrobnicate\nget -t missing -b nope\nhelp\nexit\nlist-tenants\n",
+            file_path.display()
+        );
+        let output = shell(&script);
+        assert!(output.contains("Tenant 'posts' registered successfully"));
+        let blob_id = output
+            .lines()
+            .find_map(|line| line.strip_prefix("Blob stored successfully. ID: "))
+            .unwrap()
+            .to_string();
+
+        // Later commands in the session see the earlier writes
+        assert!(output.contains(&format!("- ID: {}", blob_id)));
+        assert!(output.contains("posts: 1 blobs, 13 bytes, compression ratio 1.00"));
+
+        // Bad commands are reported without ending the session
+        assert!(output.contains("unrecognized subcommand 'frobnicate'"));
+        assert!(output.contains("Error: "));
+        assert!(output.contains("Type exit or quit to leave the shell."));
+        assert!(!output.contains("Registered tenants:"));
+
+        let output = shell(&format!("get -t posts -b {}\ndelete -t posts -b {}\nstats\n", blob_id, blob_id));
+        assert!(output.starts_with("Hello, World!"));
+        assert!(output.contains("Blob deleted successfully"));
+        assert!(output.contains("posts: 0 blobs, 0 bytes"));
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("  put -t posts  ").unwrap(), ["put", "-t", "posts"]);
+        assert_eq!(split_words(r#"put -f "my file.txt""#).unwrap(), ["put", "-f", "my file.txt"]);
+        assert_eq!(split_words(r#"put -f 'it''s' a\ b"#).unwrap(), ["put", "-f", "its", "a b"]);
+        assert_eq!(split_words(r#"say "a \"b\" \n" '' x"#).unwrap(), ["say", r#"a "b" \n"#, "", "x"]);
+        assert!(split_words("put -f 'open").is_err());
+        assert!(split_words(r#"put -f "open"#).is_err());
+        assert!(split_words("put \\").is_err());
+    }
+
+    #[test]
+    fn test_put_records_filename() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("holiday photo.jpg");
+        std::fs::write(&file_path, b"photo").unwrap();
+        let coordinator = Coordinator::new(temp_dir.path().join("storage")).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let put = |filename: Option<&str>| {
+            let command = Commands::Put {
+                tenant: "posts".into(),
+                file: file_path.clone(),
+                filename: filename.map(str::to_string),
+            };
+            run_command(&coordinator, &command, IdFormat::Uuid, &CancellationToken::new(), &mut Vec::new()).unwrap();
+        };
+        put(None);
+        put(Some("renamed.jpg"));
+        let mut names: Vec<_> = coordinator
+            .list_blobs(&tenant_id)
+            .unwrap()
+            .into_iter()
+            .map(|metadata| metadata.tags.get(FILENAME_TAG).unwrap().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["holiday photo.jpg", "renamed.jpg"]);
+
+        // Stdin has no name unless one is given
+        assert_eq!(source_filename(Path::new(STDIN_PATH), None), None);
+        assert_eq!(source_filename(Path::new(STDIN_PATH), Some("piped.txt")).as_deref(), Some("piped.txt"));
+    }
+
+    #[test]
+    fn test_short_ids() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, io::Cursor::new(b"Hello, World!")).unwrap();
+        let shell = |script: &str| {
+            let mut out = Vec::new();
+            run_shell(&coordinator, IdFormat::Uuid, &CancellationToken::new(), script.as_bytes(), &mut out, false)
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let output = shell("list-blobs -t posts --short\n");
+        assert!(output.contains(&format!("- ID: {}\n", blob_id.short())));
+
+        // get and delete accept the short form
+        let output = shell(&format!("get -t posts -b {}\ndelete -t posts -b {}\n", blob_id.short(), blob_id.short()));
+        assert!(output.starts_with("Hello, World!"));
+        assert!(output.contains("Blob deleted successfully"));
+
+        // Input that is neither an id nor a prefix says what was expected
+        let output = shell("get -t posts -b not-an-id\n");
+        assert!(output.contains("Invalid blob ID 'not-an-id'"), "{}", output);
+        assert!(output.contains("expected a UUID"), "{}", output);
+    }
+
+    #[cfg(feature = "http-client")]
+    #[test]
+    fn test_put_url() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        // Minimal HTTP server answering two requests: one success, one 404
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            for response in [
+                "HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\nHello, World!",
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+            ] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage_dir = temp_dir.path().to_str().unwrap();
+        let url = format!("http://{}/payload", addr);
+        let command = |args: &[&str]| {
+            let base = ["mini-tectonic-rs", "-s", storage_dir];
+            execute(Cli::try_parse_from(base.iter().chain(args)).unwrap(), &CancellationToken::new())
+        };
+
+        command(&["register-tenant", "-t", "posts"]).unwrap();
+        command(&["put-url", "-t", "posts", "--url", &url]).unwrap();
+        let err = command(&["put-url", "-t", "posts", "--url", &url]).unwrap_err();
+        assert!(matches!(err, crate::error::Error::HttpStatus { status: 404, .. }));
+        assert!(!err.is_retryable());
+        server.join().unwrap();
+
+        let coordinator = crate::test_support::reopen(|| Coordinator::new(temp_dir.path()));
+        let tenant_id = TenantId::new("posts");
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].tags.get("source_url"), Some(url.as_str()));
+
+        let mut reader = coordinator.get_blob(&tenant_id, &blobs[0].blob_id).unwrap();
+        let mut retrieved = Vec::new();
+        io::Read::read_to_end(&mut reader, &mut retrieved).unwrap();
+        assert_eq!(retrieved, b"Hello, World!");
+    }
+
+    #[test]
+    fn test_error_exit_codes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage_dir = temp_dir.path().to_str().unwrap();
+        let blob_id = {
+            let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+            let tenant_id = TenantId::new("posts");
+            coordinator.register_tenant(tenant_id.clone()).unwrap();
+            coordinator.put_blob(&tenant_id, io::Cursor::new(b"Hello, World!")).unwrap()
+        };
+        let missing = BlobId::new();
+        let command = |args: &[&str]| {
+            let base = ["mini-tectonic-rs", "-s", storage_dir];
+            let err = execute(Cli::try_parse_from(base.iter().chain(args)).unwrap(), &CancellationToken::new())
+                .unwrap_err();
+            let mut stderr = Vec::new();
+            let code = report_error(&err, &mut stderr);
+            (code, String::from_utf8(stderr).unwrap())
+        };
+
+        let (code, stderr) = command(&["get", "-t", "posts", "-b", &missing.to_string()]);
+        assert_eq!(code, 3);
+        assert!(stderr.starts_with("Error: ") && stderr.contains("Blob not found"), "{}", stderr);
+        assert_eq!(stderr.lines().count(), 1);
+
+        let (code, stderr) = command(&["list-blobs", "-t", "nobody"]);
+        assert_eq!(code, 4);
+        assert!(stderr.contains("Invalid tenant"), "{}", stderr);
+
+        std::fs::write(temp_dir.path().join("chunks").join(format!("{}.blob", blob_id)), b"Hello, Wrld!!").unwrap();
+        let (code, stderr) = command(&["get", "-t", "posts", "-b", &blob_id.to_string(), "-o", "/dev/null"]);
+        assert_eq!(code, 5);
+        assert!(stderr.contains("Checksum mismatch"), "{}", stderr);
+
+        // Errors without a dedicated code exit with 1
+        let (code, stderr) = command(&["get", "-t", "posts", "-b", "not-an-id"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("Invalid blob ID"), "{}", stderr);
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
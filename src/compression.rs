@@ -0,0 +1,135 @@
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// How a chunk-stored blob's data is compressed on disk. Recorded per blob,
+/// so changing the store's default never affects reading existing blobs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    /// Stored as is
+    #[default]
+    None,
+    /// zstd at the given level (1-22, higher is smaller but slower)
+    Zstd(i32),
+    /// LZ4 frames: fast, with a lower ratio
+    Lz4,
+    /// gzip at the default level, for compatibility with other tools
+    Gzip,
+}
+
+impl Compression {
+    /// Short lowercase name of the algorithm
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd(_) => "zstd",
+            Compression::Lz4 => "lz4",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    /// Returns true unless the data is stored as is
+    pub fn is_compressed(self) -> bool {
+        self != Compression::None
+    }
+
+    /// The HTTP `Content-Encoding` naming data compressed this way, if
+    /// clients can decode it. LZ4 frames have no registered encoding.
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Compression::Zstd(_) => Some("zstd"),
+            Compression::Gzip => Some("gzip"),
+            Compression::None | Compression::Lz4 => None,
+        }
+    }
+
+    /// Returns true if an HTTP `Accept-Encoding` header value lets this
+    /// compression be sent as is. An encoding is accepted when it, or `*`,
+    /// is listed without a `q=0` weight; an explicit entry wins over `*`.
+    pub fn accepted_by(self, accept_encoding: &str) -> bool {
+        let Some(encoding) = self.content_encoding() else {
+            return false;
+        };
+        let mut wildcard = false;
+        for entry in accept_encoding.split(',') {
+            let mut params = entry.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let refused = params.any(|param| {
+                param.strip_prefix("q=").is_some_and(|q| q.parse::<f32>().is_ok_and(|q| q == 0.0))
+            });
+            if name.eq_ignore_ascii_case(encoding) {
+                return !refused;
+            }
+            if name == "*" {
+                wildcard = !refused;
+            }
+        }
+        wildcard
+    }
+
+    /// Compresses everything read from `data` into `writer`
+    pub fn compress(self, mut data: impl Read, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Compression::None => {
+                io::copy(&mut data, writer)?;
+            }
+            Compression::Zstd(level) => zstd::stream::copy_encode(data, writer, level)?,
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+                io::copy(&mut data, &mut encoder)?;
+                encoder.finish().map_err(io::Error::other)?;
+            }
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                io::copy(&mut data, &mut encoder)?;
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wraps a reader over compressed data in one yielding the original bytes
+    pub fn decoder<R: Read + Send + 'static>(self, reader: R) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Zstd(_) => Box::new(zstd::Decoder::new(reader)?),
+            Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+            Compression::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = "all work and no play ".repeat(1000).into_bytes();
+        for compression in [Compression::None, Compression::Zstd(3), Compression::Lz4, Compression::Gzip] {
+            let mut compressed = Vec::new();
+            compression.compress(&data[..], &mut compressed).unwrap();
+            if compression.is_compressed() {
+                assert!(compressed.len() < data.len() / 10, "{:?}", compression);
+            }
+
+            let mut decoded = Vec::new();
+            compression.decoder(io::Cursor::new(compressed)).unwrap().read_to_end(&mut decoded).unwrap();
+            assert_eq!(decoded, data, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn test_accepted_by() {
+        assert!(Compression::Gzip.accepted_by("gzip"));
+        assert!(Compression::Gzip.accepted_by("deflate, GZIP;q=0.5, br"));
+        assert!(Compression::Zstd(3).accepted_by("br, zstd"));
+        assert!(Compression::Zstd(3).accepted_by("*"));
+        assert!(!Compression::Gzip.accepted_by("gzip;q=0"));
+        assert!(!Compression::Gzip.accepted_by("*, gzip;q=0"));
+        assert!(!Compression::Gzip.accepted_by("identity"));
+        assert!(!Compression::Gzip.accepted_by(""));
+        assert!(!Compression::Lz4.accepted_by("*"));
+        assert!(!Compression::None.accepted_by("gzip"));
+    }
+}
@@ -0,0 +1,58 @@
+use std::io::{Cursor, Read};
+use crate::Result;
+
+/// Number of leading bytes examined when inferring a content type
+const SNIFF_LEN: usize = 8192;
+
+/// Guesses a content type from the leading bytes of a blob: magic numbers
+/// first, then JSON for text that opens like a JSON document
+pub fn infer_content_type(head: &[u8]) -> Option<String> {
+    if let Some(kind) = infer::get(head) {
+        return Some(kind.mime_type().to_string());
+    }
+
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // The sniff window may split a multi-byte character
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    match text.trim_start().chars().next() {
+        Some('{') | Some('[') => Some("application/json".to_string()),
+        _ => None,
+    }
+}
+
+/// Reads just the sniff window from `data` and infers its content type,
+/// returning a reader that replays the buffered bytes before the rest of the stream
+pub fn sniff<R: Read>(mut data: R) -> Result<(Option<String>, impl Read)> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    (&mut data).take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+    let content_type = infer_content_type(&head);
+    Ok((content_type, Cursor::new(head).chain(data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_replays_stream() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let (content_type, mut reader) = sniff(&png[..]).unwrap();
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, png);
+
+        assert_eq!(infer_content_type(b"  {\"a\": 1}").as_deref(), Some("application/json"));
+        assert_eq!(infer_content_type(b"plain text"), None);
+
+        // Data longer than the sniff window is passed through intact
+        let long = vec![b'x'; SNIFF_LEN * 2 + 1];
+        let (_, mut reader) = sniff(&long[..]).unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, long);
+    }
+}
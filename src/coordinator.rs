@@ -1,157 +1,6874 @@
-use std::path::PathBuf;
-use std::io::Read;
-use chrono::Utc;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::num::NonZeroUsize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use lru::LruCache;
 
 use crate::{
-    BlobId, TenantId, BlobMetadata, Result,
-    chunk_store::ChunkStore,
-    metadata::MetadataStore,
-    tenant::TenantManager,
+    BlobId, TenantId, BlobMetadata, Result, MAX_TENANT_KEY_LEN,
+    bloom::BloomFilter,
+    cancel::CancellationToken,
+    checksum::{self, Checksum},
+    clock::{Clock, SystemClock},
+    content_type,
+    compression::Compression,
+    chunk_store::{BlobInfo, BlobReader, ChunkStore, FileMode, RangeReader, Tier},
+    error::{Error, ResultExt},
+    layout::{self, StoreLayout},
+    limiter::{ByteBudget, ConcurrencyLimiter, OverloadPolicy, Permit, Reservation},
+    metadata::{ChecksumRecord, MetadataField, MetadataStore, PartialMetadata, SizeRecord, Tombstone},
+    pack_store::{PackStore, DEFAULT_MAX_PACK_SIZE},
+    tags::Tags,
+    tenant::{TenantCharset, TenantConfig, TenantManager, TenantNamePolicy, TenantOrder},
+    transform::TransformReader,
 };
 
+/// Number of metadata records sampled to detect a store's checksum algorithm
+const ALGORITHM_SAMPLE_SIZE: usize = 100;
+
+/// Bytes a put without a size hint reserves in the in-flight upload budget
+pub const UNSIZED_UPLOAD_RESERVATION: u64 = 64 << 20;
+
+/// Supplies a blob's expected checksum once its data has been read
+pub(crate) type TrailerChecksum = Box<dyn FnOnce() -> Result<Checksum>>;
+
+/// Optional settings applied when storing a blob
+#[derive(Default)]
+pub(crate) struct PutOptions {
+    pub expected_checksum: Option<Checksum>,
+    /// Called for the expected checksum after the data has been read, for
+    /// protocols that send it after the body. Takes precedence over
+    /// `expected_checksum`.
+    pub trailer_checksum: Option<TrailerChecksum>,
+    pub tags: Tags,
+    /// Store under this id instead of minting a new one
+    pub blob_id: Option<BlobId>,
+    /// Preserve an existing creation time, e.g. when restoring an export
+    pub created_at: Option<DateTime<Utc>>,
+    /// Content type to record; inferred from the data when absent
+    pub content_type: Option<String>,
+    /// Keep the blob undeletable until this time
+    pub retain_until: Option<DateTime<Utc>>,
+    /// Treat the blob as gone after this time
+    pub expires_at: Option<DateTime<Utc>>,
+    /// What to do if `blob_id` is already taken
+    pub on_conflict: ConflictPolicy,
+    /// Size the data is expected to have. Chunk files are preallocated to
+    /// it, and data of any other size is rejected.
+    pub size_hint: Option<u64>,
+    pub extensions: BTreeMap<String, serde_json::Value>,
+    /// The caller already holds `lock_upload` for `blob_id`
+    pub upload_locked: bool,
+}
+
+/// What `write_blob_data` did
+enum Written {
+    /// The blob id was taken and the conflict policy kept the existing blob
+    Existing(BlobId, BlobInfo),
+    /// The data was stored; its metadata still has to be committed
+    New(Box<BlobMetadata>),
+    /// The data to overwrite an existing blob was stored and verified; the
+    /// existing blob is untouched until `commit_replacement`
+    Replacement(Box<Replacement>),
+}
+
+/// An overwrite staged by `write_blob_data`
+struct Replacement {
+    /// The new record
+    metadata: BlobMetadata,
+    /// The record being replaced
+    old: BlobMetadata,
+    /// Id the new data was stored under, or `None` if it went to a pack
+    staged: Option<BlobId>,
+}
+
+/// Blobs `bulk_put` commits the metadata of in one transaction
+const BULK_COMMIT_SIZE: usize = 256;
+
+/// Number of locks uploads are spread over by `Coordinator::lock_upload`
+const UPLOAD_LOCK_STRIPES: usize = 64;
+
+/// Most threads a `BlobScan` reads ahead with
+const SCAN_WORKERS: usize = 4;
+
+/// Bytes of each upcoming blob a `BlobScan` reads ahead; the rest is read
+/// when the caller gets to it
+const SCAN_CHUNK_SIZE: u64 = 1 << 20;
+
+/// When a blob's content is checked against its recorded checksum on read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Hash the whole blob before returning a reader, so corrupt data is
+    /// never handed out
+    #[default]
+    Eager,
+    /// Return a reader at once and hash as it is read; a mismatch fails the
+    /// final read. Lowers time to first byte for streaming.
+    Lazy,
+    /// Don't check the content at all, only the stored length
+    None,
+}
+
+/// Which record of a chunk-stored blob's checksum reads verify against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumSource {
+    /// Read both and fail with `ChecksumSourceDisagreement` if they differ,
+    /// so a stale record is surfaced rather than trusted
+    #[default]
+    CrossCheck,
+    /// The `.chk` sidecar, falling back to metadata for a blob without one
+    Sidecar,
+    /// The checksum in the blob's metadata, ignoring any sidecar
+    Metadata,
+}
+
+/// What reads do with a blob whose metadata records an empty checksum, as
+/// left by a crash between writing its data and its checksum
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingChecksumPolicy {
+    /// Fail with `MissingChecksum`
+    #[default]
+    Reject,
+    /// Log a warning and return the data checked against its size only
+    SkipVerification,
+}
+
+/// How thoroughly a store is checked when it is opened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CheckLevel {
+    /// Skip the check
+    #[default]
+    None,
+    /// Check that every blob's data is present at its recorded size,
+    /// without reading it
+    Quick,
+    /// Verify every blob's content against its checksum, like `verify_all`
+    Full,
+}
+
+/// What storing a blob under a caller-chosen id does when that id is taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail with `BlobAlreadyExists`
+    #[default]
+    Error,
+    /// Delete the existing blob, then store the new data under its id. Fails
+    /// with `RetentionActive` if the existing blob is under retention.
+    Overwrite,
+    /// Keep the existing blob and return its id without reading the data
+    Skip,
+}
+
+/// What `replace_named_blob` does with the blob a name pointed at before
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplacePolicy {
+    /// Delete it once the name points at the new blob. Other names linked
+    /// to it stop resolving; one under retention is kept.
+    #[default]
+    DeleteOld,
+    /// Leave it stored, reachable by its id and any other names
+    KeepOld,
+}
+
+/// Collects the settings for a new blob, obtained from `Coordinator::put`.
+/// Each option can be combined with the others; `store` uploads the data.
+pub struct PutBuilder<'a> {
+    coordinator: &'a Coordinator,
+    tenant_id: TenantId,
+    options: PutOptions,
+    expected_checksum: Option<String>,
+    ttl: Option<chrono::Duration>,
+}
+
+impl PutBuilder<'_> {
+    /// Labels the blob with the given tags
+    pub fn tags(mut self, tags: Tags) -> Self {
+        self.options.tags = tags;
+        self
+    }
+
+    /// Records an explicit content type instead of an inferred one
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.options.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// Makes the blob expire `ttl` after it is stored
+    pub fn ttl(mut self, ttl: chrono::Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Keeps the blob undeletable until `retain_until`
+    pub fn retain_until(mut self, retain_until: DateTime<Utc>) -> Self {
+        self.options.retain_until = Some(retain_until);
+        self
+    }
+
+    /// Stores the blob under a caller-chosen id, failing with
+    /// `BlobAlreadyExists` if that id is taken unless `on_conflict` says otherwise
+    pub fn id(mut self, blob_id: BlobId) -> Self {
+        self.options.blob_id = Some(blob_id);
+        self
+    }
+
+    /// Sets what happens if the id given to `id` is already taken
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.options.on_conflict = policy;
+        self
+    }
+
+    /// Attaches an arbitrary JSON value under `key`, read back from the
+    /// blob's metadata as is
+    pub fn extension(mut self, key: &str, value: serde_json::Value) -> Self {
+        self.options.extensions.insert(key.to_string(), value);
+        self
+    }
+
+    /// Rejects the data unless its checksum matches (bare hex or `<algo>:<hex>`)
+    pub fn expected_checksum(mut self, expected_checksum: &str) -> Self {
+        self.expected_checksum = Some(expected_checksum.to_string());
+        self
+    }
+
+    /// Stores the blob with the collected settings
+    pub fn store(mut self, data: impl Read) -> Result<BlobId> {
+        let context = || format!("while putting blob for tenant {}", self.tenant_id.as_str());
+        if let Some(expected) = &self.expected_checksum {
+            self.options.expected_checksum = Some(checksum::parse_checksum(expected).with_context(context)?);
+        }
+        self.options.expires_at = self.ttl.map(|ttl| self.coordinator.now() + ttl);
+        self.coordinator
+            .store_blob(&self.tenant_id, data, self.options)
+            .with_context(context)
+    }
+}
+
+/// An upload whose blob id has been reserved by `Coordinator::begin_put` but
+/// whose data hasn't been stored yet
+pub struct PendingBlob<'a> {
+    coordinator: &'a Coordinator,
+    tenant_id: TenantId,
+    blob_id: BlobId,
+}
+
+impl PendingBlob<'_> {
+    /// The id the blob will be stored under
+    pub fn blob_id(&self) -> &BlobId {
+        &self.blob_id
+    }
+
+    /// Streams the data and commits the blob, making it listable
+    pub fn finish(self, data: impl Read) -> Result<BlobId> {
+        let context = || {
+            format!("while finishing blob {} for tenant {}", self.blob_id, self.tenant_id.as_str())
+        };
+        let _upload = self.coordinator.lock_upload(&self.blob_id);
+        let store = &self.coordinator.metadata_store;
+        if store.get_pending(&self.blob_id).with_context(context)?.as_ref() != Some(&self.tenant_id) {
+            return Err(Error::BlobNotFound(self.blob_id.to_string())).with_context(context);
+        }
+
+        let options = PutOptions { blob_id: Some(self.blob_id.clone()), upload_locked: true, ..Default::default() };
+        let result = self.coordinator.store_blob(&self.tenant_id, data, options);
+        store.remove_pending(&self.blob_id).with_context(context)?;
+        result.with_context(context)
+    }
+
+    /// Cancels the upload, releasing the reserved id
+    pub fn abort(self) -> Result<()> {
+        self.coordinator.cancel_put(&self.tenant_id, &self.blob_id)
+    }
+}
+
+/// Outcome of a full store verification
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Number of blobs checked before finishing or being cancelled
+    pub checked: usize,
+    /// Blobs that failed verification, with the reason
+    pub corrupted: Vec<(BlobId, Error)>,
+    /// True if verification stopped early because it was cancelled
+    pub cancelled: bool,
+}
+
+/// Outcome of re-checksumming the store with `Coordinator::rehash_all`
+#[derive(Debug, Default)]
+pub struct RehashReport {
+    /// Blobs whose checksum was recomputed under the new algorithm
+    pub rehashed: usize,
+    /// Blobs already using the new algorithm, e.g. from an earlier run, or
+    /// without local data to hash
+    pub skipped: usize,
+    /// Blobs left unchanged because their data didn't match their current
+    /// checksum or couldn't be read, with the reason
+    pub failed: Vec<(BlobId, Error)>,
+    /// True if the run stopped early because it was cancelled
+    pub cancelled: bool,
+}
+
+/// Outcome and throughput of a `Coordinator::bulk_put`
+#[derive(Debug, Default)]
+pub struct BulkReport {
+    /// Blobs stored, in the order their items were given
+    pub stored: Vec<BlobId>,
+    /// Total size of the stored blobs
+    pub bytes: u64,
+    /// Wall time of the whole ingest, failed items included
+    pub elapsed: Duration,
+    /// Items that weren't stored, by their position in the input, with the reason
+    pub failed: Vec<(usize, Error)>,
+}
+
+impl BulkReport {
+    /// Ingest throughput in bytes per second
+    pub fn bytes_per_sec(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Disagreements between the metadata db, the tenant registry and the
+/// stored data, found by `Coordinator::check_consistency`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    /// Blobs whose metadata exists but whose data is gone
+    pub missing_chunks: Vec<BlobId>,
+    /// Chunk files that no metadata points at
+    pub orphan_chunks: Vec<BlobId>,
+    /// Blobs whose owning tenant isn't registered
+    pub unregistered_tenants: Vec<(BlobId, TenantId)>,
+    /// Tenant membership entries whose blob has no metadata
+    pub dangling_members: Vec<(TenantId, BlobId)>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A committed change to one of a tenant's blobs, delivered by
+/// `Coordinator::subscribe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// A blob was stored, or moved in from another tenant
+    Put { blob_id: BlobId },
+    /// A blob was deleted, or moved out to another tenant
+    Delete { blob_id: BlobId },
+}
+
+/// Metadata db size before and after a compaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactReport {
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+}
+
+/// Which phases `Coordinator::optimize` runs. Every phase is on by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// Delete expired blobs, orphan chunk files and dangling memberships
+    pub gc: bool,
+    /// Flush the metadata db so sled can reclaim dead log segments
+    pub compact_metadata: bool,
+    /// Rewrite packs to reclaim the space of deleted packed blobs
+    pub compact_packs: bool,
+    /// Chunk files without metadata younger than this are left alone, as
+    /// they may belong to a put that hasn't committed yet
+    pub orphan_grace: Duration,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            gc: true,
+            compact_metadata: true,
+            compact_packs: true,
+            orphan_grace: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// What the garbage collection phase of `Coordinator::optimize` deleted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcReport {
+    pub expired_blobs: usize,
+    pub orphan_chunks: usize,
+    pub dangling_members: usize,
+}
+
+/// What each phase of `Coordinator::optimize` did. Phases that were
+/// skipped, or not reached before cancellation, are `None`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeReport {
+    pub gc: Option<GcReport>,
+    pub metadata: Option<CompactReport>,
+    /// Pack bytes reclaimed
+    pub packs: Option<u64>,
+    /// True if the run stopped early because it was cancelled
+    pub cancelled: bool,
+}
+
+/// The first chunk of a blob read ahead, with the reader for the rest
+type ReadAhead = std::io::Result<(Vec<u8>, BlobReader)>;
+
+/// A blob whose first chunk is being read ahead of the scan by a worker
+type Prefetch = (BlobMetadata, Receiver<ReadAhead>);
+
+/// A blob for a `BlobScan` worker to read the first chunk of
+type ScanJob = (BlobReader, Sender<ReadAhead>);
+
+/// Iterator over a tenant's blobs and their data, returned by
+/// `Coordinator::scan_blobs`. The first chunk of up to `readahead` blobs
+/// past the one last yielded is read by a small pool of worker threads;
+/// nothing further is touched.
+pub struct BlobScan<'a> {
+    coordinator: &'a Coordinator,
+    tenant_id: TenantId,
+    blobs: Box<dyn Iterator<Item = Result<BlobMetadata>> + 'a>,
+    readahead: usize,
+    /// Blobs read ahead, or the errors to yield in their place
+    pending: VecDeque<Result<Prefetch>>,
+    /// Hands blobs to the workers, which exit once it is dropped
+    jobs: Option<Sender<ScanJob>>,
+}
+
+impl BlobScan<'_> {
+    /// Returns the next live blob's metadata
+    fn next_metadata(&mut self) -> Option<Result<BlobMetadata>> {
+        let now = self.coordinator.now();
+        self.blobs
+            .by_ref()
+            .find(|metadata| metadata.as_ref().map_or(true, |metadata| !metadata.is_expired_at(now)))
+    }
+
+    /// Opens a blob's data the way `get_blob` would
+    fn open(&self, metadata: &BlobMetadata) -> Result<BlobReader> {
+        let _pack_guard = self.coordinator.pack_lock.read().unwrap();
+        self.coordinator.read_stored_with(metadata, self.coordinator.verify_mode)
+    }
+
+    /// Starts the worker threads, each reading the first chunk of one blob
+    /// at a time
+    fn start_workers(&mut self) {
+        let (jobs, queue) = mpsc::channel::<ScanJob>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..self.readahead.min(SCAN_WORKERS) {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap().recv();
+                let Ok((mut reader, done)) = job else {
+                    return;
+                };
+                let mut chunk = Vec::new();
+                let read = reader.by_ref().take(SCAN_CHUNK_SIZE).read_to_end(&mut chunk);
+                let _ = done.send(read.map(|_| (chunk, reader)));
+            });
+        }
+        self.jobs = Some(jobs);
+    }
+
+    /// Starts reading blobs until `readahead` are in flight
+    fn fill(&mut self) {
+        while self.pending.len() < self.readahead {
+            let Some(metadata) = self.next_metadata() else {
+                return;
+            };
+            if self.jobs.is_none() {
+                self.start_workers();
+            }
+            let prefetch = metadata.and_then(|metadata| {
+                let reader = self.open(&metadata)?;
+                let (done, read_ahead) = mpsc::channel();
+                if let Some(jobs) = &self.jobs {
+                    let _ = jobs.send((reader, done));
+                }
+                Ok((metadata, read_ahead))
+            });
+            self.pending.push_back(prefetch);
+        }
+    }
+
+    fn next_blob(&mut self) -> Option<Result<(BlobMetadata, BlobReader)>> {
+        if self.readahead == 0 {
+            return self
+                .next_metadata()
+                .map(|metadata| metadata.and_then(|metadata| Ok((self.open(&metadata)?, metadata))))
+                .map(|result| result.map(|(reader, metadata)| (metadata, reader)));
+        }
+
+        self.fill();
+        let prefetch = self.pending.pop_front()?;
+        // Keep the window full while the caller works on this blob
+        self.fill();
+        let (metadata, read_ahead) = match prefetch {
+            Ok(prefetch) => prefetch,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(match read_ahead.recv() {
+            Ok(Ok((chunk, rest))) => Ok((metadata, BlobReader::new(std::io::Cursor::new(chunk).chain(rest)))),
+            Ok(Err(e)) if e.get_ref().is_some_and(|inner| inner.is::<Error>()) => {
+                Err(*e.into_inner().unwrap().downcast::<Error>().unwrap())
+            }
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(Error::System(format!("read-ahead of blob {} panicked", metadata.blob_id))),
+        })
+    }
+}
+
+impl Iterator for BlobScan<'_> {
+    type Item = Result<(BlobMetadata, BlobReader)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.next_blob()?;
+        if let Ok((metadata, _)) = &item {
+            self.coordinator.record_access(&metadata.blob_id);
+        }
+        Some(item.with_context(|| format!("while scanning blobs of tenant {}", self.tenant_id.as_str())))
+    }
+}
+
+/// Where a blob's data lives on disk, for tools that stat, copy or back up
+/// the underlying files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobLocation {
+    /// The blob has its own file, with a checksum sidecar unless sidecars
+    /// are disabled
+    File {
+        blob_path: PathBuf,
+        checksum_path: Option<PathBuf>,
+    },
+    /// The blob is a byte range of a shared pack file; its checksum is only
+    /// kept in metadata
+    Packed {
+        pack_path: PathBuf,
+        offset: u64,
+        length: u64,
+    },
+}
+
 pub struct Coordinator {
+    root_dir: PathBuf,
+    store_layout: StoreLayout,
     chunk_store: ChunkStore,
     metadata_store: MetadataStore,
     tenant_manager: TenantManager,
+    pack_store: PackStore,
+    /// Whether new blobs are appended to pack files instead of their own files
+    use_packs: bool,
+    /// Held exclusively while compaction rewrites packs, so pack blobs are
+    /// never read or written against a pack that is being removed
+    pack_lock: RwLock<()>,
+    bloom: Option<Mutex<BloomFilter>>,
+    /// Recently read blob metadata. Misses are filled while holding the lock
+    /// and mutations invalidate after writing, so an entry is never stale.
+    metadata_cache: Option<Mutex<LruCache<BlobId, BlobMetadata>>>,
+    admin: bool,
+    min_free_bytes: Option<u64>,
+    max_tenants: Option<usize>,
+    max_blobs_per_tenant: Option<usize>,
+    /// Longest tenant name accepted as is, and what happens to longer ones
+    max_tenant_name_len: usize,
+    tenant_name_policy: TenantNamePolicy,
+    /// How long an idempotency key keeps returning the blob it first created
+    idempotency_window: chrono::Duration,
+    /// How long a deleted blob's tombstone blocks its id, if tombstones are kept
+    tombstone_ttl: Option<chrono::Duration>,
+    /// Puts, gets and deletes taking longer than this are logged as warnings
+    slow_op_threshold: Option<Duration>,
+    /// How new chunk-stored blobs are compressed
+    compression: Compression,
+    /// Chunk size new chunk-stored blobs record per-chunk checksums at, if any
+    chunk_checksum_size: Option<u64>,
+    /// Algorithm new blobs' primary checksums are computed with
+    checksum_algorithm: checksum::ChecksumAlgorithm,
+    /// Extra digests computed for every new blob
+    checksum_algorithms: BTreeSet<checksum::ChecksumAlgorithm>,
+    /// Whether `copy_blob` hard-links chunk files instead of copying them
+    link_copies: bool,
+    /// Whether reads record each blob's last access time
+    track_access: bool,
+    /// How `get_blob` verifies blob content
+    verify_mode: VerifyMode,
+    /// What `replace_named_blob` does with the replaced blob
+    replace_policy: ReplacePolicy,
+    /// Which checksum record reads of chunk-stored blobs trust
+    checksum_source: ChecksumSource,
+    /// How reads treat blobs recorded without a checksum
+    missing_checksum: MissingChecksumPolicy,
+    /// Source of the current time for expiry, retention and timestamps
+    clock: Arc<dyn Clock>,
+    /// Change feed subscribers and the tenant each one follows
+    subscribers: Mutex<Vec<(TenantId, Sender<ChangeEvent>)>>,
+    /// Striped locks serializing `PendingBlob::finish` with `cancel_put`
+    /// for the same upload
+    upload_locks: Vec<Mutex<()>>,
+    /// Bounds how many puts, deletes and moves run at once, if configured
+    limiter: Option<ConcurrencyLimiter>,
+    /// Bounds the bytes of uploads in progress at once, if configured
+    upload_budget: Option<ByteBudget>,
+    /// Runs while a mutating operation holds its concurrency slot, so tests
+    /// can observe how many run at once
+    #[cfg(test)]
+    while_limited: Option<fn()>,
+    /// Scratch directory of an `in_memory` coordinator, removed on drop.
+    /// Declared last so the stores using it are dropped first.
+    scratch_dir: Option<tempfile::TempDir>,
 }
 
 impl Coordinator {
     pub fn new(root_dir: impl Into<PathBuf>) -> Result<Self> {
+        Self::with_layout(root_dir, StoreLayout::default())
+    }
+
+    /// Opens a store whose subdirectories use the names in `store_layout`
+    /// instead of the defaults. A store must always be reopened with the
+    /// same layout.
+    pub fn with_layout(root_dir: impl Into<PathBuf>, store_layout: StoreLayout) -> Result<Self> {
+        let root_dir = root_dir.into();
+        layout::check(&root_dir)?;
+        let chunk_store = ChunkStore::with_dir_name(&root_dir, &store_layout.chunks_dir)?;
+        let metadata_store = MetadataStore::new(root_dir.join(&store_layout.metadata_dir))?;
+        let tenant_manager = TenantManager::with_dir_name(&root_dir, &store_layout.tenants_dir)?;
+        Self::from_parts(root_dir, store_layout, chunk_store, metadata_store, tenant_manager)
+    }
+
+    /// Opens a store keeping chunk and pack files under `chunk_root` and its
+    /// metadata and tenant databases at separate paths, e.g. to put the
+    /// databases on faster storage than the blob data
+    pub fn with_paths(
+        chunk_root: impl Into<PathBuf>,
+        metadata_path: impl AsRef<Path>,
+        tenant_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let root_dir = chunk_root.into();
+        let store_layout = StoreLayout::default();
+        layout::check(&root_dir)?;
+        let chunk_store = ChunkStore::with_dir_name(&root_dir, &store_layout.chunks_dir)?;
+        let metadata_store = MetadataStore::new(metadata_path)?;
+        let tenant_manager = TenantManager::open(tenant_path)?;
+        Self::from_parts(root_dir, store_layout, chunk_store, metadata_store, tenant_manager)
+    }
+
+    /// Opens an existing store for reading only, e.g. on a read-only mount
+    /// or a snapshot. Every write, to blob files and metadata alike, fails
+    /// with `ReadOnly`. The dbs are opened in place, so this fails while a
+    /// writer has the store open; on a read-only filesystem, where nothing
+    /// can be writing, private copies of them are read instead.
+    pub fn open_read_only(root_dir: impl Into<PathBuf>) -> Result<Self> {
+        let root_dir = root_dir.into();
+        let store_layout = StoreLayout::default();
+        let context = || format!("while opening {} read-only", root_dir.display());
+        let metadata_dir = root_dir.join(&store_layout.metadata_dir);
+        if !metadata_dir.is_dir() {
+            return Err(Error::System(format!("No store found at {}", root_dir.display()))).with_context(context);
+        }
+        layout::verify(&root_dir).with_context(context)?;
+
+        let chunk_store = ChunkStore::open_read_only_with_dir_name(&root_dir, &store_layout.chunks_dir)?;
+        let metadata_store = MetadataStore::open_read_only(&metadata_dir).with_context(context)?;
+        let tenant_manager =
+            TenantManager::open_read_only(root_dir.join(&store_layout.tenants_dir)).with_context(context)?;
+        let mut coordinator = Self::from_parts(root_dir, store_layout, chunk_store, metadata_store, tenant_manager)?;
+        coordinator.pack_store = coordinator.pack_store.into_read_only();
+        Ok(coordinator)
+    }
+
+    /// Opens a store like `new`, falling back to `open_read_only` if it is on
+    /// a read-only filesystem but already holds data
+    pub fn new_or_read_only(root_dir: impl Into<PathBuf>) -> Result<Self> {
         let root_dir = root_dir.into();
-        let chunk_store = ChunkStore::new(&root_dir)?;
-        let metadata_store = MetadataStore::new(root_dir.join("metadata"))?;
-        let tenant_manager = TenantManager::new(&root_dir)?;
+        match Self::new(&root_dir) {
+            Err(e)
+                if matches!(e.root_cause(), Error::ReadOnlyFilesystem(_))
+                    && root_dir.join(StoreLayout::default().metadata_dir).is_dir() =>
+            {
+                tracing::warn!(path = %root_dir.display(), "storage is on a read-only filesystem, opening it read-only");
+                Self::open_read_only(root_dir)
+            }
+            result => result,
+        }
+    }
+
+    /// Opens a throwaway store for tests: the metadata and tenant dbs are
+    /// sled temporary dbs and blob files go to a scratch directory. Nothing
+    /// is left behind once the coordinator is dropped.
+    pub fn in_memory() -> Result<Self> {
+        let scratch_dir = tempfile::tempdir()?;
+        let root_dir = scratch_dir.path().to_path_buf();
+        let store_layout = StoreLayout::default();
+        let chunk_store = ChunkStore::with_dir_name(&root_dir, &store_layout.chunks_dir)?;
+        let metadata_store = MetadataStore::temporary()?;
+        let tenant_manager = TenantManager::temporary()?;
+        let mut coordinator = Self::from_parts(root_dir, store_layout, chunk_store, metadata_store, tenant_manager)?;
+        coordinator.scratch_dir = Some(scratch_dir);
+        Ok(coordinator)
+    }
 
-        Ok(Self {
+    fn from_parts(
+        root_dir: PathBuf,
+        store_layout: StoreLayout,
+        chunk_store: ChunkStore,
+        metadata_store: MetadataStore,
+        tenant_manager: TenantManager,
+    ) -> Result<Self> {
+        let checksum_algorithm = metadata_store.checksum_algorithm()?;
+        let chunk_store = chunk_store.with_checksum_algorithm(checksum_algorithm);
+        let pack_store = PackStore::with_dir_name(&root_dir, &store_layout.packs_dir, DEFAULT_MAX_PACK_SIZE)?
+            .with_checksum_algorithm(checksum_algorithm);
+
+        let coordinator = Self {
+            root_dir,
+            store_layout,
             chunk_store,
             metadata_store,
             tenant_manager,
-        })
+            pack_store,
+            use_packs: false,
+            pack_lock: RwLock::new(()),
+            bloom: None,
+            metadata_cache: None,
+            admin: false,
+            min_free_bytes: None,
+            max_tenants: None,
+            max_blobs_per_tenant: None,
+            max_tenant_name_len: MAX_TENANT_KEY_LEN,
+            tenant_name_policy: TenantNamePolicy::Reject,
+            idempotency_window: chrono::Duration::hours(24),
+            tombstone_ttl: None,
+            slow_op_threshold: None,
+            compression: Compression::None,
+            chunk_checksum_size: None,
+            checksum_algorithm,
+            checksum_algorithms: BTreeSet::new(),
+            link_copies: false,
+            track_access: false,
+            verify_mode: VerifyMode::Eager,
+            replace_policy: ReplacePolicy::DeleteOld,
+            checksum_source: ChecksumSource::CrossCheck,
+            missing_checksum: MissingChecksumPolicy::Reject,
+            clock: Arc::new(SystemClock),
+            subscribers: Mutex::new(Vec::new()),
+            upload_locks: (0..UPLOAD_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+            limiter: None,
+            upload_budget: None,
+            #[cfg(test)]
+            while_limited: None,
+            scratch_dir: None,
+        };
+        if let Some(found) = coordinator.checksum_algorithm_mismatch()? {
+            tracing::warn!(
+                found,
+                configured = checksum_algorithm.name(),
+                "existing blobs use a different checksum algorithm than new writes will"
+            );
+        }
+        if coordinator.chunk_store.check_writable().is_ok() {
+            coordinator.finish_replacements()?;
+        }
+        Ok(coordinator)
     }
 
-    /// Registers a new tenant
-    pub fn register_tenant(&self, tenant_id: TenantId) -> Result<()> {
-        self.tenant_manager.register_tenant(tenant_id)
+    /// Returns the checksum algorithm most of a sample of existing blobs were
+    /// written with, or `None` for an empty store
+    pub fn detect_checksum_algorithm(&self) -> Result<Option<String>> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for metadata in self.metadata_store.sample_metadata(ALGORITHM_SAMPLE_SIZE)? {
+            *counts.entry(metadata.checksum.algorithm().to_string()).or_default() += 1;
+        }
+        Ok(counts.into_iter().max_by_key(|(_, count)| *count).map(|(algorithm, _)| algorithm))
     }
 
-    /// Lists all registered tenants
-    pub fn list_tenants(&self) -> Result<Vec<TenantId>> {
-        self.tenant_manager.list_tenants()
+    /// Returns the detected algorithm if it differs from the one new blobs use
+    fn checksum_algorithm_mismatch(&self) -> Result<Option<String>> {
+        Ok(self.detect_checksum_algorithm()?.filter(|found| found != self.checksum_algorithm.name()))
     }
 
-    /// Stores a new blob
-    pub fn put_blob(&self, tenant_id: &TenantId, data: impl Read) -> Result<BlobId> {
-        // Validate tenant
-        self.tenant_manager.validate_tenant(tenant_id)?;
+    /// Refuses to open a store whose existing blobs predominantly use a
+    /// different checksum algorithm than new writes would, instead of only
+    /// logging a warning
+    pub fn with_strict_checksum_algorithm(self) -> Result<Self> {
+        match self.checksum_algorithm_mismatch()? {
+            Some(found) => Err(Error::ChecksumAlgorithmMismatch {
+                found,
+                configured: self.checksum_algorithm.name().to_string(),
+            }),
+            None => Ok(self),
+        }
+    }
 
-        // Generate new blob ID
-        let blob_id = BlobId::new();
+    /// Checks the store at `level` and logs a summary, with a warning per
+    /// damaged blob. Problems are only logged; see `with_strict_startup_check`
+    /// to refuse to open a damaged store.
+    pub fn with_startup_check(self, level: CheckLevel) -> Result<Self> {
+        self.startup_check(level)?;
+        Ok(self)
+    }
 
-        // Store the blob and get its info
-        let blob_info = self.chunk_store.put_blob(&blob_id, data)?;
+    /// Like `with_startup_check`, but fails with `StartupCheckFailed` if any
+    /// blob is damaged
+    pub fn with_strict_startup_check(self, level: CheckLevel) -> Result<Self> {
+        let report = self.startup_check(level)?;
+        if !report.corrupted.is_empty() {
+            return Err(Error::StartupCheckFailed { damaged: report.corrupted.len() });
+        }
+        Ok(self)
+    }
 
-        // Create and store metadata
-        let metadata = BlobMetadata {
-            blob_id: blob_id.clone(),
-            tenant_id: tenant_id.clone(),
-            size: blob_info.size,
-            checksum: blob_info.checksum,
-            created_at: Utc::now(),
+    /// Deletes temp files left behind by puts that crashed before their
+    /// data was persisted, if not modified within `grace`, logging each one
+    /// removed. Without this they accumulate across crashes.
+    pub fn with_temp_cleanup(self, grace: Duration) -> Result<Self> {
+        let removed = self
+            .chunk_store
+            .remove_stale_temp_files(grace)
+            .with_context(|| "while removing stale temp files".to_string())?;
+        for path in &removed {
+            tracing::info!(path = %path.display(), "removed stale temp file");
+        }
+        if !removed.is_empty() {
+            tracing::info!(removed = removed.len(), "temp file cleanup finished");
+        }
+        Ok(self)
+    }
+
+    /// Runs a startup check and logs its outcome
+    fn startup_check(&self, level: CheckLevel) -> Result<VerifyReport> {
+        let report = match level {
+            CheckLevel::None => return Ok(VerifyReport::default()),
+            CheckLevel::Quick => self.quick_check()?,
+            CheckLevel::Full => self.verify_all(&CancellationToken::new())?,
         };
-        self.metadata_store.put_metadata(&metadata)?;
+        for (blob_id, error) in &report.corrupted {
+            tracing::warn!(blob_id = %blob_id, %error, "startup check found a damaged blob");
+        }
+        tracing::info!(
+            level = ?level,
+            checked = report.checked,
+            damaged = report.corrupted.len(),
+            "startup check finished"
+        );
+        Ok(report)
+    }
 
-        Ok(blob_id)
+    /// Checks that every blob's stored data exists at the size its metadata
+    /// records, without reading the data
+    fn quick_check(&self) -> Result<VerifyReport> {
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let mut report = VerifyReport::default();
+        for blob_id in self.metadata_store.all_blob_ids()? {
+            let metadata = match self.metadata_store.get_metadata(&blob_id) {
+                Ok(metadata) => metadata,
+                Err(Error::BlobNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            report.checked += 1;
+            if metadata.metadata_only {
+                continue;
+            }
+            let (expected, stored) = match &metadata.pack {
+                Some(location) => (metadata.size, self.pack_store.stored_size(location)),
+                None => (
+                    metadata.compressed_size.unwrap_or(metadata.size),
+                    self.chunk_store.blob_size_in(&blob_id, metadata.tier),
+                ),
+            };
+            let problem = match stored {
+                Ok(actual) if actual == expected => continue,
+                Ok(actual) => Error::SizeMismatch { expected, actual },
+                Err(Error::BlobNotFound(_)) => Error::ChunkMissing { blob_id: blob_id.to_string() },
+                Err(e) => return Err(e),
+            };
+            report.corrupted.push((blob_id, problem));
+        }
+        Ok(report)
     }
 
-    /// Retrieves a blob
-    pub fn get_blob(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<impl Read> {
-        // Validate tenant
-        self.tenant_manager.validate_tenant(tenant_id)?;
+    /// Rejects new blobs once free space on the storage filesystem drops below `bytes`
+    pub fn with_min_free_bytes(mut self, bytes: u64) -> Self {
+        self.min_free_bytes = Some(bytes);
+        self
+    }
 
-        // Get metadata to verify tenant ownership
-        let metadata = self.metadata_store.get_metadata(blob_id)?;
-        if metadata.tenant_id != *tenant_id {
-            return Err(crate::error::Error::InvalidTenant(
-                "Blob does not belong to this tenant".into(),
-            ));
+    /// Fails with `StorageFull` if free space is below the configured minimum
+    fn check_free_space(&self) -> Result<()> {
+        if let Some(required) = self.min_free_bytes {
+            let available = fs2::available_space(&self.root_dir)?;
+            if available < required {
+                return Err(Error::StorageFull { available, required });
+            }
         }
+        Ok(())
+    }
 
-        // Get the blob (this will also verify checksum)
-        let (reader, _) = self.chunk_store.get_blob(blob_id)?;
-        Ok(reader)
+    /// Caps the number of tenants that can be registered and the number of
+    /// blobs each tenant can store
+    pub fn with_limits(mut self, max_tenants: usize, max_blobs_per_tenant: usize) -> Self {
+        self.max_tenants = Some(max_tenants);
+        self.max_blobs_per_tenant = Some(max_blobs_per_tenant);
+        self
     }
 
-    /// Lists all blobs for a tenant
-    pub fn list_blobs(&self, tenant_id: &TenantId) -> Result<Vec<BlobMetadata>> {
-        // Validate tenant
-        self.tenant_manager.validate_tenant(tenant_id)?;
+    /// Sets the longest tenant name `register_tenant` accepts and what it does
+    /// with longer names. By default names longer than `MAX_TENANT_KEY_LEN`
+    /// are rejected. The limit only decides which names are accepted: which
+    /// names are keyed by hash is fixed by `MAX_TENANT_KEY_LEN`, so changing
+    /// the limit never changes the keys of existing tenants.
+    pub fn with_tenant_name_limit(mut self, max_len: usize, policy: TenantNamePolicy) -> Self {
+        self.max_tenant_name_len = max_len;
+        self.tenant_name_policy = policy;
+        self
+    }
 
-        // Get all blob IDs for the tenant
-        let blob_ids = self.metadata_store.get_tenant_blobs(tenant_id)?;
+    /// Restricts the characters of newly registered tenant names, see
+    /// `TenantManager::with_name_policy`
+    pub fn with_tenant_charset(mut self, charset: TenantCharset) -> Self {
+        self.tenant_manager = self.tenant_manager.with_name_policy(charset);
+        self
+    }
 
-        // Get metadata for each blob
-        let mut metadata_list = Vec::new();
-        for blob_id in blob_ids {
-            if let Ok(metadata) = self.metadata_store.get_metadata(&blob_id) {
-                metadata_list.push(metadata);
+    /// Fails with `InvalidTenantId` if the name is too long and the policy
+    /// is to reject such names
+    fn check_tenant_name(&self, tenant_id: &TenantId) -> Result<()> {
+        let len = tenant_id.as_str().len();
+        if len > self.max_tenant_name_len && self.tenant_name_policy == TenantNamePolicy::Reject {
+            return Err(Error::InvalidTenantId(format!(
+                "name is {} bytes, longer than the {} allowed",
+                len, self.max_tenant_name_len
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns a blob's tombstone if tombstones are kept and it hasn't outlived their ttl
+    pub fn tombstone(&self, blob_id: &BlobId) -> Result<Option<Tombstone>> {
+        let Some(ttl) = self.tombstone_ttl else {
+            return Ok(None);
+        };
+        let tombstone = self
+            .metadata_store
+            .get_tombstone(blob_id)
+            .with_context(|| format!("while reading tombstone of blob {}", blob_id))?;
+        Ok(tombstone.filter(|tombstone| tombstone.deleted_at + ttl > self.now()))
+    }
+
+    /// Removes tombstones left by deletes more than `older_than` ago,
+    /// returning how many were removed
+    pub fn prune_tombstones(&self, older_than: chrono::Duration) -> Result<usize> {
+        self.metadata_store
+            .prune_tombstones(self.now() - older_than)
+            .with_context(|| "while pruning tombstones".to_string())
+    }
+
+    /// Removes idempotency keys older than the idempotency window, returning
+    /// how many were removed
+    pub fn prune_idempotency_keys(&self) -> Result<usize> {
+        self.metadata_store
+            .prune_idempotent(self.now() - self.idempotency_window)
+            .with_context(|| "while pruning idempotency keys".to_string())
+    }
+
+    /// Fails with `BlobTombstoned` if the blob was deleted too recently for its id to be reused
+    fn check_tombstone(&self, blob_id: &BlobId) -> Result<()> {
+        match self.tombstone(blob_id)? {
+            Some(tombstone) => Err(Error::BlobTombstoned { blob_id: blob_id.to_string(), deleted_at: tombstone.deleted_at }),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails with `BlobLimitReached` if the tenant already holds its maximum
+    /// number of blobs. Only an early check before any data is written: the
+    /// limit is enforced when the blob's metadata is committed.
+    fn check_blob_limit(&self, tenant_id: &TenantId) -> Result<()> {
+        if let Some(limit) = self.max_blobs_per_tenant {
+            if self.metadata_store.count_tenant_blobs(tenant_id)? >= limit {
+                return Err(Error::BlobLimitReached {
+                    tenant: tenant_id.as_str().to_string(),
+                    limit,
+                });
             }
         }
+        Ok(())
+    }
 
-        Ok(metadata_list)
+    /// Stores new blobs in append-only pack files of up to `max_pack_size`
+    /// bytes instead of one file per blob. Existing blobs stay where they are.
+    pub fn with_packfiles(mut self, max_pack_size: u64) -> Result<Self> {
+        self.pack_store = PackStore::with_dir_name(&self.root_dir, &self.store_layout.packs_dir, max_pack_size)?
+            .with_checksum_algorithm(self.checksum_algorithm);
+        self.use_packs = true;
+        Ok(self)
     }
 
-    /// Deletes a blob
-    pub fn delete_blob(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<()> {
-        // Validate tenant
-        self.tenant_manager.validate_tenant(tenant_id)?;
+    /// Compresses new chunk-stored blobs with `compression`. Each blob
+    /// records its algorithm, so existing blobs are read as they were stored
+    /// whatever the current setting. Packed blobs are never compressed.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
 
-        // Get metadata to verify tenant ownership
-        let metadata = self.metadata_store.get_metadata(blob_id)?;
-        if metadata.tenant_id != *tenant_id {
-            return Err(crate::error::Error::InvalidTenant(
-                "Blob does not belong to this tenant".into(),
-            ));
+    /// Sets when `get_blob` and `get_blob_with_info` verify blob content.
+    /// Defaults to `VerifyMode::Eager`.
+    pub fn with_verify_mode(mut self, mode: VerifyMode) -> Self {
+        self.verify_mode = mode;
+        self
+    }
+
+    /// Sets which record of a chunk-stored blob's checksum reads verify
+    /// against when its sidecar and metadata may disagree, e.g. after a
+    /// repair updated only one. Defaults to `ChecksumSource::CrossCheck`.
+    pub fn with_checksum_source(mut self, source: ChecksumSource) -> Self {
+        self.checksum_source = source;
+        self
+    }
+
+    /// Sets what reads do with a blob whose metadata has an empty checksum.
+    /// Defaults to `MissingChecksumPolicy::Reject`; verification always
+    /// reports such blobs.
+    pub fn with_missing_checksum_policy(mut self, policy: MissingChecksumPolicy) -> Self {
+        self.missing_checksum = policy;
+        self
+    }
+
+    /// Sets what `replace_named_blob` does with the blob it replaces.
+    /// Defaults to `ReplacePolicy::DeleteOld`.
+    pub fn with_replace_policy(mut self, policy: ReplacePolicy) -> Self {
+        self.replace_policy = policy;
+        self
+    }
+
+    /// Records a checksum for every `chunk_size` bytes of new chunk-stored
+    /// blobs. Reads of those blobs are verified chunk by chunk as they
+    /// stream instead of hashing the whole blob up front, and corruption is
+    /// reported with the index of the bad chunk.
+    pub fn with_chunk_checksums(mut self, chunk_size: u64) -> Self {
+        self.chunk_checksum_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Records new blobs' checksums with `algorithm` instead of SHA-256.
+    /// The choice is persisted, so later opens keep using it; existing blobs
+    /// keep their algorithm until `rehash_all` moves them over.
+    pub fn with_checksum_algorithm(mut self, algorithm: checksum::ChecksumAlgorithm) -> Result<Self> {
+        if algorithm != self.checksum_algorithm {
+            self.metadata_store.set_checksum_algorithm(algorithm)?;
         }
+        self.checksum_algorithm = algorithm;
+        self.chunk_store = self.chunk_store.with_checksum_algorithm(algorithm);
+        self.pack_store = self.pack_store.with_checksum_algorithm(algorithm);
+        Ok(self)
+    }
 
-        // Delete the blob and its metadata
-        self.chunk_store.delete_blob(blob_id)?;
-        self.metadata_store.delete_metadata(blob_id, tenant_id)?;
+    /// Computes these digests of every new blob in the same pass that
+    /// stores it and records them in its metadata's `checksums`, e.g. MD5
+    /// for S3 ETag compatibility
+    pub fn with_checksums(mut self, algorithms: impl IntoIterator<Item = checksum::ChecksumAlgorithm>) -> Self {
+        self.checksum_algorithms = algorithms.into_iter().collect();
+        self
+    }
 
-        Ok(())
+    /// Makes `copy_blob` hard-link a chunk-stored blob's file instead of
+    /// copying its bytes, so a copy takes no extra space. Blobs are never
+    /// modified in place, so the linked copies can't diverge; deleting one
+    /// just drops its link. Off by default.
+    pub fn with_linked_copies(mut self, enabled: bool) -> Self {
+        self.link_copies = enabled;
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+    /// Records when each blob was last read in its `last_accessed` field, for
+    /// lifecycle policies such as archiving rarely read blobs. Access times
+    /// are buffered and written in batches rather than on every read, so
+    /// they can lag; `flush_access_times` writes them out. Off by default.
+    pub fn with_access_tracking(mut self, enabled: bool) -> Self {
+        self.track_access = enabled;
+        self
+    }
 
-    #[test]
-    fn test_coordinator_operations() {
-        let temp_dir = tempfile::tempdir().unwrap();
-        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+    /// Reads the current time from `clock` instead of the system clock, so
+    /// tests can control expiry and retention
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.tenant_manager = self.tenant_manager.with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
 
-        // Register tenant
-        let tenant_id = TenantId::new("posts");
-        assert!(coordinator.register_tenant(tenant_id.clone()).is_ok());
+    /// The current time according to the configured clock
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
 
-        // Put blob
-        let data = b"Hello, World!";
-        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(data)).unwrap();
+    /// Reads every new chunk-stored blob back after writing it and fails the
+    /// put if the bytes on disk don't match. Off by default.
+    pub fn with_write_verify(mut self, enabled: bool) -> Self {
+        self.chunk_store = self.chunk_store.with_write_verify(enabled);
+        self
+    }
 
-        // Get blob
-        let mut retrieved = Vec::new();
-        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut retrieved).unwrap();
-        assert_eq!(&retrieved, data);
+    /// Serves hot chunk-stored blobs that fail their checksum from a
+    /// secondary copy of the chunk files under `backup_root`, if that copy
+    /// verifies, repairing the primary from it when `heal` is set.
+    /// `verify_blob` and `verify_all` still report the primary's damage. See
+    /// `ChunkStore::with_backup_root`.
+    pub fn with_backup_root(mut self, backup_root: impl Into<PathBuf>, heal: bool) -> Self {
+        self.chunk_store = self.chunk_store.with_backup_root(backup_root).with_backup_healing(heal);
+        self
+    }
 
-        // List blobs
-        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
-        assert_eq!(blobs.len(), 1);
-        assert_eq!(blobs[0].blob_id, blob_id);
-        assert_eq!(blobs[0].size, data.len() as u64);
+    /// Keeps uncompressed blobs of at most `max_blob_size` bytes in memory
+    /// once read, up to `max_bytes` in total, so repeated reads of small hot
+    /// blobs skip the disk. See `ChunkStore::with_blob_cache`.
+    pub fn with_blob_cache(mut self, max_bytes: u64, max_blob_size: u64) -> Self {
+        self.chunk_store = self.chunk_store.with_blob_cache(max_bytes, max_blob_size);
+        self
+    }
+
+    /// Lets at most `max` puts, deletes and moves run at once, bounding the
+    /// file descriptors and temp files used under load spikes. Operations
+    /// beyond the limit wait for a slot or fail with `Overloaded`, per `policy`.
+    pub fn with_max_concurrency(mut self, max: usize, policy: OverloadPolicy) -> Self {
+        self.limiter = Some(ConcurrencyLimiter::new(max, policy));
+        self
+    }
+
+    /// Lets uploads in progress hold at most `max` bytes between them, so
+    /// concurrent puts can't fill the disk with temp files before they
+    /// commit. A put reserves its size hint, or `UNSIZED_UPLOAD_RESERVATION`
+    /// without one, until it completes or fails. Puts that don't fit wait or
+    /// fail with `UploadBudgetExceeded`, per `policy`.
+    pub fn with_max_inflight_bytes(mut self, max: u64, policy: OverloadPolicy) -> Self {
+        self.upload_budget = Some(ByteBudget::new(max, policy));
+        self
+    }
+
+    /// Controls whether blobs get a `.chk` checksum sidecar file. Reads fall
+    /// back to the checksum in metadata for blobs without one, so sidecars
+    /// are only needed by tools reading the chunk files directly.
+    pub fn with_checksum_sidecars(mut self, enabled: bool) -> Self {
+        self.chunk_store = self.chunk_store.with_sidecars(enabled);
+        self
+    }
+
+    /// Creates chunk files, checksum files and storage directories with the
+    /// given Unix permissions instead of the defaults. A no-op on other platforms.
+    pub fn with_file_mode(mut self, mode: FileMode) -> Result<Self> {
+        self.chunk_store = self.chunk_store.with_file_mode(mode)?;
+        Ok(self)
+    }
+
+    /// Adds a cold storage root that `migrate_cold` moves old blobs to. New
+    /// blobs are always written to the hot root.
+    pub fn with_cold_root(mut self, cold_root: impl Into<PathBuf>) -> Result<Self> {
+        self.chunk_store = self.chunk_store.with_cold_root(cold_root)?;
+        Ok(self)
+    }
+
+    /// Sets how long an idempotency key is remembered (24 hours by default).
+    /// Expired keys are ignored, and removed by `prune_idempotency_keys`.
+    pub fn with_idempotency_window(mut self, window: chrono::Duration) -> Self {
+        self.idempotency_window = window;
+        self
+    }
+
+    /// Leaves a tombstone for every deleted blob, so a replica syncing later
+    /// doesn't bring it back. For `ttl` after the delete, the blob is hidden
+    /// from listings and putting its id again fails with `BlobTombstoned`.
+    pub fn with_tombstones(mut self, ttl: chrono::Duration) -> Self {
+        self.tombstone_ttl = Some(ttl);
+        self
+    }
+
+    /// Logs a warning for every put, get or delete that takes longer than `threshold`
+    pub fn with_slow_op_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_op_threshold = Some(threshold);
+        self
+    }
+
+    /// Returns a feed of a tenant's blob changes. Each event is sent only
+    /// after its metadata change has committed, so a subscriber never sees
+    /// a change that could still fail. Dropping the receiver unsubscribes.
+    pub fn subscribe(&self, tenant_id: &TenantId) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push((tenant_id.clone(), sender));
+        receiver
+    }
+
+    /// Sends an event to the tenant's subscribers, dropping any that have
+    /// hung up
+    fn notify(&self, tenant_id: &TenantId, event: ChangeEvent) {
+        self.subscribers.lock().unwrap().retain(|(subscribed, sender)| {
+            subscribed != tenant_id || sender.send(event.clone()).is_ok()
+        });
+    }
+
+    /// Warns if an operation started at `started` exceeded the slow-operation threshold
+    fn warn_if_slow(&self, operation: &str, tenant_id: &TenantId, blob_id: Option<&BlobId>, started: Instant) {
+        let Some(threshold) = self.slow_op_threshold else {
+            return;
+        };
+        let elapsed = started.elapsed();
+        if elapsed > threshold {
+            tracing::warn!(
+                operation,
+                tenant = tenant_id.as_str(),
+                blob_id = blob_id.map(ToString::to_string).unwrap_or_default(),
+                elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+                "slow operation"
+            );
+        }
+    }
+
+    /// Enables admin operations that bypass tenant ownership checks
+    pub fn with_admin_mode(mut self) -> Self {
+        self.admin = true;
+        self
+    }
+
+    /// Enables an in-memory Bloom filter of known blob ids so lookups of
+    /// missing blobs can be answered without touching the metadata store
+    pub fn with_bloom_cache(mut self) -> Result<Self> {
+        let blob_ids = self.metadata_store.all_blob_ids()?;
+        let mut filter = BloomFilter::new((blob_ids.len() * 2).max(1 << 16), 0.01);
+        for blob_id in &blob_ids {
+            filter.insert(blob_id);
+        }
+        self.bloom = Some(Mutex::new(filter));
+        Ok(self)
+    }
+
+    /// Keys blob metadata by a fixed-width hash of the blob id rather than
+    /// the id string, migrating existing records. Persisted, so it only
+    /// needs to be given once per store.
+    pub fn with_hashed_metadata_keys(mut self) -> Result<Self> {
+        self.metadata_store = self
+            .metadata_store
+            .with_hashed_keys()
+            .with_context(|| "while switching to hashed metadata keys".to_string())?;
+        Ok(self)
+    }
+
+    /// Indexes each tenant's blobs by size, so `list_blobs_by_size` is a range
+    /// scan rather than a full listing. Persisted, so it only needs to be
+    /// given once per store.
+    pub fn with_size_index(mut self) -> Result<Self> {
+        self.metadata_store = self
+            .metadata_store
+            .with_size_index()
+            .with_context(|| "while building the size index".to_string())?;
+        Ok(self)
+    }
+
+    /// Caches up to `capacity` recently read blob metadata records in memory
+    pub fn with_metadata_cache(mut self, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        self.metadata_cache = Some(Mutex::new(LruCache::new(capacity)));
+        self
+    }
+
+    /// Reads a blob's metadata, through the cache if one is installed
+    fn get_metadata(&self, blob_id: &BlobId) -> Result<BlobMetadata> {
+        let Some(cache) = &self.metadata_cache else {
+            return self.metadata_store.get_metadata(blob_id);
+        };
+        let mut cache = cache.lock().unwrap();
+        if let Some(metadata) = cache.get(blob_id) {
+            return Ok(metadata.clone());
+        }
+        let metadata = self.metadata_store.get_metadata(blob_id)?;
+        cache.put(blob_id.clone(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Drops a blob's cached metadata after it has been changed in the store
+    fn invalidate_metadata(&self, blob_id: &BlobId) {
+        if let Some(cache) = &self.metadata_cache {
+            cache.lock().unwrap().pop(blob_id);
+        }
+    }
+
+    /// Notes a read of a blob when access tracking is on. A failure to record
+    /// it is logged rather than failing the read.
+    fn record_access(&self, blob_id: &BlobId) {
+        if !self.track_access {
+            return;
+        }
+        match self.metadata_store.record_access(blob_id, self.now()) {
+            Ok(written) => written.iter().for_each(|blob_id| self.invalidate_metadata(blob_id)),
+            Err(e) => tracing::warn!(blob_id = %blob_id, error = %e, "failed to record blob access"),
+        }
+    }
+
+    /// Writes buffered access times into blob metadata now rather than
+    /// waiting for the next batch
+    pub fn flush_access_times(&self) -> Result<()> {
+        let written = self
+            .metadata_store
+            .apply_accesses()
+            .with_context(|| "while writing blob access times".to_string())?;
+        for blob_id in &written {
+            self.invalidate_metadata(blob_id);
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata and tenant dbs, exactly as stored, for `restore`.
+    /// Holds no blob data, which must be backed up separately.
+    pub fn backup(&self, mut writer: impl Write) -> Result<()> {
+        let context = || "while backing up the metadata".to_string();
+        self.metadata_store
+            .snapshot(|db| {
+                crate::dump::export_db(db, &mut writer)?;
+                self.tenant_manager.export_raw(&mut writer)
+            })
+            .with_context(context)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a backup written by `backup` into this coordinator, whose
+    /// metadata and tenant dbs must be empty. The whole backup is read and
+    /// checked first, so a truncated or corrupt one writes nothing.
+    pub fn restore(&self, mut reader: impl Read) -> Result<()> {
+        let context = || "while restoring the metadata".to_string();
+        let metadata = crate::dump::read_dump(&mut reader).with_context(context)?;
+        let tenants = crate::dump::read_dump(&mut reader).with_context(context)?;
+        self.metadata_store.check_restorable().with_context(context)?;
+        self.tenant_manager.check_restorable().with_context(context)?;
+        self.metadata_store.load_raw(metadata).with_context(context)?;
+        self.tenant_manager.load_raw(tenants).with_context(context)?;
+        if let Some(bloom) = &self.bloom {
+            let mut bloom = bloom.lock().unwrap();
+            for blob_id in self.metadata_store.all_blob_ids().with_context(context)? {
+                bloom.insert(&blob_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns false if the Bloom cache proves the blob doesn't exist
+    fn may_exist(&self, blob_id: &BlobId) -> bool {
+        match &self.bloom {
+            Some(bloom) => bloom.lock().unwrap().may_contain(blob_id),
+            None => true,
+        }
+    }
+
+    /// Migrates a store written by an older build to the current layout,
+    /// returning the layout version it was upgraded from
+    pub fn upgrade(root_dir: impl AsRef<Path>) -> Result<u32> {
+        layout::upgrade(root_dir.as_ref())
+    }
+
+    /// Registers a new tenant
+    pub fn register_tenant(&self, tenant_id: TenantId) -> Result<()> {
+        let name = tenant_id.as_str().to_string();
+        self.check_tenant_name(&tenant_id)
+            .and_then(|_| self.tenant_manager.register_tenant_within(tenant_id, TenantConfig::default(), self.max_tenants))
+            .with_context(|| format!("while registering tenant {}", name))
+    }
+
+    /// Registers a new tenant with the given settings
+    pub fn register_tenant_with_config(&self, tenant_id: TenantId, config: TenantConfig) -> Result<()> {
+        let name = tenant_id.as_str().to_string();
+        self.check_tenant_name(&tenant_id)
+            .and_then(|_| self.tenant_manager.register_tenant_within(tenant_id, config, self.max_tenants))
+            .with_context(|| format!("while registering tenant {}", name))
+    }
+
+    /// Registers several tenants at once, returning each tenant's outcome.
+    /// Tenants that already exist or would exceed the tenant limit are
+    /// reported individually without failing the rest.
+    pub fn register_tenants(&self, tenant_ids: &[TenantId]) -> Result<Vec<(TenantId, Result<()>)>> {
+        let valid: Vec<_> = tenant_ids
+            .iter()
+            .filter(|tenant_id| self.check_tenant_name(tenant_id).is_ok())
+            .cloned()
+            .collect();
+        let mut registered = self
+            .tenant_manager
+            .register_tenants(&valid, self.max_tenants)
+            .with_context(|| format!("while registering {} tenants", tenant_ids.len()))?
+            .into_iter();
+
+        // Report outcomes in the order the tenants were given
+        Ok(tenant_ids
+            .iter()
+            .filter_map(|tenant_id| match self.check_tenant_name(tenant_id) {
+                Ok(()) => registered.next(),
+                Err(e) => Some((tenant_id.clone(), Err(e))),
+            })
+            .collect())
+    }
+
+    /// Returns a tenant's settings
+    pub fn get_tenant_config(&self, tenant_id: &TenantId) -> Result<TenantConfig> {
+        self.tenant_manager.get_tenant_config(tenant_id)
+    }
+
+    /// Applies `update` to a tenant's settings and stores the result
+    pub fn update_tenant_config(
+        &self,
+        tenant_id: &TenantId,
+        update: impl FnMut(&mut TenantConfig),
+    ) -> Result<TenantConfig> {
+        self.tenant_manager.update_tenant_config(tenant_id, update)
+    }
+
+    /// Sets the tags merged into every blob stored for the tenant from now on
+    pub fn set_tenant_default_tags(&self, tenant_id: &TenantId, tags: Tags) -> Result<()> {
+        self.tenant_manager
+            .update_tenant_config(tenant_id, |config| config.default_tags = tags.clone())
+            .map(|_| ())
+            .with_context(|| format!("while setting default tags for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Returns the tags merged into every blob stored for the tenant
+    pub fn get_tenant_default_tags(&self, tenant_id: &TenantId) -> Result<Tags> {
+        self.tenant_manager
+            .get_tenant_config(tenant_id)
+            .map(|config| config.default_tags)
+            .with_context(|| format!("while getting default tags for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Lists all registered tenants
+    pub fn list_tenants(&self) -> Result<Vec<TenantId>> {
+        self.tenant_manager.list_tenants()
+    }
+
+    /// Lists the registered tenants whose names start with `prefix`, sorted by name
+    pub fn list_tenants_by_prefix(&self, prefix: &str) -> Result<Vec<TenantId>> {
+        self.tenant_manager
+            .list_by_prefix(prefix)
+            .with_context(|| format!("while listing tenants with prefix {}", prefix))
+    }
+
+    /// Lists all registered tenants sorted by name or by creation order
+    pub fn list_tenants_sorted(&self, order: TenantOrder) -> Result<Vec<TenantId>> {
+        self.tenant_manager.list_tenants_sorted(order)
+    }
+
+    /// Iterates over registered tenants lazily, for paging through or
+    /// filtering a large number of tenants
+    pub fn iter_tenants(&self) -> impl Iterator<Item = Result<TenantId>> + '_ {
+        self.tenant_manager.iter_tenants()
+    }
+
+    /// Stores a new blob. `data` is read once from start to end and hashed
+    /// on the way through, so sources that can't seek, like network streams,
+    /// work as is.
+    pub fn put_blob(&self, tenant_id: &TenantId, data: impl Read) -> Result<BlobId> {
+        self.put_blob_info(tenant_id, data).map(|(blob_id, _)| blob_id)
+    }
+
+    /// Stores a new blob, returning the size and checksum of what was
+    /// written along with its id
+    pub fn put_blob_info(&self, tenant_id: &TenantId, data: impl Read) -> Result<(BlobId, BlobInfo)> {
+        self.store_blob_info(tenant_id, data, PutOptions::default())
+            .with_context(|| format!("while putting blob for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Stores a new blob whose size is known up front, e.g. from a
+    /// Content-Length header. Its file is preallocated to `expected_size`,
+    /// so a full disk is caught before streaming, and the put fails with
+    /// `SizeMismatch`, storing nothing, if the data turns out to differ.
+    /// Reading stops one byte past the hint, so overlong data reports
+    /// `expected_size + 1` as its actual size.
+    pub fn put_blob_with_size_hint(&self, tenant_id: &TenantId, data: impl Read, expected_size: u64) -> Result<BlobId> {
+        let options = PutOptions { size_hint: Some(expected_size), ..Default::default() };
+        self.store_blob(tenant_id, data, options)
+            .with_context(|| format!("while putting blob for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Starts building a put with several options, e.g.
+    /// `coordinator.put(&tenant_id).tags(tags).ttl(ttl).store(data)`
+    pub fn put(&self, tenant_id: &TenantId) -> PutBuilder<'_> {
+        PutBuilder {
+            coordinator: self,
+            tenant_id: tenant_id.clone(),
+            options: PutOptions::default(),
+            expected_checksum: None,
+            ttl: None,
+        }
+    }
+
+    /// Stores a new blob, passing its data through `transform` chunk by chunk
+    /// on the way in. The recorded size and checksum are those of the
+    /// transformed bytes. See `TransformReader` for composing transforms with
+    /// the other put methods.
+    pub fn put_blob_transformed(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        transform: impl FnMut(&[u8]) -> Vec<u8>,
+    ) -> Result<BlobId> {
+        self.put_blob(tenant_id, TransformReader::new(data, transform))
+    }
+
+    /// Stores a new blob under a caller-chosen id, failing with
+    /// `BlobAlreadyExists` if that id is taken
+    pub fn put_blob_with_id(&self, tenant_id: &TenantId, blob_id: BlobId, data: impl Read) -> Result<BlobId> {
+        let context = || format!("while putting blob {} for tenant {}", blob_id, tenant_id.as_str());
+        let options = PutOptions { blob_id: Some(blob_id.clone()), ..Default::default() };
+        self.store_blob(tenant_id, data, options).with_context(context)
+    }
+
+    /// Stores a new blob, rejecting it unless its checksum matches `expected_checksum`
+    /// (bare hex or `<algo>:<hex>`). Nothing is kept on a mismatch.
+    pub fn put_blob_verified(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        expected_checksum: &str,
+    ) -> Result<BlobId> {
+        checksum::parse_checksum(expected_checksum)
+            .and_then(|expected| {
+                let options = PutOptions { expected_checksum: Some(expected), ..Default::default() };
+                self.store_blob(tenant_id, data, options)
+            })
+            .with_context(|| format!("while putting verified blob for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Stores a new blob whose expected checksum only arrives after its data,
+    /// as with HTTP trailers. The data is streamed and hashed as usual, then
+    /// `trailer` is called for the checksum and the blob is committed only
+    /// if they match; on a mismatch or a failing `trailer` nothing is kept.
+    /// The checksum must use the store's checksum algorithm or one added by
+    /// `with_checksums`, as only those are computed while the data streams by.
+    pub fn put_blob_with_trailer(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        trailer: impl FnOnce() -> Result<Checksum> + 'static,
+    ) -> Result<BlobId> {
+        let options = PutOptions { trailer_checksum: Some(Box::new(trailer)), ..Default::default() };
+        self.store_blob(tenant_id, data, options)
+            .with_context(|| format!("while putting blob with trailer for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Stores a new blob unless `idempotency_key` was already used by this
+    /// tenant within the idempotency window, in which case the blob created
+    /// then is returned and `data` isn't stored. Lets clients retry safely.
+    pub fn put_blob_idempotent(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        idempotency_key: Option<&str>,
+    ) -> Result<BlobId> {
+        let context = || format!("while putting blob for tenant {}", tenant_id.as_str());
+        let Some(key) = idempotency_key else {
+            return self.store_blob(tenant_id, data, PutOptions::default()).with_context(context);
+        };
+
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let now = self.now();
+        let expires_before = now - self.idempotency_window;
+        if let Some(blob_id) = self
+            .metadata_store
+            .get_idempotent(tenant_id, key, expires_before)
+            .with_context(context)?
+        {
+            return Ok(blob_id);
+        }
+
+        let blob_id = self.store_blob(tenant_id, data, PutOptions::default()).with_context(context)?;
+        match self
+            .metadata_store
+            .claim_idempotent(tenant_id, key, &blob_id, now, expires_before)
+            .with_context(context)?
+        {
+            // A concurrent retry with the same key finished first; keep its blob
+            Some(winner) => {
+                self.remove_blob(tenant_id, &blob_id).with_context(context)?;
+                Ok(winner)
+            }
+            None => Ok(blob_id),
+        }
+    }
+
+    /// Reserves a new blob id for the tenant and returns it immediately. The
+    /// blob only becomes listable once `PendingBlob::finish` stores its data.
+    pub fn begin_put(&self, tenant_id: &TenantId) -> Result<PendingBlob<'_>> {
+        let context = || format!("while beginning put for tenant {}", tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+
+        let blob_id = BlobId::new();
+        self.metadata_store.put_pending(&blob_id, tenant_id).with_context(context)?;
+        Ok(PendingBlob {
+            coordinator: self,
+            tenant_id: tenant_id.clone(),
+            blob_id,
+        })
+    }
+
+    /// Cancels an upload started with `begin_put` that hasn't been committed,
+    /// releasing its id and removing any data a failed `finish` left behind.
+    /// Waits for a `finish` of the same upload already running. Fails with
+    /// `BlobAlreadyExists` once the tenant's blob is committed; delete it
+    /// with `delete_blob` instead. Other tenants' blobs aren't found.
+    pub fn cancel_put(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<()> {
+        let context = || format!("while cancelling put of blob {} for tenant {}", blob_id, tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let _upload = self.lock_upload(blob_id);
+        match self.metadata_store.get_metadata(blob_id) {
+            Ok(metadata) if metadata.tenant_id == *tenant_id => {
+                return Err(Error::BlobAlreadyExists(blob_id.to_string())).with_context(context)
+            }
+            Ok(_) => return Err(Error::BlobNotFound(blob_id.to_string())).with_context(context),
+            Err(Error::BlobNotFound(_)) => {}
+            Err(e) => return Err(e).with_context(context),
+        }
+        if self.metadata_store.get_pending(blob_id).with_context(context)?.as_ref() != Some(tenant_id) {
+            return Err(Error::BlobNotFound(blob_id.to_string())).with_context(context);
+        }
+
+        self.chunk_store.delete_blob(blob_id).with_context(context)?;
+        self.metadata_store.remove_pending(blob_id).with_context(context)
+    }
+
+    /// Holds the lock serializing finishing and cancelling an upload
+    fn lock_upload(&self, blob_id: &BlobId) -> std::sync::MutexGuard<'_, ()> {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        blob_id.hash(&mut hasher);
+        let stripe = hasher.finish() as usize % self.upload_locks.len();
+        self.upload_locks[stripe].lock().unwrap()
+    }
+
+    /// Stores a new blob labelled with the given tags
+    pub fn put_blob_with_tags(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        tags: Tags,
+    ) -> Result<BlobId> {
+        self.store_blob(tenant_id, data, PutOptions { tags, ..Default::default() })
+            .with_context(|| format!("while putting blob for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Stores a new blob with an explicit content type instead of an inferred one
+    pub fn put_blob_with_content_type(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        content_type: &str,
+    ) -> Result<BlobId> {
+        let options = PutOptions { content_type: Some(content_type.to_string()), ..Default::default() };
+        self.store_blob(tenant_id, data, options)
+            .with_context(|| format!("while putting blob for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Stores a new write-once blob that can't be deleted or moved before
+    /// `retain_until`
+    pub fn put_blob_with_retention(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        retain_until: DateTime<Utc>,
+    ) -> Result<BlobId> {
+        let options = PutOptions { retain_until: Some(retain_until), ..Default::default() };
+        self.store_blob(tenant_id, data, options)
+            .with_context(|| format!("while putting blob for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Stores a batch of blobs for one tenant, for bulk loads and measuring
+    /// ingest throughput. Items with a name are stored as by
+    /// `put_named_blob`, the rest as by `put_blob`. The tenant is validated
+    /// once, and the metadata of unnamed blobs is committed
+    /// `BULK_COMMIT_SIZE` at a time, except with packfiles or a blob limit,
+    /// where each blob must be committed before the next is written. An
+    /// item that fails is recorded in the report and the rest are still
+    /// stored; only an invalid tenant fails the whole call.
+    pub fn bulk_put(
+        &self,
+        tenant_id: &TenantId,
+        items: impl Iterator<Item = (Option<String>, Box<dyn Read>)>,
+    ) -> Result<BulkReport> {
+        let context = || format!("while bulk putting blobs for tenant {}", tenant_id.as_str());
+        let started = Instant::now();
+        let default_tags = self.tenant_manager.get_tenant_config(tenant_id).with_context(context)?.default_tags;
+        let commit_size = if self.use_packs || self.max_blobs_per_tenant.is_some() { 1 } else { BULK_COMMIT_SIZE };
+
+        let mut report = BulkReport::default();
+        let mut stored = Vec::new();
+        let mut pending = Vec::new();
+        for (index, (name, data)) in items.enumerate() {
+            let result = match name {
+                Some(name) => self.put_named_blob(tenant_id, &name, data).and_then(|blob_id| {
+                    let size = self.get_metadata(&blob_id)?.size;
+                    stored.push((index, blob_id));
+                    report.bytes += size;
+                    Ok(())
+                }),
+                None => self
+                    .acquire_permit()
+                    .and_then(|_permit| {
+                        let _reservation = self.reserve_upload(None)?;
+                        self.write_blob_data(tenant_id, &default_tags, data, PutOptions::default())
+                    })
+                    .map(|written| {
+                        if let Written::New(metadata) = written {
+                            pending.push((index, *metadata));
+                        }
+                    })
+                    .with_context(context),
+            };
+            if let Err(e) = result {
+                report.failed.push((index, e));
+            }
+            if pending.len() >= commit_size {
+                self.commit_bulk(std::mem::take(&mut pending), &mut stored, &mut report);
+            }
+        }
+        self.commit_bulk(pending, &mut stored, &mut report);
+
+        stored.sort_by_key(|(index, _)| *index);
+        report.stored = stored.into_iter().map(|(_, blob_id)| blob_id).collect();
+        report.failed.sort_by_key(|(index, _)| *index);
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Commits the metadata of blobs written by `bulk_put` in one
+    /// transaction. If that fails, their data is removed and every one of
+    /// them is reported as failed.
+    fn commit_bulk(
+        &self,
+        pending: Vec<(usize, BlobMetadata)>,
+        stored: &mut Vec<(usize, BlobId)>,
+        report: &mut BulkReport,
+    ) {
+        if pending.is_empty() {
+            return;
+        }
+        let records: Vec<_> = pending.iter().map(|(_, metadata)| metadata.clone()).collect();
+        match self.metadata_store.put_metadata_many_within(&records, self.max_blobs_per_tenant) {
+            Ok(()) => {
+                for (index, metadata) in pending {
+                    self.committed(&metadata);
+                    report.bytes += metadata.size;
+                    stored.push((index, metadata.blob_id));
+                }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                tracing::warn!(error = %message, blobs = pending.len(), "failed to commit bulk put metadata");
+                // A blob committed on its own is reported with its own error,
+                // such as its tenant reaching the blob cap
+                let mut error = Some(e).filter(|_| pending.len() == 1);
+                for (index, metadata) in pending {
+                    self.discard_uncommitted(&metadata);
+                    let e = error
+                        .take()
+                        .unwrap_or_else(|| Error::System(format!("committing the batch failed: {}", message)))
+                        .with_context(format!("while bulk putting blobs for tenant {}", metadata.tenant_id.as_str()));
+                    report.failed.push((index, e));
+                }
+            }
+        }
+    }
+
+    /// Removes the chunk data of a blob whose metadata failed to commit.
+    /// Packed data is left for compaction.
+    fn discard_uncommitted(&self, metadata: &BlobMetadata) {
+        if metadata.pack.is_none() {
+            if let Err(e) = self.chunk_store.delete_blob(&metadata.blob_id) {
+                tracing::warn!(blob_id = %metadata.blob_id, error = %e, "failed to remove uncommitted blob");
+            }
+        }
+    }
+
+    /// Extends a blob's retention to `retain_until`. Retention can only be
+    /// lengthened: an earlier date than the current one fails with
+    /// `RetentionActive`.
+    pub fn extend_retention(
+        &self,
+        tenant_id: &TenantId,
+        blob_id: &BlobId,
+        retain_until: DateTime<Utc>,
+    ) -> Result<()> {
+        let context = || format!("while extending retention of blob {} for tenant {}", blob_id, tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let metadata = self.get_metadata(blob_id).with_context(context)?;
+        if metadata.tenant_id != *tenant_id {
+            return Err(Error::InvalidTenant("Blob does not belong to this tenant".into()))
+                .with_context(context);
+        }
+
+        let updated = self
+            .metadata_store
+            .update_metadata(blob_id, |metadata| match metadata.retain_until {
+                Some(existing) if existing > retain_until => false,
+                _ => {
+                    metadata.retain_until = Some(retain_until);
+                    true
+                }
+            })
+            .with_context(context)?;
+        self.invalidate_metadata(blob_id);
+        if updated.is_some() {
+            return Ok(());
+        }
+
+        // Either the blob is gone or it is already retained for longer
+        let metadata = self.get_metadata(blob_id).with_context(context)?;
+        match metadata.retain_until {
+            Some(existing) if existing > retain_until => Err(Error::RetentionActive {
+                blob_id: blob_id.to_string(),
+                retain_until: existing,
+            })
+            .with_context(context),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies `update` to a blob's metadata, such as its tags or content
+    /// type, only if nobody has changed the metadata since the caller read
+    /// it at `expected_generation`. Fails with `ConcurrentModification`
+    /// otherwise. Fails with `RetentionActive` while the blob is under
+    /// retention, or if `update` clears or shortens it. Returns the updated
+    /// metadata, with its generation bumped.
+    pub fn update_metadata_if<F>(
+        &self,
+        tenant_id: &TenantId,
+        blob_id: &BlobId,
+        expected_generation: u64,
+        update: F,
+    ) -> Result<BlobMetadata>
+    where
+        F: Fn(&mut BlobMetadata),
+    {
+        let context = || format!("while updating metadata of blob {} for tenant {}", blob_id, tenant_id.as_str());
+        let metadata = self.owned_metadata(tenant_id, blob_id).with_context(context)?;
+        // Every change bumps the generation, so a record at the expected
+        // generation is the one the update below applies to
+        if metadata.generation == expected_generation {
+            self.check_retention(&metadata).with_context(context)?;
+        }
+
+        // A move to another tenant bumps the generation, so ownership can't
+        // change between the check above and the update
+        let updated = self
+            .metadata_store
+            .update_metadata_if(blob_id, expected_generation, |metadata| {
+                update(metadata);
+                metadata.blob_id = blob_id.clone();
+                metadata.tenant_id = tenant_id.clone();
+            })
+            .with_context(context);
+        self.invalidate_metadata(blob_id);
+        updated
+    }
+
+    /// Fails with `RetentionActive` if the blob is still under retention
+    fn check_retention(&self, metadata: &BlobMetadata) -> Result<()> {
+        match metadata.retain_until {
+            Some(retain_until) if retain_until > self.now() => Err(Error::RetentionActive {
+                blob_id: metadata.blob_id.to_string(),
+                retain_until,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the blob and commits its metadata, verifying the checksum first if one is given
+    pub(crate) fn store_blob(&self, tenant_id: &TenantId, data: impl Read, options: PutOptions) -> Result<BlobId> {
+        self.store_blob_info(tenant_id, data, options).map(|(blob_id, _)| blob_id)
+    }
+
+    /// Like `store_blob`, also returning the size and checksum of what was written
+    fn store_blob_info(
+        &self,
+        tenant_id: &TenantId,
+        data: impl Read,
+        options: PutOptions,
+    ) -> Result<(BlobId, BlobInfo)> {
+        let _permit = self.acquire_permit()?;
+        let _reservation = self.reserve_upload(options.size_hint)?;
+        let started = Instant::now();
+        let result = self.write_blob(tenant_id, data, options);
+        self.warn_if_slow("put", tenant_id, result.as_ref().ok().map(|(blob_id, _)| blob_id), started);
+        result
+    }
+
+    /// Takes a slot from the concurrency limit, if one is configured
+    fn acquire_permit(&self) -> Result<Option<Permit<'_>>> {
+        let permit = self.limiter.as_ref().map(ConcurrencyLimiter::acquire).transpose()?;
+        #[cfg(test)]
+        if let Some(while_limited) = self.while_limited {
+            while_limited();
+        }
+        Ok(permit)
+    }
+
+    /// Reserves room for an upload of `size_hint` bytes in the in-flight
+    /// budget, if one is configured
+    fn reserve_upload(&self, size_hint: Option<u64>) -> Result<Option<Reservation<'_>>> {
+        let bytes = size_hint.unwrap_or(UNSIZED_UPLOAD_RESERVATION);
+        self.upload_budget.as_ref().map(|budget| budget.reserve(bytes)).transpose()
+    }
+
+    fn write_blob(&self, tenant_id: &TenantId, data: impl Read, options: PutOptions) -> Result<(BlobId, BlobInfo)> {
+        // Validate tenant, picking up its default tags
+        let default_tags = self.tenant_manager.get_tenant_config(tenant_id)?.default_tags;
+        // A caller-chosen id is checked, written in place and committed under
+        // its upload lock, so concurrent puts of the same id can't both
+        // find it free
+        let _upload = match &options.blob_id {
+            Some(blob_id) if !options.upload_locked => Some(self.lock_upload(blob_id)),
+            _ => None,
+        };
+        match self.write_blob_data(tenant_id, &default_tags, data, options)? {
+            Written::Existing(blob_id, blob_info) => Ok((blob_id, blob_info)),
+            Written::New(metadata) => {
+                let limit = self.max_blobs_per_tenant;
+                if let Err(e) = self.metadata_store.put_metadata_many_within(std::slice::from_ref(&metadata), limit) {
+                    self.discard_uncommitted(&metadata);
+                    return Err(e);
+                }
+                self.committed(&metadata);
+                Ok((metadata.blob_id, BlobInfo { size: metadata.size, checksum: metadata.checksum }))
+            }
+            Written::Replacement(replacement) => {
+                let metadata = self.commit_replacement(*replacement)?;
+                Ok((metadata.blob_id, BlobInfo { size: metadata.size, checksum: metadata.checksum }))
+            }
+        }
+    }
+
+    /// Swaps an overwritten blob's record for the new one in one
+    /// transaction, then moves the staged data into place and drops what
+    /// is left of the old data. If the old record changed since it was
+    /// checked, the staged data is discarded and the overwrite fails.
+    fn commit_replacement(&self, replacement: Replacement) -> Result<BlobMetadata> {
+        let Replacement { metadata, old, staged } = replacement;
+        if let Err(e) = self.metadata_store.replace_metadata(&metadata, old.generation, staged.as_ref()) {
+            if let Some(staged) = &staged {
+                self.chunk_store.delete_blob(staged)?;
+            }
+            return Err(e);
+        }
+        self.invalidate_metadata(&metadata.blob_id);
+        match &staged {
+            Some(staged) => {
+                self.chunk_store.move_blob(staged, &metadata.blob_id)?;
+                if old.tier == Tier::Cold {
+                    self.chunk_store.remove_from_tier(&metadata.blob_id, Tier::Cold)?;
+                }
+                self.metadata_store.finish_replace(staged)?;
+            }
+            // A packed old blob's region is reclaimed by compaction
+            None if old.pack.is_none() => self.chunk_store.delete_blob(&metadata.blob_id)?,
+            None => {}
+        }
+        self.committed(&metadata);
+        Ok(metadata)
+    }
+
+    /// Moves the staged data of overwrites interrupted by a crash into place
+    fn finish_replacements(&self) -> Result<()> {
+        for (staged, blob_id) in self.metadata_store.unfinished_replaces()? {
+            self.chunk_store.move_blob(&staged, &blob_id)?;
+            self.metadata_store.finish_replace(&staged)?;
+            tracing::info!(%blob_id, "finished an interrupted overwrite");
+        }
+        Ok(())
+    }
+
+    /// Stores a blob's data for an already validated tenant and returns the
+    /// metadata to commit for it. Nothing points at the data until that
+    /// metadata is written and `committed` is called.
+    fn write_blob_data(
+        &self,
+        tenant_id: &TenantId,
+        default_tags: &Tags,
+        data: impl Read,
+        mut options: PutOptions,
+    ) -> Result<Written> {
+        // The blob's own tags take precedence over the tenant's defaults
+        let mut tags = default_tags.clone();
+        tags.merge(std::mem::take(&mut options.tags));
+
+        // Refuse the upload before buffering anything if the disk is nearly full
+        // or the tenant is at its blob cap
+        self.check_free_space()?;
+        self.check_blob_limit(tenant_id)?;
+
+        // Generate new blob ID unless the caller supplied one, resolving a
+        // clash with an existing blob per the conflict policy. An overwrite
+        // stores the new data under a staging id, so the existing blob stays
+        // intact until the new data is verified.
+        let mut replacing = None;
+        let blob_id = match options.blob_id {
+            Some(blob_id) => match self.metadata_store.get_metadata(&blob_id) {
+                Ok(existing) => match options.on_conflict {
+                    ConflictPolicy::Error => return Err(Error::BlobAlreadyExists(blob_id.to_string())),
+                    ConflictPolicy::Skip => {
+                        if existing.tenant_id != *tenant_id {
+                            return Err(Error::InvalidTenant("Blob does not belong to this tenant".into()));
+                        }
+                        let info = BlobInfo { size: existing.size, checksum: existing.checksum };
+                        return Ok(Written::Existing(blob_id, info));
+                    }
+                    ConflictPolicy::Overwrite => {
+                        if existing.tenant_id != *tenant_id {
+                            return Err(Error::InvalidTenant("Blob does not belong to this tenant".into()));
+                        }
+                        self.check_retention(&existing)?;
+                        replacing = Some(existing);
+                        blob_id
+                    }
+                },
+                Err(Error::BlobNotFound(_)) => {
+                    self.check_tombstone(&blob_id)?;
+                    blob_id
+                }
+                Err(e) => return Err(e),
+            },
+            None => BlobId::new(),
+        };
+        let data_id = if replacing.is_some() { BlobId::new() } else { blob_id.clone() };
+
+        // Read at most one byte past a size hint, so data longer than the
+        // hint fails the size check below without streaming the rest
+        let data = data.take(options.size_hint.map_or(u64::MAX, |hint| hint.saturating_add(1)));
+
+        // Store the blob and get its info, sniffing a content type on the way
+        // if the caller didn't give one
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let (content_type, data): (_, Box<dyn Read>) = match options.content_type {
+            Some(content_type) => (Some(content_type), Box::new(data)),
+            None => {
+                let (content_type, data) = content_type::sniff(data)?;
+                (content_type, Box::new(data))
+            }
+        };
+        // An expected checksum under another algorithm than the one new blobs
+        // are recorded with is checked against a digest computed alongside
+        let mut algorithms = self.checksum_algorithms.clone();
+        if let Some(expected) = &options.expected_checksum {
+            algorithms.insert(expected.supported_algorithm()?);
+        }
+        let mut data = checksum::MultiHashingReader::new(data, &algorithms);
+        let (pack, compression, compressed_size, blob_info, chunks) = if self.use_packs {
+            let (location, blob_info) = self.pack_store.append(&mut data)?;
+            (Some(location), Compression::None, None, blob_info, None)
+        } else {
+            let mut data = checksum::ChunkHashingReader::new(&mut data, self.chunk_checksum_size);
+            let (compression, compressed_size, blob_info) = if self.compression.is_compressed() {
+                let (blob_info, compressed_size) =
+                    self.chunk_store.put_blob_compressed(&data_id, &mut data, self.compression)?;
+                (self.compression, Some(compressed_size), blob_info)
+            } else {
+                let blob_info = self.chunk_store.put_blob_preallocated(&data_id, &mut data, options.size_hint)?;
+                (Compression::None, None, blob_info)
+            };
+            (None, compression, compressed_size, blob_info, data.finish())
+        };
+        let mut digests = data.finish_checksums();
+        digests.insert(self.checksum_algorithm, blob_info.checksum.clone());
+
+        // A checksum sent after the data, e.g. in an HTTP trailer, is only
+        // known now
+        let expected_checksum = match options.trailer_checksum.take() {
+            Some(trailer) => match trailer().and_then(|checksum| {
+                // Only digests computed while the data streamed by can be checked
+                let algorithm = checksum.supported_algorithm()?;
+                if !digests.contains_key(&algorithm) {
+                    return Err(Error::UnsupportedChecksumAlgorithm(checksum.algorithm().to_string()));
+                }
+                Ok(checksum)
+            }) {
+                Ok(checksum) => Some(checksum),
+                Err(e) => {
+                    if pack.is_none() {
+                        self.chunk_store.delete_blob(&data_id)?;
+                    }
+                    return Err(e);
+                }
+            },
+            None => options.expected_checksum.take(),
+        };
+
+        // Reject the upload before committing metadata if the size or checksum
+        // doesn't match. A rejected packed blob's region is simply left for
+        // compaction.
+        if let Some(expected) = options.size_hint.filter(|&expected| expected != blob_info.size) {
+            if pack.is_none() {
+                self.chunk_store.delete_blob(&data_id)?;
+            }
+            return Err(Error::SizeMismatch { expected, actual: blob_info.size });
+        }
+        if let Some(expected) = expected_checksum {
+            let algorithm = expected.supported_algorithm()?;
+            let actual = &digests[&algorithm];
+            if !actual.matches(&expected) {
+                if pack.is_none() {
+                    self.chunk_store.delete_blob(&data_id)?;
+                }
+                return Err(Error::ChecksumMismatch {
+                    expected,
+                    actual: actual.clone(),
+                });
+            }
+        }
+
+        // Create and store metadata
+        let metadata = BlobMetadata {
+            blob_id: blob_id.clone(),
+            tenant_id: tenant_id.clone(),
+            size: blob_info.size,
+            checksum: blob_info.checksum.clone(),
+            created_at: options.created_at.unwrap_or_else(|| self.now()),
+            seq: self.metadata_store.next_seq()?,
+            tags,
+            content_type,
+            pack,
+            tier: Tier::Hot,
+            retain_until: options.retain_until,
+            compressed_size,
+            compression,
+            expires_at: options.expires_at,
+            chunks,
+            generation: 0,
+            metadata_only: false,
+            checksums: digests
+                .into_iter()
+                .filter(|(algorithm, _)| self.checksum_algorithms.contains(algorithm))
+                .map(|(algorithm, checksum)| (algorithm, checksum.to_hex()))
+                .collect(),
+            last_accessed: None,
+            extensions: options.extensions,
+        };
+        match replacing {
+            Some(old) => {
+                let metadata = BlobMetadata { generation: old.generation + 1, ..metadata };
+                let staged = metadata.pack.is_none().then_some(data_id);
+                Ok(Written::Replacement(Box::new(Replacement { metadata, old, staged })))
+            }
+            None => Ok(Written::New(Box::new(metadata))),
+        }
+    }
+
+    /// Updates the caches and subscribers once a new blob's metadata is written
+    fn committed(&self, metadata: &BlobMetadata) {
+        self.invalidate_metadata(&metadata.blob_id);
+        if let Some(bloom) = &self.bloom {
+            bloom.lock().unwrap().insert(&metadata.blob_id);
+        }
+        self.notify(&metadata.tenant_id, ChangeEvent::Put { blob_id: metadata.blob_id.clone() });
+    }
+
+    /// Records a metadata-only blob replicated from another node, marking it
+    /// so reads fail with `DataNotLocal`. The record gets a local insertion
+    /// sequence and no pack location, as neither carries over between
+    /// stores. Returns false, changing nothing, if a blob with the id
+    /// already exists here. A blob deleted here that still has a tombstone
+    /// fails with `BlobTombstoned` instead of coming back.
+    pub(crate) fn put_metadata_only(&self, mut metadata: BlobMetadata) -> Result<bool> {
+        self.tenant_manager.validate_tenant(&metadata.tenant_id)?;
+        self.check_tombstone(&metadata.blob_id)?;
+        metadata.metadata_only = true;
+        metadata.pack = None;
+        metadata.seq = self.metadata_store.next_seq()?;
+        if !self.metadata_store.insert_metadata(&metadata)? {
+            return Ok(false);
+        }
+        self.invalidate_metadata(&metadata.blob_id);
+        if let Some(bloom) = &self.bloom {
+            bloom.lock().unwrap().insert(&metadata.blob_id);
+        }
+        self.notify(&metadata.tenant_id, ChangeEvent::Put { blob_id: metadata.blob_id.clone() });
+        Ok(true)
+    }
+
+    /// Stores a new blob and binds `name` to it. For a versioned tenant the
+    /// name moves to the new blob and the versions beyond the tenant's
+    /// `max_versions` are deleted, oldest first; otherwise a taken name fails
+    /// with `BlobAlreadyExists`.
+    pub fn put_named_blob(&self, tenant_id: &TenantId, name: &str, data: impl Read) -> Result<BlobId> {
+        let context = || format!("while putting blob {} for tenant {}", name, tenant_id.as_str());
+        let config = self.get_tenant_config(tenant_id).with_context(context)?;
+        if config.versioned {
+            let blob_id = self.store_blob(tenant_id, data, PutOptions::default()).with_context(context)?;
+            let versions = self.metadata_store.put_version(tenant_id, name, &blob_id).with_context(context)?;
+            if let Some(max_versions) = config.max_versions {
+                let excess = versions.len().saturating_sub(max_versions);
+                self.prune_versions(tenant_id, name, &versions[..excess]).with_context(context)?;
+            }
+            return Ok(blob_id);
+        }
+
+        if self.metadata_store.get_name(tenant_id, name).with_context(context)?.is_some() {
+            return Err(Error::BlobAlreadyExists(name.to_string())).with_context(context);
+        }
+
+        let blob_id = self.store_blob(tenant_id, data, PutOptions::default()).with_context(context)?;
+        if let Err(e) = self.metadata_store.put_name(tenant_id, name, &blob_id) {
+            // Lost a race for the name; don't leave an unreachable blob behind
+            self.remove_blob(tenant_id, &blob_id).with_context(context)?;
+            return Err(e).with_context(context);
+        }
+        Ok(blob_id)
+    }
+
+    /// Stores `data` as a new blob and then repoints `name` at it in one
+    /// step, binding the name if it is free. The name resolves to either the
+    /// old or the new blob throughout: a put that fails leaves it untouched
+    /// and stores nothing. The old blob is then handled per the replace
+    /// policy; on a versioned tenant this is `put_named_blob`, which keeps it
+    /// in the history. Fails with `NameRebound` if another writer repointed
+    /// the name during the put.
+    pub fn replace_named_blob(&self, tenant_id: &TenantId, name: &str, data: impl Read) -> Result<BlobId> {
+        let context = || format!("while replacing blob {} for tenant {}", name, tenant_id.as_str());
+        let config = self.get_tenant_config(tenant_id).with_context(context)?;
+        if config.versioned {
+            return self.put_named_blob(tenant_id, name, data);
+        }
+
+        let old = self.metadata_store.get_name(tenant_id, name).with_context(context)?;
+        let blob_id = self.store_blob(tenant_id, data, PutOptions::default()).with_context(context)?;
+        if let Err(e) = self.metadata_store.replace_name(tenant_id, name, old.as_ref(), &blob_id) {
+            self.remove_blob(tenant_id, &blob_id).with_context(context)?;
+            return Err(e).with_context(context);
+        }
+        if let (Some(old), ReplacePolicy::DeleteOld) = (old, self.replace_policy) {
+            match self.remove_blob(tenant_id, &old) {
+                Ok(()) | Err(Error::RetentionActive { .. }) => {}
+                Err(e) => return Err(e).with_context(context),
+            }
+        }
+        Ok(blob_id)
+    }
+
+    /// Returns the blobs a versioned name has pointed at that are still
+    /// stored, oldest first
+    pub fn list_versions(&self, tenant_id: &TenantId, name: &str) -> Result<Vec<BlobId>> {
+        self.tenant_manager.validate_tenant(tenant_id)?;
+        self.metadata_store.get_versions(tenant_id, name)
+    }
+
+    /// Deletes old versions of a name. Versions still under retention are
+    /// kept in the history and pruned by a later put once they expire.
+    fn prune_versions(&self, tenant_id: &TenantId, name: &str, old_versions: &[BlobId]) -> Result<()> {
+        let mut pruned = Vec::new();
+        for blob_id in old_versions {
+            match self.remove_blob(tenant_id, blob_id) {
+                Ok(()) => pruned.push(blob_id.clone()),
+                Err(Error::RetentionActive { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.metadata_store.remove_versions(tenant_id, name, &pruned)
+    }
+
+    /// Points an additional name at an existing blob of the tenant. The blob
+    /// isn't copied; every name resolves to the same data.
+    pub fn link_name(&self, tenant_id: &TenantId, name: &str, blob_id: &BlobId) -> Result<()> {
+        let context = || {
+            format!("while linking name {} to blob {} for tenant {}", name, blob_id, tenant_id.as_str())
+        };
+        self.owned_metadata(tenant_id, blob_id).with_context(context)?;
+        self.metadata_store.put_name(tenant_id, name, blob_id).with_context(context)
+    }
+
+    /// Removes a name binding without touching the blob or its other names
+    pub fn unlink_name(&self, tenant_id: &TenantId, name: &str) -> Result<()> {
+        let context = || format!("while unlinking name {} for tenant {}", name, tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        match self.metadata_store.remove_name(tenant_id, name).with_context(context)? {
+            Some(_) => Ok(()),
+            None => Err(Error::BlobNotFound(name.to_string())).with_context(context),
+        }
+    }
+
+    /// Lists a tenant's name bindings with the blob each points at
+    pub fn list_names(&self, tenant_id: &TenantId) -> Result<Vec<(String, BlobId)>> {
+        self.tenant_manager
+            .validate_tenant(tenant_id)
+            .and_then(|_| self.metadata_store.list_names(tenant_id))
+            .with_context(|| format!("while listing names for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Retrieves a blob by one of its names
+    pub fn get_named_blob(&self, tenant_id: &TenantId, name: &str) -> Result<BlobReader> {
+        let context = || format!("while getting blob {} for tenant {}", name, tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let blob_id = self
+            .metadata_store
+            .get_name(tenant_id, name)
+            .with_context(context)?
+            .ok_or_else(|| Error::BlobNotFound(name.to_string()))
+            .with_context(context)?;
+        self.open_owned_blob(tenant_id, &blob_id, self.verify_mode).with_context(context)
+    }
+
+    /// Checks whether a tenant owns the given blob. Expired blobs don't exist.
+    pub fn blob_exists(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<bool> {
+        // Validate tenant
+        self.tenant_manager.validate_tenant(tenant_id)?;
+
+        if !self.may_exist(blob_id) {
+            return Ok(false);
+        }
+        match self.get_metadata(blob_id) {
+            Ok(metadata) => Ok(metadata.tenant_id == *tenant_id && !metadata.is_expired_at(self.now())),
+            Err(Error::BlobNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Retrieves a blob
+    pub fn get_blob(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<BlobReader> {
+        self.get_blob_with_mode(tenant_id, blob_id, self.verify_mode)
+    }
+
+    /// Retrieves a blob, verifying its content per `mode` instead of the
+    /// coordinator's default
+    pub fn get_blob_with_mode(&self, tenant_id: &TenantId, blob_id: &BlobId, mode: VerifyMode) -> Result<BlobReader> {
+        let started = Instant::now();
+        let result = self.open_blob(tenant_id, blob_id, mode).with_context(|| {
+            format!("while getting blob {} for tenant {}", blob_id, tenant_id.as_str())
+        });
+        self.warn_if_slow("get", tenant_id, Some(blob_id), started);
+        result
+    }
+
+    /// Retrieves a blob along with its verified size and checksum
+    pub fn get_blob_with_info(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<(BlobReader, BlobInfo)> {
+        let started = Instant::now();
+        let result = self
+            .owned_metadata(tenant_id, blob_id)
+            .and_then(|metadata| {
+                let _pack_guard = self.pack_lock.read().unwrap();
+                let reader = self.read_stored_with(&metadata, self.verify_mode)?;
+                self.record_access(blob_id);
+                Ok((reader, BlobInfo { size: metadata.size, checksum: metadata.checksum }))
+            })
+            .with_context(|| format!("while getting blob {} for tenant {}", blob_id, tenant_id.as_str()));
+        self.warn_if_slow("get", tenant_id, Some(blob_id), started);
+        result
+    }
+
+    /// Retrieves a blob for a client sending `accept_encoding`, the value of
+    /// an HTTP `Accept-Encoding` header. A blob stored compressed with an
+    /// encoding the client accepts is returned as stored, along with the
+    /// `Content-Encoding` to send, sparing a decompress and recompress; any
+    /// other blob is returned decoded with no encoding. Either way the
+    /// content is verified first.
+    pub fn get_blob_encoded(
+        &self,
+        tenant_id: &TenantId,
+        blob_id: &BlobId,
+        accept_encoding: &str,
+    ) -> Result<(BlobReader, Option<&'static str>)> {
+        let started = Instant::now();
+        let result = self
+            .owned_metadata(tenant_id, blob_id)
+            .and_then(|metadata| {
+                let compression = metadata.stored_compression();
+                let encoded = metadata.pack.is_none()
+                    && metadata.chunks.is_none()
+                    && !metadata.metadata_only
+                    && compression.accepted_by(accept_encoding);
+                let _pack_guard = self.pack_lock.read().unwrap();
+                let (reader, encoding) = match metadata.compressed_size {
+                    Some(compressed_size) if encoded => {
+                        let checksum = self.expected_checksum(&metadata)?;
+                        let reader = self.chunk_store.get_blob_encoded(
+                            blob_id,
+                            metadata.tier,
+                            metadata.size,
+                            compressed_size,
+                            compression,
+                            Some(&checksum),
+                        )?;
+                        (reader, compression.content_encoding())
+                    }
+                    _ => (self.read_stored_with(&metadata, self.verify_mode)?, None),
+                };
+                self.record_access(blob_id);
+                Ok((reader, encoding))
+            })
+            .with_context(|| format!("while getting blob {} for tenant {}", blob_id, tenant_id.as_str()));
+        self.warn_if_slow("get", tenant_id, Some(blob_id), started);
+        result
+    }
+
+    /// Retrieves `length` bytes of a blob starting at `offset`. A range
+    /// running past the end of the blob is cut short at its recorded size.
+    /// The bytes aren't checksummed, but if the stored data ends before the
+    /// range does, e.g. because the file was truncated, reading fails with
+    /// `SizeMismatch` instead of returning fewer bytes.
+    pub fn get_blob_range(&self, tenant_id: &TenantId, blob_id: &BlobId, offset: u64, length: u64) -> Result<BlobReader> {
+        let context = || format!("while getting a range of blob {} for tenant {}", blob_id, tenant_id.as_str());
+        let started = Instant::now();
+        let result = self.owned_metadata(tenant_id, blob_id).and_then(|metadata| {
+            Self::check_local(&metadata)?;
+            let offset = offset.min(metadata.size);
+            let end = offset + length.min(metadata.size - offset);
+            let _pack_guard = self.pack_lock.read().unwrap();
+            let mut data: Box<dyn Read + Send> = match (&metadata.pack, metadata.stored_compression()) {
+                (Some(location), _) => Box::new(self.pack_store.get_blob_raw(location)?),
+                (None, Compression::None) => {
+                    let mut file = self.chunk_store.open_raw(blob_id, metadata.tier)?;
+                    file.seek(SeekFrom::Start(offset))?;
+                    return Ok(BlobReader::new(RangeReader::new(file, offset, end)));
+                }
+                (None, compression) => compression.decoder(self.chunk_store.open_raw(blob_id, metadata.tier)?)?,
+            };
+            let skipped = std::io::copy(&mut (&mut data).take(offset), &mut std::io::sink())?;
+            if skipped < offset {
+                return Err(Error::SizeMismatch { expected: end, actual: skipped });
+            }
+            Ok(BlobReader::new(RangeReader::new(data, offset, end)))
+        });
+        if result.is_ok() {
+            self.record_access(blob_id);
+        }
+        self.warn_if_slow("get", tenant_id, Some(blob_id), started);
+        result.with_context(context)
+    }
+
+    /// Retrieves several blobs of one tenant, validating the tenant once.
+    /// Each blob gets its own result so one missing or foreign id doesn't
+    /// fail the whole batch.
+    pub fn get_blobs(
+        &self,
+        tenant_id: &TenantId,
+        blob_ids: &[BlobId],
+    ) -> Result<Vec<(BlobId, Result<BlobReader>)>> {
+        self.tenant_manager
+            .validate_tenant(tenant_id)
+            .with_context(|| format!("while getting blobs for tenant {}", tenant_id.as_str()))?;
+
+        Ok(blob_ids
+            .iter()
+            .map(|blob_id| {
+                let reader = self.open_owned_blob(tenant_id, blob_id, self.verify_mode).with_context(|| {
+                    format!("while getting blob {} for tenant {}", blob_id, tenant_id.as_str())
+                });
+                (blob_id.clone(), reader)
+            })
+            .collect())
+    }
+
+    /// Opens a blob owned by the tenant, verifying its checksum
+    fn open_blob(&self, tenant_id: &TenantId, blob_id: &BlobId, mode: VerifyMode) -> Result<BlobReader> {
+        // Validate tenant
+        self.tenant_manager.validate_tenant(tenant_id)?;
+
+        self.open_owned_blob(tenant_id, blob_id, mode)
+    }
+
+    /// Returns a blob's metadata after checking the tenant exists and owns it
+    pub(crate) fn owned_metadata(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<BlobMetadata> {
+        self.tenant_manager.validate_tenant(tenant_id)?;
+        let metadata = self.get_metadata(blob_id)?;
+        if metadata.tenant_id != *tenant_id {
+            return Err(Error::InvalidTenant(
+                "Blob does not belong to this tenant".into(),
+            ));
+        }
+        if metadata.is_expired_at(self.now()) {
+            return Err(Error::BlobNotFound(blob_id.to_string()));
+        }
+        Ok(metadata)
+    }
+
+    /// Opens a blob after checking the (already validated) tenant owns it
+    fn open_owned_blob(&self, tenant_id: &TenantId, blob_id: &BlobId, mode: VerifyMode) -> Result<BlobReader> {
+        if !self.may_exist(blob_id) {
+            return Err(Error::BlobNotFound(blob_id.to_string()));
+        }
+
+        // Get metadata to verify tenant ownership
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self.get_metadata(blob_id)?;
+        if metadata.tenant_id != *tenant_id {
+            return Err(Error::InvalidTenant(
+                "Blob does not belong to this tenant".into(),
+            ));
+        }
+        if metadata.is_expired_at(self.now()) {
+            return Err(Error::BlobNotFound(blob_id.to_string()));
+        }
+
+        // Get the blob, verifying its size and checksum per `mode`
+        let reader = self.read_stored_with(&metadata, mode)?;
+        self.record_access(blob_id);
+        Ok(reader)
+    }
+
+    /// Opens a blob's data from whichever backend holds it. The stored length
+    /// is checked against the metadata first, so truncation is caught without
+    /// reading the whole blob; then the checksum is verified. Data missing
+    /// despite metadata is reported as `ChunkMissing`, not `BlobNotFound`.
+    fn read_stored(&self, metadata: &BlobMetadata) -> Result<BlobReader> {
+        self.read_stored_with(metadata, VerifyMode::Eager)
+    }
+
+    /// Like `read_stored`, verifying the content per `mode`. Blobs with
+    /// chunk checksums are always verified chunk by chunk as they stream,
+    /// unless `mode` is `None`.
+    fn read_stored_with(&self, metadata: &BlobMetadata, mut mode: VerifyMode) -> Result<BlobReader> {
+        Self::check_local(metadata)?;
+        // Without a recorded checksum every hash would mismatch, which
+        // would wrongly suggest the data is corrupt
+        if metadata.checksum.is_empty() && metadata.chunks.is_none() && mode != VerifyMode::None {
+            match self.missing_checksum {
+                MissingChecksumPolicy::Reject => return Err(Error::MissingChecksum(metadata.blob_id.to_string())),
+                MissingChecksumPolicy::SkipVerification => {
+                    tracing::warn!(blob_id = %metadata.blob_id, "blob has no recorded checksum, reading it unverified");
+                    mode = VerifyMode::None;
+                }
+            }
+        }
+        let result = match (mode, &metadata.chunks) {
+            (VerifyMode::Eager, _) | (VerifyMode::Lazy, Some(_)) => self.read_stored_data(metadata),
+            (VerifyMode::Lazy, None) => self.expected_checksum(metadata).and_then(|checksum| {
+                let data = self.open_stored_data(metadata)?;
+                Ok(BlobReader::new(checksum::VerifyingReader::new(data, metadata.size, checksum)))
+            }),
+            (VerifyMode::None, _) => self.open_stored_data(metadata).map(BlobReader::new),
+        };
+        result.map_err(|e| match e {
+            Error::BlobNotFound(_) => Error::ChunkMissing {
+                blob_id: metadata.blob_id.to_string(),
+            },
+            e => e,
+        })
+    }
+
+    /// Fails with `DataNotLocal` for a metadata-only record
+    fn check_local(metadata: &BlobMetadata) -> Result<()> {
+        if metadata.metadata_only {
+            return Err(Error::DataNotLocal(metadata.blob_id.to_string()));
+        }
+        Ok(())
+    }
+
+    fn read_stored_data(&self, metadata: &BlobMetadata) -> Result<BlobReader> {
+        if let (None, Some(chunks)) = (&metadata.pack, &metadata.chunks) {
+            return self.read_chunk_verified(metadata, chunks);
+        }
+        match &metadata.pack {
+            Some(location) => {
+                let actual = self.pack_store.stored_size(location)?;
+                if actual != metadata.size {
+                    return Err(Error::SizeMismatch {
+                        expected: metadata.size,
+                        actual,
+                    });
+                }
+                self.pack_store.get_blob(location, &metadata.checksum)
+            }
+            None => {
+                // Cached data was verified against the checksum the sidecar
+                // agreed on, so a hit for the recorded one skips the sidecar
+                if metadata.compressed_size.is_none() {
+                    let cached = self.chunk_store.cached(
+                        &metadata.blob_id,
+                        metadata.tier,
+                        Some(metadata.size),
+                        Some(&metadata.checksum),
+                    );
+                    if let Some((reader, _)) = cached {
+                        return Ok(reader);
+                    }
+                }
+                let checksum = self.expected_checksum(metadata)?;
+                match metadata.compressed_size {
+                    Some(compressed_size) => self.chunk_store.get_blob_compressed(
+                        &metadata.blob_id,
+                        metadata.tier,
+                        metadata.size,
+                        compressed_size,
+                        metadata.stored_compression(),
+                        Some(&checksum),
+                    ),
+                    None => {
+                        let (reader, _) = self.chunk_store.get_blob_sized(
+                            &metadata.blob_id,
+                            metadata.tier,
+                            metadata.size,
+                            Some(&checksum),
+                        )?;
+                        Ok(reader)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the checksum a chunk-stored blob's content should have, per
+    /// the configured checksum source
+    fn expected_checksum(&self, metadata: &BlobMetadata) -> Result<Checksum> {
+        if metadata.pack.is_some() || self.checksum_source == ChecksumSource::Metadata {
+            return Ok(metadata.checksum.clone());
+        }
+        let sidecar = match self.chunk_store.read_sidecar(metadata.tier, &metadata.blob_id)? {
+            Some(sidecar) => sidecar,
+            None => return Ok(metadata.checksum.clone()),
+        };
+        // Records under different algorithms can't be compared: `rehash_blob`
+        // replaces the sidecar before the metadata, so the blob is mid-rehash
+        // or a rehash was interrupted, and the metadata still describes it
+        if sidecar.algorithm() != metadata.checksum.algorithm() {
+            return Ok(metadata.checksum.clone());
+        }
+        if self.checksum_source == ChecksumSource::CrossCheck && !sidecar.matches(&metadata.checksum) {
+            // Data that is missing or cut short is the bigger problem, so
+            // it is reported rather than the disagreement it may explain
+            let expected = metadata.compressed_size.unwrap_or(metadata.size);
+            let actual = self.chunk_store.blob_size_in(&metadata.blob_id, metadata.tier)?;
+            if actual != expected {
+                return Err(Error::SizeMismatch { expected, actual });
+            }
+            return Err(Error::ChecksumSourceDisagreement {
+                blob_id: metadata.blob_id.to_string(),
+                sidecar,
+                metadata: metadata.checksum.clone(),
+            });
+        }
+        Ok(sidecar)
+    }
+
+    /// Opens a blob as a plain `File`, for consumers that need `Seek`. The
+    /// size and checksum are verified once, up front; later reads and seeks
+    /// through the handle aren't re-verified. Packed blobs share a file with
+    /// other blobs and can't be opened this way.
+    pub fn open_blob_file(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<File> {
+        let context = || format!("while opening blob {} for tenant {}", blob_id, tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self.get_metadata(blob_id).with_context(context)?;
+        if metadata.tenant_id != *tenant_id {
+            return Err(Error::InvalidTenant("Blob does not belong to this tenant".into()))
+                .with_context(context);
+        }
+        if metadata.pack.is_some() || metadata.compressed_size.is_some() {
+            return Err(Error::System("packed and compressed blobs can't be opened as files".into()))
+                .with_context(context);
+        }
+
+        Self::check_local(&metadata).with_context(context)?;
+
+        // Open the handle before verifying so the checked bytes are the ones it reads
+        let file = self.chunk_store.open_raw(blob_id, metadata.tier).with_context(context)?;
+        self.verify_data(&metadata).with_context(context)?;
+        Ok(file)
+    }
+
+    /// Checks a blob's integrity: its stored size must match its metadata and
+    /// its contents must match the recorded checksum
+    pub fn verify_blob(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<()> {
+        let context = || format!("while verifying blob {} for tenant {}", blob_id, tenant_id.as_str());
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self.owned_metadata(tenant_id, blob_id).with_context(context)?;
+        self.verify_data(&metadata).with_context(context)
+    }
+
+    /// Replaces chunk `index` of a blob stored with chunk checksums, e.g.
+    /// with a copy fetched from a replica after `verify_blob` reported it
+    /// corrupt. `data` must match the chunk's recorded checksum; the rest
+    /// of the blob is left as it is. Compressed and packed blobs can't be
+    /// repaired by chunk.
+    pub fn repair_chunk(&self, tenant_id: &TenantId, blob_id: &BlobId, index: usize, data: &[u8]) -> Result<()> {
+        let context = || format!("while repairing chunk {} of blob {} for tenant {}", index, blob_id, tenant_id.as_str());
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self.owned_metadata(tenant_id, blob_id).with_context(context)?;
+        Self::check_local(&metadata).with_context(context)?;
+        let chunks = match &metadata.chunks {
+            Some(chunks) if metadata.pack.is_none() && metadata.compressed_size.is_none() => chunks,
+            _ => {
+                return Err(Error::System(format!("blob {} isn't stored as checksummed chunks", blob_id)))
+                    .with_context(context)
+            }
+        };
+        let mismatch = || Error::ChunkChecksumMismatch { blob_id: blob_id.to_string(), chunk: index };
+        let expected = chunks.checksums.get(index).ok_or_else(mismatch).with_context(context)?;
+        let offset = index as u64 * chunks.chunk_size;
+        let length = chunks.chunk_size.min(metadata.size.saturating_sub(offset));
+        if data.len() as u64 != length {
+            return Err(Error::SizeMismatch { expected: length, actual: data.len() as u64 }).with_context(context);
+        }
+        if !checksum::constant_time_eq(expected, checksum::compute_sha256(data)?) {
+            return Err(mismatch()).with_context(context);
+        }
+        self.chunk_store.patch_blob(blob_id, metadata.tier, offset, data).with_context(context)
+    }
+
+    /// Verifies every blob in the store, across all tenants. Checks the
+    /// cancellation token between blobs and returns a partial report if it
+    /// fires; nothing is modified, so stopping early is always safe.
+    pub fn verify_all(&self, cancel: &CancellationToken) -> Result<VerifyReport> {
+        let blob_ids = self
+            .metadata_store
+            .all_blob_ids()
+            .with_context(|| "while verifying all blobs".to_string())?;
+
+        let mut report = VerifyReport::default();
+        for blob_id in blob_ids {
+            if cancel.is_cancelled() {
+                report.cancelled = true;
+                break;
+            }
+            if let Err(e) = self.verify_stored(&blob_id) {
+                report.corrupted.push((blob_id, e));
+            }
+            report.checked += 1;
+        }
+        Ok(report)
+    }
+
+    /// Recomputes every blob's checksum under `algorithm`, updating its
+    /// metadata and sidecar without re-uploading the data. Each blob is read
+    /// once, checked against its current checksum and hashed anew in the same
+    /// pass; one that doesn't match keeps its old checksum, so corruption
+    /// isn't given a fresh checksum that hides it. Blobs already using
+    /// `algorithm` are skipped, so a cancelled or interrupted run continues
+    /// where it stopped when started again. Switch new blobs over with
+    /// `with_checksum_algorithm` first, or the migration never finishes.
+    pub fn rehash_all(&self, algorithm: checksum::ChecksumAlgorithm, cancel: &CancellationToken) -> Result<RehashReport> {
+        let context = || format!("while rehashing all blobs to {}", algorithm.name());
+        let blob_ids = self.metadata_store.all_blob_ids().with_context(context)?;
+
+        let mut report = RehashReport::default();
+        for blob_id in blob_ids {
+            if cancel.is_cancelled() {
+                report.cancelled = true;
+                break;
+            }
+            match self.rehash_blob(&blob_id, algorithm) {
+                Ok(true) => report.rehashed += 1,
+                Ok(false) => report.skipped += 1,
+                Err(e) => report.failed.push((blob_id, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Rehashes one blob, returning false if there was nothing to do. The
+    /// sidecar is replaced before the metadata, so a crash in between leaves
+    /// the blob on its old algorithm in metadata and a rerun redoes it.
+    /// Reads in between trust the metadata, see `expected_checksum`.
+    fn rehash_blob(&self, blob_id: &BlobId, algorithm: checksum::ChecksumAlgorithm) -> Result<bool> {
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = match self.metadata_store.get_metadata(blob_id) {
+            Ok(metadata) => metadata,
+            Err(Error::BlobNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if metadata.metadata_only || metadata.checksum.algorithm() == algorithm.name() {
+            return Ok(false);
+        }
+
+        let current = metadata.checksum.supported_algorithm()?;
+        let mut data = checksum::MultiHashingReader::new(self.open_stored_data(&metadata)?, &[current, algorithm].into());
+        let size = std::io::copy(&mut data, &mut std::io::sink())?;
+        let mut checksums = data.finish_checksums();
+        if size != metadata.size {
+            return Err(Error::SizeMismatch { expected: metadata.size, actual: size });
+        }
+        let actual = checksums.remove(&current).expect("hashed with the current algorithm");
+        if !actual.matches(&metadata.checksum) {
+            return Err(Error::ChecksumMismatch { expected: metadata.checksum, actual });
+        }
+
+        let rehashed = checksums.remove(&algorithm).expect("hashed with the new algorithm");
+        if metadata.pack.is_none() {
+            self.chunk_store.replace_sidecar(metadata.tier, blob_id, &rehashed)?;
+        }
+        self.metadata_store.update_metadata_if(blob_id, metadata.generation, |metadata| {
+            metadata.checksum = rehashed.clone();
+        })?;
+        self.invalidate_metadata(blob_id);
+        Ok(true)
+    }
+
+    /// Checks one blob's size and checksum against its stored metadata,
+    /// whichever tenant owns it. Metadata-only records have nothing local
+    /// to check.
+    pub(crate) fn verify_stored(&self, blob_id: &BlobId) -> Result<()> {
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self.metadata_store.get_metadata(blob_id)?;
+        self.verify_record(&metadata)
+    }
+
+    /// Verifies a blob against a metadata record read earlier. Data found
+    /// missing is only reported as `ChunkMissing` if the record still exists:
+    /// a blob deleted since is `BlobNotFound`, and one whose record changed,
+    /// say by moving tier, is checked again against the current record.
+    fn verify_record(&self, metadata: &BlobMetadata) -> Result<()> {
+        if metadata.metadata_only {
+            return Ok(());
+        }
+        match self.verify_data(metadata) {
+            Err(Error::ChunkMissing { .. }) => {
+                let current = self.metadata_store.get_metadata(&metadata.blob_id)?;
+                if current.metadata_only {
+                    return Ok(());
+                }
+                self.verify_data(&current)
+            }
+            result => result,
+        }
+    }
+
+    /// Checks a blob's stored data against its metadata, reading through
+    /// blobs that are only verified as they stream. A chunk file is checked
+    /// as is, never served from the backup root, so damage is reported.
+    fn verify_data(&self, metadata: &BlobMetadata) -> Result<()> {
+        if metadata.checksum.is_empty() && metadata.chunks.is_none() {
+            return Err(Error::MissingChecksum(metadata.blob_id.to_string()));
+        }
+        if metadata.pack.is_none() && metadata.chunks.is_none() {
+            Self::check_local(metadata)?;
+            let compressed = metadata.compressed_size.map(|size| (size, metadata.stored_compression()));
+            let checksum = self.expected_checksum(metadata)?;
+            return self
+                .chunk_store
+                .verify_primary(&metadata.blob_id, metadata.tier, metadata.size, compressed, Some(&checksum))
+                .map_err(|e| match e {
+                    Error::BlobNotFound(_) => Error::ChunkMissing { blob_id: metadata.blob_id.to_string() },
+                    e => e,
+                });
+        }
+        let mut reader = self.read_stored(metadata)?;
+        if metadata.pack.is_some() || metadata.chunks.is_none() {
+            return Ok(());
+        }
+        match std::io::copy(&mut reader, &mut std::io::sink()) {
+            Ok(_) => Ok(()),
+            Err(e) if e.get_ref().is_some_and(|inner| inner.is::<Error>()) => {
+                Err(*e.into_inner().unwrap().downcast::<Error>().unwrap())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Opens a chunk-stored blob whose content is verified chunk by chunk
+    /// as it is read. Only the stored length is checked up front.
+    fn read_chunk_verified(&self, metadata: &BlobMetadata, chunks: &checksum::ChunkChecksums) -> Result<BlobReader> {
+        let data = self.open_stored_data(metadata)?;
+        Ok(BlobReader::new(checksum::ChunkVerifyingReader::new(
+            data,
+            metadata.blob_id.to_string(),
+            chunks.clone(),
+        )))
+    }
+
+    /// Opens a blob's decoded content without verifying it, after checking
+    /// only that the stored length matches its metadata
+    fn open_stored_data(&self, metadata: &BlobMetadata) -> Result<Box<dyn Read + Send>> {
+        if let Some(location) = &metadata.pack {
+            let actual = self.pack_store.stored_size(location)?;
+            if actual != metadata.size {
+                return Err(Error::SizeMismatch { expected: metadata.size, actual });
+            }
+            return Ok(Box::new(self.pack_store.get_blob_raw(location)?));
+        }
+        let expected = metadata.compressed_size.unwrap_or(metadata.size);
+        let actual = self.chunk_store.blob_size_in(&metadata.blob_id, metadata.tier)?;
+        if actual != expected {
+            return Err(Error::SizeMismatch { expected, actual });
+        }
+        let file = self.chunk_store.open_raw(&metadata.blob_id, metadata.tier)?;
+        Ok(metadata.stored_compression().decoder(file)?)
+    }
+
+    /// Lists the ids of every blob in the store
+    pub(crate) fn all_blob_ids(&self) -> Result<Vec<BlobId>> {
+        self.metadata_store.all_blob_ids()
+    }
+
+    /// Audits the whole store for metadata without data, data without
+    /// metadata, blobs owned by unregistered tenants and tenant memberships
+    /// without metadata. Nothing is modified, so it is safe to run anytime,
+    /// but uploads in flight may show up as orphan chunks.
+    pub fn check_consistency(&self) -> Result<ConsistencyReport> {
+        let context = || "while checking store consistency".to_string();
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let mut report = ConsistencyReport::default();
+
+        let blob_ids: HashSet<BlobId> = self.metadata_store.all_blob_ids().with_context(context)?.into_iter().collect();
+        for blob_id in &blob_ids {
+            let metadata = match self.metadata_store.get_metadata(blob_id) {
+                Ok(metadata) => metadata,
+                Err(Error::BlobNotFound(_)) => continue,
+                Err(e) => return Err(e).with_context(context),
+            };
+            let stored = match &metadata.pack {
+                _ if metadata.metadata_only => Ok(0),
+                Some(location) => self.pack_store.stored_size(location),
+                None => self.chunk_store.blob_size_in(blob_id, metadata.tier),
+            };
+            match stored {
+                Ok(_) => {}
+                Err(Error::BlobNotFound(_)) => report.missing_chunks.push(blob_id.clone()),
+                Err(e) => return Err(e).with_context(context),
+            }
+            if !self.tenant_manager.tenant_exists(&metadata.tenant_id).with_context(context)? {
+                report.unregistered_tenants.push((blob_id.clone(), metadata.tenant_id));
+            }
+        }
+
+        let mut tiers = vec![Tier::Hot];
+        if self.chunk_store.has_cold_tier() {
+            tiers.push(Tier::Cold);
+        }
+        for tier in tiers {
+            for blob_id in self.chunk_store.list_blob_ids(tier).with_context(context)? {
+                if !blob_ids.contains(&blob_id) && !report.orphan_chunks.contains(&blob_id) {
+                    report.orphan_chunks.push(blob_id);
+                }
+            }
+        }
+
+        for (tenant_id, blob_id) in self.metadata_store.all_memberships().with_context(context)? {
+            if !blob_ids.contains(&blob_id) {
+                report.dangling_members.push((tenant_id, blob_id));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Fixes the problems found by `check_consistency`: metadata whose data
+    /// is gone is deleted, orphan chunks are deleted, missing tenants are
+    /// registered again and dangling memberships are removed. Each problem
+    /// is re-checked first, so a stale report is harmless. Chunk files
+    /// written within `orphan_grace`, reserved by a pending upload or staged
+    /// by an overwrite are left alone, as their metadata may not be
+    /// committed yet. Returns the number of problems fixed.
+    pub fn repair(&self, report: &ConsistencyReport, orphan_grace: Duration) -> Result<usize> {
+        let context = || "while repairing store consistency".to_string();
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let mut repaired = 0;
+
+        for blob_id in &report.missing_chunks {
+            let metadata = match self.metadata_store.get_metadata(blob_id) {
+                Ok(metadata) => metadata,
+                Err(Error::BlobNotFound(_)) => continue,
+                Err(e) => return Err(e).with_context(context),
+            };
+            let stored = match &metadata.pack {
+                Some(location) => self.pack_store.stored_size(location),
+                None => self.chunk_store.blob_size_in(blob_id, metadata.tier),
+            };
+            if let Err(Error::BlobNotFound(_)) = stored {
+                self.metadata_store.delete_metadata(blob_id, &metadata.tenant_id).with_context(context)?;
+                self.invalidate_metadata(blob_id);
+                if let Some(bloom) = &self.bloom {
+                    bloom.lock().unwrap().remove(blob_id);
+                }
+                self.notify(&metadata.tenant_id, ChangeEvent::Delete { blob_id: blob_id.clone() });
+                repaired += 1;
+            }
+        }
+
+        let staged: HashSet<BlobId> = if report.orphan_chunks.is_empty() {
+            HashSet::new()
+        } else {
+            let replaces = self.metadata_store.unfinished_replaces().with_context(context)?;
+            replaces.into_iter().map(|(staged, _)| staged).collect()
+        };
+        let mut tiers = vec![Tier::Hot];
+        if self.chunk_store.has_cold_tier() {
+            tiers.push(Tier::Cold);
+        }
+        for blob_id in &report.orphan_chunks {
+            match self.metadata_store.get_metadata(blob_id) {
+                Err(Error::BlobNotFound(_)) => {}
+                Ok(_) => continue,
+                Err(e) => return Err(e).with_context(context),
+            }
+            if staged.contains(blob_id) || self.metadata_store.get_pending(blob_id).with_context(context)?.is_some() {
+                continue;
+            }
+            let mut removed = false;
+            for &tier in &tiers {
+                match self.chunk_store.blob_age_in(blob_id, tier) {
+                    Ok(age) if age >= orphan_grace => {
+                        self.chunk_store.remove_from_tier(blob_id, tier).with_context(context)?;
+                        removed = true;
+                    }
+                    Ok(_) | Err(Error::BlobNotFound(_)) => {}
+                    Err(e) => return Err(e).with_context(context),
+                }
+            }
+            if removed {
+                repaired += 1;
+            }
+        }
+
+        for (_, tenant_id) in &report.unregistered_tenants {
+            if !self.tenant_manager.tenant_exists(tenant_id).with_context(context)? {
+                self.tenant_manager.register_tenant(tenant_id.clone()).with_context(context)?;
+                repaired += 1;
+            }
+        }
+
+        for (tenant_id, blob_id) in &report.dangling_members {
+            match self.metadata_store.get_metadata(blob_id) {
+                Err(Error::BlobNotFound(_)) => {
+                    if self.metadata_store.remove_membership(tenant_id, blob_id).with_context(context)? {
+                        repaired += 1;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => return Err(e).with_context(context),
+            }
+        }
+        Ok(repaired)
+    }
+
+    /// Resolves where a blob's files live under the current layout, including
+    /// its tier and whether it is packed
+    pub fn blob_location(&self, blob_id: &BlobId) -> Result<BlobLocation> {
+        let metadata = self
+            .get_metadata(blob_id)
+            .with_context(|| format!("while locating blob {}", blob_id))?;
+        Ok(match metadata.pack {
+            Some(location) => BlobLocation::Packed {
+                pack_path: self.pack_store.pack_path(location.pack_id),
+                offset: location.offset,
+                length: location.length,
+            },
+            None => BlobLocation::File {
+                blob_path: self.chunk_store.blob_path_in(metadata.tier, blob_id),
+                checksum_path: self
+                    .chunk_store
+                    .has_sidecars()
+                    .then(|| self.chunk_store.checksum_path_in(metadata.tier, blob_id)),
+            },
+        })
+    }
+
+    /// Retrieves a blob by id alone, regardless of which tenant owns it.
+    /// Only available when admin mode is enabled.
+    pub fn admin_get_blob(&self, blob_id: &BlobId) -> Result<(BlobReader, BlobMetadata)> {
+        if !self.admin {
+            return Err(Error::PermissionDenied("admin mode is not enabled".into()));
+        }
+
+        // Metadata records the owning tenant, so no tenant is needed to find the blob
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self
+            .get_metadata(blob_id)
+            .with_context(|| format!("while getting blob {} as admin", blob_id))?;
+        let reader = self
+            .read_stored(&metadata)
+            .with_context(|| format!("while getting blob {} as admin", blob_id))?;
+        Ok((reader, metadata))
+    }
+
+    /// Returns whatever bytes are stored for a blob, skipping the size and
+    /// checksum checks, so data can be salvaged from a corrupt blob. The
+    /// output may be truncated or damaged, and compressed blobs are returned
+    /// still compressed. Only available in admin mode.
+    pub fn recover_blob(&self, blob_id: &BlobId) -> Result<(BlobReader, BlobMetadata)> {
+        if !self.admin {
+            return Err(Error::PermissionDenied("admin mode is not enabled".into()));
+        }
+
+        let context = || format!("while recovering blob {}", blob_id);
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let metadata = self.get_metadata(blob_id).with_context(context)?;
+        let reader = match &metadata.pack {
+            Some(location) => self.pack_store.get_blob_raw(location),
+            None => self
+                .chunk_store
+                .open_raw(blob_id, metadata.tier)
+                .map(BlobReader::new),
+        }
+        .with_context(context)?;
+        tracing::warn!(blob_id = %blob_id, "reading blob without verification for recovery");
+        Ok((reader, metadata))
+    }
+
+    /// Lists all blobs for a tenant in insertion order
+    pub fn list_blobs(&self, tenant_id: &TenantId) -> Result<Vec<BlobMetadata>> {
+        // Skip blobs whose metadata can't be read
+        let blobs = self
+            .iter_blobs(tenant_id)
+            .with_context(|| format!("while listing blobs for tenant {}", tenant_id.as_str()))?;
+        let mut blobs: Vec<_> = blobs
+            .filter_map(|metadata| metadata.ok())
+            .filter(|metadata| !metadata.is_expired_at(self.now()))
+            .filter(|metadata| !matches!(self.tombstone(&metadata.blob_id), Ok(Some(_))))
+            .collect();
+        blobs.sort_by_key(|metadata| metadata.seq);
+        Ok(blobs)
+    }
+
+    /// Lists a tenant's blobs of `min` to `max` bytes inclusive, either bound
+    /// optional, smallest first
+    pub fn list_blobs_by_size(
+        &self,
+        tenant_id: &TenantId,
+        min: Option<u64>,
+        max: Option<u64>,
+    ) -> Result<Vec<BlobMetadata>> {
+        let context = || format!("while listing blobs by size for tenant {}", tenant_id.as_str());
+        let in_range = |size: u64| min.is_none_or(|min| size >= min) && max.is_none_or(|max| size <= max);
+        if !self.metadata_store.has_size_index() {
+            let mut blobs = self.list_blobs(tenant_id).with_context(context)?;
+            blobs.retain(|metadata| in_range(metadata.size));
+            blobs.sort_by_key(|metadata| metadata.size);
+            return Ok(blobs);
+        }
+
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let blob_ids = self.metadata_store.find_by_size(tenant_id, min, max).with_context(context)?;
+        let now = self.now();
+        Ok(blob_ids
+            .iter()
+            .filter_map(|blob_id| self.get_metadata(blob_id).ok())
+            .filter(|metadata| !metadata.is_expired_at(now) && in_range(metadata.size))
+            .collect())
+    }
+
+    /// Lists the ids of a tenant's blobs straight from its membership keys,
+    /// without reading any blob metadata. Unlike `list_blobs` the ids aren't
+    /// in insertion order and include expired blobs not yet purged.
+    pub fn list_blob_ids(&self, tenant_id: &TenantId) -> Result<Vec<BlobId>> {
+        self.tenant_manager
+            .validate_tenant(tenant_id)
+            .and_then(|_| self.metadata_store.get_tenant_blobs(tenant_id))
+            .with_context(|| format!("while listing blob ids for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Returns the total size of a tenant's blobs in bytes. Reads a counter
+    /// kept up to date by every put, delete and move rather than summing
+    /// blob records; expired blobs count until they are purged.
+    pub fn tenant_usage(&self, tenant_id: &TenantId) -> Result<u64> {
+        self.tenant_manager
+            .validate_tenant(tenant_id)
+            .and_then(|_| self.metadata_store.tenant_usage(tenant_id))
+            .with_context(|| format!("while reading usage for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Returns the total uncompressed size of a tenant's live blobs over the
+    /// bytes they take on disk; 1.0 when none are compressed or it has none
+    pub fn compression_ratio(&self, tenant_id: &TenantId) -> Result<f64> {
+        let context = || format!("while computing the compression ratio for tenant {}", tenant_id.as_str());
+        let (mut size, mut stored) = (0u64, 0u64);
+        for metadata in self.iter_blobs(tenant_id).with_context(context)? {
+            let metadata = metadata.with_context(context)?;
+            size += metadata.size;
+            stored += metadata.compressed_size.unwrap_or(metadata.size);
+        }
+        Ok(if stored > 0 { size as f64 / stored as f64 } else { 1.0 })
+    }
+
+    /// Rebuilds a tenant's usage counter from its blob records, for repair or
+    /// for stores written before the counter existed. Returns the new total.
+    pub fn recompute_usage(&self, tenant_id: &TenantId) -> Result<u64> {
+        self.tenant_manager
+            .validate_tenant(tenant_id)
+            .and_then(|_| self.metadata_store.recompute_usage(tenant_id))
+            .with_context(|| format!("while recomputing usage for tenant {}", tenant_id.as_str()))
+    }
+
+    /// Deletes a tenant's expired blobs, returning how many were removed.
+    /// Expired blobs still under retention are kept until it ends.
+    pub fn purge_expired(&self, tenant_id: &TenantId) -> Result<usize> {
+        let context = || format!("while purging expired blobs for tenant {}", tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let expired: Vec<_> = self
+            .metadata_store
+            .iter_tenant_metadata(tenant_id)
+            .with_context(context)?
+            .filter_map(|metadata| metadata.ok())
+            .filter(|metadata| metadata.is_expired_at(self.now()))
+            .collect();
+        let mut purged = 0;
+        for metadata in expired {
+            match self.remove_blob(tenant_id, &metadata.blob_id) {
+                Ok(()) => purged += 1,
+                Err(Error::RetentionActive { .. }) => {}
+                Err(e) => return Err(e).with_context(context),
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Lists a tenant's live blobs in insertion order with only the
+    /// requested fields populated. A size-only projection skips the
+    /// checksum, tags and other strings while parsing each record.
+    pub fn list_blobs_projected(
+        &self,
+        tenant_id: &TenantId,
+        fields: &[MetadataField],
+    ) -> Result<Vec<PartialMetadata>> {
+        let context = || format!("while listing blobs for tenant {}", tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let blob_ids = self.metadata_store.get_tenant_blobs(tenant_id).with_context(context)?;
+        let size_only = fields.iter().all(|field| *field == MetadataField::Size);
+        let now = self.now();
+        let expired = |expires_at: Option<DateTime<Utc>>| expires_at.is_some_and(|expires_at| expires_at <= now);
+
+        // Skip blobs whose metadata can't be read, as list_blobs does
+        let mut blobs: Vec<(u64, PartialMetadata)> = blob_ids
+            .into_iter()
+            .filter_map(|blob_id| {
+                if size_only {
+                    let record: SizeRecord = self.metadata_store.get_record(&blob_id).ok()?;
+                    if expired(record.expires_at) {
+                        return None;
+                    }
+                    let partial = PartialMetadata {
+                        blob_id,
+                        size: (!fields.is_empty()).then_some(record.size),
+                        checksum: None,
+                        created_at: None,
+                        tags: None,
+                        content_type: None,
+                        last_accessed: None,
+                    };
+                    Some((record.seq, partial))
+                } else {
+                    let metadata = self.metadata_store.get_metadata(&blob_id).ok()?;
+                    if metadata.is_expired_at(now) {
+                        return None;
+                    }
+                    Some((metadata.seq, PartialMetadata::project(metadata, fields)))
+                }
+            })
+            .collect();
+        blobs.sort_by_key(|(seq, _)| *seq);
+        Ok(blobs.into_iter().map(|(_, partial)| partial).collect())
+    }
+
+    /// Lists a tenant's live blobs with their recorded checksums, in
+    /// insertion order, for auditing against an external ledger. Only those
+    /// fields are parsed from metadata and no blob data is opened.
+    pub fn checksum_manifest(&self, tenant_id: &TenantId) -> Result<Vec<(BlobId, Checksum)>> {
+        let context = || format!("while listing checksums for tenant {}", tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let blob_ids = self.metadata_store.get_tenant_blobs(tenant_id).with_context(context)?;
+        let now = self.now();
+        let mut entries = Vec::with_capacity(blob_ids.len());
+        for blob_id in blob_ids {
+            let record: ChecksumRecord = match self.metadata_store.get_record(&blob_id) {
+                Ok(record) => record,
+                Err(Error::BlobNotFound(_)) => continue,
+                Err(e) => return Err(e).with_context(context),
+            };
+            if record.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                continue;
+            }
+            entries.push((record.seq, blob_id, record.checksum));
+        }
+        entries.sort_by_key(|(seq, _, _)| *seq);
+        Ok(entries.into_iter().map(|(_, blob_id, checksum)| (blob_id, checksum)).collect())
+    }
+
+    /// Streams `checksum_manifest` for every tenant, one tenant at a time,
+    /// so a whole-store audit never holds more than one tenant's entries
+    pub fn iter_checksum_manifest(&self) -> Result<impl Iterator<Item = Result<(TenantId, BlobId, Checksum)>> + '_> {
+        let tenants = self.list_tenants().with_context(|| "while listing checksums".to_string())?;
+        Ok(tenants.into_iter().flat_map(move |tenant_id| match self.checksum_manifest(&tenant_id) {
+            Ok(entries) => entries
+                .into_iter()
+                .map(|(blob_id, checksum)| Ok((tenant_id.clone(), blob_id, checksum)))
+                .collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)],
+        }))
+    }
+
+    /// Iterates over a tenant's live blobs, fetching each blob's metadata
+    /// lazily so callers can stop early or process large tenants in constant
+    /// memory. Blobs that have expired are skipped.
+    pub fn iter_blobs(
+        &self,
+        tenant_id: &TenantId,
+    ) -> Result<impl Iterator<Item = Result<BlobMetadata>> + '_> {
+        // Validate tenant
+        self.tenant_manager.validate_tenant(tenant_id)?;
+
+        let now = self.now();
+        let blobs = self.metadata_store.iter_tenant_metadata(tenant_id)?;
+        Ok(blobs.filter(move |metadata| metadata.as_ref().map_or(true, |metadata| !metadata.is_expired_at(now))))
+    }
+
+    /// Iterates over a tenant's live blobs with their data, in the same
+    /// order as `iter_blobs`. The first chunk of up to `readahead` upcoming
+    /// blobs is read on a few background threads while the caller processes
+    /// the current one, which overlaps I/O with work for large scans while
+    /// bounding memory; 0 reads each blob on demand.
+    pub fn scan_blobs(&self, tenant_id: &TenantId, readahead: usize) -> Result<BlobScan<'_>> {
+        let blobs = self
+            .iter_blobs(tenant_id)
+            .with_context(|| format!("while scanning blobs of tenant {}", tenant_id.as_str()))?;
+        Ok(BlobScan {
+            coordinator: self,
+            tenant_id: tenant_id.clone(),
+            blobs: Box::new(blobs),
+            readahead,
+            pending: VecDeque::new(),
+            jobs: None,
+        })
+    }
+
+    /// Transfers a blob from one tenant to another without copying its data
+    pub fn move_blob(&self, from_tenant: &TenantId, to_tenant: &TenantId, blob_id: &BlobId) -> Result<()> {
+        let context = || {
+            format!(
+                "while moving blob {} from tenant {} to tenant {}",
+                blob_id,
+                from_tenant.as_str(),
+                to_tenant.as_str()
+            )
+        };
+        let _permit = self.acquire_permit().with_context(context)?;
+        self.tenant_manager.validate_tenant(from_tenant).with_context(context)?;
+        self.tenant_manager.validate_tenant(to_tenant).with_context(context)?;
+
+        // Ownership and retention are checked within the move's transaction
+        let moved = self.metadata_store.move_blob(blob_id, from_tenant, to_tenant, self.now());
+        self.invalidate_metadata(blob_id);
+        moved.with_context(context)?;
+        self.notify(from_tenant, ChangeEvent::Delete { blob_id: blob_id.clone() });
+        self.notify(to_tenant, ChangeEvent::Put { blob_id: blob_id.clone() });
+        Ok(())
+    }
+
+    /// Copies a blob to a new id owned by `to_tenant`, which may be the
+    /// blob's own tenant. The copy keeps the content, tags and content type
+    /// but not the source's retention or expiry. Chunk-stored blobs are
+    /// hard-linked rather than copied when `with_linked_copies` is on.
+    pub fn copy_blob(&self, from_tenant: &TenantId, to_tenant: &TenantId, blob_id: &BlobId) -> Result<BlobId> {
+        let context = || {
+            format!(
+                "while copying blob {} from tenant {} to tenant {}",
+                blob_id,
+                from_tenant.as_str(),
+                to_tenant.as_str()
+            )
+        };
+        let _permit = self.acquire_permit().with_context(context)?;
+        self.tenant_manager.validate_tenant(to_tenant).with_context(context)?;
+        let source = self.owned_metadata(from_tenant, blob_id).with_context(context)?;
+        Self::check_local(&source).with_context(context)?;
+        self.check_free_space().with_context(context)?;
+        self.check_blob_limit(to_tenant).with_context(context)?;
+
+        let copy_id = BlobId::new();
+        let _pack_guard = self.pack_lock.read().unwrap();
+        let pack = match &source.pack {
+            Some(location) => {
+                let data = self.pack_store.get_blob_raw(location).with_context(context)?;
+                Some(self.pack_store.append(data).with_context(context)?.0)
+            }
+            None => {
+                self.chunk_store
+                    .copy_blob(blob_id, &copy_id, source.tier, self.link_copies)
+                    .with_context(context)?;
+                None
+            }
+        };
+
+        let metadata = BlobMetadata {
+            blob_id: copy_id.clone(),
+            tenant_id: to_tenant.clone(),
+            created_at: self.now(),
+            seq: self.metadata_store.next_seq().with_context(context)?,
+            pack,
+            retain_until: None,
+            expires_at: None,
+            generation: 0,
+            last_accessed: None,
+            ..source
+        };
+        let limit = self.max_blobs_per_tenant;
+        if let Err(e) = self.metadata_store.put_metadata_many_within(std::slice::from_ref(&metadata), limit) {
+            self.discard_uncommitted(&metadata);
+            return Err(e).with_context(context);
+        }
+        self.invalidate_metadata(&copy_id);
+        if let Some(bloom) = &self.bloom {
+            bloom.lock().unwrap().insert(&copy_id);
+        }
+        self.notify(to_tenant, ChangeEvent::Put { blob_id: copy_id.clone() });
+        Ok(copy_id)
+    }
+
+    /// Resolves a prefix of a blob's hex digits, such as the output of
+    /// `BlobId::short`, to the one tenant blob it matches. Hyphens and case
+    /// are ignored. Fails with `BlobNotFound` if nothing matches and
+    /// `AmbiguousBlobId` if several blobs do.
+    pub fn resolve_blob_id(&self, tenant_id: &TenantId, prefix: &str) -> Result<BlobId> {
+        let context = || format!("while resolving blob id {} for tenant {}", prefix, tenant_id.as_str());
+        let normalized = prefix.replace('-', "").to_ascii_lowercase();
+        if normalized.is_empty() || !normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::BlobNotFound(prefix.to_string())).with_context(context);
+        }
+
+        let mut matches: Vec<BlobId> = self
+            .list_blob_ids(tenant_id)
+            .with_context(context)?
+            .into_iter()
+            .filter(|blob_id| blob_id.as_uuid().simple().to_string().starts_with(&normalized))
+            .collect();
+        match matches.len() {
+            0 => Err(Error::BlobNotFound(prefix.to_string())).with_context(context),
+            1 => Ok(matches.remove(0)),
+            count => {
+                Err(Error::AmbiguousBlobId { prefix: prefix.to_string(), matches: count }).with_context(context)
+            }
+        }
+    }
+
+    /// Finds the tenant's blobs whose content has the given checksum, which
+    /// may be bare or `sha256:`-prefixed and in any supported encoding.
+    /// Duplicated content yields several ids.
+    pub fn find_by_checksum(&self, tenant_id: &TenantId, checksum: &str) -> Result<Vec<BlobId>> {
+        let context = || format!("while finding blobs by checksum for tenant {}", tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let checksum = checksum::parse_checksum(checksum).with_context(context)?;
+        let blob_ids = self.metadata_store.find_by_checksum(&checksum).with_context(context)?;
+        self.retain_tenant_blobs(tenant_id, blob_ids).with_context(context)
+    }
+
+    /// Finds the tenant's blobs carrying `value` among the values of tag `key`
+    pub fn find_by_tag(&self, tenant_id: &TenantId, key: &str, value: &str) -> Result<Vec<BlobId>> {
+        let context = || format!("while finding blobs tagged {}={} for tenant {}", key, value, tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let blob_ids = self.metadata_store.find_by_tag(key, value).with_context(context)?;
+        self.retain_tenant_blobs(tenant_id, blob_ids).with_context(context)
+    }
+
+    /// Finds the tenant's blobs matching every `(key, value)` filter, as with
+    /// `find_by_tag`. No filters match nothing.
+    pub fn find_by_tags(&self, tenant_id: &TenantId, filters: &[(String, String)]) -> Result<Vec<BlobId>> {
+        let context = || format!("while finding blobs by tags for tenant {}", tenant_id.as_str());
+        self.tenant_manager.validate_tenant(tenant_id).with_context(context)?;
+        let blob_ids = self.metadata_store.find_by_tags(filters).with_context(context)?;
+        self.retain_tenant_blobs(tenant_id, blob_ids).with_context(context)
+    }
+
+    /// Keeps the tenant's live blobs out of ids found in a store-wide index
+    fn retain_tenant_blobs(&self, tenant_id: &TenantId, blob_ids: Vec<BlobId>) -> Result<Vec<BlobId>> {
+        let mut owned = Vec::new();
+        for blob_id in blob_ids {
+            match self.get_metadata(&blob_id) {
+                Ok(metadata) if metadata.tenant_id == *tenant_id && !metadata.is_expired_at(self.now()) => owned.push(blob_id),
+                Ok(_) | Err(Error::BlobNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(owned)
+    }
+
+    /// Deletes the tenant's blobs created longer ago than its `retention_days`
+    /// policy, returning how many were deleted. Does nothing for tenants
+    /// without a policy. Blobs under write-once retention are left alone.
+    pub fn enforce_retention(&self, tenant_id: &TenantId) -> Result<usize> {
+        let context = || format!("while enforcing the retention policy of tenant {}", tenant_id.as_str());
+        let config = self.tenant_manager.get_tenant_config(tenant_id).with_context(context)?;
+        let Some(retention_days) = config.retention_days else {
+            return Ok(0);
+        };
+        let cutoff = self.now() - chrono::Duration::days(retention_days.into());
+
+        let aged: Vec<_> = self
+            .metadata_store
+            .iter_tenant_metadata(tenant_id)
+            .with_context(context)?
+            .filter_map(|metadata| metadata.ok())
+            .filter(|metadata| metadata.created_at < cutoff)
+            .collect();
+        let mut deleted = 0;
+        for metadata in aged {
+            match self.remove_blob(tenant_id, &metadata.blob_id) {
+                Ok(()) => deleted += 1,
+                Err(Error::RetentionActive { .. }) => {}
+                Err(e) => return Err(e).with_context(context),
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Applies `enforce_retention` to every tenant, returning the total
+    /// number of blobs deleted
+    pub fn enforce_retention_all(&self) -> Result<usize> {
+        let mut deleted = 0;
+        for tenant_id in self.list_tenants().with_context(|| "while enforcing retention policies".to_string())? {
+            deleted += self.enforce_retention(&tenant_id)?;
+        }
+        Ok(deleted)
+    }
+
+    /// Flushes the metadata db so sled can reclaim log segments left behind by
+    /// deleted and overwritten records, reporting its size before and after.
+    /// This may rewrite a lot of data, so run it during quiet periods.
+    pub fn compact_metadata(&self) -> Result<CompactReport> {
+        let context = || "while compacting metadata".to_string();
+        let before_bytes = self.metadata_store.size_on_disk().with_context(context)?;
+        self.metadata_store.flush().with_context(context)?;
+        let after_bytes = self.metadata_store.size_on_disk().with_context(context)?;
+        Ok(CompactReport { before_bytes, after_bytes })
+    }
+
+    /// Reclaims pack space left behind by deleted blobs. Seals the active
+    /// pack, copies the live blobs out of every pack with free regions into
+    /// a fresh pack and removes the old one. Returns the bytes reclaimed.
+    pub fn compact_packs(&self) -> Result<u64> {
+        let context = || "while compacting packs".to_string();
+        let _pack_guard = self.pack_lock.write().unwrap();
+        let active = self.pack_store.seal().with_context(context)?;
+
+        // Group live packed blobs by the sealed pack holding them
+        let mut live: BTreeMap<u64, Vec<BlobMetadata>> = BTreeMap::new();
+        for blob_id in self.metadata_store.all_blob_ids().with_context(context)? {
+            let metadata = match self.metadata_store.get_metadata(&blob_id) {
+                Ok(metadata) => metadata,
+                Err(Error::BlobNotFound(_)) => continue,
+                Err(e) => return Err(e).with_context(context),
+            };
+            if let Some(location) = metadata.pack.filter(|location| location.pack_id < active) {
+                live.entry(location.pack_id).or_default().push(metadata);
+            }
+        }
+
+        let mut reclaimed = 0;
+        for pack_id in self.pack_store.pack_ids().with_context(context)? {
+            if pack_id >= active {
+                continue;
+            }
+            let blobs = live.remove(&pack_id).unwrap_or_default();
+            let pack_len = self.pack_store.pack_len(pack_id).with_context(context)?;
+            let live_bytes: u64 = blobs.iter().filter_map(|m| m.pack).map(|l| l.length).sum();
+            if live_bytes >= pack_len {
+                continue;
+            }
+
+            for metadata in blobs {
+                let Some(from) = metadata.pack else { continue };
+                let to = self.pack_store.relocate(&from).with_context(context)?;
+                self.metadata_store
+                    .relocate_packed(&metadata.blob_id, &from, &to)
+                    .with_context(context)?;
+                self.invalidate_metadata(&metadata.blob_id);
+            }
+            self.pack_store.remove_pack(pack_id).with_context(context)?;
+            reclaimed += pack_len - live_bytes;
+        }
+
+        Ok(reclaimed)
+    }
+
+    /// Runs periodic housekeeping: garbage collection, then metadata
+    /// compaction, then pack compaction, each skippable via `options`. The
+    /// token is checked between phases and between items of garbage
+    /// collection; a cancelled run reports the work done so far.
+    pub fn optimize(&self, options: OptimizeOptions, cancel: &CancellationToken) -> Result<OptimizeReport> {
+        let mut report = OptimizeReport::default();
+        if options.gc {
+            report.gc = Some(self.collect_garbage(options.orphan_grace, cancel)?);
+        }
+        if cancel.is_cancelled() {
+            report.cancelled = true;
+            return Ok(report);
+        }
+        if options.compact_metadata {
+            report.metadata = Some(self.compact_metadata()?);
+        }
+        if cancel.is_cancelled() {
+            report.cancelled = true;
+            return Ok(report);
+        }
+        if options.compact_packs {
+            report.packs = Some(self.compact_packs()?);
+        }
+        Ok(report)
+    }
+
+    /// Purges every tenant's expired blobs, then deletes chunk files older
+    /// than `orphan_grace` that no metadata or pending upload points at, and
+    /// membership entries whose blob record is gone
+    fn collect_garbage(&self, orphan_grace: Duration, cancel: &CancellationToken) -> Result<GcReport> {
+        let mut report = GcReport::default();
+        for tenant_id in self.list_tenants()? {
+            if cancel.is_cancelled() {
+                return Ok(report);
+            }
+            report.expired_blobs += self.purge_expired(&tenant_id)?;
+        }
+
+        let consistency = self.check_consistency()?;
+        for blob_id in consistency.orphan_chunks {
+            if cancel.is_cancelled() {
+                return Ok(report);
+            }
+            let orphan = ConsistencyReport { orphan_chunks: vec![blob_id], ..ConsistencyReport::default() };
+            report.orphan_chunks += self.repair(&orphan, orphan_grace)?;
+        }
+
+        if !cancel.is_cancelled() {
+            let dangling = ConsistencyReport {
+                dangling_members: consistency.dangling_members,
+                ..ConsistencyReport::default()
+            };
+            report.dangling_members = self.repair(&dangling, orphan_grace)?;
+        }
+        Ok(report)
+    }
+
+    /// Moves blobs created more than `older_than` ago from the hot tier to the
+    /// cold tier, returning how many were moved. Packed blobs aren't tiered.
+    /// Fails if no cold root is configured. The token is checked between
+    /// blobs, so a cancelled run leaves each blob wholly in one tier.
+    pub fn migrate_cold(&self, older_than: chrono::Duration, cancel: &CancellationToken) -> Result<usize> {
+        let context = || "while migrating blobs to the cold tier".to_string();
+        if !self.chunk_store.has_cold_tier() {
+            return Err(Error::System("no cold root is configured".into())).with_context(context);
+        }
+        let cutoff = self.now() - older_than;
+
+        let mut migrated = 0;
+        for blob_id in self.metadata_store.all_blob_ids().with_context(context)? {
+            if cancel.is_cancelled() {
+                break;
+            }
+            let metadata = match self.metadata_store.get_metadata(&blob_id) {
+                Ok(metadata) => metadata,
+                Err(Error::BlobNotFound(_)) => continue,
+                Err(e) => return Err(e).with_context(context),
+            };
+            if metadata.tier != Tier::Hot || metadata.pack.is_some() || metadata.created_at > cutoff {
+                continue;
+            }
+
+            // Copy first, then flip the marker, then drop the hot copy, so
+            // the blob is readable from wherever its metadata points
+            self.chunk_store.copy_to_tier(&blob_id, Tier::Hot, Tier::Cold).with_context(context)?;
+            let updated = self
+                .metadata_store
+                .update_metadata(&blob_id, |metadata| {
+                    let hot = metadata.tier == Tier::Hot;
+                    metadata.tier = Tier::Cold;
+                    hot
+                })
+                .with_context(context)?;
+            self.invalidate_metadata(&blob_id);
+            match updated {
+                Some(_) => {
+                    self.chunk_store.remove_from_tier(&blob_id, Tier::Hot).with_context(context)?;
+                    migrated += 1;
+                }
+                // Deleted while being copied
+                None => self.chunk_store.remove_from_tier(&blob_id, Tier::Cold).with_context(context)?,
+            }
+        }
+        Ok(migrated)
+    }
+
+    /// Deletes a blob. Deleting a blob that no longer exists succeeds, so a
+    /// failed delete can always be retried.
+    pub fn delete_blob(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<()> {
+        let context = || format!("while deleting blob {} for tenant {}", blob_id, tenant_id.as_str());
+        let _permit = self.acquire_permit().with_context(context)?;
+        let started = Instant::now();
+        let result = self.remove_blob(tenant_id, blob_id).with_context(context);
+        self.warn_if_slow("delete", tenant_id, Some(blob_id), started);
+        result
+    }
+
+    /// Removes a blob owned by the tenant along with its metadata, leaving
+    /// a tombstone in the same transaction if tombstones are kept
+    fn remove_blob(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<()> {
+        // Validate tenant
+        self.tenant_manager.validate_tenant(tenant_id)?;
+
+        // Get metadata to verify tenant ownership. Metadata that is already
+        // gone means an earlier delete got at least that far.
+        let metadata = match self.get_metadata(blob_id) {
+            Ok(metadata) => metadata,
+            Err(Error::BlobNotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if metadata.tenant_id != *tenant_id {
+            return Err(Error::InvalidTenant(
+                "Blob does not belong to this tenant".into(),
+            ));
+        }
+
+        self.check_retention(&metadata)?;
+        // Refuse before touching the metadata, so a blob whose data can't
+        // be deleted is never hidden by a half-done delete
+        self.chunk_store.check_writable()?;
+
+        // Delete the metadata before the data, so a crash in between leaves
+        // an orphaned chunk rather than metadata pointing at nothing. A
+        // packed blob's region becomes free once no metadata points at it
+        // and is reclaimed by compaction.
+        let tombstone = self.tombstone_ttl.map(|_| Tombstone { tenant_id: tenant_id.clone(), deleted_at: self.now() });
+        self.metadata_store.delete_metadata_with_tombstone(blob_id, tenant_id, tombstone.as_ref())?;
+        self.invalidate_metadata(blob_id);
+        if let Some(bloom) = &self.bloom {
+            bloom.lock().unwrap().remove(blob_id);
+        }
+        self.notify(tenant_id, ChangeEvent::Delete { blob_id: blob_id.clone() });
+        if metadata.pack.is_none() {
+            self.chunk_store.delete_blob(blob_id)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{reopen, LogCapture};
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    #[test]
+    fn test_coordinator_operations() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+
+        // Register tenant
+        let tenant_id = TenantId::new("posts");
+        assert!(coordinator.register_tenant(tenant_id.clone()).is_ok());
+
+        // Put blob
+        let data = b"Hello, World!";
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(data)).unwrap();
+
+        // Get blob
+        let mut retrieved = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut retrieved).unwrap();
+        assert_eq!(&retrieved, data);
+
+        // List blobs
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].blob_id, blob_id);
+        assert_eq!(blobs[0].size, data.len() as u64);
+
+        // Listing follows insertion order
+        let second_id = coordinator.put_blob(&tenant_id, Cursor::new(b"second")).unwrap();
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        let ids: Vec<_> = blobs.into_iter().map(|m| m.blob_id).collect();
+        assert_eq!(ids, vec![blob_id.clone(), second_id.clone()]);
+        coordinator.delete_blob(&tenant_id, &second_id).unwrap();
 
         // Delete blob
         assert!(coordinator.delete_blob(&tenant_id, &blob_id).is_ok());
         assert!(coordinator.get_blob(&tenant_id, &blob_id).is_err());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_put_blob_info() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // A chained reader has no length known up front
+        let data = Cursor::new(vec![1; 70_000]).chain(Cursor::new(b"tail".to_vec()));
+        let (blob_id, info) = coordinator.put_blob_info(&tenant_id, data).unwrap();
+        let mut expected = vec![1; 70_000];
+        expected.extend_from_slice(b"tail");
+        assert_eq!(info.size, expected.len() as u64);
+        assert_eq!(info.checksum, checksum::checksum_of(&expected[..]).unwrap());
+
+        let metadata = coordinator.get_metadata(&blob_id).unwrap();
+        assert_eq!((metadata.size, metadata.checksum), (info.size, info.checksum));
+    }
+
+    #[test]
+    fn test_put_blob_verified() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let data = b"Hello, World!";
+        let checksum = "sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+
+        // Matching checksum commits the blob
+        let blob_id = coordinator
+            .put_blob_verified(&tenant_id, Cursor::new(data), checksum)
+            .unwrap();
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].blob_id, blob_id);
+
+        // Mismatching checksum leaves nothing behind
+        let err = coordinator
+            .put_blob_verified(&tenant_id, Cursor::new(b"tampered"), checksum)
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+        let chunk_files = std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count();
+        assert_eq!(chunk_files, 2);
+    }
+
+    #[test]
+    fn test_put_blob_with_trailer() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // Flags when the data has been read to the end
+        struct EofFlag<R>(R, std::rc::Rc<std::cell::Cell<bool>>);
+        impl<R: Read> Read for EofFlag<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let read = self.0.read(buf)?;
+                if read == 0 && !buf.is_empty() {
+                    self.1.set(true);
+                }
+                Ok(read)
+            }
+        }
+
+        // The trailer is only asked for once the data has been read to the end
+        let data = b"Hello, World!";
+        let read = std::rc::Rc::new(std::cell::Cell::new(false));
+        let trailer = |read: std::rc::Rc<std::cell::Cell<bool>>, checksum: &'static str| {
+            move || {
+                assert!(read.get());
+                checksum.parse::<Checksum>()
+            }
+        };
+        let reader = EofFlag(Cursor::new(data), read.clone());
+        let checksum = "sha256:dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+        let blob_id = coordinator
+            .put_blob_with_trailer(&tenant_id, reader, trailer(read.clone(), checksum))
+            .unwrap();
+        let mut stored = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut stored).unwrap();
+        assert_eq!(stored, data);
+
+        // A mismatching trailer aborts the put and cleans up its data
+        read.set(false);
+        let reader = EofFlag(Cursor::new(b"tampered"), read.clone());
+        let err = coordinator
+            .put_blob_with_trailer(&tenant_id, reader, trailer(read.clone(), checksum))
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+        let chunk_files = std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count();
+        assert_eq!(chunk_files, 2);
+    }
+
+    #[test]
+    fn test_bulk_put() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        coordinator.put_named_blob(&tenant_id, "taken.txt", Cursor::new(b"first")).unwrap();
+
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disconnected"))
+            }
+        }
+        let items: Vec<(Option<String>, Box<dyn Read>)> = vec![
+            (None, Box::new(Cursor::new(vec![1u8; 1000]))),
+            (None, Box::new(Cursor::new(vec![2u8; 10]).chain(Failing))),
+            (Some("report.txt".into()), Box::new(Cursor::new(vec![3u8; 500]))),
+            (Some("taken.txt".into()), Box::new(Cursor::new(b"second"))),
+            (None, Box::new(Cursor::new(vec![4u8; 2000]))),
+        ];
+        let report = coordinator.bulk_put(&tenant_id, items.into_iter()).unwrap();
+
+        // Failures are reported by position and don't stop the rest
+        assert_eq!(report.stored.len(), 3);
+        assert_eq!(report.bytes, 3500);
+        let failed: Vec<usize> = report.failed.iter().map(|(index, _)| *index).collect();
+        assert_eq!(failed, [1, 3]);
+        assert!(matches!(report.failed[1].1.root_cause(), Error::BlobAlreadyExists(_)));
+        assert!(report.bytes_per_sec() > 0.0);
+
+        for (blob_id, expected) in report.stored.iter().zip([vec![1u8; 1000], vec![3u8; 500], vec![4u8; 2000]]) {
+            let mut read = Vec::new();
+            coordinator.get_blob(&tenant_id, blob_id).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(read, expected);
+        }
+        assert_eq!(coordinator.metadata_store.get_name(&tenant_id, "report.txt").unwrap().as_ref(), Some(&report.stored[1]));
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 4);
+
+        // An unknown tenant fails the whole call
+        let items: Vec<(Option<String>, Box<dyn Read>)> = vec![(None, Box::new(Cursor::new(b"data")))];
+        assert!(coordinator.bulk_put(&TenantId::new("unknown"), items.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_layout_version() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        // A fresh store is stamped with the current version and reopens fine
+        drop(Coordinator::new(temp_dir.path()).unwrap());
+        assert_eq!(layout::read_version(temp_dir.path()).unwrap(), Some(layout::LAYOUT_VERSION));
+        drop(reopen(|| Coordinator::new(temp_dir.path())));
+
+        // A store from a future build is refused
+        layout::write_version(temp_dir.path(), layout::LAYOUT_VERSION + 1).unwrap();
+        assert!(matches!(
+            Coordinator::new(temp_dir.path()),
+            Err(Error::IncompatibleLayout { .. })
+        ));
+        assert!(Coordinator::upgrade(temp_dir.path()).is_err());
+
+        // An older store is refused until upgraded
+        layout::write_version(temp_dir.path(), 0).unwrap();
+        assert!(Coordinator::new(temp_dir.path()).is_err());
+        assert_eq!(Coordinator::upgrade(temp_dir.path()).unwrap(), 0);
+        assert_eq!(layout::read_version(temp_dir.path()).unwrap(), Some(layout::LAYOUT_VERSION));
+        reopen(|| Coordinator::new(temp_dir.path()));
+    }
+
+    #[test]
+    fn test_bloom_cache() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let existing = coordinator.put_blob(&tenant_id, Cursor::new(b"existing")).unwrap();
+
+        // Blobs stored before the cache was enabled are picked up on startup
+        let coordinator = coordinator.with_bloom_cache().unwrap();
+        assert!(coordinator.blob_exists(&tenant_id, &existing).unwrap());
+
+        let blob_ids: Vec<_> = (0..200u32)
+            .map(|i| coordinator.put_blob(&tenant_id, Cursor::new(i.to_le_bytes())).unwrap())
+            .collect();
+        for blob_id in &blob_ids {
+            assert!(coordinator.blob_exists(&tenant_id, blob_id).unwrap());
+            assert!(coordinator.get_blob(&tenant_id, blob_id).is_ok());
+        }
+        for _ in 0..200 {
+            let missing = BlobId::new();
+            assert!(!coordinator.blob_exists(&tenant_id, &missing).unwrap());
+            let err = coordinator.get_blob(&tenant_id, &missing).err().unwrap();
+            assert!(matches!(err.root_cause(), Error::BlobNotFound(_)));
+        }
+
+        // Deleted blobs disappear while the rest remain visible
+        for blob_id in &blob_ids[..100] {
+            coordinator.delete_blob(&tenant_id, blob_id).unwrap();
+        }
+        for blob_id in &blob_ids[..100] {
+            assert!(!coordinator.blob_exists(&tenant_id, blob_id).unwrap());
+        }
+        for blob_id in &blob_ids[100..] {
+            assert!(coordinator.blob_exists(&tenant_id, blob_id).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_error_context() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        // Tenant store rejects the unregistered tenant
+        let err = coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "while putting blob for tenant posts: Invalid tenant: posts"
+        );
+        assert!(matches!(err.root_cause(), Error::InvalidTenant(_)));
+
+        // Metadata store doesn't know the blob
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = BlobId::new();
+        let err = coordinator.get_blob(&tenant_id, &blob_id).err().unwrap();
+        let message = err.to_string();
+        assert!(message.starts_with(&format!("while getting blob {} for tenant posts", blob_id)));
+        assert!(message.ends_with(&format!("Blob not found: {}", blob_id)));
+    }
+
+    #[test]
+    fn test_admin_get_blob() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"secret")).unwrap();
+
+        // Disabled outside admin mode
+        let err = coordinator.admin_get_blob(&blob_id).err().unwrap();
+        assert!(matches!(err, Error::PermissionDenied(_)));
+
+        let coordinator = coordinator.with_admin_mode();
+        let (mut reader, metadata) = coordinator.admin_get_blob(&blob_id).unwrap();
+        let mut retrieved = Vec::new();
+        reader.read_to_end(&mut retrieved).unwrap();
+        assert_eq!(retrieved, b"secret");
+        assert_eq!(metadata.tenant_id, tenant_id);
+    }
+
+    #[test]
+    fn test_min_free_bytes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_min_free_bytes(u64::MAX);
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let err = coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::StorageFull { .. }));
+        assert!(coordinator.list_blobs(&tenant_id).unwrap().is_empty());
+
+        let coordinator = coordinator.with_min_free_bytes(1);
+        assert!(coordinator.put_blob(&tenant_id, Cursor::new(b"data")).is_ok());
+    }
+
+    #[test]
+    fn test_get_blobs_batch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let posts = TenantId::new("posts");
+        let messages = TenantId::new("messages");
+        coordinator.register_tenant(posts.clone()).unwrap();
+        coordinator.register_tenant(messages.clone()).unwrap();
+
+        let first = coordinator.put_blob(&posts, Cursor::new(b"first")).unwrap();
+        let second = coordinator.put_blob(&posts, Cursor::new(b"second")).unwrap();
+        let foreign = coordinator.put_blob(&messages, Cursor::new(b"foreign")).unwrap();
+        let missing = BlobId::new();
+
+        let ids = [first.clone(), missing.clone(), foreign.clone(), second.clone()];
+        let results = coordinator.get_blobs(&posts, &ids).unwrap();
+        assert_eq!(results.len(), 4);
+        for ((blob_id, result), expected_id) in results.into_iter().zip(&ids) {
+            assert_eq!(&blob_id, expected_id);
+            match result {
+                Ok(mut reader) => {
+                    let mut data = Vec::new();
+                    reader.read_to_end(&mut data).unwrap();
+                    let expected: &[u8] = if blob_id == first { b"first" } else { b"second" };
+                    assert_eq!(data, expected);
+                }
+                Err(err) if blob_id == missing => {
+                    assert!(matches!(err.root_cause(), Error::BlobNotFound(_)))
+                }
+                Err(err) => {
+                    assert_eq!(blob_id, foreign);
+                    assert!(matches!(err.root_cause(), Error::InvalidTenant(_)));
+                }
+            }
+        }
+
+        // An unknown tenant fails the whole batch
+        assert!(coordinator.get_blobs(&TenantId::new("ghost"), &ids).is_err());
+    }
+
+    #[test]
+    fn test_move_blob() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let posts = TenantId::new("posts");
+        let archive = TenantId::new("archive");
+        coordinator.register_tenant(posts.clone()).unwrap();
+        coordinator.register_tenant(archive.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&posts, Cursor::new(b"data")).unwrap();
+
+        // Only the owning tenant can move a blob
+        assert!(coordinator.move_blob(&archive, &posts, &blob_id).is_err());
+
+        coordinator.move_blob(&posts, &archive, &blob_id).unwrap();
+        assert!(coordinator.get_blob(&posts, &blob_id).is_err());
+        assert!(coordinator.get_blob(&archive, &blob_id).is_ok());
+        assert!(coordinator.list_blobs(&posts).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_linked_copy() {
+        use std::os::unix::fs::MetadataExt;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_linked_copies(true);
+        let posts = TenantId::new("posts");
+        let archive = TenantId::new("archive");
+        coordinator.register_tenant(posts.clone()).unwrap();
+        coordinator.register_tenant(archive.clone()).unwrap();
+        let read = |tenant_id: &TenantId, blob_id: &BlobId| {
+            let mut data = Vec::new();
+            coordinator.get_blob(tenant_id, blob_id).unwrap().read_to_end(&mut data).unwrap();
+            data
+        };
+        let chunks_dir = temp_dir.path().join("chunks");
+        let inode = |blob_id: &BlobId| std::fs::metadata(chunks_dir.join(format!("{}.blob", blob_id))).unwrap().ino();
+
+        let blob_id = coordinator.put_blob(&posts, Cursor::new(b"shared bytes")).unwrap();
+        let copy_id = coordinator.copy_blob(&posts, &archive, &blob_id).unwrap();
+        assert_ne!(copy_id, blob_id);
+        assert_eq!(inode(&copy_id), inode(&blob_id));
+        assert_eq!(read(&archive, &copy_id), b"shared bytes");
+        assert_eq!(coordinator.get_metadata(&copy_id).unwrap().checksum, coordinator.get_metadata(&blob_id).unwrap().checksum);
+
+        // Deleting either copy leaves the other's data in place
+        coordinator.delete_blob(&posts, &blob_id).unwrap();
+        assert_eq!(read(&archive, &copy_id), b"shared bytes");
+        coordinator.verify_blob(&archive, &copy_id).unwrap();
+
+        // Without linking the copy gets its own file
+        let coordinator = coordinator.with_linked_copies(false);
+        let second = coordinator.copy_blob(&archive, &archive, &copy_id).unwrap();
+        assert_ne!(inode(&second), inode(&copy_id));
+        assert!(coordinator.copy_blob(&posts, &archive, &copy_id).is_err());
+    }
+
+    #[test]
+    fn test_tenant_usage() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let posts = TenantId::new("posts");
+        let archive = TenantId::new("archive");
+        coordinator.register_tenant(posts.clone()).unwrap();
+        coordinator.register_tenant(archive.clone()).unwrap();
+        let summed = |tenant_id: &TenantId| -> u64 {
+            coordinator.list_blobs(tenant_id).unwrap().iter().map(|metadata| metadata.size).sum()
+        };
+        assert_eq!(coordinator.tenant_usage(&posts).unwrap(), 0);
+
+        let first = coordinator.put_blob(&posts, Cursor::new(b"hello")).unwrap();
+        let second = coordinator.put_blob(&posts, Cursor::new(b"hello, world")).unwrap();
+        coordinator.put_blob(&posts, Cursor::new(b"")).unwrap();
+        assert_eq!(coordinator.tenant_usage(&posts).unwrap(), 17);
+
+        // Overwrites replace the old size rather than adding to it
+        coordinator
+            .put(&posts)
+            .id(first.clone())
+            .on_conflict(ConflictPolicy::Overwrite)
+            .store(Cursor::new(b"hi"))
+            .unwrap();
+        assert_eq!(coordinator.tenant_usage(&posts).unwrap(), 14);
+
+        coordinator.move_blob(&posts, &archive, &second).unwrap();
+        coordinator.delete_blob(&posts, &first).unwrap();
+        for tenant_id in [&posts, &archive] {
+            assert_eq!(coordinator.tenant_usage(tenant_id).unwrap(), summed(tenant_id));
+        }
+        assert_eq!(coordinator.tenant_usage(&posts).unwrap(), 0);
+        assert_eq!(coordinator.tenant_usage(&archive).unwrap(), 12);
+        assert_eq!(coordinator.recompute_usage(&archive).unwrap(), 12);
+        assert!(coordinator.tenant_usage(&TenantId::new("missing")).is_err());
+    }
+
+    #[test]
+    fn test_two_phase_put() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // The id is known before the upload, but not listable yet
+        let pending = coordinator.begin_put(&tenant_id).unwrap();
+        let blob_id = pending.blob_id().clone();
+        assert!(coordinator.list_blobs(&tenant_id).unwrap().is_empty());
+        assert!(coordinator.get_blob(&tenant_id, &blob_id).is_err());
+
+        assert_eq!(pending.finish(Cursor::new(b"uploaded")).unwrap(), blob_id);
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].blob_id, blob_id);
+        assert!(coordinator.metadata_store.get_pending(&blob_id).unwrap().is_none());
+
+        // Aborting leaves nothing behind
+        let pending = coordinator.begin_put(&tenant_id).unwrap();
+        let aborted_id = pending.blob_id().clone();
+        pending.abort().unwrap();
+        assert!(coordinator.metadata_store.get_pending(&aborted_id).unwrap().is_none());
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+        let chunk_files = std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count();
+        assert_eq!(chunk_files, 2);
+    }
+
+    #[test]
+    fn test_cancel_put() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let other_tenant = TenantId::new("other");
+        coordinator.register_tenant(other_tenant.clone()).unwrap();
+        let chunks_dir = temp_dir.path().join("chunks");
+
+        // Data written by an interrupted finish is removed with the reservation
+        let blob_id = coordinator.begin_put(&tenant_id).unwrap().blob_id().clone();
+        coordinator.chunk_store.put_blob(&blob_id, Cursor::new(b"partial")).unwrap();
+        assert_ne!(std::fs::read_dir(&chunks_dir).unwrap().count(), 0);
+        assert!(coordinator.cancel_put(&other_tenant, &blob_id).unwrap_err().is_not_found());
+        coordinator.cancel_put(&tenant_id, &blob_id).unwrap();
+        assert!(coordinator.metadata_store.get_pending(&blob_id).unwrap().is_none());
+        assert_eq!(std::fs::read_dir(&chunks_dir).unwrap().count(), 0);
+        assert!(coordinator.cancel_put(&tenant_id, &blob_id).unwrap_err().is_not_found());
+
+        // Committed blobs must be deleted instead
+        let committed = coordinator.put_blob(&tenant_id, Cursor::new(b"committed")).unwrap();
+        let err = coordinator.cancel_put(&tenant_id, &committed).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobAlreadyExists(_)));
+        assert!(coordinator.get_blob(&tenant_id, &committed).is_ok());
+
+        // Another tenant's blob looks the same as one that doesn't exist
+        assert!(coordinator.cancel_put(&other_tenant, &committed).unwrap_err().is_not_found());
+        let err = coordinator.cancel_put(&TenantId::new("missing"), &committed).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidTenant(_)), "{}", err);
+
+        // A reader that tells when the upload started, then holds it open
+        struct Gated(mpsc::Sender<()>, mpsc::Receiver<()>, Cursor<Vec<u8>>);
+        impl Read for Gated {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.2.position() == 0 {
+                    self.0.send(()).unwrap();
+                    self.1.recv().unwrap();
+                }
+                self.2.read(buf)
+            }
+        }
+
+        // Cancelling waits for a finish in progress, then finds it committed
+        let pending = coordinator.begin_put(&tenant_id).unwrap();
+        let blob_id = pending.blob_id().clone();
+        let (started_tx, started) = mpsc::channel();
+        let (open_gate, gate) = mpsc::channel();
+        std::thread::scope(|scope| {
+            let finish = scope.spawn(move || pending.finish(Gated(started_tx, gate, Cursor::new(b"slow".to_vec()))));
+            started.recv().unwrap();
+            let cancel = scope.spawn(|| coordinator.cancel_put(&tenant_id, &blob_id));
+            std::thread::sleep(Duration::from_millis(50));
+            open_gate.send(()).unwrap();
+            finish.join().unwrap().unwrap();
+            let err = cancel.join().unwrap().unwrap_err();
+            assert!(matches!(err.root_cause(), Error::BlobAlreadyExists(_)), "{}", err);
+        });
+        assert!(coordinator.get_blob(&tenant_id, &blob_id).is_ok());
+    }
+
+    #[test]
+    fn test_truncated_blob_size_mismatch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        assert!(coordinator.verify_blob(&tenant_id, &blob_id).is_ok());
+
+        // Truncate the blob and make the checksum sidecar stale to match it
+        let chunks = temp_dir.path().join("chunks");
+        let blob_path = chunks.join(format!("{}.blob", blob_id));
+        std::fs::write(&blob_path, b"Hello").unwrap();
+        let truncated_checksum = checksum::compute_sha256(Cursor::new(b"Hello")).unwrap();
+        std::fs::write(chunks.join(format!("{}.blob.chk", blob_id)), truncated_checksum).unwrap();
+
+        let checksum_reads = coordinator.chunk_store.checksum_reads();
+        let err = coordinator.get_blob(&tenant_id, &blob_id).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::SizeMismatch { expected: 13, actual: 5 }));
+        assert_eq!(coordinator.chunk_store.checksum_reads(), checksum_reads);
+        let err = coordinator.verify_blob(&tenant_id, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::SizeMismatch { expected: 13, actual: 5 }));
+    }
+
+    #[test]
+    fn test_missing_checksum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        // A crash before the checksum was written leaves it empty
+        coordinator
+            .metadata_store
+            .update_metadata(&blob_id, |metadata| {
+                metadata.checksum = Checksum::parse("").unwrap();
+                true
+            })
+            .unwrap();
+        std::fs::remove_file(temp_dir.path().join("chunks").join(format!("{}.blob.chk", blob_id))).unwrap();
+
+        // By default reads fail with a distinct error rather than a mismatch
+        let err = coordinator.get_blob(&tenant_id, &blob_id).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::MissingChecksum(id) if *id == blob_id.to_string()), "{}", err);
+        let err = coordinator.get_blob_with_mode(&tenant_id, &blob_id, VerifyMode::Lazy).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::MissingChecksum(_)));
+
+        // Skipping verification reads the data with a warning, while
+        // verification still reports the blob
+        let coordinator = coordinator.with_missing_checksum_policy(MissingChecksumPolicy::SkipVerification);
+        let capture = LogCapture::default();
+        let mut data = Vec::new();
+        tracing::subscriber::with_default(capture.subscriber(), || {
+            coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        });
+        assert_eq!(data, b"Hello, World!");
+        assert!(capture.contents().contains("no recorded checksum"));
+        let err = coordinator.verify_blob(&tenant_id, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::MissingChecksum(_)));
+    }
+
+    #[test]
+    fn test_checksum_source() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        // Leave the sidecar stale, as a repair updating only metadata would
+        let stale = Checksum::new("sha256", [7; 32]);
+        let sidecar_path = temp_dir.path().join("chunks").join(format!("{}.blob.chk", blob_id));
+        std::fs::write(&sidecar_path, stale.to_compact_string()).unwrap();
+
+        // By default the disagreement itself is the error
+        let err = coordinator.get_blob(&tenant_id, &blob_id).err().unwrap();
+        let Error::ChecksumSourceDisagreement { sidecar, metadata, .. } = err.root_cause() else {
+            panic!("expected a disagreement, got {}", err);
+        };
+        assert_eq!(sidecar, &stale);
+        assert_eq!(metadata, &checksum::checksum_of(Cursor::new(b"Hello, World!")).unwrap());
+
+        // Trusting the sidecar verifies the data against the stale value
+        let coordinator = coordinator.with_checksum_source(ChecksumSource::Sidecar);
+        let err = coordinator.get_blob(&tenant_id, &blob_id).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { expected, .. } if *expected == stale));
+
+        // Trusting metadata ignores the sidecar
+        let coordinator = coordinator.with_checksum_source(ChecksumSource::Metadata);
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+    }
+
+    #[test]
+    fn test_tenant_name_length() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        coordinator.register_tenant(TenantId::new("posts")).unwrap();
+        coordinator.register_tenant(TenantId::new("a".repeat(MAX_TENANT_KEY_LEN))).unwrap();
+        let err = coordinator.register_tenant(TenantId::new("a".repeat(MAX_TENANT_KEY_LEN + 1))).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidTenantId(_)));
+        let outcomes = coordinator
+            .register_tenants(&[TenantId::new("b".repeat(5000)), TenantId::new("photos")])
+            .unwrap();
+        assert!(matches!(outcomes[0].1, Err(Error::InvalidTenantId(_))));
+        assert!(outcomes[1].1.is_ok());
+        assert_eq!(coordinator.list_tenants().unwrap().len(), 3);
+        drop(coordinator);
+
+        // With the hash policy a long name is keyed by its hash but works as usual
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()))
+            .with_tenant_name_limit(16, TenantNamePolicy::Hash);
+        let long_name = TenantId::new("c".repeat(5000));
+        assert!(long_name.key().len() < 100);
+        // Names over the limit but within the key length keep their own keys
+        let over_limit = TenantId::new("d".repeat(20));
+        coordinator.register_tenant(over_limit.clone()).unwrap();
+        assert_eq!(over_limit.key(), over_limit.as_str());
+        assert!(coordinator.list_tenants().unwrap().contains(&over_limit));
+        coordinator.register_tenant(long_name.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&long_name, Cursor::new(b"Hello, World!")).unwrap();
+        let mut data = Vec::new();
+        coordinator.get_blob(&long_name, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        assert_eq!(coordinator.list_blobs(&long_name).unwrap().len(), 1);
+        assert!(coordinator.list_tenants().unwrap().contains(&long_name));
+        assert_eq!(
+            coordinator.get_tenant_config(&long_name).unwrap().full_name.as_deref(),
+            Some(long_name.as_str())
+        );
+    }
+
+    #[test]
+    fn test_tenant_and_blob_limits() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_limits(2, 3);
+
+        coordinator.register_tenant(TenantId::new("posts")).unwrap();
+        coordinator.register_tenant(TenantId::new("photos")).unwrap();
+        let err = coordinator.register_tenant(TenantId::new("videos")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::TenantLimitReached { limit: 2 }));
+        // Re-registering an existing tenant doesn't count against the cap
+        coordinator.register_tenant(TenantId::new("posts")).unwrap();
+
+        // Concurrent registrations can't overshoot the cap
+        let racing = Coordinator::new(temp_dir.path().join("racing")).unwrap().with_limits(4, 3);
+        let registered = thread::scope(|scope| {
+            let handles: Vec<_> = (0..16)
+                .map(|i| {
+                    let racing = &racing;
+                    scope.spawn(move || racing.register_tenant(TenantId::new(format!("tenant{}", i))).is_ok())
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).filter(|&ok| ok).count()
+        });
+        assert_eq!(registered, 4);
+        assert_eq!(racing.list_tenants().unwrap().len(), 4);
+
+        let tenant_id = TenantId::new("posts");
+        for _ in 0..3 {
+            coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap();
+        }
+        let err = coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobLimitReached { limit: 3, .. }));
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 3);
+
+        // Deleting a blob frees a slot; other tenants have their own cap
+        let blob_id = coordinator.list_blobs(&tenant_id).unwrap()[0].blob_id.clone();
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap();
+        coordinator.put_blob(&TenantId::new("photos"), Cursor::new(b"data")).unwrap();
+
+        // Concurrent puts can't overshoot the blob cap, and a rejected put
+        // leaves no data behind
+        let tenants = racing.list_tenants().unwrap();
+        let (tenant_id, other) = (&tenants[0], &tenants[1]);
+        let stored = thread::scope(|scope| {
+            let handles: Vec<_> = (0..16)
+                .map(|_| {
+                    let racing = &racing;
+                    scope.spawn(move || racing.put_blob(tenant_id, Cursor::new(b"data")).is_ok())
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).filter(|&ok| ok).count()
+        });
+        assert_eq!(stored, 3);
+        assert_eq!(racing.list_blobs(tenant_id).unwrap().len(), 3);
+        assert!(racing.check_consistency().unwrap().orphan_chunks.is_empty());
+
+        // Copying into a full tenant is refused too
+        let source = racing.put_blob(other, Cursor::new(b"data")).unwrap();
+        let err = racing.copy_blob(other, tenant_id, &source).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobLimitReached { limit: 3, .. }));
+    }
+
+    #[test]
+    fn test_verify_all() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let mut blob_ids = Vec::new();
+        for i in 0..3 {
+            blob_ids.push(coordinator.put_blob(&tenant_id, Cursor::new(format!("blob {}", i))).unwrap());
+        }
+        let blob_path = temp_dir.path().join("chunks").join(format!("{}.blob", blob_ids[1]));
+        std::fs::write(blob_path, b"tampered").unwrap();
+
+        let report = coordinator.verify_all(&CancellationToken::new()).unwrap();
+        assert_eq!(report.checked, 3);
+        assert!(!report.cancelled);
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0].0, blob_ids[1]);
+
+        // A cancelled token stops verification before any blob is checked
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = coordinator.verify_all(&cancel).unwrap();
+        assert_eq!(report.checked, 0);
+        assert!(report.cancelled);
+    }
+
+    #[test]
+    fn test_verify_rechecks_missing_chunk() {
+        let hot_dir = tempfile::tempdir().unwrap();
+        let cold_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(hot_dir.path()).unwrap().with_cold_root(cold_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let deleted = coordinator.put_blob(&tenant_id, Cursor::new(b"deleted")).unwrap();
+        let moved = coordinator.put_blob(&tenant_id, Cursor::new(b"moved")).unwrap();
+        let lost = coordinator.put_blob(&tenant_id, Cursor::new(b"lost")).unwrap();
+        let stale = |blob_id| coordinator.metadata_store.get_metadata(blob_id).unwrap();
+
+        // Deleted after its record was read: not reported as missing data
+        let record = stale(&deleted);
+        coordinator.delete_blob(&tenant_id, &deleted).unwrap();
+        assert!(matches!(coordinator.verify_record(&record), Err(Error::BlobNotFound(_))));
+
+        // Moved to the cold tier after its record was read: still intact
+        let record = stale(&moved);
+        coordinator.migrate_cold(chrono::Duration::zero(), &CancellationToken::new()).unwrap();
+        coordinator.verify_record(&record).unwrap();
+
+        // Data gone while the record remains is still reported
+        let record = stale(&lost);
+        coordinator.chunk_store.delete_blob(&lost).unwrap();
+        assert!(matches!(coordinator.verify_record(&record), Err(Error::ChunkMissing { .. })));
+    }
+
+    #[test]
+    fn test_verify_ignores_backup_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let backup_root = temp_dir.path().join("backup");
+        let coordinator = Coordinator::new(temp_dir.path().join("store")).unwrap().with_backup_root(&backup_root, false);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let file_name = format!("{}.blob", blob_id);
+        std::fs::create_dir_all(backup_root.join("chunks")).unwrap();
+        let blob_path = temp_dir.path().join("store").join("chunks").join(&file_name);
+        std::fs::copy(&blob_path, backup_root.join("chunks").join(&file_name)).unwrap();
+        std::fs::write(&blob_path, b"Hello, Wxrld!").unwrap();
+
+        // Reads are served from the backup, but scrubbing reports the damage
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        assert!(matches!(
+            coordinator.verify_blob(&tenant_id, &blob_id).unwrap_err().root_cause(),
+            Error::ChecksumMismatch { .. }
+        ));
+        let report = coordinator.verify_all(&CancellationToken::new()).unwrap();
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0].0, blob_id);
+    }
+
+    #[test]
+    fn test_rehash_all() {
+        use checksum::ChecksumAlgorithm::Blake3;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // Plain, compressed and packed blobs, plus one whose data was damaged
+        let mut blobs = vec![(coordinator.put_blob(&tenant_id, Cursor::new(b"plain")).unwrap(), &b"plain"[..])];
+        let coordinator = coordinator.with_compression(Compression::Zstd(3));
+        blobs.push((coordinator.put_blob(&tenant_id, Cursor::new(b"compressed")).unwrap(), b"compressed"));
+        let damaged = coordinator.with_compression(Compression::None).put_blob(&tenant_id, Cursor::new(b"intact")).unwrap();
+        std::fs::write(temp_dir.path().join("chunks").join(format!("{}.blob", damaged)), b"broken").unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_packfiles(1 << 20).unwrap();
+        blobs.push((coordinator.put_blob(&tenant_id, Cursor::new(b"packed")).unwrap(), b"packed"));
+
+        // Cancelling before the first blob changes nothing
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = coordinator.rehash_all(Blake3, &cancel).unwrap();
+        assert!(report.cancelled);
+        assert_eq!(report.rehashed, 0);
+
+        let report = coordinator.rehash_all(Blake3, &CancellationToken::new()).unwrap();
+        assert_eq!(report.rehashed, 3);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, damaged);
+        assert!(matches!(report.failed[0].1, Error::ChecksumMismatch { .. }));
+        assert_eq!(coordinator.get_metadata(&damaged).unwrap().checksum.algorithm(), "sha256");
+
+        // Every blob now verifies under BLAKE3, sidecars included
+        for (blob_id, data) in &blobs {
+            let metadata = coordinator.get_metadata(blob_id).unwrap();
+            assert_eq!(metadata.checksum, checksum::checksum_of_as(*data, Blake3).unwrap());
+            coordinator.verify_blob(&tenant_id, blob_id).unwrap();
+            let mut read = Vec::new();
+            coordinator.get_blob(&tenant_id, blob_id).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(read, *data);
+        }
+        assert_eq!(coordinator.detect_checksum_algorithm().unwrap().as_deref(), Some("blake3"));
+
+        // Running again only retries the blob that failed
+        let report = coordinator.rehash_all(Blake3, &CancellationToken::new()).unwrap();
+        assert_eq!((report.rehashed, report.skipped, report.failed.len()), (0, 3, 1));
+    }
+
+    #[test]
+    fn test_rehash_migration_completes() {
+        use checksum::ChecksumAlgorithm::{Blake3, Sha256};
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let old = coordinator.put_blob(&tenant_id, Cursor::new(b"old")).unwrap();
+
+        // New writes switch over for good, then old blobs are rehashed
+        let coordinator = coordinator.with_checksum_algorithm(Blake3).unwrap();
+        let new = coordinator.put_blob(&tenant_id, Cursor::new(b"new")).unwrap();
+        assert_eq!(coordinator.get_metadata(&new).unwrap().checksum.algorithm(), "blake3");
+        coordinator.rehash_all(Blake3, &CancellationToken::new()).unwrap();
+        assert_eq!(coordinator.get_metadata(&old).unwrap().checksum.algorithm(), "blake3");
+        drop(coordinator);
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()).and_then(Coordinator::with_strict_checksum_algorithm));
+        let newer = coordinator.put_blob(&tenant_id, Cursor::new(b"newer")).unwrap();
+        assert_eq!(coordinator.get_metadata(&newer).unwrap().checksum.algorithm(), "blake3");
+
+        // Expected checksums under any supported algorithm are still checked
+        let sha256 = checksum::checksum_of_as(&b"checked"[..], Sha256).unwrap();
+        coordinator.put_blob_verified(&tenant_id, Cursor::new(b"checked"), &sha256.to_string()).unwrap();
+        let err = coordinator.put_blob_verified(&tenant_id, Cursor::new(b"other"), &sha256.to_string()).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+
+        // A rehashed tenant can be exported into a store still on SHA-256
+        let mut archive = Vec::new();
+        coordinator
+            .export_tenant(&tenant_id, &mut archive, &crate::archive::ExportOptions::default(), &CancellationToken::new())
+            .unwrap();
+        let target = Coordinator::in_memory().unwrap();
+        target.register_tenant(tenant_id.clone()).unwrap();
+        assert_eq!(target.import_tenant(&tenant_id, Cursor::new(archive)).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_read_during_rehash() {
+        use checksum::ChecksumAlgorithm::Blake3;
+        let coordinator = Coordinator::in_memory().unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        // Sidecar already rehashed, metadata not yet, as after a crash
+        let rehashed = checksum::checksum_of_as(&b"Hello, World!"[..], Blake3).unwrap();
+        coordinator.chunk_store.replace_sidecar(Tier::Hot, &blob_id, &rehashed).unwrap();
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+
+        // A rerun finishes the blob
+        let report = coordinator.rehash_all(Blake3, &CancellationToken::new()).unwrap();
+        assert_eq!(report.rehashed, 1);
+        coordinator.verify_blob(&tenant_id, &blob_id).unwrap();
+    }
+
+    #[test]
+    fn test_content_type() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let png = coordinator
+            .put_blob(&tenant_id, Cursor::new(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"))
+            .unwrap();
+        let json = coordinator.put_blob(&tenant_id, Cursor::new(br#"{"title": "hello"}"#)).unwrap();
+        let explicit = coordinator
+            .put_blob_with_content_type(&tenant_id, Cursor::new(br#"{"a": 1}"#), "application/vnd.custom+json")
+            .unwrap();
+        let unknown = coordinator.put_blob(&tenant_id, Cursor::new(b"plain text")).unwrap();
+
+        let content_type = |blob_id: &BlobId| {
+            coordinator.metadata_store.get_metadata(blob_id).unwrap().content_type
+        };
+        assert_eq!(content_type(&png).as_deref(), Some("image/png"));
+        assert_eq!(content_type(&json).as_deref(), Some("application/json"));
+        assert_eq!(content_type(&explicit).as_deref(), Some("application/vnd.custom+json"));
+        assert_eq!(content_type(&unknown), None);
+
+        // Sniffing doesn't consume any of the stored data
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &json).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, br#"{"title": "hello"}"#);
+    }
+
+    #[test]
+    fn test_linked_names() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let read = |name: &str| {
+            let mut data = Vec::new();
+            coordinator.get_named_blob(&tenant_id, name)?.read_to_end(&mut data)?;
+            Ok::<_, Error>(data)
+        };
+
+        let blob_id = coordinator.put_named_blob(&tenant_id, "report.txt", Cursor::new(b"quarterly")).unwrap();
+        coordinator.link_name(&tenant_id, "latest", &blob_id).unwrap();
+        assert_eq!(read("report.txt").unwrap(), b"quarterly");
+        assert_eq!(read("latest").unwrap(), b"quarterly");
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+
+        // Names are unique per tenant
+        let err = coordinator.link_name(&tenant_id, "latest", &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobAlreadyExists(_)));
+
+        // Unlinking one name leaves the blob and its other name intact
+        coordinator.unlink_name(&tenant_id, "report.txt").unwrap();
+        let err = read("report.txt").unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobNotFound(_)));
+        assert_eq!(read("latest").unwrap(), b"quarterly");
+        assert!(coordinator.unlink_name(&tenant_id, "report.txt").is_err());
+
+        // Another tenant's blob can't be linked
+        let other = TenantId::new("photos");
+        coordinator.register_tenant(other.clone()).unwrap();
+        let err = coordinator.link_name(&other, "stolen", &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidTenant(_)));
+    }
+
+    #[test]
+    fn test_max_versions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let config = TenantConfig { versioned: true, max_versions: Some(3), ..Default::default() };
+        coordinator.register_tenant_with_config(tenant_id.clone(), config).unwrap();
+
+        let versions: Vec<_> = (0..5)
+            .map(|i| {
+                let data = format!("version {}", i);
+                coordinator.put_named_blob(&tenant_id, "report.txt", Cursor::new(data)).unwrap()
+            })
+            .collect();
+        assert_eq!(coordinator.list_versions(&tenant_id, "report.txt").unwrap(), versions[2..]);
+        for (i, blob_id) in versions.iter().enumerate() {
+            match coordinator.get_blob(&tenant_id, blob_id) {
+                Err(e) => assert!(i < 2 && e.is_not_found(), "{}", e),
+                Ok(mut reader) => {
+                    let mut data = String::new();
+                    reader.read_to_string(&mut data).unwrap();
+                    assert_eq!(data, format!("version {}", i));
+                }
+            }
+        }
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 3);
+
+        let mut data = String::new();
+        coordinator.get_named_blob(&tenant_id, "report.txt").unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "version 4");
+
+        // Unversioned tenants still refuse to reuse a name
+        let other = TenantId::new("photos");
+        coordinator.register_tenant(other.clone()).unwrap();
+        coordinator.put_named_blob(&other, "cat.png", Cursor::new(b"meow")).unwrap();
+        let err = coordinator.put_named_blob(&other, "cat.png", Cursor::new(b"purr")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobAlreadyExists(_)));
+    }
+
+    #[test]
+    fn test_replace_named_blob() {
+        let coordinator = Coordinator::in_memory().unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let read = |name: &str| {
+            let mut data = Vec::new();
+            coordinator.get_named_blob(&tenant_id, name).unwrap().read_to_end(&mut data).unwrap();
+            data
+        };
+
+        // Replacing binds a free name, then repoints it and deletes the old blob
+        let first = coordinator.replace_named_blob(&tenant_id, "report.txt", Cursor::new(b"draft")).unwrap();
+        let second = coordinator.replace_named_blob(&tenant_id, "report.txt", Cursor::new(b"final")).unwrap();
+        assert_eq!(read("report.txt"), b"final");
+        assert!(coordinator.get_blob(&tenant_id, &first).err().unwrap().is_not_found());
+
+        // A put failing mid-stream leaves the name on the old blob and
+        // commits nothing for the new one
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disconnected"))
+            }
+        }
+        let data = Cursor::new(vec![7u8; 100_000]).chain(Failing);
+        assert!(coordinator.replace_named_blob(&tenant_id, "report.txt", data).is_err());
+        let ids = |coordinator: &Coordinator| -> Vec<BlobId> {
+            coordinator.list_blobs(&tenant_id).unwrap().into_iter().map(|metadata| metadata.blob_id).collect()
+        };
+        assert_eq!(read("report.txt"), b"final");
+        assert_eq!(ids(&coordinator), std::slice::from_ref(&second));
+
+        // The old blob can be kept instead
+        let coordinator = coordinator.with_replace_policy(ReplacePolicy::KeepOld);
+        let third = coordinator.replace_named_blob(&tenant_id, "report.txt", Cursor::new(b"revised")).unwrap();
+        assert_eq!(ids(&coordinator), [second, third]);
+    }
+
+    #[test]
+    fn test_packfile_backend() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_packfiles(1 << 20).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let read = |blob_id: &BlobId| {
+            let mut data = Vec::new();
+            coordinator.get_blob(&tenant_id, blob_id).unwrap().read_to_end(&mut data).unwrap();
+            String::from_utf8(data).unwrap()
+        };
+
+        let blob_ids: Vec<_> = (0..200)
+            .map(|i| coordinator.put_blob(&tenant_id, Cursor::new(format!("tiny blob {:03}", i))).unwrap())
+            .collect();
+        // Everything lands in one pack, with no per-blob files
+        let packs = temp_dir.path().join("packs");
+        assert_eq!(std::fs::read_dir(&packs).unwrap().count(), 1);
+        assert_eq!(std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count(), 0);
+        for (i, blob_id) in blob_ids.iter().enumerate() {
+            assert_eq!(read(blob_id), format!("tiny blob {:03}", i));
+        }
+
+        for blob_id in blob_ids.iter().step_by(2) {
+            coordinator.delete_blob(&tenant_id, blob_id).unwrap();
+        }
+        let reclaimed = coordinator.compact_packs().unwrap();
+        assert_eq!(reclaimed, 100 * 13);
+
+        let pack_sizes: Vec<u64> = std::fs::read_dir(&packs)
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .collect();
+        assert_eq!(pack_sizes, vec![100 * 13]);
+        for (i, blob_id) in blob_ids.iter().enumerate().skip(1).step_by(2) {
+            assert_eq!(read(blob_id), format!("tiny blob {:03}", i));
+        }
+        assert!(coordinator.verify_all(&CancellationToken::new()).unwrap().corrupted.is_empty());
+
+        // Nothing left to reclaim
+        assert_eq!(coordinator.compact_packs().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_list_blob_ids() {
+        let coordinator = Coordinator::in_memory().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let other = TenantId::new("photos");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        coordinator.register_tenant(other.clone()).unwrap();
+        let mut stored: Vec<_> = (0..10)
+            .map(|i| coordinator.put_blob(&tenant_id, Cursor::new(format!("blob {}", i))).unwrap())
+            .collect();
+        coordinator.put_blob(&other, Cursor::new(b"not mine")).unwrap();
+
+        let reads = coordinator.metadata_store.metadata_reads();
+        let mut ids = coordinator.list_blob_ids(&tenant_id).unwrap();
+        assert_eq!(coordinator.metadata_store.metadata_reads(), reads);
+        ids.sort_by_key(|id| id.to_string());
+        stored.sort_by_key(|id| id.to_string());
+        assert_eq!(ids, stored);
+
+        let err = coordinator.list_blob_ids(&TenantId::new("ghost")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidTenant(_)));
+    }
+
+    #[test]
+    fn test_blob_cache_skips_sidecar() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_blob_cache(1024, 64);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        let hits = coordinator.chunk_store.cache_hits();
+
+        // With the sidecar unreadable, only a cache hit can serve the read
+        let BlobLocation::File { checksum_path: Some(checksum_path), .. } = coordinator.blob_location(&blob_id).unwrap()
+        else {
+            panic!("expected a blob file with a sidecar");
+        };
+        std::fs::remove_file(&checksum_path).unwrap();
+        std::fs::create_dir(&checksum_path).unwrap();
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        assert_eq!(coordinator.chunk_store.cache_hits(), hits + 1);
+    }
+
+    #[test]
+    fn test_metadata_cache() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_metadata_cache(16);
+        let tenant_id = TenantId::new("posts");
+        let other = TenantId::new("photos");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        coordinator.register_tenant(other.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        // The first read fills the cache; later reads don't touch sled
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        let reads = coordinator.metadata_store.metadata_reads();
+        for _ in 0..5 {
+            coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        }
+        assert_eq!(coordinator.metadata_store.metadata_reads(), reads);
+
+        // A move invalidates the entry, so ownership is re-read
+        coordinator.move_blob(&tenant_id, &other, &blob_id).unwrap();
+        assert!(coordinator.get_blob(&tenant_id, &blob_id).is_err());
+        coordinator.get_blob(&other, &blob_id).unwrap();
+
+        // So does a delete
+        coordinator.delete_blob(&other, &blob_id).unwrap();
+        let err = coordinator.get_blob(&other, &blob_id).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::BlobNotFound(_)));
+        assert!(!coordinator.blob_exists(&other, &blob_id).unwrap());
+    }
+
+    #[test]
+    fn test_migrate_cold() {
+        let hot_dir = tempfile::tempdir().unwrap();
+        let cold_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(hot_dir.path())
+            .unwrap()
+            .with_cold_root(cold_dir.path())
+            .unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        let hot_chunks = hot_dir.path().join("chunks");
+        let cold_chunks = cold_dir.path().join("chunks");
+        let count = |dir: &Path| std::fs::read_dir(dir).unwrap().count();
+
+        // Nothing is old enough yet
+        assert_eq!(coordinator.migrate_cold(chrono::Duration::hours(1), &CancellationToken::new()).unwrap(), 0);
+        assert_eq!(count(&hot_chunks), 2);
+
+        // A cancelled run moves nothing
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert_eq!(coordinator.migrate_cold(chrono::Duration::zero(), &cancel).unwrap(), 0);
+        assert_eq!(count(&hot_chunks), 2);
+
+        assert_eq!(coordinator.migrate_cold(chrono::Duration::zero(), &CancellationToken::new()).unwrap(), 1);
+        assert_eq!(count(&hot_chunks), 0);
+        assert_eq!(count(&cold_chunks), 2);
+        assert_eq!(coordinator.metadata_store.get_metadata(&blob_id).unwrap().tier, Tier::Cold);
+
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+
+        // New writes go to the hot tier; deletes reach the cold tier
+        coordinator.put_blob(&tenant_id, Cursor::new(b"fresh")).unwrap();
+        assert_eq!(count(&hot_chunks), 2);
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        assert_eq!(count(&cold_chunks), 0);
+
+        // Orphans in the cold tier are found and repaired too
+        let orphan = BlobId::new();
+        std::fs::write(cold_chunks.join(format!("{}.blob", orphan)), b"orphan").unwrap();
+        let report = coordinator.check_consistency().unwrap();
+        assert_eq!(report.orphan_chunks, vec![orphan]);
+        assert_eq!(coordinator.repair(&report, Duration::ZERO).unwrap(), 1);
+        assert_eq!(count(&cold_chunks), 0);
+        assert_eq!(count(&hot_chunks), 2);
+    }
+
+    #[test]
+    fn test_migrate_cold_without_cold_root() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"only copy")).unwrap();
+
+        let err = coordinator.migrate_cold(chrono::Duration::zero(), &CancellationToken::new()).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::System(_)), "{}", err);
+        let err = coordinator.chunk_store.copy_to_tier(&blob_id, Tier::Hot, Tier::Cold).unwrap_err();
+        assert!(matches!(err, Error::System(_)), "{}", err);
+
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"only copy");
+        assert_eq!(coordinator.get_metadata(&blob_id).unwrap().tier, Tier::Hot);
+    }
+
+    #[test]
+    fn test_idempotent_put() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let first = coordinator
+            .put_blob_idempotent(&tenant_id, Cursor::new(b"upload"), Some("request-1"))
+            .unwrap();
+        let retry = coordinator
+            .put_blob_idempotent(&tenant_id, Cursor::new(b"upload"), Some("request-1"))
+            .unwrap();
+        assert_eq!(first, retry);
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+
+        let other = coordinator
+            .put_blob_idempotent(&tenant_id, Cursor::new(b"upload"), Some("request-2"))
+            .unwrap();
+        assert_ne!(first, other);
+        let unkeyed = coordinator.put_blob_idempotent(&tenant_id, Cursor::new(b"upload"), None).unwrap();
+        assert_ne!(unkeyed, other);
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 3);
+
+        // Deleting the blob forgets its key, so a retry doesn't get a dead id
+        coordinator.delete_blob(&tenant_id, &first).unwrap();
+        let recreated = coordinator
+            .put_blob_idempotent(&tenant_id, Cursor::new(b"upload"), Some("request-1"))
+            .unwrap();
+        assert_ne!(recreated, first);
+        assert!(coordinator.blob_exists(&tenant_id, &recreated).unwrap());
+
+        // Once the window has passed, the key creates a new blob
+        let coordinator = coordinator.with_idempotency_window(chrono::Duration::zero());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let expired = coordinator
+            .put_blob_idempotent(&tenant_id, Cursor::new(b"upload"), Some("request-1"))
+            .unwrap();
+        assert_ne!(expired, recreated);
+
+        // Expired keys are pruned, leaving the blobs alone
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(coordinator.prune_idempotency_keys().unwrap(), 2);
+        assert_eq!(coordinator.prune_idempotency_keys().unwrap(), 0);
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_verify_modes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let intact = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let corrupt = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&corrupt).unwrap() else {
+            panic!("expected a chunk file");
+        };
+        std::fs::write(&blob_path, b"Hello, Wor1d!").unwrap();
+
+        for mode in [VerifyMode::Eager, VerifyMode::Lazy, VerifyMode::None] {
+            let mut data = Vec::new();
+            coordinator.get_blob_with_mode(&tenant_id, &intact, mode).unwrap().read_to_end(&mut data).unwrap();
+            assert_eq!(data, b"Hello, World!", "{:?}", mode);
+        }
+
+        // Eager refuses to hand out a reader at all
+        let err = coordinator.get_blob_with_mode(&tenant_id, &corrupt, VerifyMode::Eager).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+
+        // Lazy hands out the bytes, then fails the final read
+        let coordinator = coordinator.with_verify_mode(VerifyMode::Lazy);
+        let mut reader = coordinator.get_blob(&tenant_id, &corrupt).unwrap();
+        let mut data = vec![0; 13];
+        reader.read_exact(&mut data).unwrap();
+        let err = reader.read(&mut [0; 16]).unwrap_err();
+        let inner = err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert!(matches!(*inner, Error::ChecksumMismatch { .. }));
+
+        // None doesn't notice
+        let mut data = Vec::new();
+        let mut reader = coordinator.get_blob_with_mode(&tenant_id, &corrupt, VerifyMode::None).unwrap();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, Wor1d!");
+    }
+
+    #[test]
+    fn test_multiple_checksums() {
+        use checksum::ChecksumAlgorithm;
+        use sha2::Digest;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path())
+            .unwrap()
+            .with_checksums([ChecksumAlgorithm::Md5, ChecksumAlgorithm::Sha256]);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let content = vec![7u8; 100_000];
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(&content)).unwrap();
+        let metadata = coordinator.get_metadata(&blob_id).unwrap();
+        let md5 = checksum::DigestEncoding::Hex.encode(&md5::Md5::digest(&content));
+        let sha256 = checksum::compute_sha256(&content[..]).unwrap();
+        assert_eq!(
+            metadata.checksums,
+            BTreeMap::from([(ChecksumAlgorithm::Md5, md5), (ChecksumAlgorithm::Sha256, sha256.clone())])
+        );
+        assert_eq!(metadata.checksum.to_hex(), sha256);
+        let mut retrieved = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut retrieved).unwrap();
+        assert_eq!(retrieved, content);
+
+        // Without the option only the primary checksum is recorded
+        let plain = Coordinator::new(temp_dir.path().join("plain")).unwrap();
+        plain.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = plain.put_blob(&tenant_id, Cursor::new(&content)).unwrap();
+        assert!(plain.get_metadata(&blob_id).unwrap().checksums.is_empty());
+    }
+
+    #[test]
+    fn test_chunk_checksums() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_chunk_checksums(1024);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(data.clone())).unwrap();
+        let compressed_id = coordinator
+            .with_compression(Compression::Gzip)
+            .put_blob(&tenant_id, Cursor::new(data.clone()))
+            .unwrap();
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()));
+
+        for blob_id in [&blob_id, &compressed_id] {
+            let chunks = coordinator.get_metadata(blob_id).unwrap().chunks.unwrap();
+            assert_eq!(chunks.checksums.len(), 5);
+            let mut read = Vec::new();
+            coordinator.get_blob(&tenant_id, blob_id).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(read, data);
+            coordinator.verify_blob(&tenant_id, blob_id).unwrap();
+        }
+
+        // Flip a byte in the fourth chunk
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&blob_id).unwrap() else {
+            panic!("expected a chunk file");
+        };
+        let mut stored = std::fs::read(&blob_path).unwrap();
+        stored[3 * 1024 + 7] ^= 0xff;
+        std::fs::write(&blob_path, stored).unwrap();
+
+        let err = coordinator.verify_blob(&tenant_id, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChunkChecksumMismatch { chunk: 3, .. }), "{}", err);
+
+        // Reads stream the intact chunks and fail at the corrupt one
+        // without returning any of its bytes
+        let mut reader = coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        let mut read = Vec::new();
+        assert!(reader.read_to_end(&mut read).is_err());
+        assert_eq!(read, data[..3 * 1024]);
+
+        // Only a chunk matching its recorded checksum is accepted as a repair
+        let chunk = &data[3 * 1024..4 * 1024];
+        let err = coordinator.repair_chunk(&tenant_id, &blob_id, 2, chunk).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChunkChecksumMismatch { chunk: 2, .. }), "{}", err);
+        coordinator.repair_chunk(&tenant_id, &blob_id, 3, chunk).unwrap();
+        coordinator.verify_blob(&tenant_id, &blob_id).unwrap();
+        let mut read = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn test_max_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+        static PEAK: AtomicUsize = AtomicUsize::new(0);
+        fn track() {
+            let in_flight = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+            PEAK.fetch_max(in_flight, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(temp_dir.path())
+            .unwrap()
+            .with_max_concurrency(3, OverloadPolicy::Queue);
+        coordinator.while_limited = Some(track);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // Every put queues for a slot and eventually succeeds
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..12)
+                .map(|i| {
+                    let (coordinator, tenant_id) = (&coordinator, &tenant_id);
+                    scope.spawn(move || coordinator.put_blob(tenant_id, Cursor::new(format!("post {}", i))))
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap().unwrap();
+            }
+        });
+        assert!(PEAK.load(Ordering::SeqCst) <= 3);
+        assert_eq!(coordinator.list_blob_ids(&tenant_id).unwrap().len(), 12);
+    }
+
+    #[test]
+    fn test_max_inflight_bytes() {
+        use std::sync::mpsc;
+        let coordinator = Coordinator::in_memory().unwrap().with_max_inflight_bytes(1_000, OverloadPolicy::Queue);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // A reader that holds its upload open until told to finish
+        struct Gated(mpsc::Receiver<()>, Cursor<Vec<u8>>);
+        impl Read for Gated {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.1.position() == 0 {
+                    self.0.recv().unwrap();
+                }
+                self.1.read(buf)
+            }
+        }
+
+        std::thread::scope(|scope| {
+            let (coordinator, tenant_id) = (&coordinator, &tenant_id);
+            let (open_gate, gate) = mpsc::channel();
+            let first = scope.spawn(move || {
+                coordinator.put_blob_with_size_hint(tenant_id, Gated(gate, Cursor::new(vec![1; 800])), 800)
+            });
+            std::thread::sleep(Duration::from_millis(50));
+
+            // The second upload doesn't fit beside the first and waits for it
+            let (done_tx, done) = mpsc::channel();
+            let second = scope.spawn(move || {
+                let result = coordinator.put_blob_with_size_hint(tenant_id, Cursor::new(vec![2; 300]), 300);
+                done_tx.send(()).unwrap();
+                result
+            });
+            assert!(done.recv_timeout(Duration::from_millis(100)).is_err());
+
+            open_gate.send(()).unwrap();
+            first.join().unwrap().unwrap();
+            done.recv_timeout(Duration::from_secs(10)).unwrap();
+            second.join().unwrap().unwrap();
+        });
+        assert_eq!(coordinator.list_blob_ids(&tenant_id).unwrap().len(), 2);
+
+        // Rejecting fails the upload that doesn't fit instead, and a failed
+        // upload gives its bytes back
+        let coordinator = coordinator.with_max_inflight_bytes(100, OverloadPolicy::Reject);
+        let held = coordinator.reserve_upload(Some(100)).unwrap();
+        let err = coordinator.put_blob_with_size_hint(&tenant_id, Cursor::new(b"late"), 4).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::UploadBudgetExceeded { requested: 4, budget: 100 }));
+        drop(held);
+        assert!(coordinator.put_blob_with_size_hint(&tenant_id, Cursor::new(b"late"), 5).is_err());
+        coordinator.put_blob_with_size_hint(&tenant_id, Cursor::new(b"late"), 4).unwrap();
+    }
+
+    #[test]
+    fn test_without_checksum_sidecars() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_checksum_sidecars(false);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let plain = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let coordinator = coordinator.with_compression(Compression::Lz4);
+        let compressed = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        for blob_id in [&plain, &compressed] {
+            let BlobLocation::File { checksum_path, .. } = coordinator.blob_location(blob_id).unwrap() else {
+                panic!("expected a chunk file");
+            };
+            assert_eq!(checksum_path, None);
+            assert!(!coordinator.chunk_store.checksum_path_in(Tier::Hot, blob_id).exists());
+
+            let mut data = Vec::new();
+            coordinator.get_blob(&tenant_id, blob_id).unwrap().read_to_end(&mut data).unwrap();
+            assert_eq!(data, b"Hello, World!");
+        }
+
+        // Corruption is still caught, using the checksum from metadata
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&plain).unwrap() else {
+            unreachable!()
+        };
+        std::fs::write(&blob_path, b"Hello, Wor1d!").unwrap();
+        let err = coordinator.get_blob(&tenant_id, &plain).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn test_blob_location() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        match coordinator.blob_location(&blob_id).unwrap() {
+            BlobLocation::File { blob_path, checksum_path } => {
+                assert_eq!(std::fs::read(blob_path).unwrap(), b"Hello, World!");
+                let expected = checksum::compute_sha256(Cursor::new(b"Hello, World!")).unwrap();
+                assert_eq!(std::fs::read_to_string(checksum_path.unwrap()).unwrap(), expected);
+            }
+            other => panic!("unexpected location {:?}", other),
+        }
+
+        let coordinator = coordinator.with_packfiles(1 << 20).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"first")).unwrap();
+        let packed = coordinator.put_blob(&tenant_id, Cursor::new(b"second")).unwrap();
+        match coordinator.blob_location(&packed).unwrap() {
+            BlobLocation::Packed { pack_path, offset, length } => {
+                let pack = std::fs::read(pack_path).unwrap();
+                assert_eq!(&pack[offset as usize..(offset + length) as usize], b"second");
+            }
+            other => panic!("unexpected location {:?}", other),
+        }
+
+        let err = coordinator.blob_location(&BlobId::new()).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobNotFound(_)));
+    }
+
+    #[test]
+    fn test_slow_op_warning() {
+        let capture = LogCapture::default();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        tracing::subscriber::with_default(capture.subscriber(), || {
+            let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+            coordinator.register_tenant(tenant_id.clone()).unwrap();
+            coordinator.put_blob(&tenant_id, Cursor::new(b"fast")).unwrap();
+            assert!(capture.contents().is_empty());
+
+            let coordinator = coordinator.with_slow_op_threshold(Duration::from_nanos(1));
+            let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"slow")).unwrap();
+            let logs = capture.contents();
+            assert!(logs.contains("WARN"));
+            assert!(logs.contains("slow operation"));
+            assert!(logs.contains("operation=\"put\""));
+            assert!(logs.contains(&blob_id.to_string()));
+        });
+    }
+
+    #[test]
+    fn test_startup_check() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let mut coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_ids: Vec<_> = (0..3)
+            .map(|i| coordinator.put_blob(&tenant_id, Cursor::new(format!("post {}", i))).unwrap())
+            .collect();
+
+        // A consistent store passes at every level
+        for level in [CheckLevel::None, CheckLevel::Quick, CheckLevel::Full] {
+            coordinator = coordinator.with_strict_startup_check(level).unwrap();
+        }
+
+        // Same-size corruption needs a full check to be seen
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&blob_ids[0]).unwrap() else {
+            panic!("expected a chunk file");
+        };
+        std::fs::write(&blob_path, b"POST 0").unwrap();
+        coordinator = coordinator.with_strict_startup_check(CheckLevel::Quick).unwrap();
+        let err = coordinator.with_strict_startup_check(CheckLevel::Full).err().unwrap();
+        assert!(matches!(err, Error::StartupCheckFailed { damaged: 1 }));
+
+        // A missing chunk is caught by the quick check and only logged unless strict
+        let coordinator = Coordinator::new(temp_dir.path().join("other")).unwrap();
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let missing = coordinator.put_blob(&tenant_id, Cursor::new(b"post")).unwrap();
+        coordinator.chunk_store.delete_blob(&missing).unwrap();
+        let capture = LogCapture::default();
+        let coordinator = tracing::subscriber::with_default(capture.subscriber(), || {
+            coordinator.with_startup_check(CheckLevel::Quick).unwrap()
+        });
+        let logs = capture.contents();
+        assert!(logs.contains(&format!("blob_id={}", missing)), "{}", logs);
+        assert!(logs.contains("checked=1 damaged=1"), "{}", logs);
+        let coordinator = coordinator.with_strict_startup_check(CheckLevel::None).unwrap();
+        let err = coordinator.with_strict_startup_check(CheckLevel::Quick).err().unwrap();
+        assert!(matches!(err, Error::StartupCheckFailed { damaged: 1 }));
+    }
+
+    #[test]
+    fn test_checksum_algorithm_drift() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        assert_eq!(coordinator.detect_checksum_algorithm().unwrap(), None);
+        coordinator.put_blob(&tenant_id, Cursor::new(b"sha256 blob")).unwrap();
+        assert_eq!(coordinator.detect_checksum_algorithm().unwrap().as_deref(), Some("sha256"));
+
+        // Seed records written by a BLAKE3 build until they predominate
+        for _ in 0..3 {
+            let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"blake3 blob")).unwrap();
+            coordinator
+                .metadata_store
+                .update_metadata(&blob_id, |metadata| {
+                    metadata.checksum = Checksum::new("blake3", [0xab; 32]);
+                    true
+                })
+                .unwrap();
+        }
+        drop(coordinator);
+
+        let capture = LogCapture::default();
+        let coordinator = tracing::subscriber::with_default(capture.subscriber(), || {
+            reopen(|| Coordinator::new(temp_dir.path()))
+        });
+        let logs = capture.contents();
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("found=\"blake3\""), "{}", logs);
+        assert!(logs.contains("configured=\"sha256\""), "{}", logs);
+
+        let err = coordinator.with_strict_checksum_algorithm().err().unwrap();
+        assert!(matches!(
+            err,
+            Error::ChecksumAlgorithmMismatch { ref found, .. } if found == "blake3"
+        ));
+
+        // An undecodable record is skipped rather than failing the open
+        let db = reopen(|| Ok(sled::open(temp_dir.path().join(StoreLayout::default().metadata_dir))?));
+        db.insert(format!("blob:{}", BlobId::new()), b"not json".as_slice()).unwrap();
+        drop(db);
+        let capture = LogCapture::default();
+        let coordinator = tracing::subscriber::with_default(capture.subscriber(), || {
+            reopen(|| Coordinator::new(temp_dir.path()))
+        });
+        assert!(capture.contents().contains("skipping undecodable blob record"), "{}", capture.contents());
+        assert_eq!(coordinator.detect_checksum_algorithm().unwrap().as_deref(), Some("blake3"));
+
+        // A store written with the configured algorithm opens in strict mode
+        let other_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(other_dir.path()).unwrap();
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap();
+        coordinator.with_strict_checksum_algorithm().unwrap();
+    }
+
+    #[test]
+    fn test_list_blobs_projected() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        for i in 0..5 {
+            let tags = [("index".to_string(), i.to_string())].into();
+            coordinator
+                .put_blob_with_tags(&tenant_id, Cursor::new(vec![0u8; i * 10]), tags)
+                .unwrap();
+        }
+        let full = coordinator.list_blobs(&tenant_id).unwrap();
+
+        let sizes = coordinator.list_blobs_projected(&tenant_id, &[MetadataField::Size]).unwrap();
+        assert_eq!(
+            sizes.iter().map(|p| (&p.blob_id, p.size)).collect::<Vec<_>>(),
+            full.iter().map(|m| (&m.blob_id, Some(m.size))).collect::<Vec<_>>()
+        );
+        assert!(sizes.iter().all(|p| p.checksum.is_none() && p.tags.is_none() && p.created_at.is_none()));
+
+        let fields = [MetadataField::Checksum, MetadataField::Tags];
+        let projected = coordinator.list_blobs_projected(&tenant_id, &fields).unwrap();
+        for (partial, metadata) in projected.iter().zip(&full) {
+            assert_eq!(partial.blob_id, metadata.blob_id);
+            assert_eq!(partial.size, None);
+            assert_eq!(partial.checksum.as_ref(), Some(&metadata.checksum));
+            assert_eq!(partial.tags.as_ref(), Some(&metadata.tags));
+        }
+
+        // An empty projection still lists every blob id
+        let ids = coordinator.list_blobs_projected(&tenant_id, &[]).unwrap();
+        assert_eq!(ids.len(), full.len());
+        assert!(ids.iter().all(|p| p.size.is_none()));
+    }
+
+    #[test]
+    fn test_metadata_and_chunk_divergence() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // Metadata without a chunk
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        coordinator.chunk_store.delete_blob(&blob_id).unwrap();
+        let err = coordinator.get_blob(&tenant_id, &blob_id).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::ChunkMissing { blob_id: id } if *id == blob_id.to_string()));
+
+        // A chunk without metadata stays invisible
+        let orphan = BlobId::new();
+        coordinator.chunk_store.put_blob(&orphan, Cursor::new(b"orphan")).unwrap();
+        let err = coordinator.get_blob(&tenant_id, &orphan).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::BlobNotFound(_)));
+
+        // The same applies to packed blobs whose pack is gone
+        let coordinator = coordinator.with_packfiles(1 << 20).unwrap();
+        let packed = coordinator.put_blob(&tenant_id, Cursor::new(b"packed")).unwrap();
+        match coordinator.blob_location(&packed).unwrap() {
+            BlobLocation::Packed { pack_path, .. } => std::fs::remove_file(pack_path).unwrap(),
+            other => panic!("unexpected location {:?}", other),
+        }
+        let err = coordinator.get_blob(&tenant_id, &packed).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::ChunkMissing { .. }));
+    }
+
+    #[test]
+    fn test_compact_metadata() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let blob_ids: Vec<_> = (0..200)
+            .map(|i| coordinator.put_blob(&tenant_id, Cursor::new(format!("blob {}", i))).unwrap())
+            .collect();
+        for blob_id in blob_ids.iter().skip(10) {
+            coordinator.delete_blob(&tenant_id, blob_id).unwrap();
+        }
+
+        let report = coordinator.compact_metadata().unwrap();
+        assert!(report.before_bytes > 0 && report.after_bytes > 0);
+
+        // Every surviving record is still readable
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.iter().map(|m| &m.blob_id).collect::<Vec<_>>(), blob_ids[..10].iter().collect::<Vec<_>>());
+        for blob_id in &blob_ids[..10] {
+            coordinator.get_blob(&tenant_id, blob_id).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_recover_blob() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&blob_id).unwrap() else {
+            panic!("expected a file-backed blob");
+        };
+        std::fs::write(&blob_path, b"Hello, Wxrld!").unwrap();
+        assert!(coordinator.get_blob(&tenant_id, &blob_id).is_err());
+
+        // Recovery is refused outside admin mode
+        let err = coordinator.recover_blob(&blob_id).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::PermissionDenied(_)));
+
+        let coordinator = coordinator.with_admin_mode();
+        let (mut reader, metadata) = coordinator.recover_blob(&blob_id).unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, Wxrld!");
+        assert_eq!(metadata.tenant_id, tenant_id);
+    }
+
+    #[test]
+    fn test_resolve_blob_id() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let first = BlobId::from_uuid("1234abcd-0000-4000-8000-000000000001".parse().unwrap());
+        let second = BlobId::from_uuid("1234abcd-0000-4000-8000-000000000002".parse().unwrap());
+        let other = BlobId::from_uuid("9876fedc-0000-4000-8000-000000000003".parse().unwrap());
+        for blob_id in [&first, &second, &other] {
+            coordinator.put_blob_with_id(&tenant_id, blob_id.clone(), Cursor::new(b"post")).unwrap();
+        }
+        assert_eq!(other.short(), "9876fedc");
+
+        // A unique prefix resolves, however it is written
+        assert_eq!(coordinator.resolve_blob_id(&tenant_id, &other.short()).unwrap(), other);
+        assert_eq!(coordinator.resolve_blob_id(&tenant_id, "9876FE").unwrap(), other);
+        assert_eq!(coordinator.resolve_blob_id(&tenant_id, "1234abcd-0000-4000-8000-000000000002").unwrap(), second);
+
+        let err = coordinator.resolve_blob_id(&tenant_id, "1234").unwrap_err();
+        assert!(matches!(err.root_cause(), Error::AmbiguousBlobId { matches: 2, .. }));
+        let err = coordinator.resolve_blob_id(&tenant_id, "ffff").unwrap_err();
+        assert!(err.is_not_found());
+    }
+
+    #[test]
+    fn test_find_by_checksum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let other_tenant = TenantId::new("archive");
+        coordinator.register_tenant(other_tenant.clone()).unwrap();
+
+        let first = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let copy = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"something else")).unwrap();
+        coordinator.put_blob(&other_tenant, Cursor::new(b"Hello, World!")).unwrap();
+
+        let checksum = checksum::compute_sha256(&b"Hello, World!"[..]).unwrap();
+        let mut found = coordinator.find_by_checksum(&tenant_id, &checksum).unwrap();
+        found.sort_by_key(|blob_id| blob_id.to_string());
+        let mut expected = vec![first.clone(), copy.clone()];
+        expected.sort_by_key(|blob_id| blob_id.to_string());
+        assert_eq!(found, expected);
+
+        // Deleted blobs drop out of the index
+        coordinator.delete_blob(&tenant_id, &first).unwrap();
+        let found = coordinator.find_by_checksum(&tenant_id, &format!("sha256:{}", checksum)).unwrap();
+        assert_eq!(found, vec![copy]);
+        let unknown = checksum::compute_sha256(&b"never stored"[..]).unwrap();
+        assert!(coordinator.find_by_checksum(&tenant_id, &unknown).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_by_tags() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let other_tenant = TenantId::new("archive");
+        coordinator.register_tenant(other_tenant.clone()).unwrap();
+
+        let put = |tenant_id: &TenantId, pairs: &[(&str, &str)]| {
+            let tags: Tags = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            coordinator.put_blob_with_tags(tenant_id, Cursor::new(b"post"), tags).unwrap()
+        };
+        let both = put(&tenant_id, &[("category", "news"), ("category", "sports"), ("lang", "en")]);
+        let news_fr = put(&tenant_id, &[("category", "news"), ("lang", "fr")]);
+        let sports_en = put(&tenant_id, &[("category", "sports"), ("lang", "en")]);
+        put(&other_tenant, &[("category", "news"), ("lang", "en")]);
+        let sorted = |mut blob_ids: Vec<BlobId>| {
+            blob_ids.sort_by_key(|blob_id| blob_id.to_string());
+            blob_ids
+        };
+
+        // A blob with two values under a key matches either one
+        assert_eq!(
+            sorted(coordinator.find_by_tag(&tenant_id, "category", "news").unwrap()),
+            sorted(vec![both.clone(), news_fr.clone()])
+        );
+        assert_eq!(
+            sorted(coordinator.find_by_tag(&tenant_id, "category", "sports").unwrap()),
+            sorted(vec![both.clone(), sports_en])
+        );
+
+        // Several filters select the intersection
+        let filters = [("category".to_string(), "news".to_string()), ("lang".to_string(), "en".to_string())];
+        assert_eq!(coordinator.find_by_tags(&tenant_id, &filters).unwrap(), vec![both]);
+        assert!(coordinator.find_by_tags(&tenant_id, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_enforce_retention_policy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("logs");
+        let config = TenantConfig { retention_days: Some(3650), ..Default::default() };
+        coordinator.register_tenant_with_config(tenant_id.clone(), config).unwrap();
+        let unmanaged = TenantId::new("posts");
+        coordinator.register_tenant(unmanaged.clone()).unwrap();
+
+        let put_aged = |tenant_id: &TenantId, days: i64| {
+            let created_at = Some(Utc::now() - chrono::Duration::days(days));
+            let options = PutOptions { created_at, ..Default::default() };
+            coordinator.store_blob(tenant_id, Cursor::new(format!("{} days old", days)), options).unwrap()
+        };
+        let recent = put_aged(&tenant_id, 29);
+        put_aged(&tenant_id, 31);
+        let today = put_aged(&tenant_id, 0);
+        put_aged(&unmanaged, 400);
+
+        // A long policy deletes nothing
+        assert_eq!(coordinator.enforce_retention_all().unwrap(), 0);
+
+        // Only the blob just past the boundary goes
+        coordinator.update_tenant_config(&tenant_id, |config| config.retention_days = Some(30)).unwrap();
+        assert_eq!(coordinator.enforce_retention(&tenant_id).unwrap(), 1);
+        let mut remaining = coordinator.list_blob_ids(&tenant_id).unwrap();
+        remaining.sort_by_key(|blob_id| blob_id.to_string());
+        let mut expected = vec![recent, today];
+        expected.sort_by_key(|blob_id| blob_id.to_string());
+        assert_eq!(remaining, expected);
+
+        // A zero-day policy deletes everything, but only for that tenant
+        coordinator.update_tenant_config(&tenant_id, |config| config.retention_days = Some(0)).unwrap();
+        assert_eq!(coordinator.enforce_retention_all().unwrap(), 2);
+        assert!(coordinator.list_blob_ids(&tenant_id).unwrap().is_empty());
+        assert_eq!(coordinator.list_blob_ids(&unmanaged).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_put_builder() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let blob_id = BlobId::new();
+        let tags = Tags::from([("kind".to_string(), "note".to_string())]);
+        let checksum = checksum::compute_sha256(&b"Hello, World!"[..]).unwrap();
+        let stored = coordinator
+            .put(&tenant_id)
+            .id(blob_id.clone())
+            .tags(tags.clone())
+            .content_type("text/markdown")
+            .ttl(chrono::Duration::hours(1))
+            .expected_checksum(&format!("sha256:{}", checksum))
+            .store(Cursor::new(b"Hello, World!"))
+            .unwrap();
+        assert_eq!(stored, blob_id);
+
+        let metadata = coordinator.get_metadata(&blob_id).unwrap();
+        assert_eq!(metadata.tags, tags);
+        assert_eq!(metadata.content_type.as_deref(), Some("text/markdown"));
+        assert_eq!(metadata.checksum.to_hex(), checksum);
+        let expires_at = metadata.expires_at.unwrap();
+        assert!(expires_at > Utc::now() + chrono::Duration::minutes(59));
+        assert!(!metadata.is_expired());
+
+        // A bad checksum stores nothing
+        let err = coordinator
+            .put(&tenant_id)
+            .expected_checksum(&checksum)
+            .store(Cursor::new(b"something else"))
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+
+        // Expired blobs read as missing until purged
+        let expired = coordinator
+            .put(&tenant_id)
+            .ttl(chrono::Duration::seconds(-1))
+            .store(Cursor::new(b"stale"))
+            .unwrap();
+        assert!(coordinator.get_blob(&tenant_id, &expired).err().unwrap().is_not_found());
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+        assert!(!coordinator.blob_exists(&tenant_id, &expired).unwrap());
+        assert_eq!(coordinator.iter_blobs(&tenant_id).unwrap().count(), 1);
+        for fields in [&[MetadataField::Size][..], &[MetadataField::Checksum]] {
+            assert_eq!(coordinator.list_blobs_projected(&tenant_id, fields).unwrap().len(), 1);
+        }
+        let mut stream = Vec::new();
+        assert_eq!(coordinator.export_metadata_stream(&mut stream).unwrap(), 1);
+        assert!(coordinator.link_name(&tenant_id, "stale", &expired).err().unwrap().is_not_found());
+        assert_eq!(coordinator.purge_expired(&tenant_id).unwrap(), 1);
+        assert_eq!(coordinator.purge_expired(&tenant_id).unwrap(), 0);
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_extensions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let review = serde_json::json!({
+            "reviewer": {"name": "ada", "roles": ["editor", "admin"]},
+            "score": 4.5,
+            "approved": true,
+            "notes": null,
+        });
+        let blob_id = coordinator
+            .put(&tenant_id)
+            .extension("review", review.clone())
+            .extension("revision", serde_json::json!(3))
+            .store(Cursor::new(b"draft"))
+            .unwrap();
+
+        // Extensions come back verbatim, including after a reopen
+        drop(coordinator);
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()));
+        let metadata = coordinator.get_metadata(&blob_id).unwrap();
+        assert_eq!(metadata.extensions.len(), 2);
+        assert_eq!(metadata.extensions["review"], review);
+        assert_eq!(metadata.extensions["revision"], serde_json::json!(3));
+
+        // Blobs stored without any have none
+        let plain = coordinator.put_blob(&tenant_id, Cursor::new(b"plain")).unwrap();
+        assert!(coordinator.get_metadata(&plain).unwrap().extensions.is_empty());
+    }
+
+    #[test]
+    fn test_clock_expires_ttl_blob() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(crate::clock::TestClock::default());
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_clock(clock.clone());
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let start = clock.now();
+
+        let blob_id = coordinator
+            .put(&tenant_id)
+            .ttl(chrono::Duration::hours(1))
+            .store(Cursor::new(b"short-lived"))
+            .unwrap();
+        assert_eq!(coordinator.get_metadata(&blob_id).unwrap().created_at, start);
+        assert_eq!(coordinator.get_tenant_config(&tenant_id).unwrap().created_at, Some(start));
+
+        clock.advance(chrono::Duration::minutes(59));
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+
+        // No real time passes, yet the blob expires once the clock says so
+        clock.advance(chrono::Duration::minutes(1));
+        assert!(coordinator.get_blob(&tenant_id, &blob_id).err().unwrap().is_not_found());
+        assert_eq!(coordinator.purge_expired(&tenant_id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_access_tracking() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(crate::clock::TestClock::default());
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_clock(clock.clone());
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let last_accessed = |coordinator: &Coordinator| coordinator.get_metadata(&blob_id).unwrap().last_accessed;
+
+        // Nothing is recorded while tracking is off
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        coordinator.flush_access_times().unwrap();
+        assert_eq!(last_accessed(&coordinator), None);
+
+        // Reads are buffered rather than written one by one
+        let coordinator = coordinator.with_access_tracking(true);
+        clock.advance(chrono::Duration::minutes(5));
+        let read_at = clock.now();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        assert_eq!(last_accessed(&coordinator), None);
+        coordinator.flush_access_times().unwrap();
+        assert_eq!(last_accessed(&coordinator), Some(read_at));
+        assert_eq!(coordinator.get_metadata(&blob_id).unwrap().generation, 0);
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap()[0].last_accessed, Some(read_at));
+
+        // Reads still buffered at shutdown are written on close
+        clock.advance(chrono::Duration::minutes(5));
+        let reread_at = clock.now();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap();
+        drop(coordinator);
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()));
+        assert_eq!(last_accessed(&coordinator), Some(reread_at));
+    }
+
+    #[test]
+    fn test_get_blob_range() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let read_range = |offset, length| {
+            let mut data = Vec::new();
+            coordinator.get_blob_range(&tenant_id, &blob_id, offset, length).unwrap().read_to_end(&mut data)?;
+            Ok::<_, std::io::Error>(data)
+        };
+
+        assert_eq!(read_range(7, 5).unwrap(), b"World");
+        assert_eq!(read_range(7, 100).unwrap(), b"World!");
+        assert!(read_range(20, 5).unwrap().is_empty());
+
+        // A truncated file fails ranges reaching past its new end instead of
+        // returning fewer bytes
+        let blob_path = temp_dir.path().join("chunks").join(format!("{}.blob", blob_id));
+        File::options().write(true).open(blob_path).unwrap().set_len(5).unwrap();
+        assert_eq!(read_range(0, 4).unwrap(), b"Hell");
+        let err = read_range(2, 6).unwrap_err();
+        let inner = err.into_inner().unwrap().downcast::<Error>().unwrap();
+        assert!(matches!(*inner, Error::SizeMismatch { expected: 8, actual: 5 }));
+    }
+
+    #[test]
+    fn test_conflict_policy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let read = |blob_id: &BlobId| {
+            let mut data = Vec::new();
+            coordinator.get_blob(&tenant_id, blob_id).unwrap().read_to_end(&mut data).unwrap();
+            data
+        };
+        let put = |blob_id: &BlobId, policy, data: &'static [u8]| {
+            coordinator.put(&tenant_id).id(blob_id.clone()).on_conflict(policy).store(Cursor::new(data))
+        };
+
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"original")).unwrap();
+        let err = put(&blob_id, ConflictPolicy::Error, b"new").unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobAlreadyExists(_)));
+        assert_eq!(read(&blob_id), b"original");
+
+        assert_eq!(put(&blob_id, ConflictPolicy::Skip, b"new").unwrap(), blob_id);
+        assert_eq!(read(&blob_id), b"original");
+
+        let generation = coordinator.get_metadata(&blob_id).unwrap().generation;
+        assert_eq!(put(&blob_id, ConflictPolicy::Overwrite, b"replacement").unwrap(), blob_id);
+        assert_eq!(read(&blob_id), b"replacement");
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+        let metadata = coordinator.get_metadata(&blob_id).unwrap();
+        assert_eq!(metadata.size, 11);
+        assert!(metadata.generation > generation);
+        assert_eq!(coordinator.tenant_usage(&tenant_id).unwrap(), 11);
+
+        // An overwrite whose data fails its checks leaves the original intact
+        let files = || std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count();
+        let before = files();
+        let err = coordinator
+            .put(&tenant_id)
+            .id(blob_id.clone())
+            .on_conflict(ConflictPolicy::Overwrite)
+            .expected_checksum(&"0".repeat(64))
+            .store(Cursor::new(b"corrupted"))
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+        let options = PutOptions {
+            blob_id: Some(blob_id.clone()),
+            on_conflict: ConflictPolicy::Overwrite,
+            size_hint: Some(3),
+            ..PutOptions::default()
+        };
+        let err = coordinator.write_blob(&tenant_id, Cursor::new(b"truncated"), options).unwrap_err();
+        assert!(matches!(err, Error::SizeMismatch { .. }));
+        assert_eq!(read(&blob_id), b"replacement");
+        assert_eq!(files(), before);
+
+        // A retention lock blocks overwriting
+        let locked = coordinator
+            .put(&tenant_id)
+            .retain_until(Utc::now() + chrono::Duration::days(1))
+            .store(Cursor::new(b"locked"))
+            .unwrap();
+        let err = put(&locked, ConflictPolicy::Overwrite, b"tampered").unwrap_err();
+        assert!(matches!(err.root_cause(), Error::RetentionActive { .. }));
+        assert_eq!(read(&locked), b"locked");
+
+        // Another tenant's id can't be skipped onto or overwritten
+        let other = TenantId::new("photos");
+        coordinator.register_tenant(other.clone()).unwrap();
+        for policy in [ConflictPolicy::Skip, ConflictPolicy::Overwrite] {
+            let err = coordinator.put(&other).id(blob_id.clone()).on_conflict(policy).store(Cursor::new(b"x"));
+            assert!(matches!(err.unwrap_err().root_cause(), Error::InvalidTenant(_)));
+        }
+        assert_eq!(read(&blob_id), b"replacement");
+
+        // Of concurrent puts of one new id, exactly one stores its data
+        let contested = BlobId::new();
+        let bodies: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 64 << 10]).collect();
+        let stored: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = bodies
+                .iter()
+                .map(|body| {
+                    let (coordinator, tenant_id, contested) = (&coordinator, &tenant_id, &contested);
+                    scope.spawn(move || {
+                        coordinator.put(tenant_id).id(contested.clone()).store(Cursor::new(body)).map(|_| body)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        let winners: Vec<_> = stored.iter().filter_map(|result| result.as_ref().ok()).collect();
+        assert_eq!(winners.len(), 1);
+        for err in stored.iter().filter_map(|result| result.as_ref().err()) {
+            assert!(matches!(err.root_cause(), Error::BlobAlreadyExists(_)), "{}", err);
+        }
+        assert_eq!(&read(&contested), *winners[0]);
+    }
+
+    #[test]
+    fn test_interrupted_overwrite_is_finished() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"original")).unwrap();
+
+        // Crash after the records were swapped but before the data moved
+        let old = coordinator.get_metadata(&blob_id).unwrap();
+        let staged = BlobId::new();
+        let info = coordinator.chunk_store.put_blob(&staged, Cursor::new(b"replacement")).unwrap();
+        let metadata = BlobMetadata { size: info.size, checksum: info.checksum, generation: 1, ..old.clone() };
+        coordinator.metadata_store.replace_metadata(&metadata, old.generation, Some(&staged)).unwrap();
+        drop(coordinator);
+
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()));
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"replacement");
+        assert!(coordinator.metadata_store.unfinished_replaces().unwrap().is_empty());
+        assert!(!coordinator.chunk_store.blob_path_in(Tier::Hot, &staged).exists());
+    }
+
+    #[test]
+    fn test_retention() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let other_tenant = TenantId::new("archive");
+        coordinator.register_tenant(other_tenant.clone()).unwrap();
+
+        let retain_until = Utc::now() + chrono::Duration::milliseconds(500);
+        let blob_id = coordinator
+            .put_blob_with_retention(&tenant_id, Cursor::new(b"ledger"), retain_until)
+            .unwrap();
+
+        let err = coordinator.delete_blob(&tenant_id, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::RetentionActive { .. }));
+        let err = coordinator.move_blob(&tenant_id, &other_tenant, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::RetentionActive { .. }));
+        let err = coordinator
+            .update_metadata_if(&tenant_id, &blob_id, 0, |metadata| metadata.tags.set("status", "changed"))
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::RetentionActive { .. }));
+        assert!(coordinator.get_metadata(&blob_id).unwrap().tags.get("status").is_none());
+
+        // Retention can be extended but never shortened
+        let err = coordinator
+            .extend_retention(&tenant_id, &blob_id, retain_until - chrono::Duration::seconds(1))
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::RetentionActive { .. }));
+        let extended = retain_until + chrono::Duration::milliseconds(200);
+        coordinator.extend_retention(&tenant_id, &blob_id, extended).unwrap();
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap()[0].retain_until, Some(extended));
+
+        // Nor can a metadata update clear or shorten it
+        std::thread::sleep((extended - Utc::now()).to_std().unwrap_or_default());
+        let generation = coordinator.get_metadata(&blob_id).unwrap().generation;
+        for shortened in [None, Some(retain_until)] {
+            let err = coordinator
+                .update_metadata_if(&tenant_id, &blob_id, generation, |metadata| metadata.retain_until = shortened)
+                .unwrap_err();
+            assert!(matches!(err.root_cause(), Error::RetentionActive { .. }));
+        }
+        assert_eq!(coordinator.get_metadata(&blob_id).unwrap().retain_until, Some(extended));
+
+        // Once retention lapses the blob can be deleted
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        assert!(!coordinator.blob_exists(&tenant_id, &blob_id).unwrap());
+    }
+
+    #[test]
+    fn test_update_metadata_if() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_metadata_cache(16);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"draft")).unwrap();
+        assert_eq!(coordinator.get_metadata(&blob_id).unwrap().generation, 0);
+
+        // Both clients read generation 0; the first update wins
+        let updated = coordinator
+            .update_metadata_if(&tenant_id, &blob_id, 0, |metadata| {
+                metadata.tags.set("status", "published");
+            })
+            .unwrap();
+        assert_eq!(updated.generation, 1);
+        let err = coordinator
+            .update_metadata_if(&tenant_id, &blob_id, 0, |metadata| {
+                metadata.content_type = Some("text/plain".into());
+            })
+            .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ConcurrentModification { expected: 0, actual: 1, .. }));
+
+        let metadata = coordinator.get_metadata(&blob_id).unwrap();
+        assert_eq!(metadata.generation, 1);
+        assert_eq!(metadata.tags.get("status"), Some("published"));
+        assert_eq!(metadata.content_type, None);
+
+        // Other tenants can't update the blob
+        let other_tenant = TenantId::new("archive");
+        coordinator.register_tenant(other_tenant.clone()).unwrap();
+        let err = coordinator.update_metadata_if(&other_tenant, &blob_id, 1, |_| {}).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidTenant(_)));
+    }
+
+    #[test]
+    fn test_put_blob_transformed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // Larger than one transform chunk, so several chunks pass through
+        let original = "hello, world! ".repeat(10_000);
+        let blob_id = coordinator
+            .put_blob_transformed(&tenant_id, Cursor::new(original.clone()), |chunk| chunk.to_ascii_uppercase())
+            .unwrap();
+
+        let expected = original.to_ascii_uppercase();
+        let mut stored = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut stored).unwrap();
+        assert_eq!(stored, expected.as_bytes());
+
+        let metadata = &coordinator.list_blobs(&tenant_id).unwrap()[0];
+        assert_eq!(metadata.size, expected.len() as u64);
+        assert_eq!(metadata.checksum, crate::checksum::checksum_of(expected.as_bytes()).unwrap());
+
+        // Transforms may change the length of the data
+        let blob_id = coordinator
+            .put_blob_transformed(&tenant_id, Cursor::new("a\r\nb\r\n"), |chunk| {
+                chunk.iter().copied().filter(|&b| b != b'\r').collect()
+            })
+            .unwrap();
+        let mut stored = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut stored).unwrap();
+        assert_eq!(stored, b"a\nb\n");
+    }
+
+    #[test]
+    fn test_open_blob_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"0123456789abcdef")).unwrap();
+
+        let mut file = coordinator.open_blob_file(&tenant_id, &blob_id).unwrap();
+        file.seek(SeekFrom::Start(8)).unwrap();
+        let mut data = [0; 4];
+        file.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"89ab");
+        file.seek(SeekFrom::End(-2)).unwrap();
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"ef");
+
+        // Corruption is caught before a handle is handed out
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&blob_id).unwrap() else {
+            panic!("expected a file-backed blob");
+        };
+        std::fs::write(&blob_path, b"0123456789abcdeX").unwrap();
+        let err = coordinator.open_blob_file(&tenant_id, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn test_delete_is_idempotent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&blob_id).unwrap() else {
+            panic!("expected a file-backed blob");
+        };
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        assert!(!blob_path.exists());
+
+        // A delete interrupted after removing the chunk can be retried
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        coordinator.chunk_store.delete_blob(&blob_id).unwrap();
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        assert!(!coordinator.blob_exists(&tenant_id, &blob_id).unwrap());
+    }
+
+    #[test]
+    fn test_tenant_default_tags() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let plain_tenant = TenantId::new("messages");
+        coordinator.register_tenant(plain_tenant.clone()).unwrap();
+
+        let tags = |pairs: &[(&str, &str)]| -> Tags {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        coordinator
+            .set_tenant_default_tags(&tenant_id, tags(&[("env", "prod"), ("team", "ops")]))
+            .unwrap();
+        assert_eq!(coordinator.get_tenant_default_tags(&tenant_id).unwrap().len(), 2);
+
+        let defaulted = coordinator.put_blob(&tenant_id, Cursor::new(b"a")).unwrap();
+        let overridden = coordinator
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"b"), tags(&[("env", "staging"), ("owner", "me")]))
+            .unwrap();
+        let plain = coordinator.put_blob(&plain_tenant, Cursor::new(b"c")).unwrap();
+
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs[0].blob_id, defaulted);
+        assert_eq!(blobs[0].tags, tags(&[("env", "prod"), ("team", "ops")]));
+        assert_eq!(blobs[1].blob_id, overridden);
+        assert_eq!(blobs[1].tags, tags(&[("env", "staging"), ("owner", "me"), ("team", "ops")]));
+
+        let blobs = coordinator.list_blobs(&plain_tenant).unwrap();
+        assert_eq!(blobs[0].blob_id, plain);
+        assert!(blobs[0].tags.is_empty());
+        assert!(coordinator.get_tenant_default_tags(&plain_tenant).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_consistency_check_and_repair() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let healthy = coordinator.put_blob(&tenant_id, Cursor::new(b"healthy")).unwrap();
+        assert!(coordinator.check_consistency().unwrap().is_consistent());
+
+        // Metadata whose chunk is gone
+        let missing = coordinator.put_blob(&tenant_id, Cursor::new(b"missing")).unwrap();
+        coordinator.chunk_store.delete_blob(&missing).unwrap();
+
+        // A chunk nothing points at
+        let orphan = BlobId::new();
+        coordinator.chunk_store.put_blob(&orphan, Cursor::new(b"orphan")).unwrap();
+
+        // A blob owned by a tenant that was never registered
+        let ghost_tenant = TenantId::new("ghost");
+        let unowned = coordinator.put_blob(&tenant_id, Cursor::new(b"unowned")).unwrap();
+        coordinator.metadata_store.move_blob(&unowned, &tenant_id, &ghost_tenant, Utc::now()).unwrap();
+
+        // A membership entry without metadata
+        let dangling = coordinator.put_blob(&tenant_id, Cursor::new(b"dangling")).unwrap();
+        coordinator.metadata_store.transaction(|tx| {
+            tx.remove(format!("blob:{}", dangling).as_bytes())?;
+            Ok(())
+        }).unwrap();
+        coordinator.chunk_store.delete_blob(&dangling).unwrap();
+
+        let report = coordinator.check_consistency().unwrap();
+        assert_eq!(report.missing_chunks, vec![missing.clone()]);
+        assert_eq!(report.orphan_chunks, vec![orphan.clone()]);
+        assert_eq!(report.unregistered_tenants, vec![(unowned.clone(), ghost_tenant.clone())]);
+        assert_eq!(report.dangling_members, vec![(tenant_id.clone(), dangling)]);
+
+        // Checking doesn't modify anything
+        assert_eq!(coordinator.check_consistency().unwrap(), report);
+
+        // A chunk written within the grace period may belong to a put that
+        // hasn't committed its metadata yet
+        let grace = Duration::from_secs(3600);
+        assert_eq!(coordinator.repair(&report, grace).unwrap(), 3);
+        assert_eq!(coordinator.check_consistency().unwrap().orphan_chunks, vec![orphan.clone()]);
+        assert_eq!(coordinator.repair(&report, Duration::ZERO).unwrap(), 1);
+        assert!(coordinator.check_consistency().unwrap().is_consistent());
+        assert_eq!(coordinator.repair(&report, Duration::ZERO).unwrap(), 0);
+
+        // Nor are chunks reserved by a pending upload
+        let pending = coordinator.begin_put(&tenant_id).unwrap();
+        coordinator.chunk_store.put_blob(pending.blob_id(), Cursor::new(b"pending")).unwrap();
+        let report = coordinator.check_consistency().unwrap();
+        assert_eq!(report.orphan_chunks, vec![pending.blob_id().clone()]);
+        assert_eq!(coordinator.repair(&report, Duration::ZERO).unwrap(), 0);
+        assert!(coordinator.chunk_store.blob_size_in(pending.blob_id(), Tier::Hot).is_ok());
+
+        coordinator.get_blob(&tenant_id, &healthy).unwrap();
+        coordinator.get_blob(&ghost_tenant, &unowned).unwrap();
+        assert!(!coordinator.blob_exists(&tenant_id, &missing).unwrap());
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_packfiles(1 << 20).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let kept = coordinator.put_blob(&tenant_id, Cursor::new(b"kept")).unwrap();
+        let deleted = coordinator.put_blob(&tenant_id, Cursor::new(b"deleted")).unwrap();
+        coordinator.delete_blob(&tenant_id, &deleted).unwrap();
+        coordinator.put(&tenant_id).ttl(chrono::Duration::seconds(-1)).store(Cursor::new(b"stale")).unwrap();
+        let orphan = BlobId::new();
+        coordinator.chunk_store.put_blob(&orphan, Cursor::new(b"orphan")).unwrap();
+        let dangling = coordinator.put_blob(&tenant_id, Cursor::new(b"dangling")).unwrap();
+        coordinator.metadata_store.transaction(|tx| {
+            tx.remove(format!("blob:{}", dangling).as_bytes())?;
+            Ok(())
+        }).unwrap();
+        let options = OptimizeOptions { orphan_grace: Duration::ZERO, ..OptimizeOptions::default() };
+
+        // A cancelled run stops before doing anything
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = coordinator.optimize(options, &cancel).unwrap();
+        assert!(report.cancelled);
+        assert_eq!(report.gc, Some(GcReport::default()));
+        assert_eq!(report.metadata, None);
+
+        // Skipped phases report nothing
+        let gc_only = OptimizeOptions { compact_metadata: false, compact_packs: false, ..options };
+        let report = coordinator.optimize(gc_only, &CancellationToken::new()).unwrap();
+        assert_eq!(report.gc, Some(GcReport { expired_blobs: 1, orphan_chunks: 1, dangling_members: 1 }));
+        assert_eq!((report.metadata, report.packs, report.cancelled), (None, None, false));
+
+        let report = coordinator.optimize(options, &CancellationToken::new()).unwrap();
+        assert_eq!(report.gc, Some(GcReport::default()));
+        assert!(report.metadata.is_some());
+        assert_eq!(report.packs, Some(7 + 5 + 8));
+        assert!(coordinator.check_consistency().unwrap().is_consistent());
+        coordinator.get_blob(&tenant_id, &kept).unwrap();
+
+        // Orphans younger than the grace period may be uploads in flight
+        let fresh = BlobId::new();
+        coordinator.chunk_store.put_blob(&fresh, Cursor::new(b"fresh")).unwrap();
+        let report = coordinator.optimize(OptimizeOptions::default(), &CancellationToken::new()).unwrap();
+        assert_eq!(report.gc.unwrap().orphan_chunks, 0);
+    }
+
+    #[test]
+    fn test_custom_store_layout() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store_layout = StoreLayout {
+            chunks_dir: "mt-chunks".into(),
+            metadata_dir: "mt-metadata".into(),
+            tenants_dir: "mt-tenants".into(),
+            packs_dir: "mt-packs".into(),
+        };
+        let coordinator = Coordinator::with_layout(temp_dir.path(), store_layout.clone()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let coordinator = coordinator.with_packfiles(DEFAULT_MAX_PACK_SIZE).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"packed")).unwrap();
+
+        let root = temp_dir.path();
+        assert!(root.join("mt-chunks").join(format!("{}.blob", blob_id)).exists());
+        assert!(root.join("mt-metadata").is_dir());
+        assert!(root.join("mt-tenants").is_dir());
+        assert!(root.join("mt-packs").join("00000000.pack").exists());
+        for default_dir in ["chunks", "metadata", "tenants", "packs"] {
+            assert!(!root.join(default_dir).exists(), "{} was created", default_dir);
+        }
+        drop(coordinator);
+
+        let coordinator = reopen(|| Coordinator::with_layout(root, store_layout.clone()));
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+    }
+
+    #[test]
+    fn test_in_memory() {
+        let coordinator = Coordinator::in_memory().unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        assert!(coordinator.list_blobs(&tenant_id).unwrap().is_empty());
+
+        // Nothing is left on disk afterwards
+        let scratch = coordinator.root_dir.clone();
+        assert!(scratch.is_dir());
+        drop(coordinator);
+        assert!(!scratch.exists());
+
+        // Each one starts out empty
+        assert!(Coordinator::in_memory().unwrap().list_tenants().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_separate_database_paths() {
+        let chunk_dir = tempfile::tempdir().unwrap();
+        let db_dir = tempfile::tempdir().unwrap();
+        let metadata_path = db_dir.path().join("meta");
+        let tenant_path = db_dir.path().join("tenants-db");
+        let coordinator = Coordinator::with_paths(chunk_dir.path(), &metadata_path, &tenant_path).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        assert!(chunk_dir.path().join("chunks").join(format!("{}.blob", blob_id)).exists());
+        assert!(metadata_path.is_dir());
+        assert!(tenant_path.is_dir());
+        assert!(!chunk_dir.path().join("metadata").exists());
+        assert!(!chunk_dir.path().join("tenants").exists());
+        drop(coordinator);
+
+        let coordinator = reopen(|| Coordinator::with_paths(chunk_dir.path(), &metadata_path, &tenant_path));
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        assert!(coordinator.list_blobs(&tenant_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_change_feed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let other = TenantId::new("messages");
+        coordinator.register_tenant(other.clone()).unwrap();
+
+        let feed = coordinator.subscribe(&tenant_id);
+        let other_feed = coordinator.subscribe(&other);
+
+        let first = coordinator.put_blob(&tenant_id, Cursor::new(b"first")).unwrap();
+        let second = coordinator.put_blob(&tenant_id, Cursor::new(b"second")).unwrap();
+        coordinator.delete_blob(&tenant_id, &first).unwrap();
+        // A rejected put commits nothing, so it isn't reported
+        assert!(coordinator.put_blob_verified(&tenant_id, Cursor::new(b"x"), "bad").is_err());
+        coordinator.move_blob(&tenant_id, &other, &second).unwrap();
+
+        let events: Vec<_> = feed.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::Put { blob_id: first.clone() },
+                ChangeEvent::Put { blob_id: second.clone() },
+                ChangeEvent::Delete { blob_id: first },
+                ChangeEvent::Delete { blob_id: second.clone() },
+            ]
+        );
+        assert_eq!(other_feed.try_iter().collect::<Vec<_>>(), vec![ChangeEvent::Put { blob_id: second }]);
+
+        // Dropped subscribers are pruned on the next event
+        drop(feed);
+        coordinator.put_blob(&tenant_id, Cursor::new(b"third")).unwrap();
+        assert_eq!(coordinator.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_get_blob_with_info() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        let (mut reader, info) = coordinator.get_blob_with_info(&tenant_id, &blob_id).unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        assert_eq!(info.size, 13);
+        assert_eq!(info.checksum, checksum::checksum_of(&data[..]).unwrap());
+
+        let other = TenantId::new("messages");
+        coordinator.register_tenant(other.clone()).unwrap();
+        assert!(coordinator.get_blob_with_info(&other, &blob_id).is_err());
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let plain = coordinator.put_blob(&tenant_id, Cursor::new(b"stored as is")).unwrap();
+
+        let coordinator = coordinator.with_compression(Compression::Zstd(3));
+        let compressible = "all work and no play ".repeat(5000);
+        let compressible_id = coordinator.put_blob(&tenant_id, Cursor::new(compressible.clone())).unwrap();
+
+        // Pseudo-random bytes from an xorshift generator don't compress
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let incompressible: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let incompressible_id = coordinator.put_blob(&tenant_id, Cursor::new(incompressible.clone())).unwrap();
+
+        let blobs = coordinator.list_blobs(&tenant_id).unwrap();
+        let ratio = |blob_id: &BlobId| blobs.iter().find(|m| m.blob_id == *blob_id).unwrap().compression_ratio();
+        assert_eq!(blobs[0].compressed_size, None);
+        assert_eq!(ratio(&plain), 1.0);
+        assert!(ratio(&compressible_id) > 50.0, "{}", ratio(&compressible_id));
+        assert!(ratio(&incompressible_id) < 1.01, "{}", ratio(&incompressible_id));
+
+        // The tenant's ratio weighs each blob by its size
+        let stored = 12 + blobs.iter().filter_map(|m| m.compressed_size).sum::<u64>();
+        let expected = (12 + compressible.len() + incompressible.len()) as f64 / stored as f64;
+        assert!((coordinator.compression_ratio(&tenant_id).unwrap() - expected).abs() < 1e-9);
+        let empty = TenantId::new("empty");
+        coordinator.register_tenant(empty.clone()).unwrap();
+        assert_eq!(coordinator.compression_ratio(&empty).unwrap(), 1.0);
+
+        // Compressed blobs read back as their original content
+        for (blob_id, expected) in [(compressible_id, compressible.into_bytes()), (incompressible_id, incompressible)] {
+            let mut data = Vec::new();
+            coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+            assert!(data == expected);
+        }
+        let mut data = Vec::new();
+        coordinator.get_blob(&tenant_id, &plain).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"stored as is");
+    }
+
+    #[test]
+    fn test_mixed_compression() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        // Each blob keeps the algorithm it was written with, so changing the
+        // default doesn't affect reading what's already stored
+        let mut stored = Vec::new();
+        for compression in [Compression::Zstd(3), Compression::Lz4, Compression::Gzip, Compression::None] {
+            coordinator = coordinator.with_compression(compression);
+            let data = format!("written with {} ", compression.name()).repeat(1000);
+            let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(data.clone())).unwrap();
+            stored.push((blob_id, compression, data));
+        }
+
+        for (blob_id, compression, expected) in stored {
+            let metadata = coordinator.metadata_store.get_metadata(&blob_id).unwrap();
+            assert_eq!(metadata.stored_compression(), compression);
+            let mut data = String::new();
+            coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_string(&mut data).unwrap();
+            assert_eq!(data, expected);
+        }
+    }
+
+    #[test]
+    fn test_get_blob_encoded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_compression(Compression::Gzip);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let data = "all work and no play ".repeat(1000);
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(data.clone())).unwrap();
+
+        // A client accepting gzip gets the stored bytes, which decode to the blob
+        let (mut reader, encoding) = coordinator.get_blob_encoded(&tenant_id, &blob_id, "br, gzip").unwrap();
+        assert_eq!(encoding, Some("gzip"));
+        let mut compressed = Vec::new();
+        reader.read_to_end(&mut compressed).unwrap();
+        assert!(compressed.len() < data.len() / 10);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+
+        // Any other client gets the blob decompressed
+        for accept_encoding in ["", "zstd, br", "gzip;q=0"] {
+            let (mut reader, encoding) = coordinator.get_blob_encoded(&tenant_id, &blob_id, accept_encoding).unwrap();
+            assert_eq!(encoding, None);
+            let mut read = String::new();
+            reader.read_to_string(&mut read).unwrap();
+            assert_eq!(read, data);
+        }
+
+        // Corrupt stored bytes are caught before they are sent
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&blob_id).unwrap() else {
+            panic!("expected a chunk file");
+        };
+        let mut stored = std::fs::read(&blob_path).unwrap();
+        let middle = stored.len() / 2;
+        stored[middle] ^= 0xff;
+        std::fs::write(&blob_path, stored).unwrap();
+        assert!(coordinator.get_blob_encoded(&tenant_id, &blob_id, "gzip").is_err());
+    }
+
+    #[test]
+    fn test_iter_blobs_is_lazy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        for i in 0..5 {
+            coordinator.put_blob(&tenant_id, Cursor::new(vec![i; 4])).unwrap();
+        }
+
+        let reads_before = coordinator.metadata_store.metadata_reads();
+        let first_two: Vec<_> = coordinator
+            .iter_blobs(&tenant_id)
+            .unwrap()
+            .take(2)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(first_two.len(), 2);
+        assert_eq!(coordinator.metadata_store.metadata_reads() - reads_before, 2);
+    }
+
+    #[test]
+    fn test_backup_restore() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let source = Coordinator::new(temp_dir.path().join("source")).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let config = TenantConfig { display_name: Some("Posts".into()), ..Default::default() };
+        source.register_tenant_with_config(tenant_id.clone(), config).unwrap();
+        source.register_tenant(TenantId::new("empty")).unwrap();
+        let tagged = source
+            .put_blob_with_tags(&tenant_id, Cursor::new(b"report"), [("filename".to_string(), "report.txt".to_string())].into())
+            .unwrap();
+        let plain = source.put_blob(&tenant_id, Cursor::new(b"plain")).unwrap();
+
+        let mut backup = Vec::new();
+        source.backup(&mut backup).unwrap();
+
+        // A truncated backup is rejected before anything is written
+        let restored = Coordinator::new(temp_dir.path().join("restored")).unwrap();
+        assert!(restored.restore(&backup[..backup.len() - 1]).is_err());
+        assert!(restored.list_tenants().unwrap().is_empty());
+        restored.restore(&backup[..]).unwrap();
+
+        let mut tenants = restored.list_tenants().unwrap();
+        tenants.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(tenants, vec![TenantId::new("empty"), tenant_id.clone()]);
+        assert_eq!(restored.get_tenant_config(&tenant_id).unwrap().display_name.as_deref(), Some("Posts"));
+        let as_json = |coordinator: &Coordinator| serde_json::to_value(coordinator.list_blobs(&tenant_id).unwrap()).unwrap();
+        assert_eq!(as_json(&restored), as_json(&source));
+        assert_eq!(restored.find_by_tag(&tenant_id, "filename", "report.txt").unwrap(), vec![tagged]);
+        assert_eq!(restored.tenant_usage(&tenant_id).unwrap(), 11);
+
+        // New blobs keep sorting after the restored ones
+        let newer = restored.put_blob(&tenant_id, Cursor::new(b"newer")).unwrap();
+        let ids: Vec<BlobId> = restored.list_blobs(&tenant_id).unwrap().into_iter().map(|m| m.blob_id).collect();
+        assert_eq!(ids.last(), Some(&newer));
+        assert!(ids.contains(&plain));
+
+        // Only an empty store can be restored into
+        let err = restored.restore(&backup[..]).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::System(_)));
+    }
+
+    #[test]
+    fn test_scan_blobs_readahead() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap().with_access_tracking(true);
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let contents: Vec<Vec<u8>> = (0..5).map(|i| format!("blob number {}", i).into_bytes()).collect();
+        for data in &contents {
+            coordinator.put_blob(&tenant_id, Cursor::new(data.clone())).unwrap();
+        }
+        let blob_ids: Vec<BlobId> =
+            coordinator.iter_blobs(&tenant_id).unwrap().map(|metadata| metadata.unwrap().blob_id).collect();
+
+        // Stopping early leaves blobs past the read-ahead window untouched
+        let mut scan = coordinator.scan_blobs(&tenant_id, 2).unwrap();
+        let (metadata, _) = scan.next().unwrap().unwrap();
+        assert_eq!(metadata.blob_id, blob_ids[0]);
+        assert_eq!(scan.pending.len(), 2);
+        drop(scan);
+        coordinator.flush_access_times().unwrap();
+        let accessed: Vec<bool> = blob_ids
+            .iter()
+            .map(|blob_id| coordinator.get_metadata(blob_id).unwrap().last_accessed.is_some())
+            .collect();
+        assert_eq!(accessed, [true, false, false, false, false]);
+
+        // Every blob comes back in order with its content, with or without
+        // read-ahead
+        for readahead in [0, 2, 10] {
+            let scanned: Vec<(BlobId, Vec<u8>)> = coordinator
+                .scan_blobs(&tenant_id, readahead)
+                .unwrap()
+                .map(|item| {
+                    let (metadata, mut reader) = item.unwrap();
+                    let mut data = Vec::new();
+                    reader.read_to_end(&mut data).unwrap();
+                    assert_eq!(data.len() as u64, metadata.size);
+                    (metadata.blob_id, data)
+                })
+                .collect();
+            assert_eq!(scanned.iter().map(|(blob_id, _)| blob_id.clone()).collect::<Vec<_>>(), blob_ids);
+            let mut data: Vec<Vec<u8>> = scanned.into_iter().map(|(_, data)| data).collect();
+            data.sort();
+            assert_eq!(data, contents);
+        }
+
+        // Only the first chunk is read ahead; the rest follows on demand
+        let large: Vec<u8> = (0..SCAN_CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        coordinator.put_blob(&tenant_id, Cursor::new(large.clone())).unwrap();
+        let (_, mut reader) = coordinator
+            .scan_blobs(&tenant_id, 10)
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|(metadata, _)| metadata.size == large.len() as u64)
+            .unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, large);
+    }
+
+    #[test]
+    fn test_temp_cleanup() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        drop(Coordinator::new(temp_dir.path()).unwrap());
+        let chunks_dir = temp_dir.path().join("chunks");
+        let stale = chunks_dir.join(".tmpStale1");
+        let fresh = chunks_dir.join(".tmpFresh1");
+        let two_hours_ago = std::time::SystemTime::now() - Duration::from_secs(7200);
+        File::create(&stale).unwrap().set_modified(two_hours_ago).unwrap();
+        File::create(&fresh).unwrap();
+        // Old files that aren't temp files are left alone
+        let other = chunks_dir.join("notes.txt");
+        File::create(&other).unwrap().set_modified(two_hours_ago).unwrap();
+
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()))
+            .with_temp_cleanup(Duration::from_secs(3600))
+            .unwrap();
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+        assert!(other.exists());
+
+        // The store still works after the sweep
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+    }
+
+    #[test]
+    fn test_put_with_size_hint() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        let data = vec![7u8; 100_000];
+        let blob_id = coordinator.put_blob_with_size_hint(&tenant_id, Cursor::new(data.clone()), 100_000).unwrap();
+        let mut stored = Vec::new();
+        coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut stored).unwrap();
+        assert_eq!(stored, data);
+        let blob_path = temp_dir.path().join("chunks").join(format!("{}.blob", blob_id));
+        assert_eq!(std::fs::metadata(&blob_path).unwrap().len(), 100_000);
+
+        // Data shorter or longer than the hint is rejected and leaves nothing
+        // behind; longer data is read only one byte past the hint
+        for (hint, read) in [(200_000, 100_000), (10, 11)] {
+            let err = coordinator.put_blob_with_size_hint(&tenant_id, Cursor::new(data.clone()), hint).unwrap_err();
+            assert!(
+                matches!(err.root_cause(), Error::SizeMismatch { expected, actual } if *expected == hint && *actual == read),
+                "{}",
+                err
+            );
+        }
+
+        // An endless stream is cut off at the hint
+        let err = coordinator.put_blob_with_size_hint(&tenant_id, std::io::repeat(7), 1000).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::SizeMismatch { expected: 1000, actual: 1001 }), "{}", err);
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+        let files = std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count();
+        assert_eq!(files, 2);
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        drop(coordinator);
+
+        let read_only = reopen(|| Coordinator::open_read_only(temp_dir.path()));
+        let mut data = Vec::new();
+        read_only.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, World!");
+        assert_eq!(read_only.list_blobs(&tenant_id).unwrap().len(), 1);
+
+        // Writes to blob files are refused and leave the store untouched
+        let files = || std::fs::read_dir(temp_dir.path().join("chunks")).unwrap().count();
+        let before = files();
+        let err = read_only.put_blob(&tenant_id, Cursor::new(b"new")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ReadOnly(_)), "{}", err);
+        let err = read_only.delete_blob(&tenant_id, &blob_id).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ReadOnly(_)), "{}", err);
+        assert_eq!(files(), before);
+
+        // So are metadata and tenant writes, and a failed delete hides nothing
+        assert!(read_only.blob_exists(&tenant_id, &blob_id).unwrap());
+        assert_eq!(read_only.list_blobs(&tenant_id).unwrap().len(), 1);
+        let err = read_only.register_tenant(TenantId::new("other")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ReadOnly(_)), "{}", err);
+        let err = read_only.update_metadata_if(&tenant_id, &blob_id, 0, |m| m.content_type = None).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::ReadOnly(_)), "{}", err);
+        drop(read_only);
+
+        // The dbs are opened in place, so a live writer keeps it out
+        let coordinator = reopen(|| Coordinator::new(temp_dir.path()));
+        assert!(Coordinator::open_read_only(temp_dir.path()).is_err());
+        assert_eq!(coordinator.list_blobs(&tenant_id).unwrap().len(), 1);
+
+        let err = Coordinator::open_read_only(temp_dir.path().join("missing")).err().unwrap();
+        assert!(matches!(err.root_cause(), Error::System(_)));
+        assert!(!temp_dir.path().join("missing").exists());
+    }
+
+    #[test]
+    fn test_tombstones() {
+        let clock = Arc::new(crate::clock::TestClock::default());
+        let coordinator = Coordinator::in_memory()
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_tombstones(chrono::Duration::hours(1));
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob_with_id(&tenant_id, BlobId::new(), Cursor::new(b"first")).unwrap();
+        let kept = coordinator.put_blob(&tenant_id, Cursor::new(b"kept")).unwrap();
+
+        // Deleting leaves a tombstone alongside removing the blob
+        assert!(coordinator.tombstone(&blob_id).unwrap().is_none());
+        coordinator.delete_blob(&tenant_id, &blob_id).unwrap();
+        let tombstone = coordinator.tombstone(&blob_id).unwrap().unwrap();
+        assert_eq!(tombstone.tenant_id, tenant_id);
+        assert_eq!(tombstone.deleted_at, clock.now());
+        assert!(!coordinator.blob_exists(&tenant_id, &blob_id).unwrap());
+
+        // Putting the id again is caught rather than resurrecting the blob
+        let err = coordinator.put_blob_with_id(&tenant_id, blob_id.clone(), Cursor::new(b"again")).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::BlobTombstoned { .. }), "{}", err);
+        assert_eq!(coordinator.list_blob_ids(&tenant_id).unwrap(), vec![kept.clone()]);
+        let mut replicated = coordinator.get_metadata(&kept).unwrap();
+        replicated.blob_id = blob_id.clone();
+        assert!(matches!(coordinator.put_metadata_only(replicated), Err(Error::BlobTombstoned { .. })));
+        assert_eq!(coordinator.list_blob_ids(&tenant_id).unwrap(), vec![kept.clone()]);
+
+        // Deleting an id that was never stored leaves no tombstone
+        let missing = BlobId::new();
+        let _ = coordinator.delete_blob(&tenant_id, &missing);
+        assert!(coordinator.metadata_store.get_tombstone(&missing).unwrap().is_none());
+
+        // Only tombstones older than the cutoff are pruned
+        assert_eq!(coordinator.prune_tombstones(chrono::Duration::hours(2)).unwrap(), 0);
+        clock.advance(chrono::Duration::hours(3));
+        coordinator.delete_blob(&tenant_id, &kept).unwrap();
+        assert!(coordinator.tombstone(&blob_id).unwrap().is_none());
+        assert_eq!(coordinator.prune_tombstones(chrono::Duration::hours(2)).unwrap(), 1);
+        assert!(coordinator.metadata_store.get_tombstone(&blob_id).unwrap().is_none());
+        assert!(coordinator.tombstone(&kept).unwrap().is_some());
+        coordinator.put_blob_with_id(&tenant_id, blob_id.clone(), Cursor::new(b"again")).unwrap();
+        assert_eq!(coordinator.list_blob_ids(&tenant_id).unwrap(), vec![blob_id.clone()]);
+
+        // Blobs removed by purging leave tombstones too
+        let expiring = coordinator.put(&tenant_id).ttl(chrono::Duration::minutes(1)).store(Cursor::new(b"brief")).unwrap();
+        clock.advance(chrono::Duration::minutes(2));
+        assert_eq!(coordinator.purge_expired(&tenant_id).unwrap(), 1);
+        assert!(coordinator.tombstone(&expiring).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_list_blobs_by_size() {
+        for indexed in [false, true] {
+            let mut coordinator = Coordinator::in_memory().unwrap();
+            let tenant_id = TenantId::new("posts");
+            coordinator.register_tenant(tenant_id.clone()).unwrap();
+            let mut ids = BTreeMap::new();
+            for size in [0usize, 10, 100, 1000] {
+                ids.insert(size as u64, coordinator.put_blob(&tenant_id, Cursor::new(vec![1u8; size])).unwrap());
+            }
+            if indexed {
+                coordinator = coordinator.with_size_index().unwrap();
+            }
+            let other = TenantId::new("images");
+            coordinator.register_tenant(other.clone()).unwrap();
+            coordinator.put_blob(&other, Cursor::new(vec![1u8; 50])).unwrap();
+
+            let sizes = |min, max| -> Vec<u64> {
+                let blobs = coordinator.list_blobs_by_size(&tenant_id, min, max).unwrap();
+                blobs.iter().map(|metadata| metadata.size).collect()
+            };
+            assert_eq!(sizes(Some(100), None), [100, 1000]);
+            assert_eq!(sizes(None, Some(10)), [0, 10]);
+            assert_eq!(sizes(Some(10), Some(100)), [10, 100]);
+            assert_eq!(sizes(Some(11), Some(99)), Vec::<u64>::new());
+            assert_eq!(sizes(Some(1000), Some(1000)), [1000]);
+            assert_eq!(sizes(None, None), [0, 10, 100, 1000]);
+
+            // Deleted blobs drop out of the index
+            coordinator.delete_blob(&tenant_id, &ids[&100]).unwrap();
+            assert_eq!(sizes(Some(100), None), [1000]);
+            let moved = &ids[&10];
+            coordinator.move_blob(&tenant_id, &other, moved).unwrap();
+            assert_eq!(sizes(None, Some(100)), [0]);
+            let blobs = coordinator.list_blobs_by_size(&other, Some(10), None).unwrap();
+            assert_eq!(blobs.iter().map(|metadata| metadata.size).collect::<Vec<_>>(), [10, 50]);
+        }
+    }
+
+    #[test]
+    fn test_put_non_seekable_source() {
+        // Hands out a few bytes per read, like a network stream, and can't seek
+        struct Trickle<R>(R);
+        impl<R: Read> Read for Trickle<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = buf.len().min(3);
+                self.0.read(&mut buf[..len])
+            }
+        }
+
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 7) as u8).collect();
+        let expected = checksum::checksum_of(&data[..]).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let coordinators = [
+            Coordinator::in_memory().unwrap(),
+            Coordinator::in_memory().unwrap().with_compression(Compression::Zstd(3)),
+            Coordinator::in_memory().unwrap().with_packfiles(1 << 20).unwrap(),
+        ];
+        for coordinator in coordinators {
+            coordinator.register_tenant(tenant_id.clone()).unwrap();
+            let (blob_id, info) = coordinator.put_blob_info(&tenant_id, Trickle(&data[..])).unwrap();
+            assert_eq!((info.size, &info.checksum), (data.len() as u64, &expected));
+            assert_eq!(coordinator.get_metadata(&blob_id).unwrap().checksum, expected);
+            let mut retrieved = Vec::new();
+            coordinator.get_blob(&tenant_id, &blob_id).unwrap().read_to_end(&mut retrieved).unwrap();
+            assert_eq!(retrieved, data);
+        }
+    }
+}
\ No newline at end of file
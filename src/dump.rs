@@ -0,0 +1,127 @@
+use std::io::{self, Read, Write};
+
+use crate::{Result, error::Error};
+
+/// Leading bytes of a dump, so restoring from the wrong file fails early
+const MAGIC: &[u8] = b"MTDUMP1\n";
+
+/// Record tags in a dump stream
+const COLLECTION: u8 = 1;
+const ENTRY: u8 = 2;
+const END: u8 = 0;
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn malformed(what: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("malformed db dump: {}", what)).into()
+}
+
+/// Writes every tree of a sled db, exactly as stored, as one
+/// self-delimiting byte stream that `import_db` restores
+pub fn export_db(db: &sled::Db, mut writer: impl Write) -> Result<()> {
+    writer.write_all(MAGIC)?;
+    for (collection_type, name, entries) in db.export() {
+        writer.write_all(&[COLLECTION])?;
+        write_bytes(&mut writer, &collection_type)?;
+        write_bytes(&mut writer, &name)?;
+        for fields in entries {
+            writer.write_all(&[ENTRY])?;
+            writer.write_all(&(fields.len() as u64).to_be_bytes())?;
+            for field in &fields {
+                write_bytes(&mut writer, field)?;
+            }
+        }
+    }
+    writer.write_all(&[END])?;
+    Ok(())
+}
+
+/// One tree of a dump: its collection type, name and entries' fields
+type Collection = (Vec<u8>, Vec<u8>, Vec<Vec<Vec<u8>>>);
+
+/// Every tree of a dump, fully read and checked but not yet loaded
+pub struct Dump {
+    collections: Vec<Collection>,
+}
+
+impl Dump {
+    /// Loads the dump into `db`, which must be empty
+    pub fn load(self, db: &sled::Db) -> Result<()> {
+        check_empty(db)?;
+        let collections = self.collections.into_iter().map(|(collection_type, name, entries)| {
+            (collection_type, name, entries.into_iter())
+        });
+        db.import(collections.collect());
+        Ok(())
+    }
+}
+
+/// Fails unless every tree of `db` is empty, so a dump can be loaded into it
+pub fn check_empty(db: &sled::Db) -> Result<()> {
+    if db.tree_names().iter().any(|name| db.open_tree(name).is_ok_and(|tree| !tree.is_empty())) {
+        return Err(Error::System("can only restore a dump into an empty db".into()));
+    }
+    Ok(())
+}
+
+/// Loads a stream written by `export_db` into `db`, which must be empty.
+/// Reads exactly one dump, so several can be concatenated.
+pub fn import_db(db: &sled::Db, reader: impl Read) -> Result<()> {
+    check_empty(db)?;
+    read_dump(reader)?.load(db)
+}
+
+/// Reads one dump written by `export_db` in full, failing on any malformed
+/// record before anything is loaded
+pub fn read_dump(mut reader: impl Read) -> Result<Dump> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(malformed("missing header"));
+    }
+
+    let mut collections = Vec::new();
+    let mut tag = read_u8(&mut reader)?;
+    while tag == COLLECTION {
+        let collection_type = read_bytes(&mut reader)?;
+        let name = read_bytes(&mut reader)?;
+        let mut entries = Vec::new();
+        tag = read_u8(&mut reader)?;
+        while tag == ENTRY {
+            let count = read_u64(&mut reader)?;
+            let fields = (0..count).map(|_| read_bytes(&mut reader)).collect::<io::Result<Vec<_>>>()?;
+            entries.push(fields);
+            tag = read_u8(&mut reader)?;
+        }
+        collections.push((collection_type, name, entries));
+    }
+    if tag != END {
+        return Err(malformed("unknown record"));
+    }
+    Ok(Dump { collections })
+}
@@ -1,5 +1,6 @@
 use thiserror::Error;
 use tempfile::PersistError;
+use sled::transaction::TransactionError;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -17,22 +18,577 @@ pub enum Error {
 
     #[error("Checksum mismatch: expected {expected}, got {actual}")]
     ChecksumMismatch {
-        expected: String,
-        actual: String,
+        expected: crate::checksum::Checksum,
+        actual: crate::checksum::Checksum,
+    },
+
+    #[error("Checksum sources disagree for blob {blob_id}: sidecar has {sidecar}, metadata has {metadata}")]
+    ChecksumSourceDisagreement {
+        blob_id: String,
+        sidecar: crate::checksum::Checksum,
+        metadata: crate::checksum::Checksum,
+    },
+
+    #[error("Blob {0} has no recorded checksum to verify against")]
+    MissingChecksum(String),
+
+    #[error("Checksum mismatch in chunk {chunk} of blob {blob_id}")]
+    ChunkChecksumMismatch {
+        blob_id: String,
+        chunk: usize,
+    },
+
+    #[error("Write verification failed: wrote {expected}, read back {actual}")]
+    WriteVerifyFailed {
+        expected: crate::checksum::Checksum,
+        actual: crate::checksum::Checksum,
+    },
+
+    #[error("Size mismatch: expected {expected} bytes, found {actual}")]
+    SizeMismatch {
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Invalid checksum '{input}': {reason}")]
+    InvalidChecksum {
+        input: String,
+        reason: String,
+    },
+
+    #[error("Unsupported checksum algorithm: {0}")]
+    UnsupportedChecksumAlgorithm(String),
+
+    #[error("Checksum algorithm mismatch: store uses {found}, configured for {configured}")]
+    ChecksumAlgorithmMismatch {
+        found: String,
+        configured: String,
     },
 
     #[error("Blob not found: {0}")]
     BlobNotFound(String),
 
+    #[error("Data missing for blob {blob_id}: its metadata exists but its chunk is gone")]
+    ChunkMissing {
+        blob_id: String,
+    },
+
+    #[error("Startup check found {damaged} damaged blobs")]
+    StartupCheckFailed {
+        damaged: usize,
+    },
+
+    #[error("Data for blob {0} is not stored on this node; only its metadata was replicated")]
+    DataNotLocal(String),
+
+    #[error("Invalid blob ID '{input}': {reason}")]
+    InvalidBlobId {
+        input: String,
+        reason: String,
+    },
+
+    #[error("Blob id prefix {prefix} is ambiguous: it matches {matches} blobs")]
+    AmbiguousBlobId {
+        prefix: String,
+        matches: usize,
+    },
+
+    #[error("Blob already exists: {0}")]
+    BlobAlreadyExists(String),
+
+    #[error("Blob {blob_id} was deleted at {deleted_at} and its id can't be reused yet")]
+    BlobTombstoned {
+        blob_id: String,
+        deleted_at: chrono::DateTime<chrono::Utc>,
+    },
+
+    #[error("Blob {blob_id} is under retention until {retain_until}")]
+    RetentionActive {
+        blob_id: String,
+        retain_until: chrono::DateTime<chrono::Utc>,
+    },
+
     #[error("Invalid tenant: {0}")]
     InvalidTenant(String),
 
+    #[error("Invalid tenant id: {0}")]
+    InvalidTenantId(String),
+
+    #[error("Blob {blob_id} was modified concurrently: expected generation {expected}, found {actual}")]
+    ConcurrentModification {
+        blob_id: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Name {0} was rebound by another writer while it was being replaced")]
+    NameRebound(String),
+
+    #[error("Tenant already exists: {0}")]
+    TenantAlreadyExists(String),
+
+    #[error("Storage full: {available} bytes available, {required} required")]
+    StorageFull {
+        available: u64,
+        required: u64,
+    },
+
+    #[error("Tenant limit reached: at most {limit} tenants allowed")]
+    TenantLimitReached {
+        limit: usize,
+    },
+
+    #[error("Blob limit reached for tenant {tenant}: at most {limit} blobs allowed")]
+    BlobLimitReached {
+        tenant: String,
+        limit: usize,
+    },
+
+    #[error("Overloaded: at most {limit} concurrent operations allowed")]
+    Overloaded {
+        limit: usize,
+    },
+
+    #[error("Upload budget exceeded: {requested} more bytes in flight would pass the limit of {budget}")]
+    UploadBudgetExceeded {
+        requested: u64,
+        budget: u64,
+    },
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
+    #[error("HTTP error: {url} returned status {status}")]
+    HttpStatus {
+        url: String,
+        status: u16,
+    },
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("Incompatible storage layout: found version {found}, expected {expected}")]
+    IncompatibleLayout {
+        found: u32,
+        expected: u32,
+    },
+
+    #[error("Storage at {} is on a read-only filesystem; open it with Coordinator::open_read_only to read its data", .0.display())]
+    ReadOnlyFilesystem(std::path::PathBuf),
+
+    #[error("Storage at {} was opened read-only", .0.display())]
+    ReadOnly(std::path::PathBuf),
+
     #[error("System error: {0}")]
     System(String),
+
+    #[error("{msg}: {source}")]
+    Context {
+        msg: String,
+        source: Box<Error>,
+    },
+}
+
+impl From<TransactionError<Error>> for Error {
+    fn from(err: TransactionError<Error>) -> Self {
+        match err {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => Error::Storage(e),
+        }
+    }
+}
+
+impl Error {
+    /// Wraps the error with a description of the operation that triggered it
+    pub fn with_context(self, msg: impl Into<String>) -> Self {
+        Error::Context {
+            msg: msg.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns the underlying error with any context layers peeled off
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root_cause(),
+            other => other,
+        }
+    }
+
+    /// Returns true if the failure may be transient, so repeating the same
+    /// operation could succeed: interrupted or timed out IO and network
+    /// errors, HTTP server errors and 429s, and operations rejected under
+    /// load. Missing blobs, bad input and corruption are never retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self.root_cause() {
+            Error::Io(e) => is_transient_io(e),
+            Error::Storage(sled::Error::Io(e)) => is_transient_io(e),
+            Error::Http(_) => true,
+            Error::HttpStatus { status, .. } => *status >= 500 || *status == 429,
+            Error::Overloaded { .. } | Error::UploadBudgetExceeded { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the requested blob doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.root_cause(), Error::BlobNotFound(_))
+    }
+
+    /// Returns true if the operation was refused for lack of permission
+    pub fn is_permission(&self) -> bool {
+        match self.root_cause() {
+            Error::PermissionDenied(_) => true,
+            Error::Io(e) => e.kind() == std::io::ErrorKind::PermissionDenied,
+            _ => false,
+        }
+    }
+
+    /// Process exit code for a CLI command failing with this error, so
+    /// scripts can branch on the kind of failure: 3 blob not found, 4
+    /// invalid tenant, 5 checksum mismatch, 6 permission denied and 1 for
+    /// anything else. Errors surfacing through a reader are classified by
+    /// the error they wrap.
+    pub fn exit_code(&self) -> u8 {
+        if let Error::Io(e) = self.root_cause() {
+            if let Some(inner) = e.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+                return inner.exit_code();
+            }
+        }
+        match self.root_cause() {
+            Error::BlobNotFound(_) => 3,
+            Error::InvalidTenant(_) | Error::InvalidTenantId(_) => 4,
+            Error::ChecksumMismatch { .. } | Error::ChunkChecksumMismatch { .. } => 5,
+            _ if self.is_permission() => 6,
+            _ => 1,
+        }
+    }
+}
+
+/// Maps a failure creating or opening storage at `path` to
+/// `ReadOnlyFilesystem` when the filesystem refused the write, so the caller
+/// gets advice rather than a raw IO error
+pub(crate) fn read_only_at<E: Into<Error>>(path: &std::path::Path) -> impl FnOnce(E) -> Error + '_ {
+    move |err| match err.into() {
+        Error::Io(e) | Error::Storage(sled::Error::Io(e)) if e.kind() == std::io::ErrorKind::ReadOnlyFilesystem => {
+            Error::ReadOnlyFilesystem(path.to_path_buf())
+        }
+        err => err,
+    }
+}
+
+fn is_transient_io(err: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        err.kind(),
+        Interrupted | WouldBlock | TimedOut | ConnectionReset | ConnectionAborted | ConnectionRefused | BrokenPipe
+    )
+}
+
+/// Extension trait for attaching context to a failed result
+pub trait ResultExt<T> {
+    fn with_context<F: FnOnce() -> String>(self, f: F) -> crate::Result<T>;
+}
+
+impl<T> ResultExt<T> for crate::Result<T> {
+    fn with_context<F: FnOnce() -> String>(self, f: F) -> crate::Result<T> {
+        self.map_err(|e| e.with_context(f()))
+    }
 }
 
 impl From<PersistError> for Error {
     fn from(err: PersistError) -> Self {
         Error::Persist(err.to_string())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, ErrorKind};
+
+    #[test]
+    fn test_error_classification() {
+        let checksum = |hex| crate::checksum::Checksum::parse(hex).unwrap();
+        let cases = [
+            // (error, retryable, not found, permission)
+            (
+                Error::Io(io::Error::from(ErrorKind::TimedOut)),
+                true,
+                false,
+                false,
+            ),
+            (
+                Error::Io(io::Error::from(ErrorKind::Interrupted)),
+                true,
+                false,
+                false,
+            ),
+            (
+                Error::Io(io::Error::from(ErrorKind::NotFound)),
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::Io(io::Error::from(ErrorKind::PermissionDenied)),
+                false,
+                false,
+                true,
+            ),
+            (
+                Error::Storage(sled::Error::Io(io::Error::from(ErrorKind::WouldBlock))),
+                true,
+                false,
+                false,
+            ),
+            (
+                Error::Storage(sled::Error::Unsupported("x".into())),
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::Serialization(serde_json::from_str::<u8>("x").unwrap_err()),
+                false,
+                false,
+                false,
+            ),
+            (Error::Persist("x".into()), false, false, false),
+            (
+                Error::ChecksumMismatch {
+                    expected: checksum("aa"),
+                    actual: checksum("bb"),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::ChecksumSourceDisagreement {
+                    blob_id: "x".into(),
+                    sidecar: checksum("aa"),
+                    metadata: checksum("bb"),
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::MissingChecksum("x".into()), false, false, false),
+            (
+                Error::ChunkChecksumMismatch {
+                    blob_id: "x".into(),
+                    chunk: 1,
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::SizeMismatch {
+                    expected: 1,
+                    actual: 2,
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::WriteVerifyFailed {
+                    expected: checksum("aa"),
+                    actual: checksum("bb"),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::InvalidChecksum {
+                    input: "x".into(),
+                    reason: "y".into(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::UnsupportedChecksumAlgorithm("md4".into()),
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::ChecksumAlgorithmMismatch {
+                    found: "a".into(),
+                    configured: "b".into(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::BlobNotFound("x".into()), false, true, false),
+            (
+                Error::ChunkMissing {
+                    blob_id: "x".into(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::StartupCheckFailed { damaged: 1 },
+                false,
+                false,
+                false,
+            ),
+            (Error::DataNotLocal("x".into()), false, false, false),
+            (
+                Error::InvalidBlobId {
+                    input: "x".into(),
+                    reason: "y".into(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::AmbiguousBlobId {
+                    prefix: "x".into(),
+                    matches: 2,
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::BlobAlreadyExists("x".into()), false, false, false),
+            (
+                Error::BlobTombstoned {
+                    blob_id: "x".into(),
+                    deleted_at: chrono::Utc::now(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                Error::RetentionActive {
+                    blob_id: "x".into(),
+                    retain_until: chrono::Utc::now(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::InvalidTenant("x".into()), false, false, false),
+            (Error::InvalidTenantId("x".into()), false, false, false),
+            (
+                Error::ConcurrentModification {
+                    blob_id: "x".into(),
+                    expected: 1,
+                    actual: 2,
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::NameRebound("x".into()), false, false, false),
+            (Error::TenantAlreadyExists("x".into()), false, false, false),
+            (
+                Error::StorageFull {
+                    available: 0,
+                    required: 1,
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::TenantLimitReached { limit: 1 }, false, false, false),
+            (
+                Error::BlobLimitReached {
+                    tenant: "x".into(),
+                    limit: 1,
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::Overloaded { limit: 1 }, true, false, false),
+            (
+                Error::UploadBudgetExceeded {
+                    requested: 2,
+                    budget: 1,
+                },
+                true,
+                false,
+                false,
+            ),
+            (Error::Http("x".into()), true, false, false),
+            (
+                Error::HttpStatus {
+                    url: "x".into(),
+                    status: 503,
+                },
+                true,
+                false,
+                false,
+            ),
+            (
+                Error::HttpStatus {
+                    url: "x".into(),
+                    status: 429,
+                },
+                true,
+                false,
+                false,
+            ),
+            (
+                Error::HttpStatus {
+                    url: "x".into(),
+                    status: 404,
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::PermissionDenied("x".into()), false, false, true),
+            (
+                Error::IncompatibleLayout {
+                    found: 1,
+                    expected: 2,
+                },
+                false,
+                false,
+                false,
+            ),
+            (Error::ReadOnly("x".into()), false, false, false),
+            (Error::System("x".into()), false, false, false),
+        ];
+        for (err, retryable, not_found, permission) in cases {
+            assert_eq!(err.is_retryable(), retryable, "{}", err);
+            assert_eq!(err.is_not_found(), not_found, "{}", err);
+            assert_eq!(err.is_permission(), permission, "{}", err);
+
+            // Context layers don't change the classification
+            let wrapped = err.with_context("while testing").with_context("outer");
+            assert_eq!(wrapped.is_retryable(), retryable);
+            assert_eq!(wrapped.is_not_found(), not_found);
+            assert_eq!(wrapped.is_permission(), permission);
+        }
+    }
+
+    #[test]
+    fn test_read_only_filesystem() {
+        let path = std::path::Path::new("/mnt/snapshot");
+        let err = read_only_at(path)(io::Error::from(ErrorKind::ReadOnlyFilesystem));
+        assert!(matches!(&err, Error::ReadOnlyFilesystem(p) if p == path));
+        assert!(err.to_string().contains("open_read_only"));
+        let err = read_only_at(path)(sled::Error::Io(io::Error::from(
+            ErrorKind::ReadOnlyFilesystem,
+        )));
+        assert!(matches!(err, Error::ReadOnlyFilesystem(_)));
+
+        // Other failures pass through unchanged
+        let err = read_only_at(path)(io::Error::from(ErrorKind::NotFound));
+        assert!(matches!(err, Error::Io(e) if e.kind() == ErrorKind::NotFound));
+    }
+}
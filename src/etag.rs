@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+use crate::{
+    BlobId, TenantId, Result,
+    coordinator::Coordinator,
+    error::ResultExt,
+};
+
+/// What a conditional download should send, decided by `evaluate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Precondition {
+    /// The client's copy is current (304)
+    NotModified,
+    /// Send the whole blob (200)
+    Full,
+    /// Send only this byte range (206)
+    Partial(Range<u64>),
+}
+
+/// Formats a blob checksum as a strong entity tag
+pub fn etag_for_checksum(checksum: &str) -> String {
+    format!("\"{}\"", checksum)
+}
+
+/// Strips the weak-validator prefix from an entity tag
+fn opaque(tag: &str) -> &str {
+    tag.trim().trim_start_matches("W/")
+}
+
+/// Decides how to answer a download given the blob's current `etag` and
+/// the request's `If-None-Match`, `If-Range` and `Range` values.
+///
+/// `If-None-Match` matching any listed tag (or `*`) means not modified. A
+/// range is only honoured if `If-Range` is absent or still matches
+/// exactly; otherwise the blob changed and the client gets it in full.
+pub fn evaluate(
+    etag: &str,
+    if_none_match: Option<&str>,
+    if_range: Option<&str>,
+    range: Option<Range<u64>>,
+) -> Precondition {
+    if let Some(if_none_match) = if_none_match {
+        let matched = if_none_match
+            .split(',')
+            .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag));
+        if matched {
+            return Precondition::NotModified;
+        }
+    }
+
+    match range {
+        // If-Range requires a strong comparison, so weak tags never match
+        Some(range) if if_range.is_none_or(|tag| tag.trim() == etag) => Precondition::Partial(range),
+        _ => Precondition::Full,
+    }
+}
+
+impl Coordinator {
+    /// Returns a tenant's blob's entity tag, derived from its checksum, so a
+    /// download handler can answer conditional requests before streaming
+    pub fn blob_etag(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<String> {
+        let context = || format!("while getting etag of blob {} for tenant {}", blob_id, tenant_id.as_str());
+        let metadata = self.owned_metadata(tenant_id, blob_id).with_context(context)?;
+        Ok(etag_for_checksum(&metadata.checksum.to_hex()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_conditional_requests() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+
+        let etag = coordinator.blob_etag(&tenant_id, &blob_id).unwrap();
+        let checksum = crate::checksum::compute_sha256(&b"Hello, World!"[..]).unwrap();
+        assert_eq!(etag, format!("\"{}\"", checksum));
+
+        // A matching tag means the client's copy is current
+        assert_eq!(evaluate(&etag, Some(&etag), None, None), Precondition::NotModified);
+        let listed = format!("\"stale\", W/{}", etag);
+        assert_eq!(evaluate(&etag, Some(&listed), None, None), Precondition::NotModified);
+        assert_eq!(evaluate(&etag, Some("*"), None, None), Precondition::NotModified);
+
+        // A stale tag gets the full blob, even when a range was asked for
+        assert_eq!(evaluate(&etag, Some("\"stale\""), None, None), Precondition::Full);
+        assert_eq!(evaluate(&etag, None, Some("\"stale\""), Some(7..12)), Precondition::Full);
+        assert_eq!(evaluate(&etag, None, Some(&format!("W/{}", etag)), Some(7..12)), Precondition::Full);
+
+        // A range validated by a current tag is served partially
+        assert_eq!(evaluate(&etag, None, Some(&etag), Some(7..12)), Precondition::Partial(7..12));
+        assert_eq!(evaluate(&etag, None, None, Some(7..12)), Precondition::Partial(7..12));
+
+        // Other tenants can't read the tag
+        let other = TenantId::new("messages");
+        coordinator.register_tenant(other.clone()).unwrap();
+        assert!(coordinator.blob_etag(&other, &blob_id).is_err());
+    }
+}
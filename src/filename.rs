@@ -0,0 +1,102 @@
+use crate::{Result, error::Error};
+
+/// Characters that can't appear in a filename on Windows (or Unix, for `/`)
+const RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*', '%'];
+
+/// Device names Windows reserves regardless of extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn percent_encode(c: char, out: &mut String) {
+    let mut buf = [0; 4];
+    for byte in c.encode_utf8(&mut buf).bytes() {
+        out.push_str(&format!("%{:02X}", byte));
+    }
+}
+
+/// Turns a user-controlled string into a filename that is valid on every
+/// platform, percent-encoding reserved characters, control characters,
+/// trailing dots/spaces and reserved device names. Reversed by `decode_filename`.
+pub fn encode_filename(name: &str) -> String {
+    let stem = name.split('.').next().unwrap_or_default();
+    let is_device_name = RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem.trim_end()));
+    let trailing_start = name.trim_end_matches(['.', ' ']).len();
+
+    let mut encoded = String::with_capacity(name.len());
+    for (i, c) in name.char_indices() {
+        let must_encode = RESERVED_CHARS.contains(&c)
+            || c.is_control()
+            || i >= trailing_start
+            || (i == 0 && is_device_name);
+        if must_encode {
+            percent_encode(c, &mut encoded);
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Recovers the original string from a filename produced by `encode_filename`
+pub fn decode_filename(encoded: &str) -> Result<String> {
+    let invalid = || Error::System(format!("Invalid encoded filename: {}", encoded));
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = encoded.get(i + 1..i + 3).ok_or_else(invalid)?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(name: &str, expected: &str) {
+        let encoded = encode_filename(name);
+        assert_eq!(encoded, expected);
+        assert!(!encoded.contains(&RESERVED_CHARS[..RESERVED_CHARS.len() - 1]));
+        assert_eq!(decode_filename(&encoded).unwrap(), name);
+    }
+
+    #[test]
+    fn test_reserved_characters() {
+        assert_round_trip("report.txt", "report.txt");
+        assert_round_trip("a:b*c", "a%3Ab%2Ac");
+        assert_round_trip("dir/file?.txt", "dir%2Ffile%3F.txt");
+        assert_round_trip("100%", "100%25");
+        assert_round_trip("ünïcode", "ünïcode");
+    }
+
+    #[test]
+    fn test_trailing_dots_and_spaces() {
+        assert_round_trip("name.", "name%2E");
+        assert_round_trip("name. .", "name%2E%20%2E");
+        assert_round_trip("..", "%2E%2E");
+    }
+
+    #[test]
+    fn test_reserved_device_names() {
+        assert_round_trip("CON", "%43ON");
+        assert_round_trip("nul.txt", "%6Eul.txt");
+        assert_round_trip("com1", "%63om1");
+        assert_round_trip("CONSOLE", "CONSOLE");
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_input() {
+        assert!(decode_filename("bad%2").is_err());
+        assert!(decode_filename("bad%zz").is_err());
+    }
+}
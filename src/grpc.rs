@@ -0,0 +1,271 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Code, Request, Response, Status, Streaming};
+
+use crate::{BlobId, BlobMetadata, Result, TenantId, coordinator::Coordinator, error::Error};
+
+/// Messages and service stubs generated from `proto/blob_store.proto`
+pub mod proto {
+    tonic::include_proto!("minitectonic.v1");
+}
+
+use proto::blob_store_server::{BlobStore, BlobStoreServer};
+use proto::{
+    DeleteRequest, DeleteResponse, GetRequest, GetResponse, HeadRequest, HeadResponse, ListRequest, ListResponse,
+    PutRequest, PutResponse,
+};
+
+/// Size of the data messages `Get` streams a blob in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Data messages buffered between the network and the coordinator per
+/// stream, so a transfer holds a few chunks in memory rather than the blob
+const STREAM_BUFFER: usize = 4;
+
+/// Serves a coordinator's blobs over gRPC. The coordinator's calls block,
+/// so each runs on tokio's blocking pool.
+pub struct GrpcService {
+    coordinator: Arc<Coordinator>,
+}
+
+impl GrpcService {
+    pub fn new(coordinator: Arc<Coordinator>) -> Self {
+        Self { coordinator }
+    }
+
+    /// Wraps the service for adding to a tonic server
+    pub fn into_server(self) -> BlobStoreServer<Self> {
+        BlobStoreServer::new(self)
+    }
+
+    /// Runs `f` with the coordinator on the blocking pool
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Coordinator) -> Result<T> + Send + 'static,
+    ) -> std::result::Result<T, Status> {
+        let coordinator = self.coordinator.clone();
+        tokio::task::spawn_blocking(move || f(&coordinator))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)
+    }
+}
+
+/// Serves the gRPC service for `coordinator` on connections accepted from
+/// `listener` until the listener fails
+pub async fn serve(coordinator: Arc<Coordinator>, listener: TcpListener) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(GrpcService::new(coordinator).into_server())
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+        .map_err(|e| Error::System(format!("gRPC server failed: {}", e)))
+}
+
+/// Maps an error to the gRPC status a client sees. Errors surfacing through
+/// a reader are classified by the error they wrap.
+pub fn status(err: Error) -> Status {
+    if let Error::Io(e) = err.root_cause() {
+        if let Some(inner) = e.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+            return Status::new(code(inner), err.to_string());
+        }
+    }
+    Status::new(code(&err), err.to_string())
+}
+
+fn code(err: &Error) -> Code {
+    match err.root_cause() {
+        Error::BlobNotFound(_) => Code::NotFound,
+        Error::BlobAlreadyExists(_) | Error::TenantAlreadyExists(_) => Code::AlreadyExists,
+        Error::InvalidTenant(_) | Error::InvalidTenantId(_) | Error::InvalidBlobId { .. } | Error::InvalidChecksum { .. } => {
+            Code::InvalidArgument
+        }
+        Error::ChecksumMismatch { .. }
+        | Error::ChecksumSourceDisagreement { .. }
+        | Error::MissingChecksum(_)
+        | Error::ChunkChecksumMismatch { .. }
+        | Error::ChunkMissing { .. } => Code::DataLoss,
+        Error::ConcurrentModification { .. } | Error::NameRebound(_) => Code::Aborted,
+        Error::RetentionActive { .. } | Error::BlobTombstoned { .. } | Error::ReadOnly(_) => Code::FailedPrecondition,
+        Error::StorageFull { .. }
+        | Error::TenantLimitReached { .. }
+        | Error::BlobLimitReached { .. }
+        | Error::Overloaded { .. }
+        | Error::UploadBudgetExceeded { .. } => Code::ResourceExhausted,
+        _ if err.is_permission() => Code::PermissionDenied,
+        _ if err.is_retryable() => Code::Unavailable,
+        _ => Code::Internal,
+    }
+}
+
+fn to_proto(metadata: BlobMetadata) -> proto::BlobMetadata {
+    proto::BlobMetadata {
+        blob_id: metadata.blob_id.to_string(),
+        size: metadata.size,
+        checksum: metadata.checksum.to_string(),
+        created_at: metadata.created_at.to_rfc3339(),
+        content_type: metadata.content_type.unwrap_or_default(),
+    }
+}
+
+/// Reads the data messages of a `Put` stream as they arrive
+struct ChannelReader {
+    messages: mpsc::Receiver<io::Result<Vec<u8>>>,
+    current: io::Cursor<Vec<u8>>,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.messages.blocking_recv() {
+                Some(data) => self.current = io::Cursor::new(data?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl BlobStore for GrpcService {
+    async fn put(&self, request: Request<Streaming<PutRequest>>) -> std::result::Result<Response<PutResponse>, Status> {
+        let mut stream = request.into_inner();
+        let first = stream.message().await?.ok_or_else(|| Status::invalid_argument("empty put stream"))?;
+        let tenant_id = TenantId::new(first.tenant_id);
+
+        // The put consumes data while the rest of the stream arrives; if it
+        // stops early the channel closes and forwarding ends
+        let (sender, messages) = mpsc::channel(STREAM_BUFFER);
+        sender.send(Ok(first.data)).await.ok();
+        let reader = ChannelReader { messages, current: io::Cursor::new(Vec::new()) };
+        let put = self.blocking(move |coordinator| coordinator.put_blob_info(&tenant_id, reader));
+        let forward = async move {
+            loop {
+                let message = match stream.message().await {
+                    Ok(Some(message)) => Ok(message.data),
+                    Ok(None) => break,
+                    Err(e) => Err(io::Error::other(e.to_string())),
+                };
+                let failed = message.is_err();
+                if sender.send(message).await.is_err() || failed {
+                    break;
+                }
+            }
+        };
+        let (put, ()) = tokio::join!(put, forward);
+        let (blob_id, info) = put?;
+        Ok(Response::new(PutResponse { blob_id: blob_id.to_string(), size: info.size, checksum: info.checksum.to_string() }))
+    }
+
+    type GetStream = ReceiverStream<std::result::Result<GetResponse, Status>>;
+
+    async fn get(&self, request: Request<GetRequest>) -> std::result::Result<Response<Self::GetStream>, Status> {
+        let GetRequest { tenant_id, blob_id } = request.into_inner();
+        let (tenant_id, blob_id) = (TenantId::new(tenant_id), blob_id.parse::<BlobId>().map_err(status)?);
+        // Open the blob before answering, so a missing blob fails the call
+        // itself rather than its stream
+        let mut reader = self.blocking(move |coordinator| coordinator.get_blob(&tenant_id, &blob_id)).await?;
+
+        let (sender, messages) = mpsc::channel(STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || loop {
+            let mut data = vec![0; CHUNK_SIZE];
+            let message = match reader.read(&mut data) {
+                Ok(0) => break,
+                Ok(read) => {
+                    data.truncate(read);
+                    Ok(GetResponse { data })
+                }
+                Err(e) => Err(status(e.into())),
+            };
+            let failed = message.is_err();
+            // A client that went away closes the channel
+            if sender.blocking_send(message).is_err() || failed {
+                break;
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(messages)))
+    }
+
+    async fn delete(&self, request: Request<DeleteRequest>) -> std::result::Result<Response<DeleteResponse>, Status> {
+        let DeleteRequest { tenant_id, blob_id } = request.into_inner();
+        let (tenant_id, blob_id) = (TenantId::new(tenant_id), blob_id.parse::<BlobId>().map_err(status)?);
+        self.blocking(move |coordinator| coordinator.delete_blob(&tenant_id, &blob_id)).await?;
+        Ok(Response::new(DeleteResponse {}))
+    }
+
+    async fn list(&self, request: Request<ListRequest>) -> std::result::Result<Response<ListResponse>, Status> {
+        let tenant_id = TenantId::new(request.into_inner().tenant_id);
+        let blobs = self.blocking(move |coordinator| coordinator.list_blobs(&tenant_id)).await?;
+        Ok(Response::new(ListResponse { blobs: blobs.into_iter().map(to_proto).collect() }))
+    }
+
+    async fn head(&self, request: Request<HeadRequest>) -> std::result::Result<Response<HeadResponse>, Status> {
+        let HeadRequest { tenant_id, blob_id } = request.into_inner();
+        let (tenant_id, blob_id) = (TenantId::new(tenant_id), blob_id.parse::<BlobId>().map_err(status)?);
+        let metadata = self.blocking(move |coordinator| coordinator.owned_metadata(&tenant_id, &blob_id)).await?;
+        Ok(Response::new(HeadResponse { blob: Some(to_proto(metadata)) }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::blob_store_client::BlobStoreClient;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_grpc_round_trip() {
+        let coordinator = Arc::new(Coordinator::in_memory().unwrap());
+        coordinator.register_tenant(TenantId::new("posts")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(coordinator.clone(), listener));
+        let mut client = BlobStoreClient::connect(format!("http://{}", addr)).await.unwrap();
+
+        // Put streams the data in several messages, and get streams it back
+        // in chunks
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let messages: Vec<_> = data
+            .chunks(30_000)
+            .enumerate()
+            .map(|(i, chunk)| PutRequest {
+                tenant_id: if i == 0 { "posts".into() } else { String::new() },
+                data: chunk.to_vec(),
+            })
+            .collect();
+        let put = client.put(tokio_stream::iter(messages)).await.unwrap().into_inner();
+        assert_eq!(put.size, data.len() as u64);
+
+        let get = GetRequest { tenant_id: "posts".into(), blob_id: put.blob_id.clone() };
+        let mut stream = client.get(get).await.unwrap().into_inner();
+        let mut read = Vec::new();
+        let mut messages = 0;
+        while let Some(message) = stream.message().await.unwrap() {
+            read.extend(message.data);
+            messages += 1;
+        }
+        assert_eq!(read, data);
+        assert!(messages > 1);
+
+        let head = HeadRequest { tenant_id: "posts".into(), blob_id: put.blob_id.clone() };
+        let blob = client.head(head).await.unwrap().into_inner().blob.unwrap();
+        assert_eq!((blob.size, &blob.checksum), (put.size, &put.checksum));
+        let list = client.list(ListRequest { tenant_id: "posts".into() }).await.unwrap().into_inner();
+        assert_eq!(list.blobs, [blob]);
+
+        // Errors map to status codes
+        let delete = DeleteRequest { tenant_id: "posts".into(), blob_id: put.blob_id.clone() };
+        client.delete(delete).await.unwrap();
+        let get = GetRequest { tenant_id: "posts".into(), blob_id: put.blob_id };
+        assert_eq!(client.get(get).await.unwrap_err().code(), Code::NotFound);
+        let get = GetRequest { tenant_id: "posts".into(), blob_id: "not-an-id".into() };
+        assert_eq!(client.get(get).await.unwrap_err().code(), Code::InvalidArgument);
+        let list = ListRequest { tenant_id: "unknown".into() };
+        assert_eq!(client.list(list).await.unwrap_err().code(), Code::InvalidArgument);
+    }
+}
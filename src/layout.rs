@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::Path;
+use crate::{Result, error::{Error, read_only_at}};
+
+/// Version of the on-disk layout written by this build
+pub const LAYOUT_VERSION: u32 = 1;
+
+/// Name of the marker file in the storage root
+const MARKER_FILE: &str = "layout_version";
+
+/// Names of the subdirectories a store creates under its root. Override them
+/// to embed a store in a directory shared with other data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreLayout {
+    pub chunks_dir: String,
+    pub metadata_dir: String,
+    pub tenants_dir: String,
+    pub packs_dir: String,
+}
+
+impl Default for StoreLayout {
+    fn default() -> Self {
+        Self {
+            chunks_dir: "chunks".into(),
+            metadata_dir: "metadata".into(),
+            tenants_dir: "tenants".into(),
+            packs_dir: "packs".into(),
+        }
+    }
+}
+
+/// Reads the layout version recorded in the storage root, if any
+pub fn read_version(root_dir: &Path) -> Result<Option<u32>> {
+    let marker = root_dir.join(MARKER_FILE);
+    if !marker.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&marker)?;
+    let version = contents.trim().parse().map_err(|_| {
+        Error::System(format!("Invalid layout version marker: {}", contents.trim()))
+    })?;
+    Ok(Some(version))
+}
+
+/// Writes the layout version marker into the storage root
+pub fn write_version(root_dir: &Path, version: u32) -> Result<()> {
+    fs::create_dir_all(root_dir).map_err(read_only_at(root_dir))?;
+    fs::write(root_dir.join(MARKER_FILE), version.to_string()).map_err(read_only_at(root_dir))?;
+    Ok(())
+}
+
+/// Checks that the storage root uses the current layout, stamping the marker
+/// on stores that don't have one yet (new stores and stores predating the marker)
+pub fn check(root_dir: &Path) -> Result<()> {
+    match read_version(root_dir)? {
+        Some(LAYOUT_VERSION) => Ok(()),
+        Some(found) => Err(Error::IncompatibleLayout {
+            found,
+            expected: LAYOUT_VERSION,
+        }),
+        None => write_version(root_dir, LAYOUT_VERSION),
+    }
+}
+
+/// Checks that an existing store's layout is readable by this build,
+/// without stamping a marker, for stores opened read-only
+pub fn verify(root_dir: &Path) -> Result<()> {
+    match read_version(root_dir)? {
+        Some(found) if found != LAYOUT_VERSION => Err(Error::IncompatibleLayout {
+            found,
+            expected: LAYOUT_VERSION,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Copies a directory tree, e.g. a sled db to open a private snapshot of it
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens an existing sled db for a store opened read-only. The db is opened
+/// in place, taking its lock, so a live writer makes this fail instead of
+/// reading a torn copy. Only if the filesystem refuses writes, and so
+/// nothing can be writing to it, is a private copy opened instead; the
+/// returned directory holds it and must outlive the db.
+pub(crate) fn open_db_read_only(path: &Path) -> Result<(sled::Db, Option<tempfile::TempDir>)> {
+    if !path.is_dir() {
+        return Err(Error::System(format!("No database found at {}", path.display())));
+    }
+    match sled::open(path).map_err(read_only_at(path)) {
+        Err(Error::ReadOnlyFilesystem(_)) => {
+            let scratch_dir = tempfile::tempdir()?;
+            let copy = scratch_dir.path().join("db");
+            copy_dir(path, &copy)?;
+            Ok((sled::open(copy)?, Some(scratch_dir)))
+        }
+        result => Ok((result?, None)),
+    }
+}
+
+/// Migrates a store written with an older layout to the current one,
+/// returning the version it was upgraded from
+pub fn upgrade(root_dir: &Path) -> Result<u32> {
+    let found = read_version(root_dir)?.unwrap_or(LAYOUT_VERSION);
+    if found > LAYOUT_VERSION {
+        return Err(Error::IncompatibleLayout {
+            found,
+            expected: LAYOUT_VERSION,
+        });
+    }
+
+    // No layout changes need migrating yet; migration steps go here as the
+    // version is bumped
+    write_version(root_dir, LAYOUT_VERSION)?;
+    Ok(found)
+}
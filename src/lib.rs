@@ -5,24 +5,165 @@ pub mod chunk_store;
 pub mod checksum;
 pub mod tenant;
 pub mod error;
+pub mod layout;
+pub mod filename;
+pub mod bloom;
+pub mod archive;
+pub mod cancel;
+pub mod content_type;
+pub mod pack_store;
+pub mod transform;
+pub mod verifier;
+pub mod etag;
+pub mod compression;
+pub mod limiter;
+pub mod clock;
+pub mod tags;
+pub mod dump;
+pub mod pool;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 
 use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
 /// Represents a unique identifier for a blob
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct BlobId(Uuid);
 
+/// Textual encodings a blob id can be printed and parsed in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdFormat {
+    /// Hyphenated UUID
+    #[default]
+    Uuid,
+    /// 32 lowercase hex digits
+    Hex,
+    /// 26 RFC 4648 base32 characters, unpadded
+    Base32,
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
 impl BlobId {
+    /// Creates a new random id
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
 
-    pub fn to_string(&self) -> String {
-        self.0.to_string()
+    /// Wraps a specific UUID, e.g. for deterministic tests or replays
+    pub fn from_uuid(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+
+    /// The all-zero id
+    pub fn nil() -> Self {
+        Self(Uuid::nil())
+    }
+
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+
+    /// The first 8 hex digits of the id, for display where the full id is
+    /// too noisy. Resolve one back with `Coordinator::resolve_blob_id`.
+    pub fn short(&self) -> String {
+        self.0.simple().to_string()[..8].to_string()
+    }
+
+    /// Renders the id in the given format
+    pub fn format(&self, format: IdFormat) -> String {
+        match format {
+            IdFormat::Uuid => self.0.to_string(),
+            IdFormat::Hex => self.0.simple().to_string(),
+            IdFormat::Base32 => {
+                let mut encoded = String::with_capacity(26);
+                let mut buffer = 0u32;
+                let mut bits = 0;
+                for &byte in self.0.as_bytes() {
+                    buffer = (buffer << 8) | byte as u32;
+                    bits += 8;
+                    while bits >= 5 {
+                        bits -= 5;
+                        encoded.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+                    }
+                }
+                if bits > 0 {
+                    encoded.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+                }
+                encoded
+            }
+        }
+    }
+
+    /// Parses an id printed in the given format. Fails with `InvalidBlobId`
+    /// naming the input and what was wrong with it.
+    pub fn parse(s: &str, format: IdFormat) -> Result<Self> {
+        let invalid = |reason: String| error::Error::InvalidBlobId { input: s.to_string(), reason };
+        match format {
+            IdFormat::Uuid | IdFormat::Hex => Uuid::parse_str(s).map(Self).map_err(|e| {
+                let expected = match format {
+                    IdFormat::Hex => "32 hex digits",
+                    _ => "a UUID such as 67e55044-10b1-426f-9247-bb680e5fe0c8",
+                };
+                invalid(format!("{}; expected {}", e, expected))
+            }),
+            IdFormat::Base32 => {
+                let invalid = || invalid("expected 26 base32 characters".into());
+                if s.len() != 26 {
+                    return Err(invalid());
+                }
+                let mut bytes = Vec::with_capacity(16);
+                let mut buffer = 0u32;
+                let mut bits = 0;
+                for c in s.bytes() {
+                    let value = BASE32_ALPHABET
+                        .iter()
+                        .position(|&a| a == c.to_ascii_uppercase())
+                        .ok_or_else(invalid)?;
+                    buffer = (buffer << 5) | value as u32;
+                    bits += 5;
+                    if bits >= 8 {
+                        bits -= 8;
+                        bytes.push((buffer >> bits) as u8);
+                    }
+                }
+                let bytes: [u8; 16] = bytes.try_into().map_err(|_| invalid())?;
+                Ok(Self(Uuid::from_bytes(bytes)))
+            }
+        }
+    }
+}
+
+impl Default for BlobId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::str::FromStr for BlobId {
+    type Err = error::Error;
+
+    /// Parses a hyphenated UUID, the inverse of `to_string`
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s, IdFormat::Uuid)
+    }
+}
+
+impl std::fmt::Display for BlobId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
+/// Longest tenant name used as is in database keys. Longer names are keyed
+/// by a fixed-length hash of the name instead. This is part of the on-disk
+/// format, independent of `Coordinator::with_tenant_name_limit`.
+pub const MAX_TENANT_KEY_LEN: usize = 128;
+
 /// Represents a tenant in the system
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct TenantId(String);
@@ -32,9 +173,33 @@ impl TenantId {
         Self(name.into())
     }
 
+    /// Creates a tenant id, failing with `InvalidTenantId` if the name has a
+    /// character `charset` doesn't allow
+    pub fn try_new(name: impl Into<String>, charset: &tenant::TenantCharset) -> Result<Self> {
+        let name = name.into();
+        charset.check(&name)?;
+        Ok(Self(name))
+    }
+
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    /// Returns the form of the name used in database keys: the name itself,
+    /// or `~` and its SHA-256 if it is longer than `MAX_TENANT_KEY_LEN`
+    pub fn key(&self) -> Cow<'_, str> {
+        if self.is_hashed() {
+            let digest = sha2::Sha256::digest(self.0.as_bytes());
+            Cow::Owned(format!("~{}", checksum::DigestEncoding::Hex.encode(&digest)))
+        } else {
+            Cow::Borrowed(&self.0)
+        }
+    }
+
+    /// Returns true if the name is too long to be used in keys directly
+    pub fn is_hashed(&self) -> bool {
+        self.0.len() > MAX_TENANT_KEY_LEN
+    }
 }
 
 /// Represents metadata about a stored blob
@@ -43,9 +208,209 @@ pub struct BlobMetadata {
     pub blob_id: BlobId,
     pub tenant_id: TenantId,
     pub size: u64,
-    pub checksum: String,
+    pub checksum: checksum::Checksum,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Strictly increasing insertion sequence, unaffected by wall-clock jumps.
+    /// Use it rather than `created_at` to order blobs.
+    #[serde(default)]
+    pub seq: u64,
+    #[serde(default)]
+    pub tags: tags::Tags,
+    /// MIME type given by the uploader or inferred from the blob's leading bytes
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Location within a pack file, for blobs stored by the pack backend
+    #[serde(default)]
+    pub pack: Option<pack_store::PackLocation>,
+    /// Storage tier holding the blob's chunk files
+    #[serde(default)]
+    pub tier: chunk_store::Tier,
+    /// Write-once retention: until this time the blob can't be deleted or
+    /// moved, and the date can only ever be pushed later
+    #[serde(default)]
+    pub retain_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Bytes on disk for blobs stored compressed; `None` if stored as is
+    #[serde(default)]
+    pub compressed_size: Option<u64>,
+    /// Algorithm the blob's data was compressed with
+    #[serde(default)]
+    pub compression: compression::Compression,
+    /// After this time the blob reads as missing and can be purged
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Per-chunk checksums, for blobs written with chunk checksums enabled
+    #[serde(default)]
+    pub chunks: Option<checksum::ChunkChecksums>,
+    /// Bumped by every metadata update, for compare-and-swap updates with
+    /// `Coordinator::update_metadata_if`
+    #[serde(default)]
+    pub generation: u64,
+    /// Set on records imported by `Coordinator::import_metadata_stream`,
+    /// whose data lives on another node
+    #[serde(default)]
+    pub metadata_only: bool,
+    /// Extra digests of the content, for coordinators configured with
+    /// `Coordinator::with_checksums`. `checksum` stays the primary checksum.
+    #[serde(default)]
+    pub checksums: BTreeMap<checksum::ChecksumAlgorithm, String>,
+    /// When the blob was last read, for coordinators with access tracking
+    /// enabled. Written in batches, so it can lag the latest reads.
+    #[serde(default)]
+    pub last_accessed: Option<chrono::DateTime<chrono::Utc>>,
+    /// Structured fields attached by integrators, stored verbatim and never
+    /// interpreted. Set with `PutBuilder::extension`.
+    #[serde(default)]
+    pub extensions: BTreeMap<String, serde_json::Value>,
+}
+
+impl BlobMetadata {
+    /// Returns how the blob's data is stored. Records written before the
+    /// algorithm was recorded only have a compressed size, which meant zstd.
+    pub fn stored_compression(&self) -> compression::Compression {
+        match (self.compression, self.compressed_size) {
+            (compression::Compression::None, Some(_)) => compression::Compression::Zstd(0),
+            (compression, _) => compression,
+        }
+    }
+
+    /// Returns true once the blob's expiry time has passed
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(chrono::Utc::now())
+    }
+
+    /// Returns true if the blob's expiry time is at or before `now`
+    pub fn is_expired_at(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Uncompressed over compressed size; 1.0 for uncompressed blobs
+    pub fn compression_ratio(&self) -> f64 {
+        match self.compressed_size {
+            Some(compressed_size) if compressed_size > 0 => self.size as f64 / compressed_size as f64,
+            _ => 1.0,
+        }
+    }
 }
 
 /// Result type for operations that can fail
-pub type Result<T> = std::result::Result<T, error::Error>; 
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Collects formatted tracing output so tests can assert on logged events
+    #[derive(Clone, Default)]
+    pub struct LogCapture(Arc<Mutex<Vec<u8>>>);
+
+    impl LogCapture {
+        /// Returns a subscriber that writes into this capture
+        pub fn subscriber(&self) -> impl tracing::Subscriber + Send + Sync {
+            let writer = self.clone();
+            tracing_subscriber::fmt()
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish()
+        }
+
+        /// Returns everything logged so far
+        pub fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl std::io::Write for LogCapture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reopens a sled-backed store. sled releases its file lock from a
+    /// background thread shortly after the previous handle is dropped, so
+    /// retry briefly while the lock is still held.
+    pub fn reopen<T>(open: impl Fn() -> crate::Result<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match open() {
+                Ok(store) => return store,
+                Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                Err(e) => panic!("failed to reopen store: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_blob_id_formats_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = coordinator::Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let blob_id = coordinator.put_blob(&tenant_id, Cursor::new(b"data")).unwrap();
+
+        for format in [IdFormat::Uuid, IdFormat::Hex, IdFormat::Base32] {
+            let printed = blob_id.format(format);
+            let parsed = BlobId::parse(&printed, format).unwrap();
+            assert_eq!(parsed, blob_id);
+            assert!(coordinator.get_blob(&tenant_id, &parsed).is_ok());
+        }
+
+        assert_eq!(blob_id.format(IdFormat::Uuid), blob_id.to_string());
+        assert_eq!(blob_id.format(IdFormat::Hex).len(), 32);
+        assert_eq!(blob_id.format(IdFormat::Base32).len(), 26);
+        let lowercase = blob_id.format(IdFormat::Base32).to_lowercase();
+        assert_eq!(BlobId::parse(&lowercase, IdFormat::Base32).unwrap(), blob_id);
+        assert!(BlobId::parse("not-an-id", IdFormat::Base32).is_err());
+    }
+
+    #[test]
+    fn test_invalid_blob_id() {
+        let truncated = "67e55044-10b1-426f-9247-bb680e5f";
+        let err = truncated.parse::<BlobId>().unwrap_err();
+        let error::Error::InvalidBlobId { input, reason } = &err else {
+            panic!("expected InvalidBlobId, got {:?}", err);
+        };
+        assert_eq!(input, truncated);
+        assert!(reason.contains("expected a UUID"), "{}", reason);
+        assert!(err.to_string().contains(truncated), "{}", err);
+
+        let err = BlobId::parse("zz", IdFormat::Base32).unwrap_err();
+        assert!(matches!(&err, error::Error::InvalidBlobId { input, .. } if input == "zz"));
+        assert!(err.to_string().contains("base32"), "{}", err);
+    }
+
+    #[test]
+    fn test_blob_id_from_uuid() {
+        let uuid = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let blob_id = BlobId::from_uuid(uuid);
+        assert_eq!(blob_id.as_uuid(), &uuid);
+        assert_eq!(blob_id.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+        assert_eq!(blob_id.to_string().parse::<BlobId>().unwrap(), blob_id);
+
+        assert_eq!(BlobId::nil().to_string(), "00000000-0000-0000-0000-000000000000");
+        assert_eq!(BlobId::nil(), BlobId::from_uuid(Uuid::nil()));
+        assert_ne!(BlobId::new(), BlobId::new());
+        assert!("not-an-id".parse::<BlobId>().is_err());
+
+        // A chosen id can be stored and read back under that id
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = coordinator::Coordinator::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+        let stored = coordinator.put_blob_with_id(&tenant_id, blob_id.clone(), Cursor::new(b"data")).unwrap();
+        assert_eq!(stored, blob_id);
+        assert!(coordinator.get_blob(&tenant_id, &blob_id).is_ok());
+        assert!(coordinator.put_blob_with_id(&tenant_id, blob_id, Cursor::new(b"again")).is_err());
+    }
+}
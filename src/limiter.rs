@@ -0,0 +1,139 @@
+use std::sync::{Condvar, Mutex};
+
+use crate::{Result, error::Error};
+
+/// What a mutating operation does when the concurrency limit is reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Wait until a running operation finishes
+    #[default]
+    Queue,
+    /// Fail immediately with `Overloaded`
+    Reject,
+}
+
+/// Counting semaphore bounding how many operations run at once
+pub(crate) struct ConcurrencyLimiter {
+    limit: usize,
+    policy: OverloadPolicy,
+    in_flight: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A slot held by a running operation, freed on drop
+pub(crate) struct Permit<'a> {
+    limiter: &'a ConcurrencyLimiter,
+}
+
+impl ConcurrencyLimiter {
+    pub(crate) fn new(limit: usize, policy: OverloadPolicy) -> Self {
+        Self {
+            limit: limit.max(1),
+            policy,
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Takes a slot, queuing or failing per the policy when none is free
+    pub(crate) fn acquire(&self) -> Result<Permit<'_>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight >= self.limit {
+            if self.policy == OverloadPolicy::Reject {
+                return Err(Error::Overloaded { limit: self.limit });
+            }
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        Ok(Permit { limiter: self })
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.limiter.in_flight.lock().unwrap() -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+/// Bounds the total bytes of uploads in progress at once
+pub(crate) struct ByteBudget {
+    budget: u64,
+    policy: OverloadPolicy,
+    reserved: Mutex<u64>,
+    released: Condvar,
+}
+
+/// Bytes held by a running upload, given back on drop
+pub(crate) struct Reservation<'a> {
+    budget: &'a ByteBudget,
+    bytes: u64,
+}
+
+impl ByteBudget {
+    pub(crate) fn new(budget: u64, policy: OverloadPolicy) -> Self {
+        Self {
+            budget: budget.max(1),
+            policy,
+            reserved: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Reserves `bytes`, queuing or failing per the policy while they don't
+    /// fit. A reservation larger than the whole budget is cut down to it, so
+    /// an oversized upload runs alone rather than never.
+    pub(crate) fn reserve(&self, bytes: u64) -> Result<Reservation<'_>> {
+        let bytes = bytes.min(self.budget);
+        let mut reserved = self.reserved.lock().unwrap();
+        while *reserved + bytes > self.budget {
+            if self.policy == OverloadPolicy::Reject {
+                return Err(Error::UploadBudgetExceeded { requested: bytes, budget: self.budget });
+            }
+            reserved = self.released.wait(reserved).unwrap();
+        }
+        *reserved += bytes;
+        Ok(Reservation { budget: self, bytes })
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.reserved.lock().unwrap() -= self.bytes;
+        // Waiters need different amounts, so each rechecks
+        self.budget.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_when_full() {
+        let limiter = ConcurrencyLimiter::new(2, OverloadPolicy::Reject);
+        let first = limiter.acquire().unwrap();
+        let _second = limiter.acquire().unwrap();
+        assert!(matches!(limiter.acquire().err().unwrap(), Error::Overloaded { limit: 2 }));
+
+        // A finished operation frees its slot
+        drop(first);
+        assert!(limiter.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_byte_budget() {
+        let budget = ByteBudget::new(100, OverloadPolicy::Reject);
+        let first = budget.reserve(60).unwrap();
+        let _second = budget.reserve(40).unwrap();
+        let err = budget.reserve(1).err().unwrap();
+        assert!(matches!(err, Error::UploadBudgetExceeded { requested: 1, budget: 100 }));
+
+        // Releasing a reservation frees exactly its bytes
+        drop(first);
+        assert!(budget.reserve(61).is_err());
+        let third = budget.reserve(60).unwrap();
+        drop((third, _second));
+        assert_eq!(budget.reserve(1_000).unwrap().bytes, 100);
+    }
+}
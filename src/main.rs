@@ -1,9 +1,13 @@
+use std::process::ExitCode;
 use mini_tectonic_rs::cli;
 
-fn main() -> mini_tectonic_rs::Result<()> {
+fn main() -> ExitCode {
     // Initialize logging
     tracing_subscriber::fmt::init();
 
-    // Run the CLI
-    cli::run()
-} 
\ No newline at end of file
+    // Run the CLI, exiting with a code scripts can branch on
+    match cli::run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => ExitCode::from(cli::report_error(&err, std::io::stderr())),
+    }
+}
\ No newline at end of file
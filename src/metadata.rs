@@ -1,51 +1,1300 @@
-use crate::{BlobId, TenantId, BlobMetadata, Result, error::Error};
+use crate::{BlobId, TenantId, BlobMetadata, Result, checksum::{Checksum, ChecksumAlgorithm}, error::{Error, read_only_at}, pack_store::PackLocation, tags::Tags};
 use sled::Db;
-use std::path::Path;
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::{HashMap, HashSet};
 use serde_json;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+/// Metadata fields that can be requested from a projected listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataField {
+    Size,
+    Checksum,
+    CreatedAt,
+    Tags,
+    ContentType,
+    LastAccessed,
+}
+
+/// Blob metadata with only the requested fields populated
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialMetadata {
+    pub blob_id: BlobId,
+    pub size: Option<u64>,
+    pub checksum: Option<Checksum>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub tags: Option<Tags>,
+    pub content_type: Option<String>,
+    pub last_accessed: Option<DateTime<Utc>>,
+}
+
+impl PartialMetadata {
+    /// Keeps just the requested fields of a full record
+    pub fn project(metadata: BlobMetadata, fields: &[MetadataField]) -> Self {
+        let wants = |field| fields.contains(&field);
+        Self {
+            blob_id: metadata.blob_id,
+            size: wants(MetadataField::Size).then_some(metadata.size),
+            checksum: wants(MetadataField::Checksum).then_some(metadata.checksum),
+            created_at: wants(MetadataField::CreatedAt).then_some(metadata.created_at),
+            tags: wants(MetadataField::Tags).then_some(metadata.tags),
+            content_type: metadata.content_type.filter(|_| wants(MetadataField::ContentType)),
+            last_accessed: metadata.last_accessed.filter(|_| wants(MetadataField::LastAccessed)),
+        }
+    }
+}
+
+/// The parts of a metadata record needed for a size-only listing. Other
+/// fields are skipped while parsing rather than allocated.
+#[derive(Deserialize)]
+pub struct SizeRecord {
+    pub size: u64,
+    #[serde(default)]
+    pub seq: u64,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// The parts of a metadata record a checksum manifest needs
+#[derive(Deserialize)]
+pub struct ChecksumRecord {
+    pub checksum: Checksum,
+    #[serde(default)]
+    pub seq: u64,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Marker left by deleting a blob when tombstones are enabled, so a sync
+/// can tell a deleted blob from one it hasn't seen yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub tenant_id: TenantId,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Prefix of every tombstone key
+const TOMBSTONE_PREFIX: &[u8] = b"tombstone:";
+
+/// Prefix of the keys recording overwrites whose staged data hasn't been
+/// moved into place yet
+const REPLACING_PREFIX: &[u8] = b"replacing:";
+
+/// Prefix of the keys pointing from a blob back to the idempotency key
+/// that created it
+const IDEMPOTENT_BLOB_PREFIX: &[u8] = b"idempotent:";
+
+/// The blob created for an idempotency key, and when
+#[derive(Serialize, Deserialize)]
+struct IdempotencyRecord {
+    blob_id: BlobId,
+    created_at: DateTime<Utc>,
+}
+
+/// One blob's metadata waiting in a `WriteBatch`
+struct BufferedRecord {
+    tenant_id: TenantId,
+    size: u64,
+    checksum: Checksum,
+    tags: Tags,
+    metadata_json: Vec<u8>,
+}
+
+/// The parts of a stored record the size index is built from
+#[derive(Deserialize)]
+struct IndexedBlob {
+    blob_id: BlobId,
+    tenant_id: TenantId,
+    size: u64,
+}
+
+/// The parts of a stored record the tag index and usage counters are
+/// kept from
+#[derive(Deserialize)]
+struct IndexedRecord {
+    tenant_id: TenantId,
+    size: u64,
+    #[serde(default)]
+    tags: Tags,
+}
+
+/// Prefix of every blob metadata record key, whichever the scheme
+const BLOB_PREFIX: &[u8] = b"blob:";
+
+/// Key recording which `KeyScheme` the store's records are under. Absent
+/// means `Raw`.
+const KEY_SCHEME_KEY: &[u8] = b"config:blob_key_scheme";
+
+/// How blob metadata records are keyed in the db
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyScheme {
+    /// `blob:` followed by the blob id as a hyphenated UUID string
+    #[default]
+    Raw,
+    /// `blob:` followed by the first 16 bytes of the SHA-256 of the blob
+    /// id, for a fixed key width spread evenly over the keyspace. The id
+    /// is recovered from the record stored under the key.
+    Hashed,
+}
+
+impl KeyScheme {
+    /// Reads the scheme a db's records are stored under
+    fn load(db: &Db) -> Result<Self> {
+        Ok(match db.get(KEY_SCHEME_KEY)? {
+            Some(value) if &*value == b"hashed" => KeyScheme::Hashed,
+            _ => KeyScheme::Raw,
+        })
+    }
+
+    /// Creates the metadata key for a blob under this scheme
+    fn blob_key(self, blob_id: &BlobId) -> Vec<u8> {
+        let mut key = BLOB_PREFIX.to_vec();
+        match self {
+            KeyScheme::Raw => key.extend_from_slice(blob_id.to_string().as_bytes()),
+            KeyScheme::Hashed => key.extend_from_slice(&Sha256::digest(blob_id.0.as_bytes())[..16]),
+        }
+        key
+    }
+}
+
+/// Key recording the algorithm new blobs' checksums are computed with.
+/// Absent means SHA-256.
+const CHECKSUM_ALGORITHM_KEY: &[u8] = b"config:checksum_algorithm";
+
+/// Metadata key present once the size index is maintained
+const SIZE_INDEX_KEY: &[u8] = b"config:size_index";
+
+/// Metadata key holding the lowest sequence number new blobs may get.
+/// Absent means zero.
+const SEQ_FLOOR_KEY: &[u8] = b"config:seq_floor";
+
+/// Metadata key present once tenant usage counters have been built from the
+/// records, so stores written before the counters existed are counted once
+const USAGE_COUNTED_KEY: &[u8] = b"config:usage_counted";
+
+/// Metadata key present once tenant blob counters have been built from the
+/// membership keys, so stores written before the counters existed are
+/// counted once
+const BLOBS_COUNTED_KEY: &[u8] = b"config:blobs_counted";
+
+/// Just the id of a stored metadata record
+#[derive(Deserialize)]
+struct IdRecord {
+    blob_id: BlobId,
+}
+
+/// Number of buffered access times that triggers writing them out
+const ACCESS_BATCH: usize = 256;
+
+/// Metadata records buffered in memory by `MetadataStore::with_batching`
+struct WriteBatch {
+    db: Db,
+    /// The store's `snapshot_lock`
+    snapshot_lock: Arc<RwLock<()>>,
+    max_records: usize,
+    /// Serialized record, owning tenant and checksum of each buffered blob
+    records: Mutex<HashMap<BlobId, BufferedRecord>>,
+}
+
+impl WriteBatch {
+    /// Writes every buffered record and membership to the db in one
+    /// transaction, adjusting usage counters together with the records
+    fn apply(&self) -> Result<()> {
+        // Hold the lock until the batch lands, so readers always find each
+        // record either buffered or in the db
+        let mut records = self.records.lock().unwrap();
+        if records.is_empty() {
+            return Ok(());
+        }
+        let key_scheme = KeyScheme::load(&self.db)?;
+        let size_index = self.db.contains_key(SIZE_INDEX_KEY)?;
+        let _snapshot = self.snapshot_lock.read().unwrap();
+        self.db.transaction(|tx| {
+            for (blob_id, record) in records.iter() {
+                let previous = tx.insert(key_scheme.blob_key(blob_id), record.metadata_json.as_slice())?;
+                if let Some(previous) = previous {
+                    let previous: IndexedRecord = serde_json::from_slice(&previous)
+                        .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                    for (key, value) in previous.tags.iter() {
+                        tx.remove(MetadataStore::tag_key(key, value, blob_id))?;
+                    }
+                    if size_index {
+                        tx.remove(MetadataStore::size_key(&previous.tenant_id, previous.size, blob_id))?;
+                    }
+                    MetadataStore::adjust_usage(tx, &previous.tenant_id, 0, previous.size)?;
+                }
+                MetadataStore::adjust_usage(tx, &record.tenant_id, record.size, 0)?;
+                MetadataStore::add_member(tx, &record.tenant_id, blob_id)?;
+                tx.insert(MetadataStore::checksum_key(&record.checksum, blob_id), &[])?;
+                if size_index {
+                    tx.insert(MetadataStore::size_key(&record.tenant_id, record.size, blob_id), &[])?;
+                }
+                for (key, value) in record.tags.iter() {
+                    tx.insert(MetadataStore::tag_key(key, value, blob_id), &[])?;
+                }
+            }
+            Ok(())
+        })?;
+        records.clear();
+        Ok(())
+    }
+}
 
 pub struct MetadataStore {
     db: Db,
+    /// Number of blob metadata records read, for instrumentation
+    reads: AtomicU64,
+    /// Buffered writes, when batching is enabled
+    batch: Option<Arc<WriteBatch>>,
+    /// Stops the thread that periodically applies the batch
+    flusher: Option<(Sender<()>, JoinHandle<()>)>,
+    /// Latest read time of each blob read since access times were last written
+    accesses: Mutex<HashMap<BlobId, DateTime<Utc>>>,
+    /// True once records are keyed by `KeyScheme::Hashed`
+    hashed_keys: AtomicBool,
+    /// True once each tenant's blobs are indexed by size
+    size_index: AtomicBool,
+    /// Held shared by every write and exclusively by `snapshot`, so a
+    /// backup never sees part of a write
+    snapshot_lock: Arc<RwLock<()>>,
+    /// Added to the db's id counter to make sequence numbers, so a restored
+    /// store numbers new blobs after the ones it was restored with
+    seq_floor: AtomicU64,
+    /// Path the store was opened read-only from, if it was
+    read_only: Option<PathBuf>,
+    /// Private copy of a db on a read-only filesystem, removed on drop.
+    /// Declared last so the db using it is dropped first.
+    _snapshot: Option<tempfile::TempDir>,
 }
 
 impl MetadataStore {
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
-        let db = sled::open(path)?;
-        Ok(Self { db })
+        let store = Self::from_db(sled::open(&path).map_err(read_only_at(path.as_ref()))?)?;
+        store.count_usage()?;
+        store.count_blobs()?;
+        Ok(store)
+    }
+
+    /// Opens a throwaway db that sled deletes when it is dropped
+    pub fn temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    /// Opens an existing store for reading only. Every write fails with
+    /// `ReadOnly`, and reads don't record access times.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (db, snapshot) = crate::layout::open_db_read_only(path)?;
+        let mut store = Self::from_db(db)?;
+        store.read_only = Some(path.to_path_buf());
+        store._snapshot = snapshot;
+        Ok(store)
+    }
+
+    fn from_db(db: Db) -> Result<Self> {
+        let hashed_keys = KeyScheme::load(&db)? == KeyScheme::Hashed;
+        let size_index = db.contains_key(SIZE_INDEX_KEY)?;
+        let seq_floor = Self::load_seq_floor(&db)?;
+        Ok(Self {
+            db,
+            reads: AtomicU64::new(0),
+            batch: None,
+            flusher: None,
+            accesses: Mutex::new(HashMap::new()),
+            hashed_keys: AtomicBool::new(hashed_keys),
+            size_index: AtomicBool::new(size_index),
+            snapshot_lock: Arc::new(RwLock::new(())),
+            seq_floor: AtomicU64::new(seq_floor),
+            read_only: None,
+            _snapshot: None,
+        })
+    }
+
+    /// Fails with `ReadOnly` if the store was opened read-only
+    fn check_writable(&self) -> Result<()> {
+        match &self.read_only {
+            Some(path) => Err(Error::ReadOnly(path.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Holds off `snapshot` until the returned guard is dropped
+    fn hold_snapshot(&self) -> RwLockReadGuard<'_, ()> {
+        self.snapshot_lock.read().unwrap()
+    }
+
+    /// Runs `f` on the db with every metadata write held off, after applying
+    /// batched writes and access times, so it sees one consistent state
+    pub(crate) fn snapshot<R>(&self, f: impl FnOnce(&Db) -> Result<R>) -> Result<R> {
+        self.apply_batch()?;
+        self.apply_accesses()?;
+        let _writes = self.snapshot_lock.write().unwrap();
+        f(&self.db)
+    }
+
+    /// Keys blob metadata records by `KeyScheme::Hashed`, rewriting any
+    /// stored under the raw scheme in one atomic batch. The choice is
+    /// persisted, so later opens use hashed keys without asking.
+    pub fn with_hashed_keys(self) -> Result<Self> {
+        if self.key_scheme() == KeyScheme::Hashed {
+            return Ok(self);
+        }
+        self.check_writable()?;
+        self.apply_batch()?;
+        let mut batch = sled::Batch::default();
+        let mut migrated = 0;
+        for entry in self.db.scan_prefix(BLOB_PREFIX) {
+            let (key, value) = entry?;
+            let Some(blob_id) = Self::parse_blob_id(&key[BLOB_PREFIX.len()..]) else {
+                continue;
+            };
+            batch.remove(key);
+            batch.insert(KeyScheme::Hashed.blob_key(&blob_id), value);
+            migrated += 1;
+        }
+        batch.insert(KEY_SCHEME_KEY, b"hashed".as_slice());
+        self.db.apply_batch(batch)?;
+        self.hashed_keys.store(true, Ordering::Relaxed);
+        tracing::info!(migrated, "switched blob metadata to hashed keys");
+        Ok(self)
+    }
+
+    /// Builds every tenant's usage counter from its blob records, unless
+    /// that has been done before. Runs at open, before any other writer.
+    /// An empty store is left untouched, so it can still take a dump.
+    fn count_usage(&self) -> Result<()> {
+        if self.db.contains_key(USAGE_COUNTED_KEY)? {
+            return Ok(());
+        }
+        let mut usage: HashMap<Vec<u8>, u64> = HashMap::new();
+        for entry in self.db.scan_prefix(BLOB_PREFIX) {
+            let (key, value) = entry?;
+            match serde_json::from_slice::<IndexedBlob>(&value) {
+                Ok(record) => *usage.entry(Self::usage_key(&record.tenant_id)).or_default() += record.size,
+                Err(e) => tracing::warn!(
+                    key = %String::from_utf8_lossy(&key),
+                    error = %e,
+                    "skipping undecodable blob record while counting usage"
+                ),
+            }
+        }
+        if usage.is_empty() {
+            return Ok(());
+        }
+        let mut batch = sled::Batch::default();
+        for (usage_key, total) in &usage {
+            batch.insert(usage_key.as_slice(), &total.to_be_bytes());
+        }
+        batch.insert(USAGE_COUNTED_KEY, &[]);
+        self.db.apply_batch(batch)?;
+        tracing::info!(tenants = usage.len(), "built tenant usage counters from existing records");
+        Ok(())
+    }
+
+    /// Builds every tenant's blob counter from its membership keys, unless
+    /// that has been done before. Runs at open, before any other writer.
+    /// An empty store is left untouched, so it can still take a dump.
+    fn count_blobs(&self) -> Result<()> {
+        if self.db.contains_key(BLOBS_COUNTED_KEY)? {
+            return Ok(());
+        }
+        let mut counts: HashMap<Vec<u8>, u64> = HashMap::new();
+        for key in self.db.scan_prefix("tenant:").keys() {
+            let key = key?;
+            let key = String::from_utf8_lossy(&key);
+            if let Some((tenant_key, _)) = key["tenant:".len()..].rsplit_once(":member:") {
+                *counts.entry(Self::blob_count_key(tenant_key)).or_default() += 1;
+            }
+        }
+        if counts.is_empty() {
+            return Ok(());
+        }
+        let mut batch = sled::Batch::default();
+        for (count_key, count) in &counts {
+            batch.insert(count_key.as_slice(), &count.to_be_bytes());
+        }
+        batch.insert(BLOBS_COUNTED_KEY, &[]);
+        self.db.apply_batch(batch)?;
+        tracing::info!(tenants = counts.len(), "built tenant blob counters from existing memberships");
+        Ok(())
+    }
+
+    /// Indexes each tenant's blobs by size, building the index from existing
+    /// records. Persisted, so every later write keeps it up to date.
+    pub fn with_size_index(self) -> Result<Self> {
+        if self.has_size_index() {
+            return Ok(self);
+        }
+        self.check_writable()?;
+        self.apply_batch()?;
+        let mut batch = sled::Batch::default();
+        for value in self.db.scan_prefix(BLOB_PREFIX).values() {
+            let record: IndexedBlob = serde_json::from_slice(&value?)?;
+            batch.insert(Self::size_key(&record.tenant_id, record.size, &record.blob_id), &[]);
+        }
+        batch.insert(SIZE_INDEX_KEY, &[]);
+        self.db.apply_batch(batch)?;
+        self.size_index.store(true, Ordering::Relaxed);
+        Ok(self)
+    }
+
+    /// Returns the algorithm new blobs' checksums are computed with
+    pub fn checksum_algorithm(&self) -> Result<ChecksumAlgorithm> {
+        let stored = self.db.get(CHECKSUM_ALGORITHM_KEY)?;
+        let name = stored.as_deref().map(String::from_utf8_lossy);
+        Ok(name.and_then(|name| ChecksumAlgorithm::from_name(&name)).unwrap_or(ChecksumAlgorithm::Sha256))
+    }
+
+    /// Persists the algorithm new blobs' checksums are computed with
+    pub fn set_checksum_algorithm(&self, algorithm: ChecksumAlgorithm) -> Result<()> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        self.db.insert(CHECKSUM_ALGORITHM_KEY, algorithm.name().as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns true if blobs are indexed by size
+    pub fn has_size_index(&self) -> bool {
+        self.size_index.load(Ordering::Relaxed)
+    }
+
+    /// Returns how blob metadata records are keyed
+    pub fn key_scheme(&self) -> KeyScheme {
+        if self.hashed_keys.load(Ordering::Relaxed) {
+            KeyScheme::Hashed
+        } else {
+            KeyScheme::Raw
+        }
+    }
+
+    /// Buffers `put_metadata` writes in memory and applies them to the db
+    /// together once `max_records` are pending or every `interval`, for
+    /// high-throughput ingestion. Buffered records are served to readers;
+    /// `flush` applies them immediately. Writes still buffered when the
+    /// process crashes are lost.
+    pub fn with_batching(mut self, max_records: usize, interval: Duration) -> Self {
+        self.stop_flusher();
+        let batch = Arc::new(WriteBatch {
+            db: self.db.clone(),
+            snapshot_lock: Arc::clone(&self.snapshot_lock),
+            max_records: max_records.max(1),
+            records: Mutex::new(HashMap::new()),
+        });
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let pending = Arc::clone(&batch);
+        let thread = thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(e) = pending.apply() {
+                        tracing::warn!(error = %e, "failed to apply batched metadata writes");
+                    }
+                }
+                _ => return,
+            }
+        });
+
+        self.batch = Some(batch);
+        self.flusher = Some((stop, thread));
+        self
+    }
+
+    /// Stops the batch flusher thread, if one is running
+    fn stop_flusher(&mut self) {
+        if let Some((stop, thread)) = self.flusher.take() {
+            let _ = stop.send(());
+            let _ = thread.join();
+        }
+    }
+
+    /// Applies any buffered writes to the db, without forcing them to disk
+    fn apply_batch(&self) -> Result<()> {
+        match &self.batch {
+            Some(batch) => batch.apply(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns how many blob metadata records have been read from the db
+    pub fn metadata_reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// Creates the legacy metadata key holding a tenant's blob list as one JSON array
+    fn legacy_tenant_key(tenant_id: &TenantId) -> Vec<u8> {
+        format!("tenant:{}:blobs", tenant_id.key()).into_bytes()
+    }
+
+    /// Creates the key prefix under which a tenant's blob memberships are stored
+    fn member_prefix(tenant_id: &TenantId) -> Vec<u8> {
+        format!("tenant:{}:member:", tenant_id.key()).into_bytes()
+    }
+
+    /// Creates a metadata key recording that a tenant owns a blob
+    fn member_key(tenant_id: &TenantId, blob_id: &BlobId) -> Vec<u8> {
+        let mut key = Self::member_prefix(tenant_id);
+        key.extend_from_slice(blob_id.to_string().as_bytes());
+        key
+    }
+
+    /// Creates the key prefix under which a tenant's size index is stored
+    fn size_prefix(tenant_id: &TenantId) -> Vec<u8> {
+        format!("tenant:{}:size:", tenant_id.key()).into_bytes()
+    }
+
+    /// Creates a size index key, zero-padded so keys sort by size
+    fn size_key(tenant_id: &TenantId, size: u64, blob_id: &BlobId) -> Vec<u8> {
+        let mut key = Self::size_prefix(tenant_id);
+        key.extend_from_slice(format!("{:020}:{}", size, blob_id).as_bytes());
+        key
+    }
+
+    /// Creates a metadata key for a blob under the store's key scheme
+    fn blob_key(&self, blob_id: &BlobId) -> Vec<u8> {
+        self.key_scheme().blob_key(blob_id)
+    }
+
+    /// Creates the key prefix indexing every blob with a given checksum
+    fn checksum_prefix(checksum: &Checksum) -> Vec<u8> {
+        format!("checksum:{}:", checksum.to_compact_string()).into_bytes()
+    }
+
+    /// Creates a checksum index key recording that a blob has a checksum
+    fn checksum_key(checksum: &Checksum, blob_id: &BlobId) -> Vec<u8> {
+        let mut key = Self::checksum_prefix(checksum);
+        key.extend_from_slice(blob_id.to_string().as_bytes());
+        key
+    }
+
+    /// Creates the key prefix indexing every blob with a given tag value. The
+    /// key and value are NUL-terminated so neither can run into the other.
+    fn tag_prefix(key: &str, value: &str) -> Vec<u8> {
+        format!("tag:{}\0{}\0", key, value).into_bytes()
+    }
+
+    /// Creates a tag index key recording that a blob carries a tag value
+    fn tag_key(key: &str, value: &str, blob_id: &BlobId) -> Vec<u8> {
+        let mut index_key = Self::tag_prefix(key, value);
+        index_key.extend_from_slice(blob_id.to_string().as_bytes());
+        index_key
+    }
+
+    /// Creates a metadata key binding a friendly name to one of a tenant's blobs
+    fn name_key(tenant_id: &TenantId, name: &str) -> Vec<u8> {
+        format!("tenant:{}:name:{}", tenant_id.key(), name).into_bytes()
+    }
+
+    /// Creates a metadata key holding every blob a versioned name has pointed at
+    fn versions_key(tenant_id: &TenantId, name: &str) -> Vec<u8> {
+        format!("tenant:{}:versions:{}", tenant_id.key(), name).into_bytes()
+    }
+
+    /// Creates a metadata key recording which blob a client idempotency key produced
+    fn idempotency_key(tenant_id: &TenantId, key: &str) -> Vec<u8> {
+        format!("tenant:{}:idempotency:{}", tenant_id.key(), key).into_bytes()
+    }
+
+    /// Creates a metadata key holding the idempotency key that produced a blob
+    fn idempotent_blob_key(blob_id: &BlobId) -> Vec<u8> {
+        [IDEMPOTENT_BLOB_PREFIX, blob_id.to_string().as_bytes()].concat()
     }
 
-    /// Creates a metadata key for a tenant's blob list
-    fn tenant_key(tenant_id: &TenantId) -> Vec<u8> {
-        format!("tenant:{}:blobs", tenant_id.as_str()).into_bytes()
+    /// Creates a metadata key holding the running total of a tenant's blob sizes
+    fn usage_key(tenant_id: &TenantId) -> Vec<u8> {
+        format!("usage:{}", tenant_id.key()).into_bytes()
     }
 
-    /// Creates a metadata key for a blob
-    fn blob_key(blob_id: &BlobId) -> Vec<u8> {
-        format!("blob:{}", blob_id.to_string()).into_bytes()
+    /// Creates a metadata key holding the number of blobs a tenant owns, from
+    /// the tenant's `TenantId::key`
+    fn blob_count_key(tenant_key: &str) -> Vec<u8> {
+        format!("blobs:{}", tenant_key).into_bytes()
+    }
+
+    /// Parses a stored usage counter, treating a malformed one as zero
+    fn decode_usage(bytes: &[u8]) -> u64 {
+        bytes.try_into().map_or(0, u64::from_be_bytes)
+    }
+
+    /// Creates a metadata key reserving the id of a blob whose upload hasn't finished
+    fn pending_key(blob_id: &BlobId) -> Vec<u8> {
+        format!("pending:{}", blob_id).into_bytes()
+    }
+
+    /// Creates a metadata key marking a deleted blob
+    fn tombstone_key(blob_id: &BlobId) -> Vec<u8> {
+        [TOMBSTONE_PREFIX, blob_id.to_string().as_bytes()].concat()
+    }
+
+    /// Creates a metadata key recording an overwrite staged under `staged`
+    fn replacing_key(staged: &BlobId) -> Vec<u8> {
+        [REPLACING_PREFIX, staged.to_string().as_bytes()].concat()
+    }
+
+    /// Parses a blob id from the tail of a key
+    fn parse_blob_id(bytes: &[u8]) -> Option<BlobId> {
+        let id = std::str::from_utf8(bytes).ok()?;
+        uuid::Uuid::parse_str(id).ok().map(BlobId)
+    }
+
+    /// Converts a tenant's legacy JSON blob list into membership keys, if one
+    /// exists. A list that no longer parses is rebuilt from the blob records.
+    fn migrate_legacy_list(&self, tenant_id: &TenantId) -> Result<()> {
+        if self.read_only.is_some() {
+            return Ok(());
+        }
+        let legacy_key = Self::legacy_tenant_key(tenant_id);
+        if let Some(bytes) = self.db.get(&legacy_key)? {
+            let blob_list: Vec<BlobId> = match serde_json::from_slice(&bytes) {
+                Ok(blob_list) => blob_list,
+                Err(e) => {
+                    tracing::warn!(
+                        tenant = tenant_id.as_str(),
+                        error = %e,
+                        "tenant blob list is corrupt, rebuilding it from blob records"
+                    );
+                    self.rebuild_tenant_list(tenant_id)?;
+                    return Ok(());
+                }
+            };
+            let _snapshot = self.hold_snapshot();
+            self.db.transaction(|tx| {
+                for blob_id in &blob_list {
+                    Self::add_member(tx, tenant_id, blob_id)?;
+                }
+                tx.remove(legacy_key.as_slice())?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds a tenant's blob list by scanning every blob record for ones it
+    /// owns, persisting the memberships and dropping any legacy list
+    pub fn rebuild_tenant_list(&self, tenant_id: &TenantId) -> Result<Vec<BlobId>> {
+        self.check_writable()?;
+        self.apply_batch()?;
+        let mut blob_list = Vec::new();
+        for value in self.db.scan_prefix(BLOB_PREFIX).values() {
+            let Ok(metadata) = serde_json::from_slice::<BlobMetadata>(&value?) else {
+                continue;
+            };
+            if metadata.tenant_id == *tenant_id {
+                blob_list.push(metadata.blob_id);
+            }
+        }
+        let legacy_key = Self::legacy_tenant_key(tenant_id);
+        let _snapshot = self.hold_snapshot();
+        self.db.transaction(|tx| {
+            for blob_id in &blob_list {
+                Self::add_member(tx, tenant_id, blob_id)?;
+            }
+            tx.remove(legacy_key.as_slice())?;
+            Ok(())
+        })?;
+        Ok(blob_list)
+    }
+
+    /// Returns the size of the metadata db's files on disk
+    pub fn size_on_disk(&self) -> Result<u64> {
+        Ok(self.db.size_on_disk()?)
+    }
+
+    /// Flushes all dirty state to disk, including batched writes, letting
+    /// sled rewrite and release log segments that only hold overwritten or
+    /// deleted records
+    pub fn flush(&self) -> Result<()> {
+        self.apply_batch()?;
+        self.apply_accesses()?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Writes the whole metadata db, including batched writes and access
+    /// times not yet applied, as a raw dump for `import_raw`. Unlike a JSON
+    /// export this preserves every key exactly, indexes included.
+    pub fn export_raw(&self, writer: impl std::io::Write) -> Result<()> {
+        self.snapshot(|db| crate::dump::export_db(db, writer))
+    }
+
+    /// Restores a dump written by `export_raw` into this store, which must
+    /// be empty. The whole dump is read before anything is written.
+    pub fn import_raw(&self, reader: impl std::io::Read) -> Result<()> {
+        self.load_raw(crate::dump::read_dump(reader)?)
+    }
+
+    /// Fails unless a dump can be loaded into this store
+    pub(crate) fn check_restorable(&self) -> Result<()> {
+        self.check_writable()?;
+        self.apply_batch()?;
+        crate::dump::check_empty(&self.db)
+    }
+
+    /// Loads a dump read by `dump::read_dump` into this store, which must
+    /// be empty
+    pub(crate) fn load_raw(&self, dump: crate::dump::Dump) -> Result<()> {
+        self.check_restorable()?;
+        dump.load(&self.db)?;
+        self.hashed_keys.store(KeyScheme::load(&self.db)? == KeyScheme::Hashed, Ordering::Relaxed);
+        self.size_index.store(self.db.contains_key(SIZE_INDEX_KEY)?, Ordering::Relaxed);
+        self.count_usage()?;
+        self.count_blobs()?;
+
+        // The id counter isn't part of a dump, so raise the floor past the
+        // restored records for new blobs to keep sorting after them
+        let mut max_seq = None;
+        for entry in self.db.scan_prefix(BLOB_PREFIX) {
+            let (key, value) = entry?;
+            match serde_json::from_slice::<SizeRecord>(&value) {
+                Ok(record) => max_seq = max_seq.max(Some(record.seq)),
+                Err(e) => tracing::warn!(
+                    key = %String::from_utf8_lossy(&key),
+                    error = %e,
+                    "skipping undecodable blob record while restoring"
+                ),
+            }
+        }
+        if let Some(max_seq) = max_seq {
+            self.db.insert(SEQ_FLOOR_KEY, &(max_seq + 1).to_be_bytes())?;
+        }
+        self.seq_floor.store(Self::load_seq_floor(&self.db)?, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Reads the lowest sequence number new blobs may get
+    fn load_seq_floor(db: &Db) -> Result<u64> {
+        let floor = db.get(SEQ_FLOOR_KEY)?;
+        Ok(floor.map_or(0, |bytes| (*bytes).try_into().map_or(0, u64::from_be_bytes)))
+    }
+
+    /// Returns the next insertion sequence number. The counter is persisted
+    /// and strictly increasing across restarts.
+    pub fn next_seq(&self) -> Result<u64> {
+        self.check_writable()?;
+        Ok(self.db.generate_id()? + self.seq_floor.load(Ordering::Relaxed))
+    }
+
+    /// Runs `f` as a single transaction over the metadata db: either all of
+    /// its writes land or none do. Aborting with an error rolls it back.
+    pub fn transaction<F, R>(&self, f: F) -> Result<R>
+    where
+        F: Fn(&TransactionalTree) -> ConflictableTransactionResult<R, Error>,
+    {
+        self.check_writable()?;
+        self.apply_batch()?;
+        let _snapshot = self.hold_snapshot();
+        Ok(self.db.transaction(f)?)
+    }
+
+    /// Writes a blob's metadata record, tenant membership, checksum and tag
+    /// index entries and usage within a transaction
+    fn write_metadata(
+        &self,
+        tx: &TransactionalTree,
+        metadata: &BlobMetadata,
+        metadata_json: &[u8],
+    ) -> ConflictableTransactionResult<(), Error> {
+        let previous = tx.insert(self.blob_key(&metadata.blob_id), metadata_json)?;
+        Self::add_member(tx, &metadata.tenant_id, &metadata.blob_id)?;
+        tx.insert(Self::checksum_key(&metadata.checksum, &metadata.blob_id), &[])?;
+        let previous_tags = match previous {
+            Some(bytes) => {
+                let previous: IndexedRecord = serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                Self::adjust_usage(tx, &previous.tenant_id, 0, previous.size)?;
+                if self.has_size_index() {
+                    tx.remove(Self::size_key(&previous.tenant_id, previous.size, &metadata.blob_id))?;
+                }
+                previous.tags
+            }
+            None => Tags::new(),
+        };
+        Self::adjust_usage(tx, &metadata.tenant_id, metadata.size, 0)?;
+        if self.has_size_index() {
+            tx.insert(Self::size_key(&metadata.tenant_id, metadata.size, &metadata.blob_id), &[])?;
+        }
+        Self::index_tags(tx, &metadata.blob_id, &previous_tags, &metadata.tags)
+    }
+
+    /// Adds `added` bytes to a tenant's usage counter and takes away `removed`
+    fn adjust_usage(
+        tx: &TransactionalTree,
+        tenant_id: &TenantId,
+        added: u64,
+        removed: u64,
+    ) -> ConflictableTransactionResult<(), Error> {
+        Self::adjust_counter(tx, Self::usage_key(tenant_id), added, removed)
+    }
+
+    /// Adds `added` to the counter stored under `key` and takes away `removed`
+    fn adjust_counter(
+        tx: &TransactionalTree,
+        key: Vec<u8>,
+        added: u64,
+        removed: u64,
+    ) -> ConflictableTransactionResult<(), Error> {
+        if added == removed {
+            return Ok(());
+        }
+        let current = tx.get(&key)?.map_or(0, |bytes| Self::decode_usage(&bytes));
+        let updated = current.saturating_add(added).saturating_sub(removed);
+        tx.insert(key, &updated.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Records that a tenant owns a blob, counting it if it is new
+    fn add_member(
+        tx: &TransactionalTree,
+        tenant_id: &TenantId,
+        blob_id: &BlobId,
+    ) -> ConflictableTransactionResult<(), Error> {
+        if tx.insert(Self::member_key(tenant_id, blob_id), &[])?.is_none() {
+            Self::adjust_counter(tx, Self::blob_count_key(&tenant_id.key()), 1, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Removes a tenant's membership of a blob, returning false if there was
+    /// none
+    fn remove_member(
+        tx: &TransactionalTree,
+        tenant_id: &TenantId,
+        blob_id: &BlobId,
+    ) -> ConflictableTransactionResult<bool, Error> {
+        if tx.remove(Self::member_key(tenant_id, blob_id))?.is_none() {
+            return Ok(false);
+        }
+        Self::adjust_counter(tx, Self::blob_count_key(&tenant_id.key()), 0, 1)?;
+        Ok(true)
+    }
+
+    /// Fails with `BlobLimitReached` if adding `blob_id` to its tenant would
+    /// take the tenant past `limit` blobs
+    fn check_blob_limit(
+        tx: &TransactionalTree,
+        tenant_id: &TenantId,
+        blob_id: &BlobId,
+        limit: usize,
+    ) -> ConflictableTransactionResult<(), Error> {
+        if tx.get(Self::member_key(tenant_id, blob_id))?.is_some() {
+            return Ok(());
+        }
+        let count = tx.get(Self::blob_count_key(&tenant_id.key()))?.map_or(0, |bytes| Self::decode_usage(&bytes));
+        if count >= limit as u64 {
+            return Err(ConflictableTransactionError::Abort(Error::BlobLimitReached {
+                tenant: tenant_id.as_str().to_string(),
+                limit,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Moves a blob's usage from its `old` record's tenant and size to its
+    /// `new` one's
+    fn reaccount_usage(
+        tx: &TransactionalTree,
+        old: &BlobMetadata,
+        new: &BlobMetadata,
+    ) -> ConflictableTransactionResult<(), Error> {
+        if old.tenant_id == new.tenant_id {
+            return Self::adjust_usage(tx, &new.tenant_id, new.size, old.size);
+        }
+        Self::adjust_usage(tx, &old.tenant_id, 0, old.size)?;
+        Self::adjust_usage(tx, &new.tenant_id, new.size, 0)
+    }
+
+    /// Moves a blob's size index entry from its `old` record's tenant and
+    /// size to its `new` one's, if blobs are indexed by size
+    fn reindex_size(
+        &self,
+        tx: &TransactionalTree,
+        old: &BlobMetadata,
+        new: &BlobMetadata,
+    ) -> ConflictableTransactionResult<(), Error> {
+        if self.has_size_index() && (old.tenant_id != new.tenant_id || old.size != new.size) {
+            tx.remove(Self::size_key(&old.tenant_id, old.size, &old.blob_id))?;
+            tx.insert(Self::size_key(&new.tenant_id, new.size, &new.blob_id), &[])?;
+        }
+        Ok(())
+    }
+
+    /// Moves a blob's checksum index entry to its `new` record's checksum
+    fn reindex_checksum(
+        tx: &TransactionalTree,
+        old: &BlobMetadata,
+        new: &BlobMetadata,
+    ) -> ConflictableTransactionResult<(), Error> {
+        if old.checksum != new.checksum {
+            tx.remove(Self::checksum_key(&old.checksum, &old.blob_id))?;
+            tx.insert(Self::checksum_key(&new.checksum, &new.blob_id), &[])?;
+        }
+        Ok(())
+    }
+
+    /// Replaces a blob's tag index entries for its `old` tags with ones for
+    /// its `new` tags
+    fn index_tags(
+        tx: &TransactionalTree,
+        blob_id: &BlobId,
+        old: &Tags,
+        new: &Tags,
+    ) -> ConflictableTransactionResult<(), Error> {
+        for (key, value) in old.iter().filter(|(key, value)| !new.contains(key, value)) {
+            tx.remove(Self::tag_key(key, value, blob_id))?;
+        }
+        for (key, value) in new.iter() {
+            tx.insert(Self::tag_key(key, value, blob_id), &[])?;
+        }
+        Ok(())
     }
 
     /// Stores blob metadata
     pub fn put_metadata(&self, metadata: &BlobMetadata) -> Result<()> {
-        let blob_key = Self::blob_key(&metadata.blob_id);
-        let tenant_key = Self::tenant_key(&metadata.tenant_id);
+        self.check_writable()?;
+        self.migrate_legacy_list(&metadata.tenant_id)?;
 
-        // Store the blob metadata
+        // Store the metadata and membership together
         let metadata_json = serde_json::to_vec(metadata)?;
-        self.db.insert(blob_key, metadata_json)?;
+        if let Some(batch) = &self.batch {
+            let pending = {
+                let mut records = batch.records.lock().unwrap();
+                let record = BufferedRecord {
+                    tenant_id: metadata.tenant_id.clone(),
+                    size: metadata.size,
+                    checksum: metadata.checksum.clone(),
+                    tags: metadata.tags.clone(),
+                    metadata_json,
+                };
+                records.insert(metadata.blob_id.clone(), record);
+                records.len()
+            };
+            if pending >= batch.max_records {
+                batch.apply()?;
+            }
+            return Ok(());
+        }
+        self.transaction(|tx| self.write_metadata(tx, metadata, &metadata_json))
+    }
 
-        // Update the tenant's blob list
-        let mut blob_list = self.get_tenant_blobs(&metadata.tenant_id)?;
-        if !blob_list.contains(&metadata.blob_id) {
-            blob_list.push(metadata.blob_id.clone());
-            let blob_list_json = serde_json::to_vec(&blob_list)?;
-            self.db.insert(tenant_key, blob_list_json)?;
+    /// Stores blob metadata unless a record with its id already exists,
+    /// returning whether it was stored
+    pub fn insert_metadata(&self, metadata: &BlobMetadata) -> Result<bool> {
+        self.check_writable()?;
+        self.migrate_legacy_list(&metadata.tenant_id)?;
+        let metadata_json = serde_json::to_vec(metadata)?;
+        let blob_key = self.blob_key(&metadata.blob_id);
+        self.transaction(|tx| {
+            if tx.get(&blob_key)?.is_some() {
+                return Ok(false);
+            }
+            self.write_metadata(tx, metadata, &metadata_json)?;
+            Ok(true)
+        })
+    }
+
+    /// Stores several records, together with their memberships, in one
+    /// transaction. With batching on they are buffered like `put_metadata`.
+    pub fn put_metadata_many(&self, records: &[BlobMetadata]) -> Result<()> {
+        self.put_metadata_many_within(records, None)
+    }
+
+    /// Stores several records like `put_metadata_many`, failing with
+    /// `BlobLimitReached` and storing none of them if that would give a
+    /// tenant more than `limit` blobs. The limit is checked in the writing
+    /// transaction, so concurrent writers can't overshoot it, and records
+    /// are never buffered while one is set.
+    pub fn put_metadata_many_within(&self, records: &[BlobMetadata], limit: Option<usize>) -> Result<()> {
+        self.check_writable()?;
+        if self.batch.is_some() && limit.is_none() {
+            return records.iter().try_for_each(|metadata| self.put_metadata(metadata));
+        }
+        let mut encoded = Vec::with_capacity(records.len());
+        for metadata in records {
+            self.migrate_legacy_list(&metadata.tenant_id)?;
+            encoded.push((metadata, serde_json::to_vec(metadata)?));
         }
+        self.transaction(|tx| {
+            encoded.iter().try_for_each(|(metadata, metadata_json)| {
+                if let Some(limit) = limit {
+                    Self::check_blob_limit(tx, &metadata.tenant_id, &metadata.blob_id, limit)?;
+                }
+                self.write_metadata(tx, metadata, metadata_json)
+            })
+        })
+    }
+
+    /// Replaces the record of an existing blob with `metadata` in one
+    /// transaction, failing with `ConcurrentModification` if the record is no
+    /// longer at `expected_generation`. If the new data was staged under
+    /// another id, `staged` is recorded alongside until `finish_replace`, so
+    /// an interrupted overwrite can be completed.
+    pub fn replace_metadata(
+        &self,
+        metadata: &BlobMetadata,
+        expected_generation: u64,
+        staged: Option<&BlobId>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let blob_key = self.blob_key(&metadata.blob_id);
+        let metadata_json = serde_json::to_vec(metadata)?;
+        self.transaction(|tx| {
+            let old: BlobMetadata = match tx.get(&blob_key)? {
+                Some(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?,
+                None => {
+                    return Err(ConflictableTransactionError::Abort(Error::BlobNotFound(
+                        metadata.blob_id.to_string(),
+                    )))
+                }
+            };
+            if old.generation != expected_generation {
+                return Err(ConflictableTransactionError::Abort(Error::ConcurrentModification {
+                    blob_id: metadata.blob_id.to_string(),
+                    expected: expected_generation,
+                    actual: old.generation,
+                }));
+            }
+            if old.checksum != metadata.checksum {
+                tx.remove(Self::checksum_key(&old.checksum, &old.blob_id))?;
+            }
+            if old.tenant_id != metadata.tenant_id {
+                Self::remove_member(tx, &old.tenant_id, &old.blob_id)?;
+            }
+            self.write_metadata(tx, metadata, &metadata_json)?;
+            if let Some(staged) = staged {
+                tx.insert(Self::replacing_key(staged), metadata.blob_id.to_string().as_bytes())?;
+            }
+            Ok(())
+        })
+    }
 
+    /// Forgets an overwrite once its staged data is in place
+    pub fn finish_replace(&self, staged: &BlobId) -> Result<()> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        self.db.remove(Self::replacing_key(staged))?;
         Ok(())
     }
 
+    /// Lists overwrites whose staged data may not be in place yet, as
+    /// pairs of the staged id and the blob it replaces
+    pub fn unfinished_replaces(&self) -> Result<Vec<(BlobId, BlobId)>> {
+        let mut replaces = Vec::new();
+        for entry in self.db.scan_prefix(REPLACING_PREFIX) {
+            let (key, value) = entry?;
+            if let (Some(staged), Some(blob_id)) =
+                (Self::parse_blob_id(&key[REPLACING_PREFIX.len()..]), Self::parse_blob_id(&value))
+            {
+                replaces.push((staged, blob_id));
+            }
+        }
+        Ok(replaces)
+    }
+
+    /// Transfers a blob from `from_tenant` to another tenant, updating its
+    /// record and both tenants' membership sets atomically. Ownership and
+    /// retention are checked in the same transaction: a blob owned by
+    /// another tenant fails with `InvalidTenant`, and one retained past
+    /// `now` with `RetentionActive`.
+    pub fn move_blob(
+        &self,
+        blob_id: &BlobId,
+        from_tenant: &TenantId,
+        to_tenant: &TenantId,
+        now: DateTime<Utc>,
+    ) -> Result<BlobMetadata> {
+        self.check_writable()?;
+        self.migrate_legacy_list(from_tenant)?;
+        self.migrate_legacy_list(to_tenant)?;
+
+        let blob_key = self.blob_key(blob_id);
+        self.transaction(|tx| {
+            let abort = |e: Error| ConflictableTransactionError::Abort(e);
+            let bytes = tx.get(&blob_key)?.ok_or_else(|| abort(Error::BlobNotFound(blob_id.to_string())))?;
+            let mut metadata: BlobMetadata = serde_json::from_slice(&bytes).map_err(|e| abort(e.into()))?;
+            if metadata.tenant_id != *from_tenant {
+                return Err(abort(Error::InvalidTenant("Blob does not belong to this tenant".into())));
+            }
+            if let Some(retain_until) = metadata.retain_until.filter(|retain_until| *retain_until > now) {
+                return Err(abort(Error::RetentionActive { blob_id: blob_id.to_string(), retain_until }));
+            }
+            metadata.tenant_id = to_tenant.clone();
+            metadata.generation += 1;
+            let metadata_json = serde_json::to_vec(&metadata).map_err(|e| abort(e.into()))?;
+            Self::remove_member(tx, from_tenant, blob_id)?;
+            self.write_metadata(tx, &metadata, &metadata_json)?;
+            Ok(metadata)
+        })
+    }
+
+    /// Applies `update` to a blob's metadata record within a transaction,
+    /// bumping its generation. `update` returns false to leave the record
+    /// unchanged. Returns the updated record, or `None` if the blob is gone
+    /// or nothing changed.
+    pub fn update_metadata<F>(&self, blob_id: &BlobId, update: F) -> Result<Option<BlobMetadata>>
+    where
+        F: Fn(&mut BlobMetadata) -> bool,
+    {
+        let blob_key = self.blob_key(blob_id);
+        self.transaction(|tx| {
+            let Some(bytes) = tx.get(&blob_key)? else {
+                return Ok(None);
+            };
+            let mut metadata: BlobMetadata = serde_json::from_slice(&bytes)
+                .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            let old = metadata.clone();
+            if !update(&mut metadata) {
+                return Ok(None);
+            }
+            metadata.generation += 1;
+            let metadata_json = serde_json::to_vec(&metadata)
+                .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            tx.insert(blob_key.as_slice(), metadata_json)?;
+            Self::index_tags(tx, blob_id, &old.tags, &metadata.tags)?;
+            Self::reaccount_usage(tx, &old, &metadata)?;
+            self.reindex_size(tx, &old, &metadata)?;
+            Self::reindex_checksum(tx, &old, &metadata)?;
+            Ok(Some(metadata))
+        })
+    }
+
+    /// Applies `update` to a blob's metadata record only if its generation
+    /// is still `expected_generation`, failing with `ConcurrentModification`
+    /// otherwise. An update that clears or shortens `retain_until` fails
+    /// with `RetentionActive`. Returns the updated record.
+    pub fn update_metadata_if<F>(&self, blob_id: &BlobId, expected_generation: u64, update: F) -> Result<BlobMetadata>
+    where
+        F: Fn(&mut BlobMetadata),
+    {
+        let blob_key = self.blob_key(blob_id);
+        self.transaction(|tx| {
+            let bytes = tx.get(&blob_key)?.ok_or_else(|| {
+                ConflictableTransactionError::Abort(Error::BlobNotFound(blob_id.to_string()))
+            })?;
+            let mut metadata: BlobMetadata = serde_json::from_slice(&bytes)
+                .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            if metadata.generation != expected_generation {
+                return Err(ConflictableTransactionError::Abort(Error::ConcurrentModification {
+                    blob_id: blob_id.to_string(),
+                    expected: expected_generation,
+                    actual: metadata.generation,
+                }));
+            }
+            let old = metadata.clone();
+            update(&mut metadata);
+            // Retention can only be lengthened, as with `extend_retention`
+            if let Some(retain_until) = old.retain_until {
+                if metadata.retain_until.is_none_or(|updated| updated < retain_until) {
+                    return Err(ConflictableTransactionError::Abort(Error::RetentionActive {
+                        blob_id: blob_id.to_string(),
+                        retain_until,
+                    }));
+                }
+            }
+            metadata.generation = expected_generation + 1;
+            let metadata_json = serde_json::to_vec(&metadata)
+                .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            tx.insert(blob_key.as_slice(), metadata_json)?;
+            Self::index_tags(tx, blob_id, &old.tags, &metadata.tags)?;
+            Self::reaccount_usage(tx, &old, &metadata)?;
+            self.reindex_size(tx, &old, &metadata)?;
+            Self::reindex_checksum(tx, &old, &metadata)?;
+            Ok(metadata)
+        })
+    }
+
+    /// Points a packed blob at a new location, but only if it still lives at
+    /// `from`. Returns false if the blob was deleted or moved in the meantime.
+    pub fn relocate_packed(
+        &self,
+        blob_id: &BlobId,
+        from: &PackLocation,
+        to: &PackLocation,
+    ) -> Result<bool> {
+        let updated = self.update_metadata(blob_id, |metadata| {
+            if metadata.pack.as_ref() != Some(from) {
+                return false;
+            }
+            metadata.pack = Some(*to);
+            true
+        })?;
+        Ok(updated.is_some())
+    }
+
+    /// Notes that a blob was read at `at`. Access times are buffered and
+    /// written together once enough are pending, by `apply_accesses` or on
+    /// `flush`. Returns the blobs whose records were rewritten, if any.
+    pub fn record_access(&self, blob_id: &BlobId, at: DateTime<Utc>) -> Result<Vec<BlobId>> {
+        if self.read_only.is_some() {
+            return Ok(Vec::new());
+        }
+        let pending = {
+            let mut accesses = self.accesses.lock().unwrap();
+            let latest = accesses.entry(blob_id.clone()).or_insert(at);
+            *latest = (*latest).max(at);
+            accesses.len()
+        };
+        if pending < ACCESS_BATCH {
+            return Ok(Vec::new());
+        }
+        self.apply_accesses()
+    }
+
+    /// Writes buffered access times into their blobs' records, leaving
+    /// generations alone since the content didn't change. Blobs deleted
+    /// since are skipped. Returns the blobs whose records were rewritten.
+    pub fn apply_accesses(&self) -> Result<Vec<BlobId>> {
+        let accesses = std::mem::take(&mut *self.accesses.lock().unwrap());
+        if accesses.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.apply_batch()?;
+        let mut written = Vec::new();
+        for (blob_id, at) in accesses {
+            let blob_key = self.blob_key(&blob_id);
+            let updated = self.transaction(|tx| {
+                let Some(bytes) = tx.get(&blob_key)? else {
+                    return Ok(false);
+                };
+                let mut metadata: BlobMetadata = serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                if metadata.last_accessed.is_some_and(|last| last >= at) {
+                    return Ok(false);
+                }
+                metadata.last_accessed = Some(at);
+                let metadata_json = serde_json::to_vec(&metadata)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                tx.insert(blob_key.as_slice(), metadata_json)?;
+                Ok(true)
+            })?;
+            if updated {
+                written.push(blob_id);
+            }
+        }
+        Ok(written)
+    }
+
     /// Retrieves blob metadata
     pub fn get_metadata(&self, blob_id: &BlobId) -> Result<BlobMetadata> {
-        let blob_key = Self::blob_key(blob_id);
+        self.get_record(blob_id)
+    }
+
+    /// Retrieves a blob's metadata record, parsing only the fields of `T`
+    pub fn get_record<T: DeserializeOwned>(&self, blob_id: &BlobId) -> Result<T> {
+        let blob_key = self.blob_key(blob_id);
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if let Some(batch) = &self.batch {
+            if let Some(record) = batch.records.lock().unwrap().get(blob_id) {
+                return Ok(serde_json::from_slice(&record.metadata_json)?);
+            }
+        }
         let metadata_bytes = self.db
             .get(blob_key)?
             .ok_or_else(|| Error::BlobNotFound(blob_id.to_string()))?;
@@ -55,32 +1304,542 @@ impl MetadataStore {
 
     /// Lists all blobs for a tenant
     pub fn get_tenant_blobs(&self, tenant_id: &TenantId) -> Result<Vec<BlobId>> {
-        let tenant_key = Self::tenant_key(tenant_id);
-        let blob_list = match self.db.get(tenant_key)? {
-            Some(bytes) => serde_json::from_slice(&bytes)?,
-            None => Vec::new(),
-        };
+        self.migrate_legacy_list(tenant_id)?;
+
+        let prefix = Self::member_prefix(tenant_id);
+        let mut blob_list = Vec::new();
+        for key in self.db.scan_prefix(&prefix).keys() {
+            let key = key?;
+            if let Some(blob_id) = Self::parse_blob_id(&key[prefix.len()..]) {
+                blob_list.push(blob_id);
+            }
+        }
+        if let Some(batch) = &self.batch {
+            for (blob_id, record) in batch.records.lock().unwrap().iter() {
+                if record.tenant_id == *tenant_id && !blob_list.contains(blob_id) {
+                    blob_list.push(blob_id.clone());
+                }
+            }
+        }
         Ok(blob_list)
     }
 
-    /// Deletes blob metadata
-    pub fn delete_metadata(&self, blob_id: &BlobId, tenant_id: &TenantId) -> Result<()> {
-        let blob_key = Self::blob_key(blob_id);
-        let tenant_key = Self::tenant_key(tenant_id);
+    /// Counts a tenant's blobs by scanning membership keys, without
+    /// collecting ids or reading metadata
+    pub fn count_tenant_blobs(&self, tenant_id: &TenantId) -> Result<usize> {
+        self.migrate_legacy_list(tenant_id)?;
+        self.apply_batch()?;
 
-        // Remove from tenant's blob list
-        if let Some(blob_list_bytes) = self.db.get(&tenant_key)? {
-            let mut blob_list: Vec<BlobId> = serde_json::from_slice(&blob_list_bytes)?;
-            blob_list.retain(|id| id != blob_id);
-            let updated_list = serde_json::to_vec(&blob_list)?;
-            self.db.insert(tenant_key, updated_list)?;
+        let mut count = 0;
+        for key in self.db.scan_prefix(Self::member_prefix(tenant_id)).keys() {
+            key?;
+            count += 1;
         }
+        Ok(count)
+    }
+
+    /// Binds a name to a blob, failing with `BlobAlreadyExists` if the name is taken
+    pub fn put_name(&self, tenant_id: &TenantId, name: &str, blob_id: &BlobId) -> Result<()> {
+        self.check_writable()?;
+        let value = blob_id.to_string();
+        self.db
+            .compare_and_swap(Self::name_key(tenant_id, name), None as Option<&[u8]>, Some(value.as_bytes()))?
+            .map_err(|_| Error::BlobAlreadyExists(name.to_string()))
+    }
+
+    /// Repoints a name from `old` to `new` in one step, binding it if `old`
+    /// is `None`. Fails with `NameRebound` if the name no longer points at
+    /// `old`, leaving it as is.
+    pub fn replace_name(&self, tenant_id: &TenantId, name: &str, old: Option<&BlobId>, new: &BlobId) -> Result<()> {
+        self.check_writable()?;
+        let old = old.map(BlobId::to_string);
+        let value = new.to_string();
+        self.db
+            .compare_and_swap(Self::name_key(tenant_id, name), old.as_deref().map(str::as_bytes), Some(value.as_bytes()))?
+            .map_err(|_| Error::NameRebound(name.to_string()))
+    }
 
-        // Remove blob metadata
-        self.db.remove(blob_key)?;
+    /// Returns every name bound in a tenant with the blob it points at
+    pub fn list_names(&self, tenant_id: &TenantId) -> Result<Vec<(String, BlobId)>> {
+        let prefix = Self::name_key(tenant_id, "");
+        let mut names = Vec::new();
+        for entry in self.db.scan_prefix(&prefix) {
+            let (key, value) = entry?;
+            if let (Ok(name), Some(blob_id)) = (std::str::from_utf8(&key[prefix.len()..]), Self::parse_blob_id(&value)) {
+                names.push((name.to_string(), blob_id));
+            }
+        }
+        Ok(names)
+    }
 
+    /// Returns the blob a name is bound to, if any
+    pub fn get_name(&self, tenant_id: &TenantId, name: &str) -> Result<Option<BlobId>> {
+        let bytes = self.db.get(Self::name_key(tenant_id, name))?;
+        Ok(bytes.and_then(|bytes| Self::parse_blob_id(&bytes)))
+    }
+
+    /// Removes a name binding, returning the blob it pointed at
+    pub fn remove_name(&self, tenant_id: &TenantId, name: &str) -> Result<Option<BlobId>> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        let bytes = self.db.remove(Self::name_key(tenant_id, name))?;
+        Ok(bytes.and_then(|bytes| Self::parse_blob_id(&bytes)))
+    }
+
+    /// Returns a name's version history, oldest first
+    pub fn get_versions(&self, tenant_id: &TenantId, name: &str) -> Result<Vec<BlobId>> {
+        match self.db.get(Self::versions_key(tenant_id, name))? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Binds a name to a new version, replacing any existing binding, and
+    /// appends the blob to the name's history. Returns the history, oldest first.
+    pub fn put_version(&self, tenant_id: &TenantId, name: &str, blob_id: &BlobId) -> Result<Vec<BlobId>> {
+        let name_key = Self::name_key(tenant_id, name);
+        let versions_key = Self::versions_key(tenant_id, name);
+        self.transaction(|tx| {
+            let mut versions: Vec<BlobId> = match tx.get(&versions_key)? {
+                Some(bytes) => serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?,
+                None => Vec::new(),
+            };
+            versions.push(blob_id.clone());
+            let value = serde_json::to_vec(&versions)
+                .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            tx.insert(versions_key.as_slice(), value)?;
+            tx.insert(name_key.as_slice(), blob_id.to_string().as_bytes())?;
+            Ok(versions)
+        })
+    }
+
+    /// Drops blobs from a name's version history, leaving the name binding as is
+    pub fn remove_versions(&self, tenant_id: &TenantId, name: &str, removed: &[BlobId]) -> Result<()> {
+        self.check_writable()?;
+        let versions_key = Self::versions_key(tenant_id, name);
+        self.db
+            .fetch_and_update(&versions_key, |bytes| {
+                let mut versions: Vec<BlobId> = serde_json::from_slice(bytes?).unwrap_or_default();
+                versions.retain(|blob_id| !removed.contains(blob_id));
+                serde_json::to_vec(&versions).ok()
+            })?;
+        Ok(())
+    }
+
+    /// Returns the blob recorded for an idempotency key, unless the record
+    /// was made before `expires_before`
+    pub fn get_idempotent(
+        &self,
+        tenant_id: &TenantId,
+        key: &str,
+        expires_before: DateTime<Utc>,
+    ) -> Result<Option<BlobId>> {
+        let Some(bytes) = self.db.get(Self::idempotency_key(tenant_id, key))? else {
+            return Ok(None);
+        };
+        let record: IdempotencyRecord = serde_json::from_slice(&bytes)?;
+        Ok((record.created_at >= expires_before).then_some(record.blob_id))
+    }
+
+    /// Records `blob_id` as the result of an idempotency key, replacing an
+    /// expired record. If another live record already holds the key, leaves
+    /// it in place and returns its blob instead. The record is dropped when
+    /// the blob's metadata is deleted.
+    pub fn claim_idempotent(
+        &self,
+        tenant_id: &TenantId,
+        key: &str,
+        blob_id: &BlobId,
+        now: DateTime<Utc>,
+        expires_before: DateTime<Utc>,
+    ) -> Result<Option<BlobId>> {
+        let db_key = Self::idempotency_key(tenant_id, key);
+        let record = serde_json::to_vec(&IdempotencyRecord {
+            blob_id: blob_id.clone(),
+            created_at: now,
+        })?;
+        self.transaction(|tx| {
+            if let Some(bytes) = tx.get(&db_key)? {
+                let existing: IdempotencyRecord = serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                if existing.created_at >= expires_before {
+                    return Ok(Some(existing.blob_id));
+                }
+                tx.remove(Self::idempotent_blob_key(&existing.blob_id))?;
+            }
+            tx.insert(db_key.as_slice(), record.as_slice())?;
+            tx.insert(Self::idempotent_blob_key(blob_id), db_key.as_slice())?;
+            Ok(None)
+        })
+    }
+
+    /// Removes every idempotency record made before `expires_before`,
+    /// returning how many
+    pub fn prune_idempotent(&self, expires_before: DateTime<Utc>) -> Result<usize> {
+        self.check_writable()?;
+        let mut pruned = 0;
+        for entry in self.db.scan_prefix(IDEMPOTENT_BLOB_PREFIX) {
+            let (blob_key, db_key) = entry?;
+            // Recheck within a transaction, as the key may be claimed again meanwhile
+            let removed = self.transaction(|tx| {
+                let Some(bytes) = tx.get(&db_key)? else {
+                    tx.remove(&blob_key)?;
+                    return Ok(false);
+                };
+                let record: IdempotencyRecord = serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                if record.created_at >= expires_before {
+                    return Ok(false);
+                }
+                tx.remove(&db_key)?;
+                tx.remove(Self::idempotent_blob_key(&record.blob_id))?;
+                Ok(true)
+            })?;
+            if removed {
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Reserves a blob id for a tenant's upload that hasn't finished yet
+    pub fn put_pending(&self, blob_id: &BlobId, tenant_id: &TenantId) -> Result<()> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        self.db.insert(Self::pending_key(blob_id), tenant_id.as_str().as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the tenant a pending upload was reserved for, if it is still pending
+    pub fn get_pending(&self, blob_id: &BlobId) -> Result<Option<TenantId>> {
+        let tenant = self.db.get(Self::pending_key(blob_id))?;
+        Ok(tenant.map(|bytes| TenantId::new(String::from_utf8_lossy(&bytes))))
+    }
+
+    /// Releases a pending upload reservation
+    pub fn remove_pending(&self, blob_id: &BlobId) -> Result<()> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        self.db.remove(Self::pending_key(blob_id))?;
+        Ok(())
+    }
+
+    /// Records that a blob was deleted, replacing any earlier tombstone
+    pub fn put_tombstone(&self, blob_id: &BlobId, tombstone: &Tombstone) -> Result<()> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        self.db.insert(Self::tombstone_key(blob_id), serde_json::to_vec(tombstone)?)?;
         Ok(())
     }
+
+    /// Returns a blob's tombstone, if it has one
+    pub fn get_tombstone(&self, blob_id: &BlobId) -> Result<Option<Tombstone>> {
+        match self.db.get(Self::tombstone_key(blob_id))? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes every tombstone made before `deleted_before`, returning how many
+    pub fn prune_tombstones(&self, deleted_before: DateTime<Utc>) -> Result<usize> {
+        self.check_writable()?;
+        let _snapshot = self.hold_snapshot();
+        let mut pruned = 0;
+        for entry in self.db.scan_prefix(TOMBSTONE_PREFIX) {
+            let (key, value) = entry?;
+            let tombstone: Tombstone = serde_json::from_slice(&value)?;
+            if tombstone.deleted_at < deleted_before {
+                self.db.remove(key)?;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Returns the ids of a tenant's blobs of `min` to `max` bytes inclusive,
+    /// smallest first, from the size index
+    pub fn find_by_size(&self, tenant_id: &TenantId, min: Option<u64>, max: Option<u64>) -> Result<Vec<BlobId>> {
+        if !self.has_size_index() {
+            return Err(Error::System("blobs aren't indexed by size".into()));
+        }
+        self.apply_batch()?;
+        let prefix = Self::size_prefix(tenant_id);
+        let mut start = prefix.clone();
+        start.extend_from_slice(format!("{:020}:", min.unwrap_or(0)).as_bytes());
+        let mut blob_ids = Vec::new();
+        for key in self.db.range(start..).keys() {
+            let key = key?;
+            let Some(entry) = key.strip_prefix(prefix.as_slice()) else {
+                break;
+            };
+            let Some((size, blob_id)) = std::str::from_utf8(entry).ok().and_then(|entry| entry.split_once(':')) else {
+                continue;
+            };
+            if max.is_some_and(|max| size.parse::<u64>().map_or(true, |size| size > max)) {
+                break;
+            }
+            if let Some(blob_id) = Self::parse_blob_id(blob_id.as_bytes()) {
+                blob_ids.push(blob_id);
+            }
+        }
+        Ok(blob_ids)
+    }
+
+    /// Returns up to `limit` blob metadata records, across all tenants.
+    /// Records that fail to decode are logged and left out of the sample
+    pub fn sample_metadata(&self, limit: usize) -> Result<Vec<BlobMetadata>> {
+        self.apply_batch()?;
+        let mut sample = Vec::new();
+        for entry in self.db.scan_prefix(BLOB_PREFIX) {
+            if sample.len() >= limit {
+                break;
+            }
+            let (key, value) = entry?;
+            match serde_json::from_slice(&value) {
+                Ok(metadata) => sample.push(metadata),
+                Err(e) => tracing::warn!(
+                    key = %String::from_utf8_lossy(&key),
+                    error = %e,
+                    "skipping undecodable blob record while sampling"
+                ),
+            }
+        }
+        Ok(sample)
+    }
+
+    /// Lists the ids of all blobs with stored metadata, across all tenants
+    pub fn all_blob_ids(&self) -> Result<Vec<BlobId>> {
+        self.apply_batch()?;
+        let mut blob_ids = Vec::new();
+        if self.key_scheme() == KeyScheme::Hashed {
+            for value in self.db.scan_prefix(BLOB_PREFIX).values() {
+                blob_ids.push(serde_json::from_slice::<IdRecord>(&value?)?.blob_id);
+            }
+            return Ok(blob_ids);
+        }
+        for key in self.db.scan_prefix(BLOB_PREFIX).keys() {
+            let key = key?;
+            if let Some(blob_id) = Self::parse_blob_id(&key[BLOB_PREFIX.len()..]) {
+                blob_ids.push(blob_id);
+            }
+        }
+        Ok(blob_ids)
+    }
+
+    /// Lists the blobs of any tenant whose content has the given hex checksum.
+    /// Blobs stored before the index existed are only found after
+    /// `reindex_checksums`.
+    pub fn find_by_checksum(&self, checksum: &Checksum) -> Result<Vec<BlobId>> {
+        self.apply_batch()?;
+        let prefix = Self::checksum_prefix(checksum);
+        let mut blob_ids = Vec::new();
+        for key in self.db.scan_prefix(&prefix).keys() {
+            let key = key?;
+            if let Some(blob_id) = Self::parse_blob_id(&key[prefix.len()..]) {
+                blob_ids.push(blob_id);
+            }
+        }
+        Ok(blob_ids)
+    }
+
+    /// Adds a checksum index entry for every blob record, for stores written
+    /// before the index existed. Returns how many records were indexed.
+    pub fn reindex_checksums(&self) -> Result<usize> {
+        self.check_writable()?;
+        self.apply_batch()?;
+        let mut batch = sled::Batch::default();
+        let mut indexed = 0;
+        for value in self.db.scan_prefix(BLOB_PREFIX).values() {
+            let metadata: BlobMetadata = serde_json::from_slice(&value?)?;
+            batch.insert(Self::checksum_key(&metadata.checksum, &metadata.blob_id), &[]);
+            indexed += 1;
+        }
+        let _snapshot = self.hold_snapshot();
+        self.db.apply_batch(batch)?;
+        Ok(indexed)
+    }
+
+    /// Lists the blobs of any tenant carrying `value` among the values of tag
+    /// `key`. Blobs stored before the index existed are only found after
+    /// `reindex_tags`.
+    pub fn find_by_tag(&self, key: &str, value: &str) -> Result<Vec<BlobId>> {
+        self.apply_batch()?;
+        let prefix = Self::tag_prefix(key, value);
+        let mut blob_ids = Vec::new();
+        for index_key in self.db.scan_prefix(&prefix).keys() {
+            let index_key = index_key?;
+            if let Some(blob_id) = Self::parse_blob_id(&index_key[prefix.len()..]) {
+                blob_ids.push(blob_id);
+            }
+        }
+        Ok(blob_ids)
+    }
+
+    /// Lists the blobs matching every `(key, value)` filter, as with
+    /// `find_by_tag`. No filters match nothing.
+    pub fn find_by_tags(&self, filters: &[(String, String)]) -> Result<Vec<BlobId>> {
+        let Some(((key, value), rest)) = filters.split_first() else {
+            return Ok(Vec::new());
+        };
+        let mut blob_ids = self.find_by_tag(key, value)?;
+        for (key, value) in rest {
+            let matching: HashSet<BlobId> = self.find_by_tag(key, value)?.into_iter().collect();
+            blob_ids.retain(|blob_id| matching.contains(blob_id));
+        }
+        Ok(blob_ids)
+    }
+
+    /// Adds tag index entries for every blob record, for stores written
+    /// before the index existed. Returns how many records were indexed.
+    pub fn reindex_tags(&self) -> Result<usize> {
+        self.check_writable()?;
+        self.apply_batch()?;
+        let mut batch = sled::Batch::default();
+        let mut indexed = 0;
+        for value in self.db.scan_prefix(BLOB_PREFIX).values() {
+            let metadata: BlobMetadata = serde_json::from_slice(&value?)?;
+            for (key, value) in metadata.tags.iter() {
+                batch.insert(Self::tag_key(key, value, &metadata.blob_id), &[]);
+            }
+            indexed += 1;
+        }
+        let _snapshot = self.hold_snapshot();
+        self.db.apply_batch(batch)?;
+        Ok(indexed)
+    }
+
+    /// Lists every tenant membership entry, including ones whose blob
+    /// metadata is gone. Legacy JSON blob lists aren't included.
+    pub fn all_memberships(&self) -> Result<Vec<(TenantId, BlobId)>> {
+        self.apply_batch()?;
+        let mut memberships = Vec::new();
+        for key in self.db.scan_prefix(b"tenant:").keys() {
+            let key = key?;
+            let Ok(key) = std::str::from_utf8(&key) else { continue };
+            let Some((tenant, blob_id)) = key["tenant:".len()..].rsplit_once(":member:") else {
+                continue;
+            };
+            if let Some(blob_id) = Self::parse_blob_id(blob_id.as_bytes()) {
+                memberships.push((TenantId::new(tenant), blob_id));
+            }
+        }
+        Ok(memberships)
+    }
+
+    /// Removes one tenant membership entry, leaving any metadata untouched.
+    /// Returns false if there was no such entry.
+    pub fn remove_membership(&self, tenant_id: &TenantId, blob_id: &BlobId) -> Result<bool> {
+        self.transaction(|tx| Self::remove_member(tx, tenant_id, blob_id))
+    }
+
+    /// Lazily yields the metadata of each blob owned by a tenant, fetching
+    /// each record only when the iterator is advanced
+    pub fn iter_tenant_metadata(
+        &self,
+        tenant_id: &TenantId,
+    ) -> Result<impl Iterator<Item = Result<BlobMetadata>> + '_> {
+        let blob_ids = self.get_tenant_blobs(tenant_id)?;
+        Ok(blob_ids.into_iter().map(move |blob_id| self.get_metadata(&blob_id)))
+    }
+
+    /// Deletes blob metadata
+    pub fn delete_metadata(&self, blob_id: &BlobId, tenant_id: &TenantId) -> Result<()> {
+        self.delete_metadata_with_tombstone(blob_id, tenant_id, None)
+    }
+
+    /// Deletes a blob's metadata like `delete_metadata`, recording
+    /// `tombstone` in the same transaction if the record existed
+    pub fn delete_metadata_with_tombstone(
+        &self,
+        blob_id: &BlobId,
+        tenant_id: &TenantId,
+        tombstone: Option<&Tombstone>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let tombstone = tombstone.map(serde_json::to_vec).transpose()?;
+        self.migrate_legacy_list(tenant_id)?;
+
+        // Remove the membership, index entries and metadata record together
+        self.transaction(|tx| {
+            Self::remove_member(tx, tenant_id, blob_id)?;
+            if let Some(bytes) = tx.remove(self.blob_key(blob_id))? {
+                let metadata: BlobMetadata = serde_json::from_slice(&bytes)
+                    .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                tx.remove(Self::checksum_key(&metadata.checksum, blob_id))?;
+                for (key, value) in metadata.tags.iter() {
+                    tx.remove(Self::tag_key(key, value, blob_id))?;
+                }
+                Self::adjust_usage(tx, &metadata.tenant_id, 0, metadata.size)?;
+                if self.has_size_index() {
+                    tx.remove(Self::size_key(&metadata.tenant_id, metadata.size, blob_id))?;
+                }
+                if let Some(tombstone) = &tombstone {
+                    tx.insert(Self::tombstone_key(blob_id), tombstone.as_slice())?;
+                }
+                // A retry must not be answered with a deleted blob
+                if let Some(db_key) = tx.remove(Self::idempotent_blob_key(blob_id))? {
+                    tx.remove(db_key)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the total size of a tenant's blobs from its running counter,
+    /// without reading any blob records. Stores written before the counter
+    /// existed have it built when they are first opened.
+    pub fn tenant_usage(&self, tenant_id: &TenantId) -> Result<u64> {
+        self.apply_batch()?;
+        let usage = self.db.get(Self::usage_key(tenant_id))?;
+        Ok(usage.map_or(0, |bytes| Self::decode_usage(&bytes)))
+    }
+
+    /// Rebuilds a tenant's usage counter by summing the sizes of its blob
+    /// records, returning the corrected total. The total is written only if
+    /// no write moved the counter while the records were listed; otherwise
+    /// they are listed again.
+    pub fn recompute_usage(&self, tenant_id: &TenantId) -> Result<u64> {
+        self.check_writable()?;
+        let usage_key = Self::usage_key(tenant_id);
+        loop {
+            let before = self.tenant_usage(tenant_id)?;
+            let blob_ids = self.get_tenant_blobs(tenant_id)?;
+            let usage = self.transaction(|tx| {
+                let current = tx.get(&usage_key)?.map_or(0, |bytes| Self::decode_usage(&bytes));
+                if current != before {
+                    return Ok(None);
+                }
+                let mut usage = 0u64;
+                for blob_id in &blob_ids {
+                    if let Some(bytes) = tx.get(self.blob_key(blob_id))? {
+                        let record: IndexedRecord = serde_json::from_slice(&bytes)
+                            .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                        if record.tenant_id == *tenant_id {
+                            usage += record.size;
+                        }
+                    }
+                }
+                tx.insert(usage_key.as_slice(), &usage.to_be_bytes())?;
+                Ok(Some(usage))
+            })?;
+            if let Some(usage) = usage {
+                return Ok(usage);
+            }
+        }
+    }
+}
+
+impl Drop for MetadataStore {
+    fn drop(&mut self) {
+        self.stop_flusher();
+        if let Err(e) = self.apply_batch() {
+            tracing::warn!(error = %e, "failed to apply batched metadata writes on close");
+        }
+        if let Err(e) = self.apply_accesses() {
+            tracing::warn!(error = %e, "failed to write access times on close");
+        }
+    }
 }
 
 #[cfg(test)]
@@ -99,8 +1858,23 @@ mod tests {
             blob_id: blob_id.clone(),
             tenant_id: tenant_id.clone(),
             size: 42,
-            checksum: "test_checksum".to_string(),
+            checksum: Checksum::new("sha256", [1; 32]),
             created_at: Utc::now(),
+            seq: 0,
+            tags: Default::default(),
+            content_type: None,
+            pack: None,
+            tier: Default::default(),
+            retain_until: None,
+            compressed_size: None,
+            compression: Default::default(),
+            expires_at: None,
+            chunks: None,
+            generation: 0,
+            metadata_only: false,
+            checksums: Default::default(),
+            last_accessed: None,
+            extensions: Default::default(),
         };
 
         // Test put_metadata
@@ -121,4 +1895,422 @@ mod tests {
         assert!(store.get_metadata(&blob_id).is_err());
         assert_eq!(store.get_tenant_blobs(&tenant_id).unwrap().len(), 0);
     }
-} 
\ No newline at end of file
+
+    fn sample_metadata(tenant_id: &TenantId) -> BlobMetadata {
+        BlobMetadata {
+            blob_id: BlobId::new(),
+            tenant_id: tenant_id.clone(),
+            size: 42,
+            checksum: Checksum::new("sha256", [1; 32]),
+            created_at: Utc::now(),
+            seq: 0,
+            tags: Default::default(),
+            content_type: None,
+            pack: None,
+            tier: Default::default(),
+            retain_until: None,
+            compressed_size: None,
+            compression: Default::default(),
+            expires_at: None,
+            chunks: None,
+            generation: 0,
+            metadata_only: false,
+            checksums: Default::default(),
+            last_accessed: None,
+            extensions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_bulk_ingest_listing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        // Each put is a constant number of writes, so 2000 puts stay fast
+        let start = std::time::Instant::now();
+        let mut expected = Vec::new();
+        for _ in 0..2000 {
+            let metadata = sample_metadata(&tenant_id);
+            store.put_metadata(&metadata).unwrap();
+            expected.push(metadata.blob_id);
+        }
+        assert!(start.elapsed() < std::time::Duration::from_secs(20));
+
+        let mut listed = store.get_tenant_blobs(&tenant_id).unwrap();
+        listed.sort_by_key(|id| id.to_string());
+        expected.sort_by_key(|id| id.to_string());
+        assert_eq!(listed, expected);
+
+        // Another tenant's listing is unaffected
+        assert!(store.get_tenant_blobs(&TenantId::new("other")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_legacy_blob_list_migration() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        let first = sample_metadata(&tenant_id);
+        let second = sample_metadata(&tenant_id);
+        for metadata in [&first, &second] {
+            let metadata_json = serde_json::to_vec(metadata).unwrap();
+            store.db.insert(store.blob_key(&metadata.blob_id), metadata_json).unwrap();
+        }
+        let legacy_list = serde_json::to_vec(&vec![first.blob_id.clone(), second.blob_id.clone()]).unwrap();
+        store.db.insert(MetadataStore::legacy_tenant_key(&tenant_id), legacy_list).unwrap();
+
+        let blobs = store.get_tenant_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert!(blobs.contains(&first.blob_id) && blobs.contains(&second.blob_id));
+        assert!(store.db.get(MetadataStore::legacy_tenant_key(&tenant_id)).unwrap().is_none());
+
+        store.delete_metadata(&first.blob_id, &tenant_id).unwrap();
+        assert_eq!(store.get_tenant_blobs(&tenant_id).unwrap(), vec![second.blob_id]);
+    }
+
+    #[test]
+    fn test_corrupt_blob_list_is_rebuilt() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        let first = sample_metadata(&tenant_id);
+        let second = sample_metadata(&tenant_id);
+        let other = sample_metadata(&TenantId::new("other"));
+        for metadata in [&first, &second, &other] {
+            let metadata_json = serde_json::to_vec(metadata).unwrap();
+            store.db.insert(store.blob_key(&metadata.blob_id), metadata_json).unwrap();
+        }
+        store.db.insert(MetadataStore::legacy_tenant_key(&tenant_id), &b"[\"not a valid"[..]).unwrap();
+
+        let blobs = store.get_tenant_blobs(&tenant_id).unwrap();
+        assert_eq!(blobs.len(), 2);
+        assert!(blobs.contains(&first.blob_id) && blobs.contains(&second.blob_id));
+
+        // The repair is persisted, so the next listing reads membership keys
+        assert!(store.db.get(MetadataStore::legacy_tenant_key(&tenant_id)).unwrap().is_none());
+        assert_eq!(store.count_tenant_blobs(&tenant_id).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_batched_writes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let records: Vec<BlobMetadata> = (0..1000).map(|_| sample_metadata(&tenant_id)).collect();
+        {
+            // Neither limit is reached, so everything stays buffered until the flush
+            let store = MetadataStore::new(temp_dir.path())
+                .unwrap()
+                .with_batching(usize::MAX, Duration::from_secs(3600));
+            for metadata in &records {
+                store.put_metadata(metadata).unwrap();
+            }
+            assert!(store.db.get(store.blob_key(&records[500].blob_id)).unwrap().is_none());
+
+            // Readers are served from the batch
+            assert_eq!(store.get_metadata(&records[500].blob_id).unwrap().blob_id, records[500].blob_id);
+            assert_eq!(store.get_tenant_blobs(&tenant_id).unwrap().len(), 1000);
+
+            store.flush().unwrap();
+            assert!(store.db.get(store.blob_key(&records[500].blob_id)).unwrap().is_some());
+        }
+
+        let store = crate::test_support::reopen(|| MetadataStore::new(temp_dir.path()));
+        assert_eq!(store.get_tenant_blobs(&tenant_id).unwrap().len(), 1000);
+        for metadata in &records {
+            assert_eq!(store.get_metadata(&metadata.blob_id).unwrap().blob_id, metadata.blob_id);
+        }
+    }
+
+    #[test]
+    fn test_batch_applied_at_record_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path())
+            .unwrap()
+            .with_batching(10, Duration::from_secs(3600));
+        let tenant_id = TenantId::new("posts");
+        let records: Vec<BlobMetadata> = (0..15).map(|_| sample_metadata(&tenant_id)).collect();
+        for metadata in &records {
+            store.put_metadata(metadata).unwrap();
+        }
+        assert!(store.db.get(store.blob_key(&records[9].blob_id)).unwrap().is_some());
+        assert!(store.db.get(store.blob_key(&records[14].blob_id)).unwrap().is_none());
+        assert_eq!(store.count_tenant_blobs(&tenant_id).unwrap(), 15);
+    }
+
+    #[test]
+    fn test_checksum_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        let first = sample_metadata(&tenant_id);
+        let duplicate = sample_metadata(&tenant_id);
+        let mut other = sample_metadata(&tenant_id);
+        other.checksum = Checksum::new("sha256", [2; 32]);
+        for metadata in [&first, &duplicate, &other] {
+            store.put_metadata(metadata).unwrap();
+        }
+
+        let mut found = store.find_by_checksum(&first.checksum).unwrap();
+        found.sort_by_key(|blob_id| blob_id.to_string());
+        let mut expected = vec![first.blob_id.clone(), duplicate.blob_id.clone()];
+        expected.sort_by_key(|blob_id| blob_id.to_string());
+        assert_eq!(found, expected);
+
+        // Deleting a blob drops its index entry
+        store.delete_metadata(&first.blob_id, &tenant_id).unwrap();
+        assert_eq!(store.find_by_checksum(&first.checksum).unwrap(), vec![duplicate.blob_id.clone()]);
+
+        // Records written before the index existed are picked up by a reindex
+        store.db.remove(MetadataStore::checksum_key(&other.checksum, &other.blob_id)).unwrap();
+        assert!(store.find_by_checksum(&other.checksum).unwrap().is_empty());
+        assert_eq!(store.reindex_checksums().unwrap(), 2);
+        assert_eq!(store.find_by_checksum(&other.checksum).unwrap(), vec![other.blob_id]);
+    }
+
+    #[test]
+    fn test_tag_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        let mut metadata = sample_metadata(&tenant_id);
+        metadata.tags.add("category", "news");
+        metadata.tags.add("category", "sports");
+        store.put_metadata(&metadata).unwrap();
+        let blob_id = metadata.blob_id.clone();
+        assert_eq!(store.find_by_tag("category", "news").unwrap(), vec![blob_id.clone()]);
+        assert_eq!(store.find_by_tag("category", "sports").unwrap(), vec![blob_id.clone()]);
+
+        // Removing one value drops just its entry; added values are indexed
+        store
+            .update_metadata(&blob_id, |metadata| {
+                metadata.tags.remove_value("category", "news");
+                metadata.tags.add("category", "weather");
+                true
+            })
+            .unwrap();
+        assert!(store.find_by_tag("category", "news").unwrap().is_empty());
+        assert_eq!(store.find_by_tag("category", "weather").unwrap(), vec![blob_id.clone()]);
+
+        // Overwriting the record replaces its entries
+        metadata.tags = Tags::from([("category".to_string(), "travel".to_string())]);
+        store.put_metadata(&metadata).unwrap();
+        assert!(store.find_by_tag("category", "sports").unwrap().is_empty());
+        assert_eq!(store.find_by_tag("category", "travel").unwrap(), vec![blob_id.clone()]);
+
+        store.delete_metadata(&blob_id, &tenant_id).unwrap();
+        assert!(store.find_by_tag("category", "travel").unwrap().is_empty());
+
+        // Records written before the index existed are picked up by a reindex
+        let store = store.with_batching(100, Duration::from_secs(60));
+        store.put_metadata(&metadata).unwrap();
+        assert_eq!(store.find_by_tag("category", "travel").unwrap(), vec![blob_id.clone()]);
+        store.db.remove(MetadataStore::tag_key("category", "travel", &blob_id)).unwrap();
+        assert!(store.find_by_tag("category", "travel").unwrap().is_empty());
+        assert_eq!(store.reindex_tags().unwrap(), 1);
+        assert_eq!(store.find_by_tag("category", "travel").unwrap(), vec![blob_id]);
+    }
+
+    #[test]
+    fn test_usage_counter() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let mut records: Vec<_> = (0..4).map(|_| sample_metadata(&tenant_id)).collect();
+        for (i, metadata) in records.iter_mut().enumerate() {
+            metadata.size = 100 * (i as u64 + 1);
+            store.put_metadata(metadata).unwrap();
+        }
+        store.delete_metadata(&records[0].blob_id, &tenant_id).unwrap();
+        store.update_metadata(&records[1].blob_id, |metadata| {
+            metadata.size = 50;
+            true
+        }).unwrap();
+        assert_eq!(store.tenant_usage(&tenant_id).unwrap(), 50 + 300 + 400);
+
+        // A wrong counter is repaired from the records
+        store.db.insert(MetadataStore::usage_key(&tenant_id), &9999u64.to_be_bytes()).unwrap();
+        assert_eq!(store.tenant_usage(&tenant_id).unwrap(), 9999);
+        assert_eq!(store.recompute_usage(&tenant_id).unwrap(), 750);
+        assert_eq!(store.tenant_usage(&tenant_id).unwrap(), 750);
+
+        // Batched writes are counted once they are applied
+        let store = store.with_batching(100, Duration::from_secs(60));
+        let mut batched = sample_metadata(&tenant_id);
+        batched.size = 250;
+        store.put_metadata(&batched).unwrap();
+        batched.size = 25;
+        store.put_metadata(&batched).unwrap();
+        assert_eq!(store.tenant_usage(&tenant_id).unwrap(), 775);
+
+        // A store written before the counters existed is counted on open
+        store.db.remove(MetadataStore::usage_key(&tenant_id)).unwrap();
+        store.db.remove(USAGE_COUNTED_KEY).unwrap();
+        drop(store);
+        let store = crate::test_support::reopen(|| MetadataStore::new(temp_dir.path()));
+        assert_eq!(store.tenant_usage(&tenant_id).unwrap(), 775);
+    }
+
+    #[test]
+    fn test_blob_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let records: Vec<_> = (0..3).map(|_| sample_metadata(&tenant_id)).collect();
+        store.put_metadata_many_within(&records[..2], Some(2)).unwrap();
+
+        // A full tenant takes no new blob, but its own blobs can be rewritten
+        let err = store.put_metadata_many_within(&records[2..], Some(2)).unwrap_err();
+        assert!(matches!(err, Error::BlobLimitReached { limit: 2, .. }));
+        store.put_metadata_many_within(&records[..1], Some(2)).unwrap();
+
+        // A batch that would overshoot is rejected whole
+        store.delete_metadata(&records[0].blob_id, &tenant_id).unwrap();
+        let err = store.put_metadata_many_within(&records, Some(2)).unwrap_err();
+        assert!(matches!(err, Error::BlobLimitReached { limit: 2, .. }));
+        assert_eq!(store.count_tenant_blobs(&tenant_id).unwrap(), 1);
+
+        // A store written before the counters existed is counted on open
+        store.db.remove(MetadataStore::blob_count_key(&tenant_id.key())).unwrap();
+        store.db.remove(BLOBS_COUNTED_KEY).unwrap();
+        drop(store);
+        let store = crate::test_support::reopen(|| MetadataStore::new(temp_dir.path()));
+        store.put_metadata_many_within(&records[2..], Some(2)).unwrap();
+        let err = store.put_metadata_many_within(&records[..1], Some(2)).unwrap_err();
+        assert!(matches!(err, Error::BlobLimitReached { limit: 2, .. }));
+    }
+
+    #[test]
+    fn test_seq_orders_despite_backward_clock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        // Each blob is written while the clock has stepped further backward
+        let now = Utc::now();
+        let mut inserted = Vec::new();
+        for i in 0..5 {
+            let mut metadata = sample_metadata(&tenant_id);
+            metadata.created_at = now - chrono::Duration::minutes(i);
+            metadata.seq = store.next_seq().unwrap();
+            store.put_metadata(&metadata).unwrap();
+            inserted.push(metadata.blob_id);
+        }
+
+        let mut listed: Vec<_> = store
+            .iter_tenant_metadata(&tenant_id)
+            .unwrap()
+            .map(|m| m.unwrap())
+            .collect();
+        listed.sort_by_key(|m| m.seq);
+        assert!(listed.windows(2).all(|w| w[0].seq < w[1].seq));
+        assert!(listed.windows(2).all(|w| w[0].created_at > w[1].created_at));
+        let by_seq: Vec<_> = listed.into_iter().map(|m| m.blob_id).collect();
+        assert_eq!(by_seq, inserted);
+
+        // The counter keeps increasing after a reopen
+        let last = store.next_seq().unwrap();
+        drop(store);
+        let store = crate::test_support::reopen(|| MetadataStore::new(temp_dir.path()));
+        assert!(store.next_seq().unwrap() > last);
+    }
+
+    #[test]
+    fn test_aborted_transaction_leaves_no_partial_state() {
+        use sled::transaction::ConflictableTransactionError;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+        let metadata = sample_metadata(&tenant_id);
+        let metadata_json = serde_json::to_vec(&metadata).unwrap();
+
+        // Fail after the metadata record and membership have been written
+        let result: Result<()> = store.transaction(|tx| {
+            store.write_metadata(tx, &metadata, &metadata_json)?;
+            Err(ConflictableTransactionError::Abort(Error::System("injected".into())))
+        });
+        assert!(matches!(result, Err(Error::System(_))));
+        assert!(store.get_metadata(&metadata.blob_id).is_err());
+        assert!(store.get_tenant_blobs(&tenant_id).unwrap().is_empty());
+
+        // The same writes commit when the transaction succeeds
+        store.put_metadata(&metadata).unwrap();
+        assert_eq!(store.get_tenant_blobs(&tenant_id).unwrap(), vec![metadata.blob_id.clone()]);
+    }
+
+    #[test]
+    fn test_move_blob() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = MetadataStore::new(temp_dir.path()).unwrap();
+        let posts = TenantId::new("posts");
+        let archive = TenantId::new("archive");
+        let metadata = sample_metadata(&posts);
+        store.put_metadata(&metadata).unwrap();
+
+        let moved = store.move_blob(&metadata.blob_id, &posts, &archive, Utc::now()).unwrap();
+        assert_eq!(moved.tenant_id, archive);
+        assert_eq!(store.get_metadata(&metadata.blob_id).unwrap().tenant_id, archive);
+        assert!(store.get_tenant_blobs(&posts).unwrap().is_empty());
+        assert_eq!(store.get_tenant_blobs(&archive).unwrap(), vec![metadata.blob_id.clone()]);
+
+        // Ownership and retention are checked against the stored record
+        let err = store.move_blob(&metadata.blob_id, &posts, &archive, Utc::now()).unwrap_err();
+        assert!(matches!(err, Error::InvalidTenant(_)));
+        let retained = BlobMetadata { retain_until: Some(Utc::now() + chrono::Duration::hours(1)), ..sample_metadata(&posts) };
+        store.put_metadata(&retained).unwrap();
+        let err = store.move_blob(&retained.blob_id, &posts, &archive, Utc::now()).unwrap_err();
+        assert!(matches!(err, Error::RetentionActive { .. }));
+        assert_eq!(store.get_tenant_blobs(&posts).unwrap(), vec![retained.blob_id]);
+    }
+
+    #[test]
+    fn test_hashed_keys() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let tenant_id = TenantId::new("posts");
+        let records: Vec<BlobMetadata> = (0..500).map(|_| sample_metadata(&tenant_id)).collect();
+        let (raw, hashed) = records.split_at(250);
+        {
+            let store = MetadataStore::new(temp_dir.path()).unwrap();
+            assert_eq!(store.key_scheme(), KeyScheme::Raw);
+            for metadata in raw {
+                store.put_metadata(metadata).unwrap();
+            }
+
+            // Existing records move to hashed keys, and new ones land there
+            let store = store.with_hashed_keys().unwrap();
+            for metadata in hashed {
+                store.put_metadata(metadata).unwrap();
+            }
+            for key in store.db.scan_prefix(BLOB_PREFIX).keys() {
+                assert_eq!(key.unwrap().len(), BLOB_PREFIX.len() + 16);
+            }
+        }
+
+        // The scheme sticks without being asked for again
+        let store = crate::test_support::reopen(|| MetadataStore::new(temp_dir.path()));
+        assert_eq!(store.key_scheme(), KeyScheme::Hashed);
+        for metadata in &records {
+            assert_eq!(store.get_metadata(&metadata.blob_id).unwrap().blob_id, metadata.blob_id);
+        }
+        let mut expected: Vec<BlobId> = records.iter().map(|metadata| metadata.blob_id.clone()).collect();
+        expected.sort_by_key(|id| id.to_string());
+        let mut listed = store.get_tenant_blobs(&tenant_id).unwrap();
+        listed.sort_by_key(|id| id.to_string());
+        assert_eq!(listed, expected);
+        let mut all = store.all_blob_ids().unwrap();
+        all.sort_by_key(|id| id.to_string());
+        assert_eq!(all, expected);
+
+        store.delete_metadata(&records[0].blob_id, &tenant_id).unwrap();
+        store.delete_metadata(&records[300].blob_id, &tenant_id).unwrap();
+        assert!(matches!(store.get_metadata(&records[0].blob_id), Err(Error::BlobNotFound(_))));
+        assert!(matches!(store.get_metadata(&records[300].blob_id), Err(Error::BlobNotFound(_))));
+        assert_eq!(store.all_blob_ids().unwrap().len(), 498);
+        assert_eq!(store.tenant_usage(&tenant_id).unwrap(), 498 * 42);
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Result,
+    checksum::{self, Checksum, ChecksumAlgorithm},
+    chunk_store::{BlobInfo, BlobReader},
+    error::{Error, read_only_at},
+};
+
+/// Size at which the active pack is sealed and a new one started
+pub const DEFAULT_MAX_PACK_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Where a packed blob's bytes live
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackLocation {
+    pub pack_id: u64,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Stores blobs back to back in large append-only pack files, saving the
+/// inode and block-rounding overhead of one file per blob.
+///
+/// The store keeps no index of its own: each blob's `PackLocation` lives in
+/// its metadata. A region becomes free once no metadata points at it, and
+/// compaction copies the remaining live regions into a fresh pack.
+pub struct PackStore {
+    dir: PathBuf,
+    max_pack_size: u64,
+    /// Id of the pack new blobs are appended to
+    active: Mutex<u64>,
+    /// Whether writes are refused, for stores opened read-only
+    read_only: bool,
+    /// Algorithm new blobs' checksums are computed with
+    algorithm: ChecksumAlgorithm,
+}
+
+impl PackStore {
+    pub fn new(root_dir: impl Into<PathBuf>, max_pack_size: u64) -> Result<Self> {
+        Self::with_dir_name(root_dir, "packs", max_pack_size)
+    }
+
+    /// Creates a store keeping pack files in `dir_name` under the root
+    pub fn with_dir_name(root_dir: impl Into<PathBuf>, dir_name: &str, max_pack_size: u64) -> Result<Self> {
+        let dir = root_dir.into().join(dir_name);
+        fs::create_dir_all(&dir).map_err(read_only_at(&dir))?;
+        let store = Self {
+            dir,
+            max_pack_size,
+            active: Mutex::new(0),
+            read_only: false,
+            algorithm: ChecksumAlgorithm::Sha256,
+        };
+        let active = store.pack_ids()?.into_iter().max().unwrap_or(0);
+        *store.active.lock().unwrap() = active;
+        Ok(store)
+    }
+
+    /// Computes new blobs' checksums with `algorithm` instead of SHA-256
+    pub fn with_checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Refuses every write from now on, for stores opened read-only
+    pub(crate) fn into_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Fails if the store was opened read-only
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly(self.dir.clone()));
+        }
+        Ok(())
+    }
+
+    /// Returns the path to a pack file
+    pub(crate) fn pack_path(&self, pack_id: u64) -> PathBuf {
+        self.dir.join(format!("{:08}.pack", pack_id))
+    }
+
+    /// Lists the ids of all pack files on disk
+    pub fn pack_ids(&self) -> Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let id = name.to_str().and_then(|name| name.strip_suffix(".pack")?.parse::<u64>().ok());
+            ids.extend(id);
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Returns the current length of a pack file, or 0 if it doesn't exist
+    pub fn pack_len(&self, pack_id: u64) -> Result<u64> {
+        match fs::metadata(self.pack_path(pack_id)) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Appends a blob to the active pack, starting a new pack first if the
+    /// active one is full
+    pub fn append(&self, data: impl Read) -> Result<(PackLocation, BlobInfo)> {
+        self.check_writable()?;
+        let mut active = self.active.lock().unwrap();
+        if self.pack_len(*active)? >= self.max_pack_size {
+            *active += 1;
+        }
+
+        let pack_path = self.pack_path(*active);
+        let mut file = OpenOptions::new().create(true).append(true).open(&pack_path)?;
+        let offset = file.metadata()?.len();
+        let mut data = checksum::HashingReader::with_algorithm(data, self.algorithm);
+        let length = match io::copy(&mut data, &mut file) {
+            Ok(length) => length,
+            Err(e) => {
+                // Drop the partial write so the next blob starts at a clean offset
+                file.set_len(offset)?;
+                return Err(e.into());
+            }
+        };
+
+        let location = PackLocation { pack_id: *active, offset, length };
+        let (_, checksum) = data.finish();
+        Ok((location, BlobInfo { size: length, checksum }))
+    }
+
+    /// Opens a reader over just the blob's region of its pack
+    fn open_range(&self, location: &PackLocation) -> Result<impl Read + Send> {
+        let mut file = match File::open(self.pack_path(location.pack_id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::BlobNotFound(format!("pack {}", location.pack_id)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        file.seek(SeekFrom::Start(location.offset))?;
+        Ok(file.take(location.length))
+    }
+
+    /// Returns how many of the blob's bytes are actually present in its pack
+    pub fn stored_size(&self, location: &PackLocation) -> Result<u64> {
+        let pack_len = match fs::metadata(self.pack_path(location.pack_id)) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::BlobNotFound(format!("pack {}", location.pack_id)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let available = pack_len.saturating_sub(location.offset);
+        Ok(available.min(location.length))
+    }
+
+    /// Reads a packed blob, verifying it against the expected checksum first
+    pub fn get_blob(&self, location: &PackLocation, expected_checksum: &Checksum) -> Result<BlobReader> {
+        let actual = checksum::checksum_of_as(self.open_range(location)?, expected_checksum.supported_algorithm()?)?;
+        if !actual.matches(expected_checksum) {
+            return Err(Error::ChecksumMismatch {
+                expected: expected_checksum.clone(),
+                actual,
+            });
+        }
+        Ok(BlobReader::new(self.open_range(location)?))
+    }
+
+    /// Opens a packed blob's region without verifying it, for salvaging data
+    /// from a blob known to be corrupt
+    pub fn get_blob_raw(&self, location: &PackLocation) -> Result<BlobReader> {
+        Ok(BlobReader::new(self.open_range(location)?))
+    }
+
+    /// Seals the active pack so new blobs go to a fresh one, returning the
+    /// id of the new active pack. Every older pack can then be compacted.
+    pub fn seal(&self) -> Result<u64> {
+        let mut active = self.active.lock().unwrap();
+        if self.pack_len(*active)? > 0 {
+            *active += 1;
+        }
+        Ok(*active)
+    }
+
+    /// Copies a live blob out of a sealed pack into the active one
+    pub fn relocate(&self, location: &PackLocation) -> Result<PackLocation> {
+        let (new_location, _) = self.append(self.open_range(location)?)?;
+        Ok(new_location)
+    }
+
+    /// Deletes a pack whose live blobs have all been relocated
+    pub fn remove_pack(&self, pack_id: u64) -> Result<()> {
+        self.check_writable()?;
+        fs::remove_file(self.pack_path(pack_id))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn read_all(mut reader: impl Read) -> Vec<u8> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_append_and_read_by_offset() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = PackStore::new(temp_dir.path(), DEFAULT_MAX_PACK_SIZE).unwrap();
+
+        let blobs: Vec<_> = (0..100)
+            .map(|i| {
+                let data = format!("blob number {}", i);
+                let (location, info) = store.append(Cursor::new(data.clone())).unwrap();
+                (data, location, info)
+            })
+            .collect();
+        assert_eq!(store.pack_ids().unwrap(), vec![0]);
+
+        let mut expected_offset = 0;
+        for (data, location, info) in &blobs {
+            assert_eq!(location.offset, expected_offset);
+            expected_offset += location.length;
+            let reader = store.get_blob(location, &info.checksum).unwrap();
+            assert_eq!(read_all(reader), data.as_bytes());
+        }
+
+        // A checksum that doesn't match the region is rejected
+        let (_, location, _) = &blobs[0];
+        assert!(matches!(
+            store.get_blob(location, &blobs[1].2.checksum),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rotation_and_seal() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store = PackStore::new(temp_dir.path(), 16).unwrap();
+
+        let (first, _) = store.append(Cursor::new(vec![1; 16])).unwrap();
+        let (second, _) = store.append(Cursor::new(vec![2; 4])).unwrap();
+        assert_eq!((first.pack_id, second.pack_id), (0, 1));
+
+        assert_eq!(store.seal().unwrap(), 2);
+        // Sealing an empty active pack doesn't skip ids
+        assert_eq!(store.seal().unwrap(), 2);
+
+        let moved = store.relocate(&second).unwrap();
+        assert_eq!(moved.pack_id, 2);
+        store.remove_pack(1).unwrap();
+        assert_eq!(store.pack_ids().unwrap(), vec![0, 2]);
+        assert_eq!(read_all(store.open_range(&moved).unwrap()), vec![2; 4]);
+
+        // Reopening resumes appending to the newest pack
+        let reopened = PackStore::new(temp_dir.path(), 16).unwrap();
+        let (next, _) = reopened.append(Cursor::new(vec![3; 4])).unwrap();
+        assert_eq!(next, PackLocation { pack_id: 2, offset: 4, length: 4 });
+    }
+}
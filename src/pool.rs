@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Result, coordinator::Coordinator, error::ResultExt};
+
+/// Opens the coordinator for a root directory
+type Opener = Box<dyn Fn(&Path) -> Result<Coordinator> + Send + Sync>;
+
+/// A pooled coordinator and when it was last handed out
+struct PoolEntry {
+    coordinator: Arc<Coordinator>,
+    last_used: Instant,
+}
+
+/// Coordinators for many stores, opened on first use and shared by every
+/// caller asking for the same root. A root's dbs can only be opened once at
+/// a time, so the pool holds one coordinator per root and only closes it
+/// once nobody else holds it and it has been idle for the ttl.
+pub struct CoordinatorPool {
+    idle_ttl: Duration,
+    opener: Opener,
+    entries: Mutex<HashMap<PathBuf, PoolEntry>>,
+}
+
+impl CoordinatorPool {
+    /// Creates an empty pool closing coordinators idle for longer than `idle_ttl`
+    pub fn new(idle_ttl: Duration) -> Self {
+        Self {
+            idle_ttl,
+            opener: Box::new(|root: &Path| Coordinator::new(root)),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Opens coordinators with `opener` instead of `Coordinator::new`, so
+    /// they can be configured
+    pub fn with_opener(mut self, opener: impl Fn(&Path) -> Result<Coordinator> + Send + Sync + 'static) -> Self {
+        self.opener = Box::new(opener);
+        self
+    }
+
+    /// Returns the coordinator for `root`, opening it if the pool doesn't
+    /// hold one yet. Paths naming the same directory share a coordinator.
+    /// Also closes any other coordinators that have gone idle.
+    pub fn get(&self, root: impl AsRef<Path>) -> Result<Arc<Coordinator>> {
+        let root = root.as_ref();
+        let context = || format!("while opening pooled store {}", root.display());
+        let key = fs::create_dir_all(root)
+            .and_then(|_| fs::canonicalize(root))
+            .map_err(Into::into)
+            .with_context(context)?;
+
+        let mut entries = self.entries.lock().unwrap();
+        Self::evict(&mut entries, self.idle_ttl);
+        if let Some(entry) = entries.get_mut(&key) {
+            entry.last_used = Instant::now();
+            return Ok(entry.coordinator.clone());
+        }
+        let coordinator = Arc::new((self.opener)(&key).with_context(context)?);
+        entries.insert(key, PoolEntry { coordinator: coordinator.clone(), last_used: Instant::now() });
+        Ok(coordinator)
+    }
+
+    /// Closes coordinators idle for longer than the ttl that nobody else
+    /// holds, returning how many were closed
+    pub fn evict_idle(&self) -> usize {
+        Self::evict(&mut self.entries.lock().unwrap(), self.idle_ttl)
+    }
+
+    /// Number of coordinators currently open
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn evict(entries: &mut HashMap<PathBuf, PoolEntry>, idle_ttl: Duration) -> usize {
+        let before = entries.len();
+        entries.retain(|root, entry| {
+            let idle = entry.last_used.elapsed() > idle_ttl && Arc::strong_count(&entry.coordinator) == 1;
+            if idle {
+                tracing::debug!(root = %root.display(), "closing idle pooled coordinator");
+            }
+            !idle
+        });
+        before - entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TenantId;
+
+    #[test]
+    fn test_coordinator_pool() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (first_root, second_root) = (temp_dir.path().join("first"), temp_dir.path().join("second"));
+        let pool = CoordinatorPool::new(Duration::ZERO);
+
+        // The same root, however it is spelled, shares one coordinator
+        let first = pool.get(&first_root).unwrap();
+        let again = pool.get(temp_dir.path().join("second/../first")).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        first.register_tenant(TenantId::new("posts")).unwrap();
+        assert_eq!(again.list_tenants().unwrap(), [TenantId::new("posts")]);
+
+        let second = pool.get(&second_root).unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(pool.len(), 2);
+
+        // Only coordinators nobody holds are closed
+        drop(second);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(pool.evict_idle(), 1);
+        assert_eq!(pool.len(), 1);
+        drop((first, again));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(pool.evict_idle(), 1);
+        assert!(pool.is_empty());
+    }
+}
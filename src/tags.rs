@@ -0,0 +1,159 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+
+/// A blob's labels. Each key holds one or more values, kept in the order
+/// they were added.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Tags(BTreeMap<String, Vec<String>>);
+
+/// A stored tag value: a list, or a single string as written by builds
+/// before tags were multi-valued
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredValues {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for Tags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = BTreeMap::<String, StoredValues>::deserialize(deserializer)?;
+        let tags = stored
+            .into_iter()
+            .map(|(key, values)| match values {
+                StoredValues::One(value) => (key, vec![value]),
+                StoredValues::Many(values) => (key, values),
+            })
+            .filter(|(_, values)| !values.is_empty())
+            .collect();
+        Ok(Tags(tags))
+    }
+}
+
+impl Tags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first value under `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|values| values.first()).map(String::as_str)
+    }
+
+    /// Returns every value under `key`
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.0.get(key).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns true if any value under `key` equals `value`
+    pub fn contains(&self, key: &str, value: &str) -> bool {
+        self.get_all(key).iter().any(|v| v == value)
+    }
+
+    /// Adds `value` under `key`, keeping the key's other values
+    pub fn add(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let values = self.0.entry(key.into()).or_default();
+        let value = value.into();
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+
+    /// Replaces every value under `key` with `value`
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into(), vec![value.into()]);
+    }
+
+    /// Removes one value under `key`, dropping the key with its last value.
+    /// Returns false if it wasn't there.
+    pub fn remove_value(&mut self, key: &str, value: &str) -> bool {
+        let Some(values) = self.0.get_mut(key) else {
+            return false;
+        };
+        let before = values.len();
+        values.retain(|v| v != value);
+        let removed = values.len() != before;
+        if values.is_empty() {
+            self.0.remove(key);
+        }
+        removed
+    }
+
+    /// Removes `key` and all its values
+    pub fn remove(&mut self, key: &str) -> Option<Vec<String>> {
+        self.0.remove(key)
+    }
+
+    /// Moves every key of `other` into these tags, replacing the values of
+    /// keys both have
+    pub fn merge(&mut self, other: Tags) {
+        self.0.extend(other.0);
+    }
+
+    /// Number of distinct keys
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over every key-value pair, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (key.as_str(), value.as_str())))
+    }
+}
+
+impl FromIterator<(String, String)> for Tags {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let mut tags = Tags::new();
+        for (key, value) in iter {
+            tags.add(key, value);
+        }
+        tags
+    }
+}
+
+impl From<BTreeMap<String, String>> for Tags {
+    fn from(map: BTreeMap<String, String>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<const N: usize> From<[(String, String); N]> for Tags {
+    fn from(pairs: [(String, String); N]) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_valued_tags() {
+        let mut tags = Tags::from([("category".to_string(), "news".to_string())]);
+        tags.add("category", "sports");
+        tags.add("category", "news");
+        assert_eq!(tags.get_all("category"), ["news", "sports"]);
+        assert_eq!(tags.get("category"), Some("news"));
+        assert!(tags.contains("category", "sports"));
+        assert_eq!(tags.iter().count(), 2);
+
+        assert!(tags.remove_value("category", "news"));
+        assert!(!tags.remove_value("category", "news"));
+        assert_eq!(tags.get("category"), Some("sports"));
+        assert!(tags.remove_value("category", "sports"));
+        assert!(tags.is_empty());
+
+        // Records written before tags were multi-valued still load
+        let legacy: Tags = serde_json::from_str(r#"{"owner":"ops","category":["a","b"]}"#).unwrap();
+        assert_eq!(legacy.get_all("owner"), ["ops"]);
+        assert_eq!(legacy.get_all("category"), ["a", "b"]);
+        let round_trip: Tags = serde_json::from_str(&serde_json::to_string(&legacy).unwrap()).unwrap();
+        assert_eq!(round_trip, legacy);
+    }
+}
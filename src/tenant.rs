@@ -1,43 +1,462 @@
-use crate::{TenantId, Result, error::Error};
-use std::collections::HashSet;
-use sled::Db;
-use serde_json;
+use crate::{TenantId, Result, clock::{Clock, SystemClock}, error::Error, tags::Tags};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sled::{Db, Transactional, Tree};
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree};
+
+/// Tree of the registry db holding its counters, apart from the tenants
+const COUNTERS_TREE: &[u8] = b"counters";
+
+/// Counter of registered tenants, updated in the registering transaction
+const TENANT_COUNT_KEY: &[u8] = b"tenants";
+
+/// Per-tenant settings stored alongside the tenant registration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub quota_bytes: Option<u64>,
+    #[serde(default)]
+    pub default_compression: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Tags added to every new blob; a blob's own tags win on conflict
+    #[serde(default)]
+    pub default_tags: Tags,
+    /// Whether storing a blob under a name that is taken adds a new version
+    /// instead of failing
+    #[serde(default)]
+    pub versioned: bool,
+    /// How many versions of each name a versioned tenant keeps; older ones
+    /// are deleted as new ones are stored. `None` keeps every version.
+    #[serde(default)]
+    pub max_versions: Option<usize>,
+    /// The tenant's name, kept for tenants keyed by a hash of a long name
+    #[serde(default)]
+    pub full_name: Option<String>,
+    /// Blobs created more than this many days ago are deleted by
+    /// `Coordinator::enforce_retention`. `None` keeps blobs indefinitely.
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Increases with each registration, giving tenants a stable creation
+    /// order. `None` for tenants registered by older versions.
+    #[serde(default)]
+    pub sequence: Option<u64>,
+}
+
+/// The order `TenantManager::list_tenants_sorted` returns tenants in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TenantOrder {
+    /// Alphabetically by name
+    #[default]
+    Name,
+    /// Oldest first. Tenants registered before creation order was recorded
+    /// come first, ordered by registration time and then by name.
+    Creation,
+}
+
+/// What registering a tenant whose name is longer than the configured
+/// maximum does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TenantNamePolicy {
+    /// Fail with `InvalidTenantId`
+    #[default]
+    Reject,
+    /// Accept the name whatever its length. Names longer than
+    /// `MAX_TENANT_KEY_LEN` are keyed by a hash of the name; that cutoff is
+    /// part of the on-disk key format and doesn't follow the configured
+    /// limit.
+    Hash,
+}
+
+/// Which characters tenant names may contain
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TenantCharset {
+    /// Any character
+    #[default]
+    Any,
+    /// Only characters within one of these ranges
+    Allow(Vec<RangeInclusive<char>>),
+}
+
+impl TenantCharset {
+    /// Builds an allowlist from a spec such as `a-z0-9-`: single characters
+    /// and `first-last` ranges, where a `-` at either end stands for itself
+    pub fn allow(spec: &str) -> Self {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut ranges = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if i + 2 < chars.len() && chars[i + 1] == '-' {
+                ranges.push(chars[i]..=chars[i + 2]);
+                i += 3;
+            } else {
+                ranges.push(chars[i]..=chars[i]);
+                i += 1;
+            }
+        }
+        Self::Allow(ranges)
+    }
+
+    pub fn allows(&self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Allow(ranges) => ranges.iter().any(|range| range.contains(&c)),
+        }
+    }
+
+    /// Fails with `InvalidTenantId` naming the first disallowed character
+    pub fn check(&self, name: &str) -> Result<()> {
+        match name.chars().find(|&c| !self.allows(c)) {
+            Some(c) => Err(Error::InvalidTenantId(format!("'{}' contains the disallowed character {:?}", name, c))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TenantConfig {
+    /// Decodes a stored config, treating the empty value written by older
+    /// versions as the default config
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
 
 pub struct TenantManager {
     db: Db,
+    /// The `COUNTERS_TREE` of `db`
+    counters: Tree,
+    /// Stamps each tenant's registration time
+    clock: Arc<dyn Clock>,
+    /// Characters new tenant names may contain
+    charset: TenantCharset,
+    /// Path the registry was opened read-only from, if it was
+    read_only: Option<PathBuf>,
+    /// Private copy of a db on a read-only filesystem, removed on drop.
+    /// Declared last so the db using it is dropped first.
+    _snapshot: Option<tempfile::TempDir>,
 }
 
 impl TenantManager {
     pub fn new(path: impl AsRef<std::path::Path>) -> Result<Self> {
-        let db = sled::open(path.as_ref().join("tenants"))?;
-        Ok(Self { db })
+        Self::with_dir_name(path, "tenants")
+    }
+
+    /// Opens the tenant registry stored in `dir_name` under `path`
+    pub fn with_dir_name(path: impl AsRef<std::path::Path>, dir_name: &str) -> Result<Self> {
+        Self::open(path.as_ref().join(dir_name))
+    }
+
+    /// Opens the tenant registry database at exactly `path`
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let db = sled::open(&path).map_err(crate::error::read_only_at(path.as_ref()))?;
+        let manager = Self::from_db(db)?;
+        manager.count_tenants()?;
+        Ok(manager)
+    }
+
+    /// Opens a throwaway registry that sled deletes when it is dropped
+    pub fn temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    /// Opens an existing registry for reading only. Every write fails with
+    /// `ReadOnly`.
+    pub fn open_read_only(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (db, snapshot) = crate::layout::open_db_read_only(path)?;
+        Ok(Self { read_only: Some(path.to_path_buf()), _snapshot: snapshot, ..Self::from_db(db)? })
+    }
+
+    fn from_db(db: Db) -> Result<Self> {
+        let counters = db.open_tree(COUNTERS_TREE)?;
+        Ok(Self {
+            db,
+            counters,
+            clock: Arc::new(SystemClock),
+            charset: TenantCharset::Any,
+            read_only: None,
+            _snapshot: None,
+        })
+    }
+
+    /// Sets the tenant counter of a registry written before it existed.
+    /// An empty registry is left untouched, so it can still take a dump.
+    fn count_tenants(&self) -> Result<()> {
+        if self.counters.contains_key(TENANT_COUNT_KEY)? || self.db.is_empty() {
+            return Ok(());
+        }
+        self.counters.insert(TENANT_COUNT_KEY, &(self.db.len() as u64).to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the tenant counter within a transaction. Absent means no
+    /// tenant has been registered, see `count_tenants`.
+    fn read_count(counters: &TransactionalTree) -> ConflictableTransactionResult<u64, Error> {
+        let count = counters.get(TENANT_COUNT_KEY)?;
+        Ok(count.map_or(0, |bytes| (*bytes).try_into().map_or(0, u64::from_be_bytes)))
+    }
+
+    /// Fails with `ReadOnly` if the registry was opened read-only
+    fn check_writable(&self) -> Result<()> {
+        match &self.read_only {
+            Some(path) => Err(Error::ReadOnly(path.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads registration times from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Restricts the characters of newly registered tenant names; others
+    /// are rejected with `InvalidTenantId`. Tenants already registered are
+    /// unaffected. Any name is accepted by default. This is unrelated to
+    /// `TenantNamePolicy`, which governs over-long names.
+    pub fn with_name_policy(mut self, charset: TenantCharset) -> Self {
+        self.charset = charset;
+        self
     }
 
     /// Registers a new tenant
     pub fn register_tenant(&self, tenant_id: TenantId) -> Result<()> {
-        let key = tenant_id.as_str().as_bytes();
-        self.db.insert(key, &[])?;
+        self.register_tenant_with_config(tenant_id, TenantConfig::default())
+    }
+
+    /// Registers a new tenant with the given settings. Registering an existing
+    /// tenant again leaves its settings untouched.
+    pub fn register_tenant_with_config(&self, tenant_id: TenantId, config: TenantConfig) -> Result<()> {
+        self.register_tenant_within(tenant_id, config, None)
+    }
+
+    /// Registers a new tenant like `register_tenant_with_config`, failing
+    /// with `TenantLimitReached` if `limit` tenants are already registered.
+    /// The limit is checked in the registering transaction, so concurrent
+    /// registrations can't overshoot it.
+    pub fn register_tenant_within(
+        &self,
+        tenant_id: TenantId,
+        mut config: TenantConfig,
+        limit: Option<usize>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        self.charset.check(tenant_id.as_str())?;
+        let key = tenant_id.key();
+        config.created_at.get_or_insert_with(|| self.clock.now());
+        if tenant_id.is_hashed() {
+            config.full_name = Some(tenant_id.as_str().to_string());
+        }
+        (&*self.db, &self.counters).transaction(|(tenants, counters)| {
+            // An existing tenant keeps its settings
+            if tenants.get(key.as_bytes())?.is_some() {
+                return Ok(());
+            }
+            let count = Self::read_count(counters)?;
+            if let Some(limit) = limit.filter(|&limit| count >= limit as u64) {
+                return Err(ConflictableTransactionError::Abort(Error::TenantLimitReached { limit }));
+            }
+            let config = TenantConfig { sequence: Some(tenants.generate_id()?), ..config.clone() };
+            let value = serde_json::to_vec(&config).map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            tenants.insert(key.as_bytes(), value)?;
+            counters.insert(TENANT_COUNT_KEY, &(count + 1).to_be_bytes())?;
+            Ok(())
+        })?;
         Ok(())
     }
 
+    /// Registers several tenants in one transaction, reporting for each one
+    /// whether it was registered, already existed or would exceed `limit`
+    pub fn register_tenants(
+        &self,
+        tenant_ids: &[TenantId],
+        limit: Option<usize>,
+    ) -> Result<Vec<(TenantId, Result<()>)>> {
+        self.check_writable()?;
+        let config = TenantConfig { created_at: Some(self.clock.now()), ..TenantConfig::default() };
+        let outcomes = (&*self.db, &self.counters).transaction(|(tx, counters)| {
+            let existing = Self::read_count(counters)? as usize;
+            let mut registered = 0;
+            let mut outcomes = Vec::with_capacity(tenant_ids.len());
+            for tenant_id in tenant_ids {
+                let key = tenant_id.key();
+                let outcome = if let Err(e) = self.charset.check(tenant_id.as_str()) {
+                    Err(e)
+                } else if tx.get(key.as_bytes())?.is_some() {
+                    Err(Error::TenantAlreadyExists(tenant_id.as_str().to_string()))
+                } else if let Some(limit) = limit.filter(|&limit| existing + registered >= limit) {
+                    Err(Error::TenantLimitReached { limit })
+                } else {
+                    let config = TenantConfig {
+                        full_name: tenant_id.is_hashed().then(|| tenant_id.as_str().to_string()),
+                        sequence: Some(tx.generate_id()?),
+                        ..config.clone()
+                    };
+                    let value = serde_json::to_vec(&config)
+                        .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+                    tx.insert(key.as_bytes(), value)?;
+                    registered += 1;
+                    Ok(())
+                };
+                outcomes.push((tenant_id.clone(), outcome));
+            }
+            if registered > 0 {
+                counters.insert(TENANT_COUNT_KEY, &((existing + registered) as u64).to_be_bytes())?;
+            }
+            Ok(outcomes)
+        })?;
+        Ok(outcomes)
+    }
+
+    /// Returns a tenant's settings
+    pub fn get_tenant_config(&self, tenant_id: &TenantId) -> Result<TenantConfig> {
+        let key = tenant_id.key();
+        let bytes = self
+            .db
+            .get(key.as_bytes())?
+            .ok_or_else(|| Error::InvalidTenant(tenant_id.as_str().to_string()))?;
+        TenantConfig::from_bytes(&bytes)
+    }
+
+    /// Applies `update` to a tenant's settings and stores the result
+    pub fn update_tenant_config(
+        &self,
+        tenant_id: &TenantId,
+        mut update: impl FnMut(&mut TenantConfig),
+    ) -> Result<TenantConfig> {
+        self.check_writable()?;
+        let key = tenant_id.key();
+        loop {
+            let current = self
+                .db
+                .get(key.as_bytes())?
+                .ok_or_else(|| Error::InvalidTenant(tenant_id.as_str().to_string()))?;
+            let mut config = TenantConfig::from_bytes(&current)?;
+            update(&mut config);
+            let value = serde_json::to_vec(&config)?;
+
+            // Retry if the config changed underneath us
+            if self.db.compare_and_swap(key.as_bytes(), Some(current), Some(value))?.is_ok() {
+                return Ok(config);
+            }
+        }
+    }
+
     /// Checks if a tenant exists
     pub fn tenant_exists(&self, tenant_id: &TenantId) -> Result<bool> {
-        let key = tenant_id.as_str().as_bytes();
-        Ok(self.db.contains_key(key)?)
+        let key = tenant_id.key();
+        Ok(self.db.contains_key(key.as_bytes())?)
+    }
+
+    /// Returns the number of registered tenants from the counter kept by
+    /// each registration, without reading the tenants
+    pub fn tenant_count(&self) -> usize {
+        match self.counters.get(TENANT_COUNT_KEY) {
+            Ok(Some(count)) => (*count).try_into().map_or(0, u64::from_be_bytes) as usize,
+            // A registry opened read-only from before the counter existed
+            _ => self.db.len(),
+        }
     }
 
     /// Lists all registered tenants
     pub fn list_tenants(&self) -> Result<Vec<TenantId>> {
+        self.iter_tenants().collect()
+    }
+
+    /// Lists the registered tenants whose names start with `prefix`, sorted
+    /// by name. Tenants keyed by their name are found with a prefix scan;
+    /// those keyed by a hash of a long name are matched on the name kept in
+    /// their config.
+    pub fn list_by_prefix(&self, prefix: &str) -> Result<Vec<TenantId>> {
         let mut tenants = Vec::new();
-        for key in self.db.iter().keys() {
-            let key = key?;
-            if let Ok(tenant_str) = std::str::from_utf8(&key) {
-                tenants.push(TenantId::new(tenant_str));
+        for entry in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = entry?;
+            // A hashed key can itself start with the prefix
+            if TenantConfig::from_bytes(&value).is_ok_and(|config| config.full_name.is_some()) {
+                continue;
             }
+            tenants.extend(std::str::from_utf8(&key).ok().map(TenantId::new));
         }
+        for entry in self.db.scan_prefix(b"~") {
+            let (_, value) = entry?;
+            let full_name = TenantConfig::from_bytes(&value).ok().and_then(|config| config.full_name);
+            tenants.extend(full_name.filter(|name| name.starts_with(prefix)).map(TenantId::new));
+        }
+        tenants.sort_by(|a, b| a.as_str().cmp(b.as_str()));
         Ok(tenants)
     }
 
+    /// Lists all registered tenants in the given order. Unlike
+    /// `list_tenants`, whose order follows the stored keys, the order is
+    /// guaranteed.
+    pub fn list_tenants_sorted(&self, order: TenantOrder) -> Result<Vec<TenantId>> {
+        let mut tenants = self.iter_tenant_configs().collect::<Result<Vec<_>>>()?;
+        match order {
+            TenantOrder::Name => tenants.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str())),
+            TenantOrder::Creation => tenants.sort_by(|(a, a_config), (b, b_config)| {
+                (a_config.sequence, a_config.created_at, a.as_str())
+                    .cmp(&(b_config.sequence, b_config.created_at, b.as_str()))
+            }),
+        }
+        Ok(tenants.into_iter().map(|(tenant_id, _)| tenant_id).collect())
+    }
+
+    /// Iterates over registered tenants in key order, reading each one from
+    /// the database only as the iterator advances
+    pub fn iter_tenants(&self) -> impl Iterator<Item = Result<TenantId>> + '_ {
+        self.iter_tenant_configs().map(|entry| entry.map(|(tenant_id, _)| tenant_id))
+    }
+
+    /// Iterates over registered tenants and their settings in key order
+    fn iter_tenant_configs(&self) -> impl Iterator<Item = Result<(TenantId, TenantConfig)>> + '_ {
+        self.db.iter().filter_map(|entry| {
+            let (key, value) = match entry {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e.into())),
+            };
+            let config = TenantConfig::from_bytes(&value).unwrap_or_default();
+            // Tenants keyed by a hash of their name keep the name in their config
+            if let Some(full_name) = config.full_name.clone() {
+                Some(Ok((TenantId::new(full_name), config)))
+            } else {
+                std::str::from_utf8(&key).ok().map(|tenant_str| Ok((TenantId::new(tenant_str), config)))
+            }
+        })
+    }
+
+    /// Writes the whole registry db as a raw dump for `import_raw`
+    pub fn export_raw(&self, writer: impl std::io::Write) -> Result<()> {
+        crate::dump::export_db(&self.db, writer)
+    }
+
+    /// Restores a dump written by `export_raw` into this registry, which
+    /// must be empty
+    pub fn import_raw(&self, reader: impl std::io::Read) -> Result<()> {
+        self.load_raw(crate::dump::read_dump(reader)?)
+    }
+
+    /// Fails unless a dump can be loaded into this registry
+    pub(crate) fn check_restorable(&self) -> Result<()> {
+        self.check_writable()?;
+        crate::dump::check_empty(&self.db)
+    }
+
+    /// Loads a dump read by `dump::read_dump` into this registry, which
+    /// must be empty
+    pub(crate) fn load_raw(&self, dump: crate::dump::Dump) -> Result<()> {
+        self.check_restorable()?;
+        dump.load(&self.db)?;
+        self.count_tenants()
+    }
+
     /// Validates a tenant ID and returns an error if it doesn't exist
     pub fn validate_tenant(&self, tenant_id: &TenantId) -> Result<()> {
         if !self.tenant_exists(tenant_id)? {
@@ -78,4 +497,169 @@ mod tests {
         assert!(tenants.iter().any(|t| t.as_str() == tenant1.as_str()));
         assert!(tenants.iter().any(|t| t.as_str() == tenant2.as_str()));
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_name_policy() {
+        let charset = TenantCharset::allow("a-z0-9-");
+        let manager = TenantManager::temporary().unwrap().with_name_policy(charset.clone());
+        manager.register_tenant(TenantId::new("my-tenant")).unwrap();
+        let err = manager.register_tenant(TenantId::new("my tenant!")).unwrap_err();
+        assert!(matches!(err, Error::InvalidTenantId(ref msg) if msg.contains("' '")));
+        assert!(matches!(manager.register_tenant(TenantId::new("MyTenant")), Err(Error::InvalidTenantId(_))));
+        assert!(!manager.tenant_exists(&TenantId::new("my tenant!")).unwrap());
+
+        let outcomes = manager.register_tenants(&[TenantId::new("ok-1"), TenantId::new("no_1")], None).unwrap();
+        assert!(outcomes[0].1.is_ok());
+        assert!(matches!(outcomes[1].1, Err(Error::InvalidTenantId(_))));
+
+        assert!(TenantId::try_new("team-7", &charset).is_ok());
+        assert!(TenantId::try_new("team/7", &charset).is_err());
+        assert!(TenantCharset::default().check("my tenant!").is_ok());
+    }
+
+    #[test]
+    fn test_tenant_config() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = TenantManager::new(temp_dir.path()).unwrap();
+        let tenant_id = TenantId::new("posts");
+
+        let config = TenantConfig {
+            display_name: Some("Posts".into()),
+            quota_bytes: Some(1024),
+            ..Default::default()
+        };
+        manager.register_tenant_with_config(tenant_id.clone(), config).unwrap();
+
+        let stored = manager.get_tenant_config(&tenant_id).unwrap();
+        assert_eq!(stored.display_name.as_deref(), Some("Posts"));
+        assert_eq!(stored.quota_bytes, Some(1024));
+        assert!(stored.created_at.is_some());
+
+        // Updating one field keeps the others
+        let updated = manager
+            .update_tenant_config(&tenant_id, |config| config.quota_bytes = Some(2048))
+            .unwrap();
+        assert_eq!(updated.quota_bytes, Some(2048));
+        assert_eq!(manager.get_tenant_config(&tenant_id).unwrap(), updated);
+
+        // Re-registering doesn't reset the config
+        manager.register_tenant(tenant_id.clone()).unwrap();
+        assert_eq!(manager.get_tenant_config(&tenant_id).unwrap(), updated);
+
+        // Tenants registered by older versions have an empty value
+        let legacy = TenantId::new("legacy");
+        manager.db.insert(legacy.as_str().as_bytes(), &[]).unwrap();
+        assert_eq!(manager.get_tenant_config(&legacy).unwrap(), TenantConfig::default());
+        assert_eq!(manager.list_tenants().unwrap().len(), 2);
+
+        assert!(manager.get_tenant_config(&TenantId::new("nonexistent")).is_err());
+    }
+
+    #[test]
+    fn test_register_tenants() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = TenantManager::new(temp_dir.path()).unwrap();
+        manager.register_tenant(TenantId::new("photos")).unwrap();
+
+        let ids: Vec<_> = ["posts", "photos", "videos", "messages", "likes"]
+            .into_iter()
+            .map(TenantId::new)
+            .collect();
+        let outcomes = manager.register_tenants(&ids, None).unwrap();
+        assert_eq!(outcomes.len(), 5);
+        for (tenant_id, outcome) in &outcomes {
+            if tenant_id.as_str() == "photos" {
+                assert!(matches!(outcome, Err(Error::TenantAlreadyExists(name)) if name == "photos"));
+            } else {
+                assert!(outcome.is_ok(), "{}", tenant_id.as_str());
+                assert!(manager.get_tenant_config(tenant_id).unwrap().created_at.is_some());
+            }
+        }
+        assert_eq!(manager.tenant_count(), 5);
+
+        // Tenants past the limit are rejected individually
+        let ids = [TenantId::new("a"), TenantId::new("b")];
+        let outcomes = manager.register_tenants(&ids, Some(6)).unwrap();
+        assert!(outcomes[0].1.is_ok());
+        assert!(matches!(outcomes[1].1, Err(Error::TenantLimitReached { limit: 6 })));
+        assert!(!manager.tenant_exists(&TenantId::new("b")).unwrap());
+        assert_eq!(manager.tenant_count(), 6);
+
+        // A registry written before the counter existed is counted on open
+        manager.counters.remove(TENANT_COUNT_KEY).unwrap();
+        drop(manager);
+        let manager = crate::test_support::reopen(|| TenantManager::new(temp_dir.path()));
+        assert_eq!(manager.tenant_count(), 6);
+    }
+
+    #[test]
+    fn test_iter_tenants() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = TenantManager::new(temp_dir.path()).unwrap();
+        let ids: Vec<_> = (0..2000).map(|i| TenantId::new(format!("tenant-{:04}", i))).collect();
+        manager.register_tenants(&ids, None).unwrap();
+
+        // Taking a page reads only that page, in key order
+        let page: Vec<TenantId> = manager.iter_tenants().take(10).collect::<Result<_>>().unwrap();
+        assert_eq!(page, ids[..10]);
+        let filtered = manager
+            .iter_tenants()
+            .map(|tenant_id| tenant_id.unwrap())
+            .filter(|tenant_id| tenant_id.as_str().ends_with("99"))
+            .count();
+        assert_eq!(filtered, 20);
+        assert_eq!(manager.list_tenants().unwrap(), ids);
+    }
+
+    #[test]
+    fn test_list_by_prefix() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = TenantManager::new(temp_dir.path()).unwrap();
+        let long = format!("team-a-{}", "x".repeat(crate::MAX_TENANT_KEY_LEN));
+        let names = ["team-a", "team-a-web", "team-ab", "team-ab-api", "team-b", "team", long.as_str()];
+        for name in names {
+            manager.register_tenant(TenantId::new(name)).unwrap();
+        }
+        let listed = |prefix| -> Vec<String> {
+            let tenants = manager.list_by_prefix(prefix).unwrap();
+            tenants.iter().map(|tenant_id| tenant_id.as_str().to_string()).collect()
+        };
+
+        // Tenants keyed by a hash of a long name are matched by the name
+        assert_eq!(listed("team-a-"), ["team-a-web".to_string(), long.clone()]);
+        assert_eq!(listed("team-a"), ["team-a", "team-a-web", long.as_str(), "team-ab", "team-ab-api"]);
+        // The scan stops at the prefix boundary
+        assert_eq!(listed("team-ab"), ["team-ab", "team-ab-api"]);
+        assert!(listed("team-c").is_empty());
+        assert!(listed("~").is_empty());
+        assert_eq!(listed("").len(), names.len());
+    }
+
+    #[test]
+    fn test_list_tenants_sorted() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = TenantManager::new(temp_dir.path()).unwrap();
+        manager.register_tenant(TenantId::new("videos")).unwrap();
+        manager.register_tenant(TenantId::new("Posts")).unwrap();
+        let batch = [TenantId::new("likes"), TenantId::new("archive")];
+        manager.register_tenants(&batch, None).unwrap();
+        manager.register_tenant(TenantId::new("messages")).unwrap();
+        // A tenant from before creation order was recorded comes first
+        manager.db.insert("zebra", &[]).unwrap();
+
+        let names = |order| -> Vec<String> {
+            let tenants = manager.list_tenants_sorted(order).unwrap();
+            tenants.iter().map(|tenant_id| tenant_id.as_str().to_string()).collect()
+        };
+        assert_eq!(names(TenantOrder::Name), ["Posts", "archive", "likes", "messages", "videos", "zebra"]);
+        assert_eq!(names(TenantOrder::Creation), ["zebra", "videos", "Posts", "likes", "archive", "messages"]);
+
+        // Re-registering a tenant doesn't move it, and the order survives a reopen
+        manager.register_tenant(TenantId::new("videos")).unwrap();
+        drop(manager);
+        let manager = TenantManager::new(temp_dir.path()).unwrap();
+        let tenants = manager.list_tenants_sorted(TenantOrder::Creation).unwrap();
+        assert_eq!(tenants[1], TenantId::new("videos"));
+        assert_eq!(tenants[5], TenantId::new("messages"));
+    }
+}
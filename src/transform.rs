@@ -0,0 +1,66 @@
+use std::io::{self, Read};
+
+/// Size of the chunks read from the inner reader and handed to the transform
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Wraps a reader, passing each chunk it yields through a transform before
+/// handing it on. The whole stream is never held in memory.
+///
+/// Chunk boundaries are arbitrary, so a transform that matches multi-byte
+/// patterns must carry any partial match over to its next call itself.
+///
+/// Any reader can be stored, so transforms compose with every put method:
+///
+/// ```
+/// use std::io::Cursor;
+/// use mini_tectonic_rs::{TenantId, coordinator::Coordinator, transform::TransformReader};
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let coordinator = Coordinator::new(dir.path()).unwrap();
+/// let tenant_id = TenantId::new("posts");
+/// coordinator.register_tenant(tenant_id.clone()).unwrap();
+///
+/// let data = TransformReader::new(Cursor::new("a\r\nb\r\n"), |chunk: &[u8]| {
+///     chunk.iter().copied().filter(|&b| b != b'\r').collect()
+/// });
+/// coordinator.put_blob(&tenant_id, data).unwrap();
+/// ```
+pub struct TransformReader<R, F> {
+    inner: R,
+    transform: F,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read, F: FnMut(&[u8]) -> Vec<u8>> TransformReader<R, F> {
+    pub fn new(inner: R, transform: F) -> Self {
+        Self {
+            inner,
+            transform,
+            input: vec![0; CHUNK_SIZE],
+            output: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(&[u8]) -> Vec<u8>> Read for TransformReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A transform may return nothing for a chunk, so keep pulling until
+        // there is output or the inner reader is exhausted
+        while self.pos == self.output.len() {
+            let n = self.inner.read(&mut self.input)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            self.output = (self.transform)(&self.input[..n]);
+            self.pos = 0;
+        }
+
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{BlobId, coordinator::Coordinator, error::Error};
+
+/// Controls a background verifier started by `Coordinator::spawn_verifier`.
+/// Dropping the handle also stops the worker, but without waiting for it.
+pub struct VerifierHandle {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl VerifierHandle {
+    /// Stops the verifier and waits for its thread to exit. A scrub in
+    /// progress stops at the next blob.
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+    }
+}
+
+impl Coordinator {
+    /// Starts a background thread that scrubs every blob in the store,
+    /// then waits `interval` before the next pass. At most `rate_limit`
+    /// blobs are verified per second so foreground traffic keeps most of
+    /// the disk bandwidth. `on_corrupt` is called for every blob that fails
+    /// its size or checksum check.
+    pub fn spawn_verifier<F>(self: &Arc<Self>, interval: Duration, rate_limit: u32, on_corrupt: F) -> VerifierHandle
+    where
+        F: Fn(&BlobId, &Error) + Send + 'static,
+    {
+        let coordinator = Arc::clone(self);
+        let pause = Duration::from_secs(1) / rate_limit.max(1);
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        // Sleeps by waiting on the stop channel, returning true if stopped
+        let wait = move |duration| !matches!(stopped.recv_timeout(duration), Err(RecvTimeoutError::Timeout));
+
+        let thread = thread::spawn(move || loop {
+            match coordinator.all_blob_ids() {
+                Ok(blob_ids) => {
+                    for blob_id in blob_ids {
+                        match coordinator.verify_stored(&blob_id) {
+                            // Deleted since the pass started
+                            Err(Error::BlobNotFound(_)) | Ok(()) => {}
+                            Err(e) => on_corrupt(&blob_id, &e),
+                        }
+                        if wait(pause) {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "verifier failed to list blobs"),
+            }
+            if wait(interval) {
+                return;
+            }
+        });
+
+        VerifierHandle { stop, thread }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TenantId, coordinator::BlobLocation};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_verifier_reports_corruption() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let coordinator = Arc::new(Coordinator::new(temp_dir.path()).unwrap());
+        let tenant_id = TenantId::new("posts");
+        coordinator.register_tenant(tenant_id.clone()).unwrap();
+
+        coordinator.put_blob(&tenant_id, Cursor::new(b"intact")).unwrap();
+        let corrupt = coordinator.put_blob(&tenant_id, Cursor::new(b"Hello, World!")).unwrap();
+        let BlobLocation::File { blob_path, .. } = coordinator.blob_location(&corrupt).unwrap() else {
+            panic!("expected a file-backed blob");
+        };
+        std::fs::write(&blob_path, b"Hello, Wxrld!").unwrap();
+
+        let (found, reports) = mpsc::channel();
+        let handle = coordinator.spawn_verifier(Duration::from_millis(20), 1000, move |blob_id, err| {
+            let _ = found.send((blob_id.clone(), err.to_string()));
+        });
+
+        let (blob_id, err) = reports.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(blob_id, corrupt);
+        assert!(err.contains("Checksum mismatch"));
+
+        // Foreground operations keep working while the verifier runs
+        coordinator.put_blob(&tenant_id, Cursor::new(b"more")).unwrap();
+
+        // Later passes report the same blob again, and nothing else
+        let (blob_id, _) = reports.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(blob_id, corrupt);
+
+        handle.stop();
+        while reports.try_recv().is_ok() {}
+        assert!(matches!(reports.recv_timeout(Duration::from_millis(100)), Err(RecvTimeoutError::Disconnected)));
+    }
+}